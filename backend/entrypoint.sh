@@ -1,5 +1,11 @@
 #!/bin/bash
 
+# Khoá ký JWT bắt buộc (xem backend/.env.example), thiếu thì dừng với thông báo rõ ràng
+if [ "${JWT_ALG:-HS256}" = "HS256" ] && [ -z "$JWT_SECRET" ]; then
+  echo "❌ Thiếu JWT_SECRET (truyền qua -e JWT_SECRET=... hoặc secret của container)" >&2
+  exit 1
+fi
+
 echo "🚀 Starting Milan backend..."
 /usr/local/bin/milan &
 
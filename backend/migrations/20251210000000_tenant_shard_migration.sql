@@ -0,0 +1,29 @@
+-- ============================================================
+-- TENANT SHARD MIGRATION – chuyển dữ liệu 1 tenant giữa các shard
+--  - Bảng nằm ở meta DB (cùng chỗ với bảng tenant)
+--  - Mỗi tenant chỉ có tối đa 1 job đang chạy
+--  - status: pending → fenced → copying → verifying → completed | failed
+-- ============================================================
+
+CREATE TABLE IF NOT EXISTS tenant_shard_migration (
+  id              UUID PRIMARY KEY,
+  tenant_id       UUID NOT NULL REFERENCES tenant(tenant_id) ON DELETE CASCADE,
+  source_shard_id TEXT NOT NULL,
+  target_shard_id TEXT NOT NULL,
+  status          TEXT NOT NULL DEFAULT 'pending'
+                  CHECK (status IN ('pending', 'fenced', 'copying', 'verifying', 'completed', 'failed')),
+  delete_source   BOOLEAN NOT NULL DEFAULT FALSE,         -- Xoá dữ liệu ở shard cũ sau khi chuyển
+  report          JSONB NOT NULL DEFAULT '[]',            -- [{table, rows, checksum}]
+  error           TEXT,
+  created_by      UUID NOT NULL,
+  started_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+  finished_at     TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_tenant_shard_migration_tenant
+  ON tenant_shard_migration (tenant_id, started_at DESC);
+
+-- Chặn 2 job chạy song song cho cùng tenant
+CREATE UNIQUE INDEX IF NOT EXISTS uq_tenant_shard_migration_active
+  ON tenant_shard_migration (tenant_id)
+  WHERE status IN ('pending', 'fenced', 'copying', 'verifying');
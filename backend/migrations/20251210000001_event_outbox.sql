@@ -0,0 +1,26 @@
+-- ============================================================
+-- EVENT OUTBOX – domain event ghi cùng transaction với command
+--  - Mỗi shard có bảng riêng (ghi cùng DB với dữ liệu nghiệp vụ)
+--  - Relay nền đọc các dòng chưa publish và gửi qua EventPublisher
+--  - Giao nhận at-least-once: consumer khử trùng lặp theo id
+-- ============================================================
+
+CREATE TABLE IF NOT EXISTS event_outbox (
+  tenant_id       UUID NOT NULL,
+  id              UUID NOT NULL,                      -- event id
+  topic           TEXT NOT NULL,                      -- loan | invoice | contact | user ...
+  event_type      TEXT NOT NULL,                      -- LoanCreated, InvoicePosted ...
+  aggregate_id    UUID NOT NULL,                      -- partition key
+  payload         JSONB NOT NULL,
+  created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+  published_at    TIMESTAMPTZ,                        -- NULL = chưa gửi
+  attempts        INT NOT NULL DEFAULT 0,
+  last_error      TEXT,
+  next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (tenant_id, id)
+);
+
+-- Relay chỉ quét các dòng chưa publish
+CREATE INDEX IF NOT EXISTS idx_event_outbox_pending
+  ON event_outbox (next_attempt_at, created_at)
+  WHERE published_at IS NULL;
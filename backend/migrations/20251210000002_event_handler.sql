@@ -0,0 +1,39 @@
+-- ============================================================
+-- EVENT HANDLER – subscriber nội bộ cho domain event
+--  - event_handler_inbox: mỗi (handler, event) 1 dòng → idempotent theo event id
+--  - Retry với backoff; quá số lần thử thì chuyển sang event_dead_letter
+-- ============================================================
+
+CREATE TABLE IF NOT EXISTS event_handler_inbox (
+  tenant_id       UUID NOT NULL,
+  handler_name    TEXT NOT NULL,
+  event_id        UUID NOT NULL,
+  event           JSONB NOT NULL,                     -- EventEnvelope đầy đủ
+  status          TEXT NOT NULL DEFAULT 'pending'
+                  CHECK (status IN ('pending', 'done', 'dead')),
+  attempts        INT NOT NULL DEFAULT 0,
+  last_error      TEXT,
+  next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+  processed_at    TIMESTAMPTZ,
+  PRIMARY KEY (tenant_id, handler_name, event_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_event_handler_inbox_pending
+  ON event_handler_inbox (next_attempt_at, created_at)
+  WHERE status = 'pending';
+
+CREATE TABLE IF NOT EXISTS event_dead_letter (
+  tenant_id    UUID NOT NULL,
+  id           UUID NOT NULL,
+  handler_name TEXT NOT NULL,
+  event_id     UUID NOT NULL,
+  event        JSONB NOT NULL,
+  error        TEXT,
+  attempts     INT NOT NULL,
+  failed_at    TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (tenant_id, id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_event_dead_letter_handler
+  ON event_dead_letter (tenant_id, handler_name, failed_at DESC);
@@ -0,0 +1,22 @@
+-- ============================================================
+-- COMMAND AUDIT LOG – mỗi lệnh ghi đi qua command bus để lại 1 dòng
+--  outcome: ok | rejected (không có quyền / dữ liệu sai) | failed (lỗi hệ thống)
+-- ============================================================
+
+CREATE TABLE IF NOT EXISTS command_audit_log (
+  tenant_id   UUID NOT NULL,
+  id          UUID NOT NULL,
+  user_id     UUID NOT NULL,
+  command     TEXT NOT NULL,                          -- vd: loan.create_contract
+  outcome     TEXT NOT NULL CHECK (outcome IN ('ok', 'rejected', 'failed')),
+  error       TEXT,
+  duration_ms BIGINT NOT NULL,
+  created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (tenant_id, id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_command_audit_log_tenant_time
+  ON command_audit_log (tenant_id, created_at DESC);
+
+CREATE INDEX IF NOT EXISTS idx_command_audit_log_user
+  ON command_audit_log (tenant_id, user_id, created_at DESC);
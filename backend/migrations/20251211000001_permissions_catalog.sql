@@ -0,0 +1,20 @@
+-- Seed quyền chi tiết cho các module nghiệp vụ (RequirePermission trên route)
+INSERT INTO permissions (resource, action, label) VALUES
+ ('loan','read','Xem hợp đồng vay'),
+ ('loan','create','Tạo hợp đồng vay'),
+ ('loan','update','Cập nhật hợp đồng vay'),
+ ('loan','delete','Xoá hợp đồng vay'),
+ ('contact','read','Xem danh bạ'),
+ ('contact','create','Tạo liên hệ'),
+ ('contact','update','Cập nhật liên hệ'),
+ ('contact','delete','Xoá liên hệ'),
+ ('invoice','read','Xem hoá đơn'),
+ ('invoice','create','Tạo hoá đơn'),
+ ('invoice','update','Cập nhật hoá đơn'),
+ ('invoice','delete','Xoá hoá đơn'),
+ ('invoice','confirm','Xác nhận hoá đơn'),
+ ('invoice','cancel','Huỷ hoá đơn'),
+ ('invoice_link','read','Xem liên kết hoá đơn điện tử'),
+ ('invoice_link','manage','Quản lý kết nối nhà cung cấp hoá đơn'),
+ ('invoice_link','send','Gửi hoá đơn sang nhà cung cấp')
+ON CONFLICT DO NOTHING;
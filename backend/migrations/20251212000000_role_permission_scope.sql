@@ -0,0 +1,4 @@
+-- ABAC: biểu thức scope giới hạn bản ghi cho từng quyền của role
+-- vd: assignee_id = $user OR $user IN shared_with
+-- NULL = không giới hạn trong tenant
+ALTER TABLE role_permissions ADD COLUMN IF NOT EXISTS scope TEXT;
@@ -0,0 +1,18 @@
+-- Refresh token (chỉ lưu SHA-256), xoay vòng mỗi lần /user/refresh
+-- replaced_by: token mới thay thế → phát hiện token đã dùng bị dùng lại
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+  tenant_id   UUID        NOT NULL,
+  id          UUID        NOT NULL,
+  user_id     UUID        NOT NULL,
+  token_hash  TEXT        NOT NULL,
+  expires_at  TIMESTAMPTZ NOT NULL,
+  revoked_at  TIMESTAMPTZ,
+  replaced_by UUID,
+  created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (tenant_id, id),
+  FOREIGN KEY (tenant_id, user_id) REFERENCES users(tenant_id, user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_refresh_tokens_hash ON refresh_tokens (token_hash);
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_user_active
+  ON refresh_tokens (tenant_id, user_id) WHERE revoked_at IS NULL;
@@ -0,0 +1,3 @@
+-- Phiên bản quyền của user: tăng khi gán role / đổi quyền của role
+-- JWT mang perm_ver lúc phát hành → token cũ hơn bị từ chối, client phải refresh
+ALTER TABLE users ADD COLUMN IF NOT EXISTS perm_version BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,23 @@
+-- Lịch trả nợ dự kiến theo hợp đồng (sinh lại mỗi lần tạo / cập nhật hợp đồng)
+ALTER TABLE loan_contract
+  ADD COLUMN IF NOT EXISTS repayment_plan TEXT NOT NULL DEFAULT 'equal_principal';
+
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS ck_loan_contract_repayment_plan;
+ALTER TABLE loan_contract ADD CONSTRAINT ck_loan_contract_repayment_plan
+  CHECK (repayment_plan IN ('equal_principal','annuity','interest_only_bullet','custom'));
+
+CREATE TABLE IF NOT EXISTS loan_schedule (
+  tenant_id         UUID    NOT NULL,
+  contract_id       UUID    NOT NULL,
+  seq               INT     NOT NULL,              -- kỳ thứ (1..n)
+  due_date          DATE    NOT NULL,              -- ngày đến hạn (giờ địa phương)
+  principal         BIGINT  NOT NULL,              -- gốc phải trả kỳ này
+  interest          BIGINT  NOT NULL,              -- lãi dự kiến kỳ này
+  total_due         BIGINT  NOT NULL,
+  remaining_balance BIGINT  NOT NULL,              -- dư nợ gốc sau kỳ
+  created_at        TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (tenant_id, contract_id, seq),
+  FOREIGN KEY (tenant_id, contract_id) REFERENCES loan_contract(tenant_id, id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_loan_schedule_due ON loan_schedule (tenant_id, due_date);
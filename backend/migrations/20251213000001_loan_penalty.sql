@@ -0,0 +1,12 @@
+-- Lãi phạt trên số tiền quá hạn (%/năm), 0 = không phạt
+ALTER TABLE loan_contract
+  ADD COLUMN IF NOT EXISTS penalty_rate DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS ck_loan_contract_penalty_rate;
+ALTER TABLE loan_contract ADD CONSTRAINT ck_loan_contract_penalty_rate CHECK (penalty_rate >= 0);
+
+-- Giao dịch thu lãi phạt
+ALTER TABLE loan_transaction DROP CONSTRAINT IF EXISTS loan_transaction_transaction_type_check;
+ALTER TABLE loan_transaction ADD CONSTRAINT loan_transaction_transaction_type_check CHECK (
+    transaction_type IN ('disbursement','additional','interest','principal','penalty','liquidation','settlement')
+);
@@ -0,0 +1,16 @@
+-- Quy ước tính lãi theo hợp đồng + múi giờ nghiệp vụ theo tenant
+ALTER TABLE loan_contract
+  ADD COLUMN IF NOT EXISTS day_count   TEXT NOT NULL DEFAULT 'act_365',
+  ADD COLUMN IF NOT EXISTS compounding TEXT NOT NULL DEFAULT 'simple';
+
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS ck_loan_contract_day_count;
+ALTER TABLE loan_contract ADD CONSTRAINT ck_loan_contract_day_count
+  CHECK (day_count IN ('act_365','act_360','act_act','30_360'));
+
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS ck_loan_contract_compounding;
+ALTER TABLE loan_contract ADD CONSTRAINT ck_loan_contract_compounding
+  CHECK (compounding IN ('simple','daily','monthly'));
+
+-- Tên múi giờ IANA (vd: Asia/Ho_Chi_Minh), dùng để quy đổi ngày nghiệp vụ
+ALTER TABLE tenant
+  ADD COLUMN IF NOT EXISTS timezone TEXT NOT NULL DEFAULT 'Asia/Bangkok';
@@ -0,0 +1,20 @@
+-- Phí lưu kho (hợp đồng cầm đồ) tính theo ngày:
+--   storage_fee_rate  % giá trị ước tính tài sản đang cầm cố / ngày (đã có)
+--   storage_fee_flat  phí cố định / ngày
+--   storage_fee       tổng phí lưu kho đã phát sinh (projection)
+ALTER TABLE loan_contract
+  ADD COLUMN IF NOT EXISTS storage_fee_flat BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS ck_loan_contract_storage_fee;
+ALTER TABLE loan_contract ADD CONSTRAINT ck_loan_contract_storage_fee
+  CHECK (storage_fee_rate >= 0 AND storage_fee_flat >= 0);
+
+-- Giao dịch thu phí lưu kho
+ALTER TABLE loan_transaction DROP CONSTRAINT IF EXISTS loan_transaction_transaction_type_check;
+ALTER TABLE loan_transaction ADD CONSTRAINT loan_transaction_transaction_type_check CHECK (
+    transaction_type IN ('disbursement','additional','interest','principal','penalty','storage_fee','liquidation','settlement')
+);
+
+-- Phí lưu kho chưa thu trong báo cáo pivot
+ALTER TABLE loan_report
+  ADD COLUMN IF NOT EXISTS current_storage_fee BIGINT;
@@ -0,0 +1,10 @@
+-- Phí trả nợ trước hạn theo hợp đồng, vd:
+--   {"type":"none"}
+--   {"type":"percent","rate":2.0,"waive_after_months":6}   -- 2% dư nợ gốc, miễn sau 6 tháng
+--   {"type":"fixed","amount":500000}
+ALTER TABLE loan_contract
+  ADD COLUMN IF NOT EXISTS prepayment_penalty JSONB NOT NULL DEFAULT '{"type":"none"}'::jsonb;
+
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS ck_loan_contract_prepayment_penalty;
+ALTER TABLE loan_contract ADD CONSTRAINT ck_loan_contract_prepayment_penalty
+  CHECK (prepayment_penalty->>'type' IN ('none','percent','fixed'));
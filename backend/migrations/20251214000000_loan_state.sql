@@ -0,0 +1,42 @@
+-- Trạng thái hợp đồng: lưu mã thay cho chuỗi hiển thị tiếng Việt
+UPDATE loan_contract SET state = CASE state
+    WHEN 'Nháp'        THEN 'draft'
+    WHEN 'Hoạt động'   THEN 'active'
+    WHEN 'Quá hạn'     THEN 'overdue'
+    WHEN 'Đã tất toán' THEN 'settled'
+    WHEN 'Đã thanh lý' THEN 'liquidated'
+    ELSE state
+END;
+UPDATE loan_contract SET state = 'draft'
+ WHERE state NOT IN ('draft','active','overdue','settled','liquidated','written_off');
+
+ALTER TABLE loan_contract ALTER COLUMN state SET DEFAULT 'draft';
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS ck_loan_contract_state;
+ALTER TABLE loan_contract ADD CONSTRAINT ck_loan_contract_state
+  CHECK (state IN ('draft','active','overdue','settled','liquidated','written_off'));
+
+UPDATE loan_report r SET state = c.state
+  FROM loan_contract c
+ WHERE c.tenant_id = r.tenant_id AND c.id = r.contract_id;
+
+-- Lịch sử chuyển trạng thái (changed_by NULL = hệ thống tự chuyển, vd quá hạn)
+CREATE TABLE IF NOT EXISTS loan_state_history (
+    tenant_id    UUID NOT NULL,
+    id           UUID NOT NULL DEFAULT gen_random_uuid(),
+    contract_id  UUID NOT NULL,
+    from_state   TEXT,
+    to_state     TEXT NOT NULL,
+    reason       TEXT,
+    changed_by   UUID,
+    changed_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, id),
+    FOREIGN KEY (tenant_id, contract_id) REFERENCES loan_contract (tenant_id, id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_loan_state_history_contract
+  ON loan_state_history (tenant_id, contract_id, changed_at);
+
+INSERT INTO permissions (resource, action, label) VALUES
+ ('loan','write_off','Xoá nợ hợp đồng vay')
+ON CONFLICT DO NOTHING;
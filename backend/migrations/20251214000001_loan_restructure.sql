@@ -0,0 +1,35 @@
+-- Cơ cấu lại hợp đồng: thay đổi lãi suất / kỳ hạn có hiệu lực từ 1 ngày
+-- (lãi trước ngày hiệu lực vẫn tính theo điều khoản cũ)
+CREATE TABLE IF NOT EXISTS loan_term_change (
+    tenant_id              UUID NOT NULL,
+    id                     UUID NOT NULL DEFAULT gen_random_uuid(),
+    contract_id            UUID NOT NULL,
+    effective_date         TIMESTAMPTZ NOT NULL,
+    previous_interest_rate DOUBLE PRECISION NOT NULL,
+    interest_rate          DOUBLE PRECISION NOT NULL,
+    previous_term_months   INT NOT NULL,
+    term_months            INT NOT NULL,
+    reason                 TEXT,
+    created_by             UUID NOT NULL,
+    created_at             TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, id),
+    FOREIGN KEY (tenant_id, contract_id) REFERENCES loan_contract (tenant_id, id) ON DELETE CASCADE,
+    CONSTRAINT ck_loan_term_change_terms CHECK (interest_rate >= 0 AND term_months > 0)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_loan_term_change_effective
+  ON loan_term_change (tenant_id, contract_id, effective_date);
+
+-- Tái cấp vốn: hợp đồng mới trỏ về hợp đồng cũ đã tất toán
+ALTER TABLE loan_contract ADD COLUMN IF NOT EXISTS refinanced_from UUID;
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS fk_loan_contract_refinanced_from;
+ALTER TABLE loan_contract ADD CONSTRAINT fk_loan_contract_refinanced_from
+  FOREIGN KEY (tenant_id, refinanced_from) REFERENCES loan_contract (tenant_id, id) ON DELETE SET NULL (refinanced_from);
+
+CREATE INDEX IF NOT EXISTS idx_loan_contract_refinanced_from
+  ON loan_contract (tenant_id, refinanced_from) WHERE refinanced_from IS NOT NULL;
+
+INSERT INTO permissions (resource, action, label) VALUES
+ ('loan','refinance','Tái cấp vốn hợp đồng vay')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,36 @@
+-- Lãi suất thả nổi: chỉ số tham chiếu theo tenant (vd "VNIBOR_3M", "BASE") + biên độ theo hợp đồng
+CREATE TABLE IF NOT EXISTS loan_rate_index (
+    tenant_id   UUID NOT NULL,
+    code        TEXT NOT NULL,
+    name        TEXT NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, code)
+);
+
+-- Giá trị chỉ số (%/năm) có hiệu lực từ ngày `effective_date`
+CREATE TABLE IF NOT EXISTS loan_rate_index_value (
+    tenant_id       UUID NOT NULL,
+    index_code      TEXT NOT NULL,
+    effective_date  TIMESTAMPTZ NOT NULL,
+    rate            DOUBLE PRECISION NOT NULL,
+    created_by      UUID NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, index_code, effective_date),
+    FOREIGN KEY (tenant_id, index_code) REFERENCES loan_rate_index (tenant_id, code) ON DELETE CASCADE
+);
+
+-- rate_index NULL = lãi cố định theo interest_rate
+ALTER TABLE loan_contract ADD COLUMN IF NOT EXISTS rate_index TEXT;
+ALTER TABLE loan_contract ADD COLUMN IF NOT EXISTS rate_margin DOUBLE PRECISION NOT NULL DEFAULT 0;
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS fk_loan_contract_rate_index;
+ALTER TABLE loan_contract ADD CONSTRAINT fk_loan_contract_rate_index
+  FOREIGN KEY (tenant_id, rate_index) REFERENCES loan_rate_index (tenant_id, code);
+
+CREATE INDEX IF NOT EXISTS idx_loan_contract_rate_index
+  ON loan_contract (tenant_id, rate_index) WHERE rate_index IS NOT NULL;
+
+INSERT INTO permissions (resource, action, label) VALUES
+ ('loan','manage_rates','Quản lý chỉ số lãi suất tham chiếu')
+ON CONFLICT DO NOTHING;
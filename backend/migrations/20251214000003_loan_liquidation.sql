@@ -0,0 +1,86 @@
+-- Thanh lý tài sản cầm cố: thu giữ → ghi nhận tiền bán từng tài sản → phân bổ lãi, gốc, phí.
+-- Tiền thừa trả lại khách (khoản phải trả), tiền thiếu để lại dưới dạng nợ đã xoá.
+
+-- Tài sản đang bị thu giữ chờ bán
+ALTER TABLE collateral_assets DROP CONSTRAINT IF EXISTS ck_collateral_status;
+ALTER TABLE collateral_assets ADD CONSTRAINT ck_collateral_status
+  CHECK (status IN ('available','pledged','released','seized','sold','disposed'));
+
+CREATE TABLE IF NOT EXISTS loan_liquidation (
+    tenant_id           UUID NOT NULL,
+    id                  UUID NOT NULL DEFAULT gen_random_uuid(),
+    contract_id         UUID NOT NULL,
+    status              TEXT NOT NULL DEFAULT 'seized',           -- seized/completed
+    reason              TEXT,
+    seized_by           UUID NOT NULL,
+    seized_at           TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    -- Kết quả phân bổ (khi hoàn tất)
+    liquidation_date    TIMESTAMPTZ,
+    proceeds_total      BIGINT NOT NULL DEFAULT 0,
+    interest_allocated  BIGINT NOT NULL DEFAULT 0,
+    principal_allocated BIGINT NOT NULL DEFAULT 0,
+    fees_allocated      BIGINT NOT NULL DEFAULT 0,
+    surplus             BIGINT NOT NULL DEFAULT 0,
+    shortfall           BIGINT NOT NULL DEFAULT 0,
+    completed_by        UUID,
+    completed_at        TIMESTAMPTZ,
+
+    PRIMARY KEY (tenant_id, id),
+    FOREIGN KEY (tenant_id, contract_id) REFERENCES loan_contract (tenant_id, id) ON DELETE CASCADE,
+    CONSTRAINT ck_loan_liquidation_status CHECK (status IN ('seized','completed')),
+    CONSTRAINT ck_loan_liquidation_amounts CHECK (
+        proceeds_total >= 0 AND interest_allocated >= 0 AND principal_allocated >= 0
+        AND fees_allocated >= 0 AND surplus >= 0 AND shortfall >= 0
+    )
+);
+
+-- Mỗi hợp đồng chỉ thanh lý 1 lần
+CREATE UNIQUE INDEX IF NOT EXISTS uq_loan_liquidation_contract
+  ON loan_liquidation (tenant_id, contract_id);
+
+-- Tài sản bị thu giữ + tiền bán từng tài sản
+CREATE TABLE IF NOT EXISTS loan_liquidation_asset (
+    tenant_id      UUID NOT NULL,
+    liquidation_id UUID NOT NULL,
+    asset_id       UUID NOT NULL,
+    value_estimate NUMERIC(18,2),                                 -- giá trị ước tính lúc thu giữ
+    proceeds       BIGINT,                                        -- NULL = chưa bán
+    sold_at        TIMESTAMPTZ,
+    note           TEXT,
+    recorded_by    UUID,
+    recorded_at    TIMESTAMPTZ,
+
+    PRIMARY KEY (tenant_id, liquidation_id, asset_id),
+    FOREIGN KEY (tenant_id, liquidation_id) REFERENCES loan_liquidation (tenant_id, id) ON DELETE CASCADE,
+    FOREIGN KEY (tenant_id, asset_id) REFERENCES collateral_assets (tenant_id, asset_id),
+    CONSTRAINT ck_loan_liquidation_asset_proceeds CHECK (proceeds IS NULL OR proceeds >= 0)
+);
+
+-- Khoản phải trả lại khách hàng (tiền thừa sau thanh lý)
+CREATE TABLE IF NOT EXISTS loan_contact_payable (
+    tenant_id      UUID NOT NULL,
+    id             UUID NOT NULL DEFAULT gen_random_uuid(),
+    contact_id     UUID NOT NULL,
+    contract_id    UUID NOT NULL,
+    liquidation_id UUID,
+    amount         BIGINT NOT NULL,
+    status         TEXT NOT NULL DEFAULT 'open',                  -- open/paid
+    note           TEXT,
+    created_by     UUID NOT NULL,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    paid_at        TIMESTAMPTZ,
+
+    PRIMARY KEY (tenant_id, id),
+    FOREIGN KEY (tenant_id, contract_id) REFERENCES loan_contract (tenant_id, id) ON DELETE CASCADE,
+    FOREIGN KEY (tenant_id, liquidation_id) REFERENCES loan_liquidation (tenant_id, id) ON DELETE SET NULL (liquidation_id),
+    CONSTRAINT ck_loan_contact_payable_amount CHECK (amount > 0),
+    CONSTRAINT ck_loan_contact_payable_status CHECK (status IN ('open','paid'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_loan_contact_payable_contact
+  ON loan_contact_payable (tenant_id, contact_id, status);
+
+INSERT INTO permissions (resource, action, label) VALUES
+ ('loan','liquidate','Thanh lý tài sản cầm cố')
+ON CONFLICT DO NOTHING;
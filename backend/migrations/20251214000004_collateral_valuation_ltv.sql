@@ -0,0 +1,63 @@
+-- Lịch sử định giá tài sản cầm cố; `collateral_assets.value_estimate` luôn = lần định giá mới nhất
+CREATE TABLE IF NOT EXISTS collateral_valuation (
+    tenant_id      UUID NOT NULL,
+    id             UUID NOT NULL DEFAULT gen_random_uuid(),
+    asset_id       UUID NOT NULL,
+    valuation_date TIMESTAMPTZ NOT NULL,
+    value          NUMERIC(18,2) NOT NULL,
+    appraiser      TEXT,                                            -- người / đơn vị thẩm định
+    method         TEXT NOT NULL DEFAULT 'manual',                  -- manual/market/appraisal/auction/...
+    note           TEXT,
+    created_by     UUID NOT NULL,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, id),
+    FOREIGN KEY (tenant_id, asset_id) REFERENCES collateral_assets (tenant_id, asset_id) ON DELETE CASCADE,
+    CONSTRAINT ck_collateral_valuation_value  CHECK (value >= 0),
+    CONSTRAINT ck_collateral_valuation_method CHECK (length(trim(method)) > 0)
+);
+
+CREATE INDEX IF NOT EXISTS idx_collateral_valuation_asset
+  ON collateral_valuation (tenant_id, asset_id, valuation_date DESC);
+
+-- Giá trị hiện có → lần định giá đầu tiên
+INSERT INTO collateral_valuation (tenant_id, asset_id, valuation_date, value, method, created_by, created_at)
+SELECT a.tenant_id, a.asset_id, a.created_at, a.value_estimate, 'initial', a.created_by, a.created_at
+FROM collateral_assets a
+WHERE a.value_estimate IS NOT NULL
+  AND NOT EXISTS (
+    SELECT 1 FROM collateral_valuation v WHERE v.tenant_id = a.tenant_id AND v.asset_id = a.asset_id
+  );
+
+-- Ngưỡng LTV (dư nợ gốc / giá trị tài sản cầm cố, %) do tenant tự đặt
+CREATE TABLE IF NOT EXISTS loan_ltv_threshold (
+    tenant_id  UUID NOT NULL,
+    code       TEXT NOT NULL,                                       -- vd: warning, margin_call
+    name       TEXT NOT NULL,
+    ltv        DOUBLE PRECISION NOT NULL,                           -- %
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, code),
+    CONSTRAINT ck_loan_ltv_threshold_code CHECK (length(trim(code)) > 0),
+    CONSTRAINT ck_loan_ltv_threshold_ltv  CHECK (ltv > 0)
+);
+
+-- Hợp đồng đang vượt ngưỡng (job giám sát LTV ghi, hết vượt ngưỡng thì xoá)
+CREATE TABLE IF NOT EXISTS loan_ltv_alert (
+    tenant_id        UUID NOT NULL,
+    contract_id      UUID NOT NULL,
+    threshold_code   TEXT NOT NULL,
+    ltv              DOUBLE PRECISION NOT NULL,
+    principal        BIGINT NOT NULL,
+    collateral_value BIGINT NOT NULL,
+    flagged_at       TIMESTAMPTZ NOT NULL DEFAULT now(),            -- lần đầu vượt ngưỡng hiện tại
+    checked_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, contract_id),
+    FOREIGN KEY (tenant_id, contract_id) REFERENCES loan_contract (tenant_id, id) ON DELETE CASCADE,
+    FOREIGN KEY (tenant_id, threshold_code) REFERENCES loan_ltv_threshold (tenant_id, code) ON DELETE CASCADE
+);
+
+INSERT INTO permissions (resource, action, label) VALUES
+ ('loan','manage_ltv','Cấu hình ngưỡng LTV')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,27 @@
+-- Job snapshot loan_report cuối ngày nghiệp vụ: lưu trạng thái chạy để server khởi động lại chạy tiếp
+CREATE TABLE IF NOT EXISTS loan_report_snapshot_run (
+    tenant_id  UUID PRIMARY KEY,
+    last_date  DATE NOT NULL,                                   -- ngày nghiệp vụ cuối cùng đã snapshot xong
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Yêu cầu chạy bù snapshot cho 1 khoảng ngày
+CREATE TABLE IF NOT EXISTS loan_report_backfill (
+    tenant_id    UUID NOT NULL,
+    id           UUID NOT NULL DEFAULT gen_random_uuid(),
+    date_from    DATE NOT NULL,
+    date_to      DATE NOT NULL,
+    next_date    DATE NOT NULL,                                 -- ngày tiếp theo cần chạy
+    status       TEXT NOT NULL DEFAULT 'pending',               -- pending/done/failed
+    error        TEXT,
+    requested_by UUID NOT NULL,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at  TIMESTAMPTZ,
+
+    PRIMARY KEY (tenant_id, id),
+    CONSTRAINT ck_loan_report_backfill_range  CHECK (date_from <= date_to AND next_date >= date_from),
+    CONSTRAINT ck_loan_report_backfill_status CHECK (status IN ('pending','done','failed'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_loan_report_backfill_pending
+  ON loan_report_backfill (tenant_id, created_at) WHERE status = 'pending';
@@ -0,0 +1,33 @@
+-- Khoản trả nợ gộp: phân bổ theo thứ tự cấu hình (phạt → phí → lãi → gốc, hoặc gốc trước)
+ALTER TABLE loan_transaction DROP CONSTRAINT IF EXISTS loan_transaction_transaction_type_check;
+ALTER TABLE loan_transaction ADD CONSTRAINT loan_transaction_transaction_type_check CHECK (
+    transaction_type IN ('disbursement','additional','interest','principal','penalty','storage_fee','repayment','liquidation','settlement')
+);
+
+-- Phần tiền của giao dịch đã phân bổ vào từng nghĩa vụ (job tính lại loan_report ghi)
+ALTER TABLE loan_transaction
+  ADD COLUMN IF NOT EXISTS principal_applied BIGINT NOT NULL DEFAULT 0,
+  ADD COLUMN IF NOT EXISTS interest_applied  BIGINT NOT NULL DEFAULT 0,
+  ADD COLUMN IF NOT EXISTS penalty_applied   BIGINT NOT NULL DEFAULT 0,
+  ADD COLUMN IF NOT EXISTS fee_applied       BIGINT NOT NULL DEFAULT 0;
+
+-- Thứ tự phân bổ theo sản phẩm vay của tenant
+CREATE TABLE IF NOT EXISTS loan_repayment_waterfall (
+    tenant_id  UUID NOT NULL,
+    code       TEXT NOT NULL,
+    name       TEXT NOT NULL,
+    steps      JSONB NOT NULL,                                      -- vd: ["penalty","fees","interest","principal"]
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, code),
+    CONSTRAINT ck_loan_repayment_waterfall_code  CHECK (length(trim(code)) > 0),
+    CONSTRAINT ck_loan_repayment_waterfall_steps CHECK (jsonb_typeof(steps) = 'array')
+);
+
+-- Hợp đồng giữ bản sao thứ tự phân bổ lúc tạo
+ALTER TABLE loan_contract
+  ADD COLUMN IF NOT EXISTS repayment_waterfall JSONB NOT NULL DEFAULT '["penalty","fees","interest","principal"]';
+
+INSERT INTO permissions (resource, action, label) VALUES
+ ('loan','manage_products','Cấu hình sản phẩm vay')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,53 @@
+-- Sản phẩm vay của tenant: khung lãi suất / kỳ hạn / số tiền, quy ước tính lãi, tài sản bắt buộc, biểu phí
+CREATE TABLE IF NOT EXISTS loan_product (
+    tenant_id                 UUID NOT NULL,
+    id                        UUID NOT NULL DEFAULT gen_random_uuid(),
+    code                      TEXT NOT NULL,
+    name                      TEXT NOT NULL,
+    description               TEXT,
+
+    -- Khung lãi suất (%/năm), kỳ hạn (tháng), số tiền vay; NULL = không giới hạn
+    min_rate                  DOUBLE PRECISION,
+    max_rate                  DOUBLE PRECISION,
+    default_rate              DOUBLE PRECISION,
+    min_term_months           INT,
+    max_term_months           INT,
+    default_term_months       INT,
+    min_principal             BIGINT,
+    max_principal             BIGINT,
+
+    day_count                 TEXT NOT NULL DEFAULT 'act_365',
+    compounding               TEXT NOT NULL DEFAULT 'simple',
+    required_collateral_types TEXT[] NOT NULL DEFAULT '{}',     -- mỗi loại cần ít nhất 1 tài sản khi tạo hợp đồng
+
+    -- Biểu phí mặc định
+    storage_fee_rate          DOUBLE PRECISION NOT NULL DEFAULT 0,
+    storage_fee_flat          BIGINT NOT NULL DEFAULT 0,
+    penalty_rate              DOUBLE PRECISION NOT NULL DEFAULT 0,
+    prepayment_penalty        JSONB NOT NULL DEFAULT '{"type":"none"}',
+    repayment_waterfall       TEXT,                              -- mã loan_repayment_waterfall
+
+    number_prefix             TEXT,                              -- tiền tố số hợp đồng (mặc định LOAN)
+    active                    BOOLEAN NOT NULL DEFAULT true,
+    created_by                UUID NOT NULL,
+    created_at                TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at                TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, id),
+    CONSTRAINT uq_loan_product_code UNIQUE (tenant_id, code),
+    CONSTRAINT ck_loan_product_code CHECK (length(trim(code)) > 0),
+    CONSTRAINT ck_loan_product_rate CHECK (min_rate IS NULL OR max_rate IS NULL OR min_rate <= max_rate),
+    CONSTRAINT ck_loan_product_term CHECK (min_term_months IS NULL OR max_term_months IS NULL OR min_term_months <= max_term_months),
+    CONSTRAINT ck_loan_product_principal CHECK (min_principal IS NULL OR max_principal IS NULL OR min_principal <= max_principal),
+    CONSTRAINT ck_loan_product_day_count CHECK (day_count IN ('act_365','act_360','act_act','30_360')),
+    CONSTRAINT ck_loan_product_compounding CHECK (compounding IN ('simple','daily','monthly')),
+    CONSTRAINT ck_loan_product_fees CHECK (storage_fee_rate >= 0 AND storage_fee_flat >= 0 AND penalty_rate >= 0)
+);
+
+-- Hợp đồng tạo theo sản phẩm (không xoá sản phẩm đã dùng, chỉ ngừng kích hoạt)
+ALTER TABLE loan_contract
+  ADD COLUMN IF NOT EXISTS product_id UUID;
+
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS fk_loan_contract_product;
+ALTER TABLE loan_contract ADD CONSTRAINT fk_loan_contract_product
+  FOREIGN KEY (tenant_id, product_id) REFERENCES loan_product (tenant_id, id);
@@ -0,0 +1,38 @@
+-- Dãy số chứng từ dùng chung (số hợp đồng vay, số hoá đơn, ...)
+-- Cấu hình theo tenant; mã chưa cấu hình dùng mặc định trong code
+CREATE TABLE IF NOT EXISTS number_sequence (
+    tenant_id    UUID NOT NULL,
+    code         TEXT NOT NULL,                                 -- loan_contract / invoice / ...
+    prefix       TEXT NOT NULL DEFAULT '',
+    pattern      TEXT NOT NULL,                                 -- vd '{prefix}-{tenant}-{yyyy}{mm}-{seq}'
+    padding      INT  NOT NULL DEFAULT 6,                       -- số chữ số của {seq}
+    reset_period TEXT NOT NULL DEFAULT 'monthly',               -- yearly/monthly/never
+    timezone     TEXT,                                          -- NULL = múi giờ của tenant
+    updated_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, code),
+    CONSTRAINT ck_number_sequence_padding CHECK (padding BETWEEN 1 AND 12),
+    CONSTRAINT ck_number_sequence_reset   CHECK (reset_period IN ('yearly','monthly','never'))
+);
+
+-- Counter theo dãy + kỳ (YYYY / YYYYMM / 0), tăng trong transaction của chứng từ → rollback không lủng số
+CREATE TABLE IF NOT EXISTS number_sequence_counter (
+    tenant_id  UUID   NOT NULL,
+    code       TEXT   NOT NULL,
+    period_key INT    NOT NULL,
+    counter    BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tenant_id, code, period_key)
+);
+
+-- Chuyển counter số hợp đồng cũ (YYYYMM) sang dãy loan_contract để không cấp trùng số
+INSERT INTO number_sequence_counter (tenant_id, code, period_key, counter, updated_at)
+SELECT tenant_id, 'loan_contract', period_ym, counter, updated_at
+FROM loan_counters_monthly
+ON CONFLICT DO NOTHING;
+
+INSERT INTO permissions (resource, action, label) VALUES
+ ('sequence','read','Xem dãy số chứng từ'),
+ ('sequence','manage','Cấu hình dãy số chứng từ')
+ON CONFLICT DO NOTHING;
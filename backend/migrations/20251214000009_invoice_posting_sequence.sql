@@ -0,0 +1,40 @@
+-- Số hoá đơn liên tục theo sổ nhật ký + ký hiệu, cấp lúc ghi sổ và khoá sau khi cấp
+
+-- Ký hiệu hoá đơn (vd 1C26TAA) của sổ, NULL = không dùng ký hiệu
+ALTER TABLE account_journal ADD COLUMN IF NOT EXISTS invoice_series VARCHAR(20);
+-- Ký hiệu đã dùng khi cấp số cho hoá đơn
+ALTER TABLE account_move ADD COLUMN IF NOT EXISTS invoice_series VARCHAR(20);
+-- sequence_prefix = nhóm đánh số '{dãy}/{kỳ}', vd 'invoice.SALE.1C26TAA/2026'
+ALTER TABLE account_move ALTER COLUMN sequence_prefix TYPE VARCHAR(100);
+
+-- Hoá đơn nháp chưa có số
+UPDATE account_move SET name = '/' WHERE state = 'draft' AND sequence_number IS NULL;
+
+-- Không cấp trùng số trong 1 nhóm
+CREATE UNIQUE INDEX IF NOT EXISTS uq_move_sequence
+  ON account_move (tenant_id, journal_id, sequence_prefix, sequence_number)
+  WHERE sequence_number IS NOT NULL;
+
+-- Đã cấp số → không sửa số / sổ / ký hiệu (xoá chỉ cho phép với hoá đơn nháp ở tầng ứng dụng;
+-- chuyển shard vẫn cần xoá dữ liệu nguồn nên trigger không chặn DELETE)
+CREATE OR REPLACE FUNCTION trg_account_move_lock_sequence()
+RETURNS trigger AS $$
+BEGIN
+  IF OLD.sequence_number IS NOT NULL AND (
+     NEW.name IS DISTINCT FROM OLD.name
+     OR NEW.sequence_number IS DISTINCT FROM OLD.sequence_number
+     OR NEW.sequence_prefix IS DISTINCT FROM OLD.sequence_prefix
+     OR NEW.journal_id IS DISTINCT FROM OLD.journal_id
+     OR NEW.invoice_series IS DISTINCT FROM OLD.invoice_series) THEN
+    RAISE EXCEPTION 'account_move % already numbered %, number is locked', OLD.id, OLD.name
+      USING ERRCODE = 'check_violation';
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS account_move_lock_sequence ON account_move;
+CREATE TRIGGER account_move_lock_sequence
+BEFORE UPDATE ON account_move
+FOR EACH ROW
+EXECUTE FUNCTION trg_account_move_lock_sequence();
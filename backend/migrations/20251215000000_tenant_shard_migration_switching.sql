@@ -0,0 +1,21 @@
+-- ============================================================
+-- TENANT SHARD MIGRATION – bước "switching"
+--  - Dữ liệu đã commit ở shard đích, đang đổi routing / dọn shard cũ
+--  - Vẫn chặn ghi (fence) cho tới khi xong; lỗi ở bước này giữ nguyên
+--    status để chạy lại (POST .../shard-migrations/:id/resume)
+--  - status: pending → fenced → copying → verifying → switching → completed | failed
+-- ============================================================
+
+ALTER TABLE tenant_shard_migration DROP CONSTRAINT IF EXISTS tenant_shard_migration_status_check;
+ALTER TABLE tenant_shard_migration ADD CONSTRAINT tenant_shard_migration_status_check
+  CHECK (status IN ('pending', 'fenced', 'copying', 'verifying', 'switching', 'completed', 'failed'));
+
+DROP INDEX IF EXISTS uq_tenant_shard_migration_active;
+CREATE UNIQUE INDEX IF NOT EXISTS uq_tenant_shard_migration_active
+  ON tenant_shard_migration (tenant_id)
+  WHERE status IN ('pending', 'fenced', 'copying', 'verifying', 'switching');
+
+-- Middleware đọc trạng thái fence mỗi request
+CREATE INDEX IF NOT EXISTS idx_tenant_shard_migration_fenced
+  ON tenant_shard_migration (tenant_id)
+  WHERE status IN ('fenced', 'copying', 'verifying', 'switching');
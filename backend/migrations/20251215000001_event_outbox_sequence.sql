@@ -0,0 +1,17 @@
+-- ============================================================
+-- EVENT OUTBOX – thứ tự theo aggregate
+--  - created_at = now() giống nhau cho mọi event trong 1 transaction,
+--    id là UUID ngẫu nhiên → cần seq tăng dần để giữ thứ tự ghi
+--  - Relay chỉ gửi event khi aggregate không còn event nào trước nó chưa publish
+-- ============================================================
+
+ALTER TABLE event_outbox ADD COLUMN IF NOT EXISTS seq BIGSERIAL;
+
+DROP INDEX IF EXISTS idx_event_outbox_pending;
+CREATE INDEX IF NOT EXISTS idx_event_outbox_pending
+  ON event_outbox (next_attempt_at, seq)
+  WHERE published_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_event_outbox_aggregate_pending
+  ON event_outbox (tenant_id, aggregate_id, seq)
+  WHERE published_at IS NULL;
@@ -0,0 +1,58 @@
+-- ============================================================
+-- Phí lưu kho tính theo từng ngày
+--  - loan_collateral.pledged_at: ngày tài sản bắt đầu bảo đảm cho hợp đồng
+--    (cầm cố lúc tạo hợp đồng = date_start, thêm sau = thời điểm thêm)
+--  - Giá trị mỗi ngày = lần định giá gần nhất tới ngày đó, chưa định giá → pledge_value
+-- loan_contract_derived: các cột tính sẵn dùng chung cho mọi truy vấn nạp LoanContract
+--   (JOIN loan_contract_derived d USING (tenant_id, id))
+-- ============================================================
+
+ALTER TABLE loan_collateral
+  ADD COLUMN IF NOT EXISTS pledged_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+-- Dòng ghi cùng transaction tạo hợp đồng (created_at = now() của transaction đó) → từ ngày bắt đầu
+UPDATE loan_collateral lc
+SET pledged_at = CASE WHEN lc.created_at <= c.created_at THEN c.date_start ELSE lc.created_at END
+FROM loan_contract c
+WHERE c.tenant_id = lc.tenant_id AND c.id = lc.contract_id;
+
+CREATE OR REPLACE VIEW loan_contract_derived AS
+SELECT
+    c.tenant_id,
+    c.id,
+    -- Tổng giá trị ước tính tài sản đang cầm cố (LTV)
+    COALESCE((
+        SELECT SUM(a.value_estimate) FROM loan_collateral lc
+        JOIN collateral_assets a ON a.tenant_id = lc.tenant_id AND a.asset_id = lc.asset_id
+        WHERE lc.tenant_id = c.tenant_id AND lc.contract_id = c.id AND lc.status = 'active'
+    ), 0)::int8 AS collateral_value,
+    -- Lịch sử cầm cố + định giá từng tài sản (phí lưu kho theo ngày)
+    COALESCE((
+        SELECT jsonb_agg(jsonb_build_object(
+            'pledged_at', lc.pledged_at,
+            'released_at', lc.released_at,
+            'pledge_value', lc.pledge_value,
+            'valuations', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object('date', v.valuation_date, 'value', v.value) ORDER BY v.valuation_date)
+                FROM collateral_valuation v
+                WHERE v.tenant_id = lc.tenant_id AND v.asset_id = lc.asset_id
+            ), '[]'::jsonb)
+        ) ORDER BY lc.pledged_at)
+        FROM loan_collateral lc
+        WHERE lc.tenant_id = c.tenant_id AND lc.contract_id = c.id
+    ), '[]'::jsonb) AS collateral_pledges,
+    COALESCE((SELECT t.timezone FROM tenant t WHERE t.tenant_id = c.tenant_id), 'Asia/Bangkok') AS timezone,
+    COALESCE((
+        SELECT jsonb_agg(to_jsonb(s) ORDER BY s.seq) FROM loan_schedule s
+        WHERE s.tenant_id = c.tenant_id AND s.contract_id = c.id
+    ), '[]'::jsonb) AS schedule,
+    COALESCE((
+        SELECT jsonb_agg(to_jsonb(tc) ORDER BY tc.effective_date) FROM loan_term_change tc
+        WHERE tc.tenant_id = c.tenant_id AND tc.contract_id = c.id
+    ), '[]'::jsonb) AS term_changes,
+    COALESCE((
+        SELECT jsonb_agg(jsonb_build_object('effective_date', v.effective_date, 'rate', v.rate) ORDER BY v.effective_date)
+        FROM loan_rate_index_value v
+        WHERE v.tenant_id = c.tenant_id AND v.index_code = c.rate_index
+    ), '[]'::jsonb) AS rate_index_values
+FROM loan_contract c;
@@ -0,0 +1,20 @@
+-- Phí trả nợ trước hạn thu thành giao dịch riêng khi tất toán (không gộp vào `settlement`)
+ALTER TABLE loan_transaction DROP CONSTRAINT IF EXISTS loan_transaction_transaction_type_check;
+ALTER TABLE loan_transaction ADD CONSTRAINT loan_transaction_transaction_type_check CHECK (
+    transaction_type IN ('disbursement','additional','interest','principal','penalty','storage_fee','repayment','liquidation','settlement','prepayment_fee')
+);
+
+-- Tỉ lệ / số tiền phí trả trước không được âm
+ALTER TABLE loan_contract DROP CONSTRAINT IF EXISTS ck_loan_contract_prepayment_penalty;
+ALTER TABLE loan_contract ADD CONSTRAINT ck_loan_contract_prepayment_penalty CHECK (
+    prepayment_penalty->>'type' IN ('none','percent','fixed')
+    AND COALESCE((prepayment_penalty->>'rate')::float8, 0) >= 0
+    AND COALESCE((prepayment_penalty->>'amount')::int8, 0) >= 0
+);
+
+ALTER TABLE loan_product DROP CONSTRAINT IF EXISTS ck_loan_product_prepayment_penalty;
+ALTER TABLE loan_product ADD CONSTRAINT ck_loan_product_prepayment_penalty CHECK (
+    prepayment_penalty->>'type' IN ('none','percent','fixed')
+    AND COALESCE((prepayment_penalty->>'rate')::float8, 0) >= 0
+    AND COALESCE((prepayment_penalty->>'amount')::int8, 0) >= 0
+);
@@ -0,0 +1,15 @@
+-- Tài sản cầm cố đã bán khi thanh lý: loan_collateral.status = 'liquidated' (released_at = ngày thanh lý)
+ALTER TABLE loan_collateral DROP CONSTRAINT IF EXISTS ck_loan_collateral_status;
+ALTER TABLE loan_collateral ADD CONSTRAINT ck_loan_collateral_status
+  CHECK (status IN ('active', 'released', 'liquidated'));
+
+-- Thanh lý đã hoàn tất trước đây: tài sản đã thu giữ → liquidated, còn lại → released
+UPDATE loan_collateral lc
+SET status = CASE WHEN EXISTS (
+        SELECT 1 FROM loan_liquidation_asset la
+        WHERE la.tenant_id = l.tenant_id AND la.liquidation_id = l.id AND la.asset_id = lc.asset_id
+    ) THEN 'liquidated' ELSE 'released' END,
+    released_at = l.liquidation_date
+FROM loan_liquidation l
+WHERE l.tenant_id = lc.tenant_id AND l.contract_id = lc.contract_id
+  AND l.status = 'completed' AND lc.status = 'active';
@@ -0,0 +1,6 @@
+-- Ký hiệu hoá đơn có dãy số riêng dùng chung cho mọi sổ cùng ký hiệu ('invoice.series.{ký hiệu}'),
+-- sổ không dùng ký hiệu đánh số theo sổ ('invoice.{sổ}') → nhóm đánh số không còn gắn với journal_id
+DROP INDEX IF EXISTS uq_move_sequence;
+CREATE UNIQUE INDEX uq_move_sequence
+  ON account_move (tenant_id, sequence_prefix, sequence_number)
+  WHERE sequence_number IS NOT NULL;
@@ -6,7 +6,7 @@ use serde_json::{Value, json};
 use std::sync::Arc;
 use std::collections::HashMap;
 
-use crate::core::{auth::{AuthUser, jwt_auth}, state::AppState, error::AppError};
+use crate::core::{auth::{AuthUser, jwt_auth}, iam::RequirePermission, state::AppState, error::AppError};
 use sqlx::{Row, Pool, Postgres, Column};
 use uuid::Uuid;
 use bigdecimal::BigDecimal;
@@ -38,25 +38,29 @@ pub fn routes(_state: Arc<AppState>) -> Router<Arc<AppState>> {
             "/:module_name/list",
             get(|State(state): State<Arc<AppState>>, auth: AuthUser, Path(module_name): Path<String>, query: Query<HashMap<String, String>>| async move {
                 list_handler(State(state), auth, query, module_name).await
-            }),
+            })
+            .route_layer(RequirePermission::from_path("module_name", "read")),
         )
         .route(
             "/:module_name/create",
             post(|State(state): State<Arc<AppState>>, auth: AuthUser, Path(module_name): Path<String>, body: Json<Value>| async move {
                 create_handler(State(state), auth, body, module_name).await
-            }),
+            })
+            .route_layer(RequirePermission::from_path("module_name", "create")),
         )
         .route(
             "/:module_name/:id",
             get(|State(state): State<Arc<AppState>>, auth: AuthUser, Path((module_name, id)): Path<(String, String)>| async move {
                 get_by_id_handler(State(state), auth, Path(id), module_name).await
-            }),
+            })
+            .route_layer(RequirePermission::from_path("module_name", "read")),
         )
         .route(
             "/:module_name/:id/update",
             post(|State(state): State<Arc<AppState>>, auth: AuthUser, Path((module_name, id)): Path<(String, String)>, body: Json<Value>| async move {
                 update_handler(State(state), auth, Path(id), body, module_name).await
-            }),
+            })
+            .route_layer(RequirePermission::from_path("module_name", "update")),
         )
         .layer(middleware::from_fn(jwt_auth));
     
@@ -156,7 +160,7 @@ async fn list_handler(
         select_cols, root_table
     );
 
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     let rows = sqlx::query(&sql)
         .bind(auth.tenant_id)
         .fetch_all(pool)
@@ -589,7 +593,7 @@ async fn create_handler(
 
     tracing::debug!("SQL: {} params (cols={}, placeholders={})", expected_params, col_count, placeholder_count);
 
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     // Bind with correct types: tenant_id (Uuid), id (Uuid), dynamic fields (with proper types), created_by (Uuid)
     let mut q = sqlx::query(&sql)
         .bind(auth.tenant_id)
@@ -721,7 +725,7 @@ async fn update_handler(
         })
         .collect();
 
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     let record_id = uuid::Uuid::parse_str(&id)
         .map_err(|_| AppError::bad_request("Invalid UUID format"))?;
 
@@ -904,7 +908,7 @@ async fn get_by_id_handler(
         .map(|s| s.to_string())
         .unwrap_or_else(|| format!("{}_{}s", module_name, module_name));
 
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     let record_id = uuid::Uuid::parse_str(&id)
         .map_err(|_| AppError::bad_request("Invalid UUID format"))?;
 
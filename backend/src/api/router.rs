@@ -1,11 +1,12 @@
 use std::sync::Arc;
-use axum::{Router, routing::{get, post}, middleware};
+use axum::{Extension, Router, routing::{get, post}, middleware};
 use axum::http::{Method, header::{self, HeaderName}};
 use tower_http::cors::{Any, CorsLayer};
 
 use crate::module::{user, tenant, iam};
 use crate::core::{auth::jwt_auth, state::AppState, i18n_middleware::i18n_middleware};
 use crate::api::i18n;
+use crate::tenant_router::tenant_write_fence;
 
 /// Build tất cả router từ các module.
 /// Sử dụng `Arc<AppState>` thay vì `PgPool` để hỗ trợ sharding.
@@ -29,6 +30,8 @@ pub fn build_router(state: Arc<AppState>) -> Router<Arc<AppState>> {
         // 🔐 Auth route (public)
         .route("/user/register", post(user::handler::register))
         .route("/user/login", post(user::handler::login))
+        .route("/user/refresh", post(user::handler::refresh))
+        .route("/user/logout", post(user::handler::logout))
 
         // 🔒 Route cần auth bằng JWT
         .nest(
@@ -60,12 +63,21 @@ pub fn build_router(state: Arc<AppState>) -> Router<Arc<AppState>> {
         // 🛡️ Route module app
         .merge(crate::module::app::router::routes())
 
+        // 🔢 Route dãy số chứng từ
+        .merge(crate::module::sequence::router::routes())
+
         // 🎓 Routes động từ modules ngoài binary (load từ manifest.json)
         .merge(crate::api::external_modules::routes(state.clone()))
 
+        // 🚧 Chặn ghi cho tenant đang chuyển shard
+        .layer(middleware::from_fn_with_state(state.clone(), tenant_write_fence))
+
         // 🌐 i18n middleware to detect language from headers
         .layer(middleware::from_fn(i18n_middleware))
 
+        // 🔐 RequirePermission đọc AppState (cache quyền) từ extensions
+        .layer(Extension(state.clone()))
+
         // 🌐 Gắn state + middleware CORS
         .with_state(state)
         .layer(cors)
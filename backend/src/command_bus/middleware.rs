@@ -0,0 +1,101 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::command_bus::CommandMeta;
+use crate::core::{error::AppError, iam::PermissionCache};
+
+/// Chính sách dùng chung cho mọi command
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Chạy trước khi mở transaction; trả lỗi để chặn lệnh
+    async fn before(&self, _meta: &CommandMeta<'_>) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    /// Luôn chạy sau khi lệnh kết thúc (kể cả bị middleware khác chặn)
+    async fn after(&self, _meta: &CommandMeta<'_>, _outcome: Result<(), &AppError>) {}
+}
+
+/// Phân quyền theo `Command::PERMISSION` (dùng chung cache với RequirePermission)
+pub struct Authorization {
+    permissions: Arc<PermissionCache>,
+}
+
+impl Authorization {
+    pub fn new(permissions: Arc<PermissionCache>) -> Self {
+        Self { permissions }
+    }
+}
+
+#[async_trait]
+impl Middleware for Authorization {
+    async fn before(&self, meta: &CommandMeta<'_>) -> Result<(), AppError> {
+        let Some((resource, action)) = meta.permission else {
+            return Ok(());
+        };
+
+        if self.permissions.check(meta.shard, meta.auth, resource, action).await? {
+            Ok(())
+        } else {
+            tracing::warn!(
+                "🚫 User {} không có quyền {}.{} cho lệnh {}",
+                meta.auth.user_id, resource, action, meta.name
+            );
+            Err(AppError::forbidden_i18n(meta.i18n, "error.auth.forbidden"))
+        }
+    }
+}
+
+/// Ghi audit log vào shard của tenant
+pub struct AuditLog;
+
+#[async_trait]
+impl Middleware for AuditLog {
+    async fn after(&self, meta: &CommandMeta<'_>, outcome: Result<(), &AppError>) {
+        let (status, error) = match outcome {
+            Ok(()) => ("ok", None),
+            Err(e @ (AppError::Validation(_) | AppError::Forbidden(_) | AppError::Unauthorized(_) | AppError::NotFound(_))) => {
+                ("rejected", Some(error_text(e)))
+            }
+            Err(e) => ("failed", Some(error_text(e))),
+        };
+        let duration_ms = meta.started_at.elapsed().as_millis() as i64;
+
+        tracing::info!(
+            "📝 [COMMAND] {} user={} tenant={} outcome={} {}ms",
+            meta.name, meta.auth.user_id, meta.auth.tenant_id, status, duration_ms
+        );
+
+        let res = sqlx::query!(
+            r#"
+            INSERT INTO command_audit_log (tenant_id, id, user_id, command, outcome, error, duration_ms)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            meta.auth.tenant_id,
+            Uuid::new_v4(),
+            meta.auth.user_id,
+            meta.name,
+            status,
+            error,
+            duration_ms
+        )
+        .execute(meta.pool)
+        .await;
+
+        if let Err(e) = res {
+            tracing::warn!("⚠️ Không ghi được audit log cho {}: {}", meta.name, e);
+        }
+    }
+}
+
+fn error_text(e: &AppError) -> String {
+    match e {
+        AppError::Validation(err) => err.to_string(),
+        AppError::Db(err) => err.to_string(),
+        AppError::InternalServerError(msg)
+        | AppError::NotFound(msg)
+        | AppError::Forbidden(msg)
+        | AppError::Unauthorized(msg) => msg.clone(),
+    }
+}
@@ -1 +1,189 @@
 // command_bus: định tuyến lệnh ghi (CQRS)
+//
+// Pipeline cho mỗi command:
+//   middleware.before (phân quyền, ...) → validate → BEGIN → handle
+//   → ghi event đã emit vào outbox → COMMIT → middleware.after (audit, ...)
+// Handler lỗi thì transaction rollback, event không được ghi.
+pub mod middleware;
+
+use async_trait::async_trait;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::core::{auth::AuthUser, error::AppError, i18n::I18n, iam::PermissionCache, state::AppState};
+use crate::core::scope::{Scope, ScopeTarget};
+use crate::infra::db::ShardManager;
+use crate::infra::outbox::{self, DomainEvent, OutboxEvent};
+
+pub use middleware::Middleware;
+
+/// Lệnh ghi (mỗi struct = 1 use case, tự mang handler)
+#[async_trait]
+pub trait Command: Send + Sized + 'static {
+    type Output: Send;
+
+    /// Tên lệnh dùng cho audit/log, vd: "loan.create_contract"
+    const NAME: &'static str;
+
+    /// Quyền (resource, action) cần có để chạy lệnh
+    const PERMISSION: Option<(&'static str, &'static str)> = None;
+
+    /// Kiểm tra dữ liệu đầu vào trước khi mở transaction
+    fn validate(&self, _i18n: &I18n) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    /// Thực thi lệnh trong transaction của bus
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Self::Output, AppError>;
+}
+
+/// Ngữ cảnh của 1 lệnh đang chạy
+pub struct CommandContext {
+    pub auth: AuthUser,
+    pub i18n: I18n,
+    tx: Transaction<'static, Postgres>,
+    events: Vec<OutboxEvent>,
+}
+
+impl CommandContext {
+    pub fn tenant_id(&self) -> Uuid {
+        self.auth.tenant_id
+    }
+
+    pub fn user_id(&self) -> Uuid {
+        self.auth.user_id
+    }
+
+    /// Connection trong transaction của lệnh
+    pub fn conn(&mut self) -> &mut PgConnection {
+        self.tx.as_mut()
+    }
+
+    /// Bản ghi `id` phải nằm trong scope ABAC của user, ngoài scope → NotFound (`not_found_key`)
+    pub async fn ensure_in_scope(
+        &mut self,
+        scope: &Scope,
+        target: &ScopeTarget,
+        id: Uuid,
+        not_found_key: &str,
+    ) -> Result<(), AppError> {
+        if scope.is_visible(self.tx.as_mut(), target, &self.auth, id).await? {
+            Ok(())
+        } else {
+            Err(AppError::not_found_i18n(&self.i18n, not_found_key))
+        }
+    }
+
+    /// Ghi nhận domain event, chỉ ghi vào outbox khi lệnh thành công
+    pub fn emit<E: DomainEvent>(&mut self, event: E) -> Result<(), AppError> {
+        let event = OutboxEvent::from_domain(&event)
+            .map_err(|e| AppError::internal(format!("Không serialize được event: {}", e)))?;
+        self.events.push(event);
+        Ok(())
+    }
+}
+
+/// Thông tin lệnh truyền cho middleware (không phụ thuộc kiểu command)
+pub struct CommandMeta<'a> {
+    pub name: &'static str,
+    pub permission: Option<(&'static str, &'static str)>,
+    pub auth: &'a AuthUser,
+    pub i18n: &'a I18n,
+    pub shard: &'a ShardManager,
+    pub pool: &'a PgPool,
+    pub started_at: Instant,
+}
+
+/// Bus điều phối command qua chuỗi middleware
+#[derive(Default)]
+pub struct CommandBus {
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl CommandBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bus mặc định: phân quyền + audit log
+    pub fn standard(permissions: Arc<PermissionCache>) -> Self {
+        Self::new()
+            .with(middleware::Authorization::new(permissions))
+            .with(middleware::AuditLog)
+    }
+
+    /// Thêm middleware (chạy `before` theo thứ tự thêm, `after` theo thứ tự ngược lại)
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    pub async fn dispatch<C: Command>(
+        &self,
+        state: &Arc<AppState>,
+        auth: &AuthUser,
+        i18n: &I18n,
+        cmd: C,
+    ) -> Result<C::Output, AppError> {
+        let meta = CommandMeta {
+            name: C::NAME,
+            permission: C::PERMISSION,
+            auth,
+            i18n,
+            shard: &state.shard,
+            pool: state.shard.get_pool_for_tenant(&auth.tenant_id).await?,
+            started_at: Instant::now(),
+        };
+
+        let result = self.run(&meta, cmd).await;
+
+        let outcome = result.as_ref().map(|_| ());
+        for m in self.middlewares.iter().rev() {
+            m.after(&meta, outcome).await;
+        }
+        result
+    }
+
+    async fn run<C: Command>(
+        &self,
+        meta: &CommandMeta<'_>,
+        cmd: C,
+    ) -> Result<C::Output, AppError> {
+        for m in &self.middlewares {
+            m.before(meta).await?;
+        }
+
+        cmd.validate(meta.i18n)?;
+
+        let tx = meta.pool.begin().await?;
+        let mut ctx = CommandContext {
+            auth: meta.auth.clone(),
+            i18n: meta.i18n.clone(),
+            tx,
+            events: Vec::new(),
+        };
+
+        // Lỗi → ctx bị drop → transaction rollback
+        let output = cmd.handle(&mut ctx).await?;
+
+        let CommandContext { mut tx, events, .. } = ctx;
+        for event in &events {
+            outbox::enqueue_event(tx.as_mut(), meta.auth.tenant_id, event).await?;
+        }
+        tx.commit().await?;
+
+        Ok(output)
+    }
+}
+
+/// Gửi command qua bus của ứng dụng
+pub async fn dispatch<C: Command>(
+    state: &Arc<AppState>,
+    auth: &AuthUser,
+    i18n: &I18n,
+    cmd: C,
+) -> Result<C::Output, AppError> {
+    state.command_bus.dispatch(state, auth, i18n, cmd).await
+}
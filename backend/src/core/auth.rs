@@ -1,26 +1,108 @@
 use axum::{
     async_trait,
     extract::FromRequestParts,
-    http::{request::Parts, StatusCode, Request as AxumRequest},
+    http::{request::Parts, HeaderMap, StatusCode, Request as AxumRequest},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
-use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use uuid::Uuid;
 use tracing::{debug, error}; // 👈 log nhẹ nhàng hơn
 
+use crate::core::{error::AppError, i18n::I18n, iam::{self, AccessClaims}, jwt, state::AppState};
+
+/// Claims của access token (ký bằng `jwt::keys()`)
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub tenant_id: String,
     pub exp: usize,
+    #[serde(default)]
+    pub iat: usize,
+    /// Tên role của user (tuỳ chọn, chỉ để client hiển thị – server luôn kiểm tra quyền từ DB)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<String>>,
+    /// `users.perm_version` lúc cấp token – cũ hơn DB thì token bị từ chối
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub perm_ver: Option<i64>,
+    /// Dấu vân tay tập quyền
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub perm_digest: Option<String>,
+    /// Module đang bật của tenant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modules: Option<Vec<String>>,
+}
+
+impl Claims {
+    /// Claims access token cho user, hết hạn sau `JWT_ACCESS_TTL_SECS`
+    pub fn for_user(user_id: Uuid, tenant_id: Uuid) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        Self {
+            sub: user_id.to_string(),
+            tenant_id: tenant_id.to_string(),
+            exp: (now + jwt::keys().access_ttl_secs) as usize,
+            iat: now as usize,
+            roles: None,
+            perm_ver: None,
+            perm_digest: None,
+            modules: None,
+        }
+    }
+
+    /// Gắn snapshot quyền (role, phiên bản, module) vào token
+    pub fn with_access(mut self, access: AccessClaims) -> Self {
+        self.roles = Some(access.roles);
+        self.perm_ver = Some(access.perm_version);
+        self.perm_digest = Some(access.perm_digest);
+        self.modules = Some(access.modules);
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct AuthUser {
     pub user_id: Uuid,
     pub tenant_id: Uuid,
+    /// `perm_ver` trong token (token cũ không có → không kiểm tra phiên bản)
+    pub perm_version: Option<i64>,
+}
+
+/// Giải mã Bearer token trong header → AuthUser (đường xác thực duy nhất cho extractor + middleware)
+pub fn auth_user_from_headers(headers: &HeaderMap) -> Result<AuthUser, StatusCode> {
+    let auth_header = headers.get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| {
+            error!("❌ Không tìm thấy header Authorization");
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    let token = auth_header.strip_prefix("Bearer ")
+        .ok_or_else(|| {
+            error!("❌ Authorization không phải Bearer token");
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    let claims = jwt::keys().verify::<Claims>(token).map_err(|err| {
+        error!("❌ Lỗi decode JWT: {:?}", err);
+        StatusCode::UNAUTHORIZED
+    })?;
+
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|err| {
+        error!("❌ Lỗi parse sub UUID: {:?}", err);
+        StatusCode::UNAUTHORIZED
+    })?;
+
+    let tenant_id = Uuid::parse_str(&claims.tenant_id).map_err(|err| {
+        error!("❌ Lỗi parse tenant_id UUID: {:?}", err);
+        StatusCode::UNAUTHORIZED
+    })?;
+
+    Ok(AuthUser {
+        user_id,
+        tenant_id,
+        perm_version: claims.perm_ver,
+    })
 }
 
 #[async_trait]
@@ -34,88 +116,34 @@ where
         parts: &mut Parts,
         _state: &S,
     ) -> Result<Self, Self::Rejection> {
-        let auth_header = parts.headers.get("Authorization")
-            .and_then(|h| h.to_str().ok())
-            .ok_or_else(|| {
-                error!("❌ Không tìm thấy header Authorization");
-                StatusCode::UNAUTHORIZED
-            })?;
-
-        let token = auth_header.strip_prefix("Bearer ")
-            .ok_or_else(|| {
-                error!("❌ Authorization không phải Bearer token");
-                StatusCode::UNAUTHORIZED
-            })?;
-
-        let claims = decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(b"super_secret_jwt_key"),
-            &Validation::default(),
-        )
-        .map_err(|err| {
-            error!("❌ Lỗi decode JWT: {:?}", err);
-            StatusCode::UNAUTHORIZED
-        })?
-        .claims;
-
-        let user_id = Uuid::parse_str(&claims.sub).map_err(|err| {
-            error!("❌ Lỗi parse sub UUID: {:?}", err);
-            StatusCode::UNAUTHORIZED
-        })?;
-
-        let tenant_id = Uuid::parse_str(&claims.tenant_id).map_err(|err| {
-            error!("❌ Lỗi parse tenant_id UUID: {:?}", err);
-            StatusCode::UNAUTHORIZED
-        })?;
-
-        Ok(AuthUser {
-            user_id,
-            tenant_id,
-        })
+        // jwt_auth đã xác thực → dùng lại, không decode token lần 2
+        if let Some(user) = parts.extensions.get::<AuthUser>() {
+            return Ok(user.clone());
+        }
+        auth_user_from_headers(&parts.headers)
     }
 }
 
 pub async fn jwt_auth(
     mut req: AxumRequest<axum::body::Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, Response> {
     debug!("🔐 Middleware nhận request: {:?}", req.uri()); // <-- log luôn, không phụ thuộc debug_assertions
 
-    let headers = req.headers();
-    let auth_header = headers.get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| {
-            error!("❌ Không tìm thấy header Authorization (middleware)");
-            StatusCode::UNAUTHORIZED
-        })?;
-
-    let token = auth_header.strip_prefix("Bearer ")
-        .ok_or_else(|| {
-            error!("❌ Authorization không phải Bearer token (middleware)");
-            StatusCode::UNAUTHORIZED
-        })?;
-
-    let claims = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(b"super_secret_jwt_key"),
-        &Validation::default(),
-    )
-    .map_err(|err| {
-        error!("❌ Middleware decode JWT lỗi: {:?}", err);
-        StatusCode::UNAUTHORIZED
-    })?
-    .claims;
-
-    let user = AuthUser {
-        user_id: Uuid::parse_str(&claims.sub).map_err(|err| {
-            error!("❌ Middleware parse user_id UUID lỗi: {:?}", err);
-            StatusCode::UNAUTHORIZED
-        })?,
-        tenant_id: Uuid::parse_str(&claims.tenant_id).map_err(|err| {
-            error!("❌ Middleware parse tenant_id UUID lỗi: {:?}", err);
-            StatusCode::UNAUTHORIZED
-        })?,
-    };
+    let user = auth_user_from_headers(req.headers()).map_err(IntoResponse::into_response)?;
+
+    // Quyền đã đổi sau khi cấp token → buộc client refresh để nhận claims mới
+    if let Some(state) = req.extensions().get::<Arc<AppState>>() {
+        let stale = state
+            .permissions
+            .is_stale(&state.shard, &user)
+            .await
+            .map_err(|e| iam::load_error(&I18n::from_headers(req.headers()), e))?;
+        if stale {
+            let i18n = I18n::from_headers(req.headers());
+            return Err(AppError::unauthorized_i18n(&i18n, "error.auth.stale_token").into_response());
+        }
+    }
 
     req.extensions_mut().insert(user);
 
@@ -6,6 +6,7 @@ use axum::{
 use serde::Serialize;
 use std::fmt;
 use crate::core::i18n::I18n;
+use crate::infra::db::RoutingError;
 
 #[derive(Debug, Serialize, Clone)]
 pub struct ErrorResponse {
@@ -31,6 +32,8 @@ pub enum AppError {
     Db(sqlx::Error),
     InternalServerError(String),
     NotFound(String), // ✅ Thêm variant NotFound
+    Forbidden(String),
+    Unauthorized(String),
 }
 
 impl From<sqlx::Error> for AppError {
@@ -39,6 +42,16 @@ impl From<sqlx::Error> for AppError {
     }
 }
 
+impl From<RoutingError> for AppError {
+    fn from(e: RoutingError) -> Self {
+        match e {
+            RoutingError::UnknownTenant(_) => AppError::NotFound(I18n::default().t("error.tenant.not_found")),
+            RoutingError::UnknownShard(_) => AppError::InternalServerError(e.to_string()),
+            RoutingError::Db(e) => AppError::Db(e),
+        }
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         // Use default i18n for error messages
@@ -78,6 +91,22 @@ impl IntoResponse for AppError {
                 };
                 (StatusCode::NOT_FOUND, Json(payload)).into_response()
             }
+
+            AppError::Forbidden(msg) => {
+                let payload = ErrorResponse {
+                    code: "forbidden",
+                    message: msg,
+                };
+                (StatusCode::FORBIDDEN, Json(payload)).into_response()
+            }
+
+            AppError::Unauthorized(msg) => {
+                let payload = ErrorResponse {
+                    code: "unauthorized",
+                    message: msg,
+                };
+                (StatusCode::UNAUTHORIZED, Json(payload)).into_response()
+            }
         }
     }
 }
@@ -111,6 +140,16 @@ impl AppError {
         AppError::NotFound(i18n.t(key))
     }
 
+    /// Create forbidden (403) error with i18n
+    pub fn forbidden_i18n(i18n: &I18n, key: &str) -> Self {
+        AppError::Forbidden(i18n.t(key))
+    }
+
+    /// Create unauthorized (401) error with i18n
+    pub fn unauthorized_i18n(i18n: &I18n, key: &str) -> Self {
+        AppError::Unauthorized(i18n.t(key))
+    }
+
     /// Create internal error with i18n
     pub fn internal_i18n(i18n: &I18n, key: &str) -> Self {
         AppError::InternalServerError(i18n.t(key))
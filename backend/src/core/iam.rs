@@ -1,14 +1,441 @@
 // src/core/iam.rs
-use crate::core::auth::AuthUser;
+use axum::{
+    body::Body,
+    extract::{FromRequestParts, RawPathParams},
+    http::{Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
 use uuid::Uuid;
 
+use crate::core::auth::AuthUser;
+use crate::core::error::AppError;
+use crate::core::i18n::I18n;
+use crate::core::scope::{self, Scope};
+use crate::core::state::AppState;
+use crate::infra::db::ShardManager;
+
+/// Lỗi nạp quyền → response. Tenant trong token không còn định tuyến được shard
+/// (`RoutingError::UnknownTenant` → `RowNotFound`) trả 404 thay vì lỗi DB.
+pub(crate) fn load_error(i18n: &I18n, e: sqlx::Error) -> Response {
+    match e {
+        sqlx::Error::RowNotFound => AppError::not_found_i18n(i18n, "error.tenant.not_found"),
+        e => AppError::from(e),
+    }
+    .into_response()
+}
+
 /// ✅ Kiểm tra user có phải admin hệ thống không (tenant_id == nil)
 pub fn is_sys_admin(user: &AuthUser) -> bool {
     user.tenant_id == Uuid::nil()
 }
 
-// 📌 Tạm thời chưa có kiểm tra permission cụ thể vì chưa caching
-// Sau này có thể mở rộng: kiểm tra user có quyền cụ thể hay không theo permission
-// pub fn has_permission(user: &AuthUser, resource: &str, action: &str) -> bool {
-//     ...
-// }
+/// Quyền hiệu lực của 1 user trong tenant
+#[derive(Debug, Default)]
+pub struct UserPermissions {
+    /// Có role `admin` của tenant → mọi quyền
+    pub is_admin: bool,
+    /// Tên các role (trực tiếp + qua nhóm)
+    pub roles: Vec<String>,
+    /// `users.perm_version` lúc nạp
+    pub version: i64,
+    /// (resource, action) được cấp qua role / nhóm role → phạm vi bản ghi (ABAC)
+    pub granted: HashMap<(String, String), Scope>,
+}
+
+impl UserPermissions {
+    /// Dấu vân tay của tập quyền (đưa vào JWT để client biết quyền đã đổi)
+    pub fn digest(&self) -> String {
+        if self.is_admin {
+            return "admin".into();
+        }
+        let mut entries: Vec<String> = self
+            .granted
+            .iter()
+            .map(|((resource, action), scope)| format!("{}.{}:{:?}", resource, action, scope))
+            .collect();
+        entries.sort();
+        hex::encode(&Sha256::digest(entries.join("\n").as_bytes())[..8])
+    }
+
+    /// Phạm vi bản ghi của quyền, `None` nếu không có quyền
+    pub fn scope(&self, resource: &str, action: &str) -> Option<Scope> {
+        if self.is_admin {
+            return Some(Scope::All);
+        }
+        self.granted.get(&(resource.to_string(), action.to_string())).cloned()
+    }
+}
+
+/// (tenant_id, user_id) → (quyền, thời điểm nạp)
+type CacheEntries = HashMap<(Uuid, Uuid), (Arc<UserPermissions>, Instant)>;
+
+/// Cache quyền theo (tenant_id, user_id), hết hạn sau `ttl`
+/// - roles / user_roles / nhóm role nằm ở shard của tenant
+/// - role_permissions / permissions nằm ở meta DB
+pub struct PermissionCache {
+    ttl: Duration,
+    entries: RwLock<CacheEntries>,
+}
+
+impl PermissionCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, entries: RwLock::new(HashMap::new()) }
+    }
+
+    /// TTL đọc từ env `PERMISSION_CACHE_TTL_SECS` (mặc định 60s)
+    pub fn from_env() -> Self {
+        let secs = std::env::var("PERMISSION_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(60);
+        Self::new(Duration::from_secs(secs))
+    }
+
+    /// ✅ Kiểm tra user có quyền (resource, action) không
+    pub async fn check(
+        &self,
+        shard: &ShardManager,
+        user: &AuthUser,
+        resource: &str,
+        action: &str,
+    ) -> Result<bool, sqlx::Error> {
+        Ok(self.resolve(shard, user, resource, action).await?.is_some())
+    }
+
+    /// ✅ Quyền (resource, action) kèm phạm vi bản ghi, `None` nếu không có quyền
+    pub async fn resolve(
+        &self,
+        shard: &ShardManager,
+        user: &AuthUser,
+        resource: &str,
+        action: &str,
+    ) -> Result<Option<Scope>, sqlx::Error> {
+        if is_sys_admin(user) {
+            return Ok(Some(Scope::All));
+        }
+        Ok(self.get(shard, user).await?.scope(resource, action))
+    }
+
+    /// Quyền hiệu lực của user (đọc cache, miss/hết hạn thì nạp lại từ DB)
+    pub async fn get(&self, shard: &ShardManager, user: &AuthUser) -> Result<Arc<UserPermissions>, sqlx::Error> {
+        let key = (user.tenant_id, user.user_id);
+        if let Some(hit) = self.cached(&key) {
+            return Ok(hit);
+        }
+
+        let perms = Arc::new(load_permissions(shard, user).await?);
+        if let Ok(mut map) = self.entries.write() {
+            map.insert(key, (perms.clone(), Instant::now()));
+        }
+        Ok(perms)
+    }
+
+    /// Xoá cache của 1 user (sau khi gán role)
+    pub fn invalidate_user(&self, tenant_id: Uuid, user_id: Uuid) {
+        if let Ok(mut map) = self.entries.write() {
+            map.remove(&(tenant_id, user_id));
+        }
+    }
+
+    /// Xoá toàn bộ cache (sau khi đổi quyền của role → ảnh hưởng nhiều user)
+    pub fn invalidate_all(&self) {
+        if let Ok(mut map) = self.entries.write() {
+            map.clear();
+        }
+    }
+
+    /// Token mang `perm_ver` cũ hơn phiên bản quyền hiện tại của user → phải refresh
+    pub async fn is_stale(&self, shard: &ShardManager, user: &AuthUser) -> Result<bool, sqlx::Error> {
+        let Some(token_version) = user.perm_version else {
+            return Ok(false);
+        };
+        let mut perms = self.get(shard, user).await?;
+        if token_version > perms.version {
+            // Token mới hơn cache (quyền vừa đổi ở instance khác) → nạp lại
+            self.invalidate_user(user.tenant_id, user.user_id);
+            perms = self.get(shard, user).await?;
+        }
+        Ok(token_version < perms.version)
+    }
+
+    fn cached(&self, key: &(Uuid, Uuid)) -> Option<Arc<UserPermissions>> {
+        let map = self.entries.read().ok()?;
+        let (perms, loaded_at) = map.get(key)?;
+        (loaded_at.elapsed() < self.ttl).then(|| perms.clone())
+    }
+}
+
+async fn load_permissions(shard: &ShardManager, user: &AuthUser) -> Result<UserPermissions, sqlx::Error> {
+    let pool_tenant = shard.get_pool_for_tenant(&user.tenant_id).await?;
+
+    // role gán trực tiếp + role thừa hưởng từ nhóm
+    let roles = sqlx::query!(
+        r#"
+        SELECT r.id, r.name
+        FROM roles r
+        WHERE r.tenant_id = $1
+          AND (
+            r.id IN (SELECT role_id FROM user_roles WHERE tenant_id = $1 AND user_id = $2)
+            OR r.id IN (
+              SELECT rgr.role_id
+              FROM user_role_groups urg
+              JOIN role_group_roles rgr
+                ON rgr.tenant_id = urg.tenant_id
+               AND rgr.group_id  = urg.group_id
+              WHERE urg.tenant_id = $1 AND urg.user_id = $2
+            )
+          )
+        "#,
+        user.tenant_id,
+        user.user_id
+    )
+    .fetch_all(pool_tenant)
+    .await?;
+
+    let version = sqlx::query_scalar!(
+        "SELECT perm_version FROM users WHERE tenant_id = $1 AND user_id = $2",
+        user.tenant_id,
+        user.user_id
+    )
+    .fetch_optional(pool_tenant)
+    .await?
+    .unwrap_or(0);
+
+    let is_admin = roles.iter().any(|r| r.name == "admin");
+    let role_names: Vec<String> = roles.iter().map(|r| r.name.clone()).collect();
+    if is_admin || roles.is_empty() {
+        return Ok(UserPermissions { is_admin, roles: role_names, version, granted: HashMap::new() });
+    }
+
+    let role_ids: Vec<Uuid> = roles.iter().map(|r| r.id).collect();
+    let rows = sqlx::query!(
+        r#"
+        SELECT p.resource, p.action, rp.scope
+        FROM role_permissions rp
+        JOIN permissions p ON p.id = rp.permission_id
+        WHERE rp.role_id = ANY($1)
+        "#,
+        &role_ids
+    )
+    .fetch_all(shard.get_pool_for_system())
+    .await?;
+
+    // Nhiều role cùng cấp 1 quyền → gộp scope (OR), grant không scope = toàn bộ
+    let mut granted: HashMap<(String, String), Scope> = HashMap::new();
+    for row in rows {
+        let grant = match row.scope.as_deref().map(str::trim) {
+            None | Some("") => Scope::All,
+            Some(expr) => match scope::parse_with_columns(expr, scope::OWNERSHIP_COLUMNS) {
+                Ok(expr) => Scope::Any(vec![expr]),
+                Err(e) => {
+                    tracing::warn!("⚠️ Scope không hợp lệ cho {}.{}: {} ({})", row.resource, row.action, expr, e);
+                    Scope::Any(vec![])
+                }
+            },
+        };
+        let key = (row.resource, row.action);
+        let merged = match granted.remove(&key) {
+            Some(existing) => existing.merge(grant),
+            None => grant,
+        };
+        granted.insert(key, merged);
+    }
+
+    Ok(UserPermissions { is_admin, roles: role_names, version, granted })
+}
+
+/// Thông tin quyền đóng gói vào JWT lúc login / refresh
+#[derive(Debug, Clone)]
+pub struct AccessClaims {
+    pub roles: Vec<String>,
+    pub perm_version: i64,
+    pub perm_digest: String,
+    pub modules: Vec<String>,
+}
+
+/// ✅ Snapshot quyền + module đang bật của tenant để nhúng vào token
+pub async fn access_claims(
+    cache: &PermissionCache,
+    shard: &ShardManager,
+    tenant_id: Uuid,
+    user_id: Uuid,
+) -> Result<AccessClaims, sqlx::Error> {
+    let user = AuthUser { user_id, tenant_id, perm_version: None };
+    // luôn nạp mới: token phải phản ánh đúng phiên bản quyền hiện tại
+    cache.invalidate_user(tenant_id, user_id);
+    let perms = cache.get(shard, &user).await?;
+
+    let modules = sqlx::query_scalar!(
+        "SELECT module_name FROM tenant_module WHERE tenant_id = $1 ORDER BY module_name",
+        tenant_id
+    )
+    .fetch_all(shard.get_pool_for_tenant(&tenant_id).await?)
+    .await?;
+
+    Ok(AccessClaims {
+        roles: perms.roles.clone(),
+        perm_version: perms.version,
+        perm_digest: perms.digest(),
+        modules,
+    })
+}
+
+/// Tăng phiên bản quyền của 1 user (sau khi gán role)
+pub async fn bump_user_version<'e>(
+    executor: impl sqlx::PgExecutor<'e>,
+    tenant_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users SET perm_version = perm_version + 1 WHERE tenant_id = $1 AND user_id = $2",
+        tenant_id,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Tăng phiên bản quyền của mọi user có role (trực tiếp hoặc qua nhóm).
+/// role_permissions nằm ở meta DB nên không biết role thuộc shard nào → chạy trên mọi shard.
+pub async fn bump_role_version(shard: &ShardManager, role_id: Uuid) -> Result<u64, sqlx::Error> {
+    let mut affected = 0;
+    for pool in shard.distinct_pools() {
+        let res = sqlx::query!(
+            r#"
+            UPDATE users u SET perm_version = perm_version + 1
+            WHERE (u.tenant_id, u.user_id) IN (
+              SELECT tenant_id, user_id FROM user_roles WHERE role_id = $1
+              UNION
+              SELECT urg.tenant_id, urg.user_id
+              FROM user_role_groups urg
+              JOIN role_group_roles rgr
+                ON rgr.tenant_id = urg.tenant_id
+               AND rgr.group_id  = urg.group_id
+              WHERE rgr.role_id = $1
+            )
+            "#,
+            role_id
+        )
+        .execute(pool)
+        .await?;
+        affected += res.rows_affected();
+    }
+    Ok(affected)
+}
+
+/// Resource của quyền: cố định, hoặc lấy từ path param (route động của module ngoài)
+#[derive(Clone, Copy, Debug)]
+enum Resource {
+    Static(&'static str),
+    PathParam(&'static str),
+}
+
+/// 🔐 Layer kiểm tra quyền cho route (đặt sau `jwt_auth`):
+/// `.route("/create", post(handler).route_layer(RequirePermission::new("loan", "create")))`
+/// Thiếu quyền → 403 kèm thông báo i18n. Có quyền → gắn `Scope` vào extensions cho handler lọc bản ghi.
+#[derive(Clone, Copy, Debug)]
+pub struct RequirePermission {
+    resource: Resource,
+    action: &'static str,
+}
+
+impl RequirePermission {
+    pub fn new(resource: &'static str, action: &'static str) -> Self {
+        Self { resource: Resource::Static(resource), action }
+    }
+
+    /// Resource là giá trị của path param `param` (vd: `:module_name`)
+    pub fn from_path(param: &'static str, action: &'static str) -> Self {
+        Self { resource: Resource::PathParam(param), action }
+    }
+
+    async fn check(self, req: Request<Body>) -> Result<Request<Body>, Response> {
+        let i18n = I18n::from_headers(req.headers());
+        let forbidden = || AppError::forbidden_i18n(&i18n, "error.auth.forbidden").into_response();
+
+        // jwt_auth chạy trước và gắn AuthUser vào extensions
+        let Some(user) = req.extensions().get::<AuthUser>().cloned() else {
+            return Err(StatusCode::UNAUTHORIZED.into_response());
+        };
+        let state = req
+            .extensions()
+            .get::<Arc<AppState>>()
+            .cloned()
+            .ok_or_else(|| AppError::internal("RequirePermission: thiếu AppState trong request extensions").into_response())?;
+
+        let (mut parts, body) = req.into_parts();
+        let resource = match self.resource {
+            Resource::Static(resource) => resource.to_string(),
+            Resource::PathParam(param) => RawPathParams::from_request_parts(&mut parts, &())
+                .await
+                .ok()
+                .and_then(|params| params.iter().find(|(k, _)| *k == param).map(|(_, v)| v.to_string()))
+                .ok_or_else(forbidden)?,
+        };
+
+        let scope = state
+            .permissions
+            .resolve(&state.shard, &user, &resource, self.action)
+            .await
+            .map_err(|e| load_error(&i18n, e))?;
+
+        if let Some(scope) = scope {
+            parts.extensions.insert(scope);
+            Ok(Request::from_parts(parts, body))
+        } else {
+            tracing::warn!("🚫 User {} không có quyền {}.{}", user.user_id, resource, self.action);
+            Err(forbidden())
+        }
+    }
+}
+
+impl<S> Layer<S> for RequirePermission {
+    type Service = RequirePermissionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequirePermissionService { inner, permission: *self }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequirePermissionService<S> {
+    inner: S,
+    permission: RequirePermission,
+}
+
+impl<S> Service<Request<Body>> for RequirePermissionService<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // Lấy service đã poll_ready, để lại bản clone cho lần gọi sau
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let permission = self.permission;
+
+        Box::pin(async move {
+            match permission.check(req).await {
+                Ok(req) => inner.call(req).await,
+                Err(response) => Ok(response),
+            }
+        })
+    }
+}
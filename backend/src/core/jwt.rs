@@ -0,0 +1,212 @@
+// src/core/jwt.rs
+//! Ký / xác thực JWT với khoá nạp từ cấu hình, header `kid` cho phép xoay vòng khoá.
+//!
+//! Biến môi trường:
+//!   JWT_ALG              HS256 (mặc định) | RS256 | EdDSA
+//!   JWT_KID              kid của khoá ký hiện tại (mặc định "default")
+//!   JWT_SECRET           secret cho HS256
+//!   JWT_PRIVATE_KEY      đường dẫn PEM khoá riêng (RS256/EdDSA)
+//!   JWT_PUBLIC_KEY       đường dẫn PEM khoá công khai (RS256/EdDSA)
+//!   JWT_PREVIOUS_KEYS    khoá cũ chỉ để verify khi xoay vòng: "kid1:secret|pem_path,kid2:..."
+//!   JWT_ACCESS_TTL_SECS  thời hạn access token (mặc định 3600)
+//!   JWT_REFRESH_TTL_DAYS thời hạn refresh token (mặc định 30)
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use once_cell::sync::OnceCell;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+static KEYS: OnceCell<JwtKeys> = OnceCell::new();
+
+pub struct JwtKeys {
+    alg: Algorithm,
+    kid: String,
+    encoding: EncodingKey,
+    /// kid → khoá verify (gồm khoá hiện tại + khoá cũ còn hiệu lực)
+    decoding: HashMap<String, DecodingKey>,
+    pub access_ttl_secs: i64,
+    pub refresh_ttl_days: i64,
+}
+
+impl JwtKeys {
+    /// Nạp khoá từ env, lỗi cấu hình trả về mô tả để dừng server sớm
+    pub fn from_env() -> Result<Self, String> {
+        let alg = match env_or("JWT_ALG", "HS256").to_uppercase().as_str() {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "EDDSA" => Algorithm::EdDSA,
+            other => return Err(format!("JWT_ALG không hỗ trợ: {}", other)),
+        };
+        let kid = env_or("JWT_KID", "default");
+
+        let (encoding, current) = match alg {
+            Algorithm::HS256 => {
+                let secret = std::env::var("JWT_SECRET").map_err(|_| "Thiếu JWT_SECRET".to_string())?;
+                (EncodingKey::from_secret(secret.as_bytes()), DecodingKey::from_secret(secret.as_bytes()))
+            }
+            _ => {
+                let private = read_pem("JWT_PRIVATE_KEY")?;
+                let public = read_pem("JWT_PUBLIC_KEY")?;
+                let encoding = match alg {
+                    Algorithm::RS256 => EncodingKey::from_rsa_pem(&private),
+                    _ => EncodingKey::from_ed_pem(&private),
+                }
+                .map_err(|e| format!("JWT_PRIVATE_KEY không hợp lệ: {}", e))?;
+                (encoding, decoding_from_pem(alg, &public)?)
+            }
+        };
+
+        let mut decoding = HashMap::new();
+        decoding.insert(kid.clone(), current);
+
+        // Khoá cũ: "kid:value" – value là secret (HS256) hoặc đường dẫn PEM public key
+        for entry in env_or("JWT_PREVIOUS_KEYS", "").split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (old_kid, value) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("JWT_PREVIOUS_KEYS sai định dạng: {}", entry))?;
+            let key = match alg {
+                Algorithm::HS256 => DecodingKey::from_secret(value.as_bytes()),
+                _ => {
+                    let pem = std::fs::read(value).map_err(|e| format!("Không đọc được {}: {}", value, e))?;
+                    decoding_from_pem(alg, &pem)?
+                }
+            };
+            decoding.insert(old_kid.to_string(), key);
+        }
+
+        Ok(Self {
+            alg,
+            kid,
+            encoding,
+            decoding,
+            access_ttl_secs: env_or("JWT_ACCESS_TTL_SECS", "3600").parse().unwrap_or(3600),
+            refresh_ttl_days: env_or("JWT_REFRESH_TTL_DAYS", "30").parse().unwrap_or(30),
+        })
+    }
+
+    /// Khoá HS256 dựng sẵn cho test
+    #[cfg(test)]
+    pub fn hs256(kid: &str, secret: &[u8]) -> Self {
+        let mut decoding = HashMap::new();
+        decoding.insert(kid.to_string(), DecodingKey::from_secret(secret));
+        Self {
+            alg: Algorithm::HS256,
+            kid: kid.to_string(),
+            encoding: EncodingKey::from_secret(secret),
+            decoding,
+            access_ttl_secs: 3600,
+            refresh_ttl_days: 30,
+        }
+    }
+
+    /// Ký token bằng khoá hiện tại (header có `kid`)
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut header = Header::new(self.alg);
+        header.kid = Some(self.kid.clone());
+        encode(&header, claims, &self.encoding)
+    }
+
+    /// Xác thực token: chọn khoá theo `kid` (token cũ không có kid → khoá hiện tại)
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, jsonwebtoken::errors::Error> {
+        let header = decode_header(token)?;
+        let kid = header.kid.as_deref().unwrap_or(&self.kid);
+        let key = self
+            .decoding
+            .get(kid)
+            .ok_or(jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+        decode::<T>(token, key, &Validation::new(self.alg)).map(|data| data.claims)
+    }
+}
+
+/// Khởi tạo khoá lúc khởi động server
+pub fn init() -> Result<(), String> {
+    let keys = JwtKeys::from_env()?;
+    tracing::info!("🔑 JWT {:?} kid={} ({} khoá verify)", keys.alg, keys.kid, keys.decoding.len());
+    KEYS.set(keys).map_err(|_| "JWT keys đã được khởi tạo".to_string())
+}
+
+/// Khoá đang dùng (nạp từ env nếu chưa `init`)
+pub fn keys() -> &'static JwtKeys {
+    KEYS.get_or_init(|| JwtKeys::from_env().expect("❌ Cấu hình JWT không hợp lệ"))
+}
+
+// ================== Refresh token ==================
+
+/// Refresh token dạng `<tenant_id>.<random>`: tenant_id để định tuyến shard, DB chỉ lưu hash
+pub fn generate_refresh_token(tenant_id: Uuid) -> String {
+    format!("{}.{}{}", tenant_id, Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// SHA-256 (hex) của refresh token
+pub fn hash_refresh_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Tenant của refresh token (không xác thực, chỉ để chọn shard)
+pub fn refresh_token_tenant(token: &str) -> Option<Uuid> {
+    token.split_once('.').and_then(|(tenant, _)| Uuid::parse_str(tenant).ok())
+}
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn read_pem(var: &str) -> Result<Vec<u8>, String> {
+    let path = std::env::var(var).map_err(|_| format!("Thiếu {}", var))?;
+    std::fs::read(&path).map_err(|e| format!("Không đọc được {} ({}): {}", var, path, e))
+}
+
+fn decoding_from_pem(alg: Algorithm, pem: &[u8]) -> Result<DecodingKey, String> {
+    match alg {
+        Algorithm::RS256 => DecodingKey::from_rsa_pem(pem),
+        _ => DecodingKey::from_ed_pem(pem),
+    }
+    .map_err(|e| format!("Public key không hợp lệ: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn claims() -> TestClaims {
+        TestClaims { sub: "u1".into(), exp: (chrono::Utc::now().timestamp() + 60) as usize }
+    }
+
+    #[test]
+    fn verifies_current_and_rotated_keys() {
+        let old = JwtKeys::hs256("k1", b"old-secret");
+        let old_token = old.sign(&claims()).unwrap();
+
+        let mut current = JwtKeys::hs256("k2", b"new-secret");
+        let new_token = current.sign(&claims()).unwrap();
+        assert_eq!(decode_header(&new_token).unwrap().kid.as_deref(), Some("k2"));
+        assert_eq!(current.verify::<TestClaims>(&new_token).unwrap().sub, "u1");
+
+        // chưa khai báo khoá cũ → token cũ bị từ chối
+        assert!(current.verify::<TestClaims>(&old_token).is_err());
+        current.decoding.insert("k1".into(), DecodingKey::from_secret(b"old-secret"));
+        assert_eq!(current.verify::<TestClaims>(&old_token).unwrap(), old.verify::<TestClaims>(&old_token).unwrap());
+
+        // sai chữ ký
+        let forged = JwtKeys::hs256("k2", b"attacker").sign(&claims()).unwrap();
+        assert!(current.verify::<TestClaims>(&forged).is_err());
+    }
+
+    #[test]
+    fn refresh_token_routes_to_tenant_and_hashes() {
+        let tenant = Uuid::new_v4();
+        let token = generate_refresh_token(tenant);
+        assert_eq!(refresh_token_tenant(&token), Some(tenant));
+        assert_eq!(hash_refresh_token(&token).len(), 64);
+        assert_ne!(hash_refresh_token(&token), hash_refresh_token(&generate_refresh_token(tenant)));
+        assert_eq!(refresh_token_tenant("garbage"), None);
+    }
+}
@@ -1,10 +1,13 @@
 // core: các kiểu dữ liệu và tiện ích dùng chung
 pub mod auth; 
+pub mod jwt;
 pub mod state; 
 pub mod iam; 
+pub mod scope;
 pub mod log; 
 pub mod json_with_log; 
 pub mod error;
 pub mod cache;
 pub mod i18n;
-pub mod i18n_middleware; 
\ No newline at end of file
+pub mod i18n_middleware; 
+pub mod timezone;
\ No newline at end of file
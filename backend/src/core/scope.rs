@@ -0,0 +1,518 @@
+// src/core/scope.rs
+//! Scope expression (ABAC) gắn trên `role_permissions.scope`, giới hạn bản ghi user được thấy/sửa.
+//! Ví dụ: `assignee_id = $user OR $user IN shared_with`
+//!
+//! Cú pháp (từ khoá không phân biệt hoa thường):
+//!   expr  := and ("OR" and)*
+//!   and   := unary ("AND" unary)*
+//!   unary := "NOT" unary | "(" expr ")" | cond
+//!   cond  := field ("=" | "!=" | "<>") value
+//!          | field "IS" ["NOT"] "NULL"
+//!          | field "IN" "(" value ("," value)* ")"
+//!          | value "IN" field                      -- field là cột mảng (vd: shared_with)
+//!   value := $user | $tenant | 'chuỗi'
+//!
+//! Biểu thức được dịch sang SQL với tham số bind, tên cột phải nằm trong whitelist của `ScopeTarget`:
+//! lúc gán quyền cột lạ bị từ chối, lúc lọc dữ liệu scope có cột lạ bị coi là không thấy bản ghi nào
+//! (cả scope, không riêng điều kiện đó – tránh `NOT (cột_lạ = ...)` thành luôn đúng).
+use sqlx::{PgExecutor, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::core::auth::AuthUser;
+
+/// Giá trị bên phải của điều kiện
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeValue {
+    User,
+    Tenant,
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeExpr {
+    Eq(String, ScopeValue),
+    NotEq(String, ScopeValue),
+    IsNull(String),
+    IsNotNull(String),
+    InList(String, Vec<ScopeValue>),
+    /// `value IN field` – cột mảng chứa giá trị
+    Contains(String, ScopeValue),
+    Not(Box<ScopeExpr>),
+    And(Box<ScopeExpr>, Box<ScopeExpr>),
+    Or(Box<ScopeExpr>, Box<ScopeExpr>),
+}
+
+impl ScopeExpr {
+    /// Cột đầu tiên không có trong whitelist (nếu có)
+    pub fn unknown_column(&self, columns: &[&str]) -> Option<&str> {
+        match self {
+            ScopeExpr::Eq(field, _)
+            | ScopeExpr::NotEq(field, _)
+            | ScopeExpr::IsNull(field)
+            | ScopeExpr::IsNotNull(field)
+            | ScopeExpr::InList(field, _)
+            | ScopeExpr::Contains(field, _) => (!columns.contains(&field.as_str())).then_some(field.as_str()),
+            ScopeExpr::Not(inner) => inner.unknown_column(columns),
+            ScopeExpr::And(a, b) | ScopeExpr::Or(a, b) => {
+                a.unknown_column(columns).or_else(|| b.unknown_column(columns))
+            }
+        }
+    }
+}
+
+/// Phạm vi bản ghi của 1 quyền (resource, action)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scope {
+    /// Không giới hạn (admin, hoặc có grant không kèm scope)
+    All,
+    /// Thoả ít nhất 1 biểu thức (rỗng = không thấy bản ghi nào)
+    Any(Vec<ScopeExpr>),
+}
+
+/// Bảng áp dụng scope + whitelist cột được phép dùng trong biểu thức
+pub struct ScopeTarget {
+    pub table: &'static str,
+    pub columns: &'static [&'static str],
+}
+
+/// Cột dùng chung cho các bảng có owner/assignee/shared_with
+pub const OWNERSHIP_COLUMNS: &[&str] = &["id", "created_by", "assignee_id", "shared_with", "state"];
+
+impl Scope {
+    /// Gộp 2 grant: grant không giới hạn thắng, còn lại OR các biểu thức
+    pub fn merge(self, other: Scope) -> Scope {
+        match (self, other) {
+            (Scope::All, _) | (_, Scope::All) => Scope::All,
+            (Scope::Any(mut a), Scope::Any(b)) => {
+                a.extend(b);
+                Scope::Any(a)
+            }
+        }
+    }
+
+    /// Nối ` AND (...)` vào câu query. `alias` là tiền tố bảng (vd: "am"), rỗng nếu không có.
+    /// Biểu thức nào dùng cột ngoài whitelist → cả scope thành ` AND FALSE` (fail closed).
+    pub fn push_filter(&self, qb: &mut QueryBuilder<'_, Postgres>, target: &ScopeTarget, alias: &str, user: &AuthUser) {
+        let exprs = match self {
+            Scope::All => return,
+            Scope::Any(exprs) if exprs.is_empty() => {
+                qb.push(" AND FALSE");
+                return;
+            }
+            Scope::Any(exprs) => exprs,
+        };
+
+        if let Some(field) = exprs.iter().find_map(|e| e.unknown_column(target.columns)) {
+            tracing::warn!("⚠️ Scope dùng cột không hợp lệ '{}' trên bảng {}", field, target.table);
+            qb.push(" AND FALSE");
+            return;
+        }
+
+        qb.push(" AND (");
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                qb.push(" OR ");
+            }
+            qb.push("(");
+            push_expr(qb, expr, alias, user);
+            qb.push(")");
+        }
+        qb.push(")");
+    }
+
+    /// Bản ghi `id` có nằm trong scope không (không kiểm tra tồn tại nếu scope = All)
+    pub async fn is_visible<'e>(
+        &self,
+        executor: impl PgExecutor<'e>,
+        target: &ScopeTarget,
+        user: &AuthUser,
+        id: Uuid,
+    ) -> sqlx::Result<bool> {
+        if *self == Scope::All {
+            return Ok(true);
+        }
+
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT EXISTS (SELECT 1 FROM ");
+        qb.push(target.table);
+        qb.push(" WHERE tenant_id = ");
+        qb.push_bind(user.tenant_id);
+        qb.push(" AND id = ");
+        qb.push_bind(id);
+        self.push_filter(&mut qb, target, "", user);
+        qb.push(")");
+
+        qb.build_query_scalar::<bool>().fetch_one(executor).await
+    }
+}
+
+/// Dịch 1 biểu thức đã kiểm tra cột (xem `push_filter`)
+fn push_expr(qb: &mut QueryBuilder<'_, Postgres>, expr: &ScopeExpr, alias: &str, user: &AuthUser) {
+    let column = |field: &str| -> String {
+        if alias.is_empty() { field.to_string() } else { format!("{}.{}", alias, field) }
+    };
+
+    match expr {
+        ScopeExpr::Eq(field, value) | ScopeExpr::NotEq(field, value) => {
+            let col = column(field);
+            let op = if matches!(expr, ScopeExpr::Eq(..)) { " = " } else { " IS DISTINCT FROM " };
+            match value {
+                ScopeValue::Text(text) => {
+                    qb.push(format!("{}::text{}", col, op));
+                    qb.push_bind(text.clone());
+                }
+                uuid_value => {
+                    qb.push(format!("{}{}", col, op));
+                    qb.push_bind(resolve_uuid(uuid_value, user));
+                }
+            }
+        }
+        ScopeExpr::IsNull(field) | ScopeExpr::IsNotNull(field) => {
+            let col = column(field);
+            let op = if matches!(expr, ScopeExpr::IsNull(_)) { " IS NULL" } else { " IS NOT NULL" };
+            qb.push(format!("{}{}", col, op));
+        }
+        ScopeExpr::InList(field, values) => {
+            qb.push("(");
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    qb.push(" OR ");
+                }
+                push_expr(qb, &ScopeExpr::Eq(field.clone(), value.clone()), alias, user);
+            }
+            qb.push(")");
+        }
+        ScopeExpr::Contains(field, value) => {
+            let col = column(field);
+            match value {
+                ScopeValue::Text(text) => {
+                    qb.push_bind(text.clone());
+                    qb.push(format!(" = ANY({}::text[])", col));
+                }
+                uuid_value => {
+                    qb.push_bind(resolve_uuid(uuid_value, user));
+                    qb.push(format!(" = ANY({})", col));
+                }
+            }
+        }
+        ScopeExpr::Not(inner) => {
+            qb.push("NOT (");
+            push_expr(qb, inner, alias, user);
+            qb.push(")");
+        }
+        ScopeExpr::And(a, b) | ScopeExpr::Or(a, b) => {
+            let op = if matches!(expr, ScopeExpr::And(..)) { " AND " } else { " OR " };
+            qb.push("(");
+            push_expr(qb, a, alias, user);
+            qb.push(op);
+            push_expr(qb, b, alias, user);
+            qb.push(")");
+        }
+    }
+}
+
+fn resolve_uuid(value: &ScopeValue, user: &AuthUser) -> Uuid {
+    match value {
+        ScopeValue::Tenant => user.tenant_id,
+        _ => user.user_id,
+    }
+}
+
+// ================== Parser ==================
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Var(String),
+    Str(String),
+    LParen,
+    RParen,
+    Comma,
+    Eq,
+    NotEq,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => { tokens.push(Token::LParen); i += 1; }
+            ')' => { tokens.push(Token::RParen); i += 1; }
+            ',' => { tokens.push(Token::Comma); i += 1; }
+            '=' => { tokens.push(Token::Eq); i += 1; }
+            '!' | '<' => {
+                let next = chars.get(i + 1).copied();
+                if (c == '!' && next == Some('=')) || (c == '<' && next == Some('>')) {
+                    tokens.push(Token::NotEq);
+                    i += 2;
+                } else {
+                    return Err(format!("Ký tự không hợp lệ '{}' tại vị trí {}", c, i));
+                }
+            }
+            '\'' => {
+                // chuỗi, '' là dấu nháy đơn
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err("Chuỗi chưa đóng dấu nháy".into()),
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => { s.push('\''); i += 2; }
+                        Some('\'') => { i += 1; break; }
+                        Some(ch) => { s.push(*ch); i += 1; }
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            '$' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().collect();
+                tokens.push(Token::Var(name.to_lowercase()));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            _ => return Err(format!("Ký tự không hợp lệ '{}' tại vị trí {}", c, i)),
+        }
+    }
+    Ok(tokens)
+}
+
+const KEYWORDS: &[&str] = &["and", "or", "not", "in", "is", "null"];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn peek_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(kw))
+    }
+
+    fn expect_keyword(&mut self, kw: &str) -> Result<(), String> {
+        if self.peek_keyword(kw) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("Thiếu từ khoá {}", kw.to_uppercase()))
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == token => Ok(()),
+            other => Err(format!("Cần {:?}, gặp {:?}", token, other)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<ScopeExpr, String> {
+        let mut left = self.and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            left = ScopeExpr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<ScopeExpr, String> {
+        let mut left = self.unary()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            left = ScopeExpr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<ScopeExpr, String> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            return Ok(ScopeExpr::Not(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let inner = self.expr()?;
+            self.expect(Token::RParen)?;
+            return Ok(inner);
+        }
+        self.cond()
+    }
+
+    fn cond(&mut self) -> Result<ScopeExpr, String> {
+        match self.peek() {
+            Some(Token::Var(_)) | Some(Token::Str(_)) => {
+                let value = self.value()?;
+                self.expect_keyword("in")?;
+                Ok(ScopeExpr::Contains(self.field()?, value))
+            }
+            _ => {
+                let field = self.field()?;
+                match self.next() {
+                    Some(Token::Eq) => Ok(ScopeExpr::Eq(field, self.value()?)),
+                    Some(Token::NotEq) => Ok(ScopeExpr::NotEq(field, self.value()?)),
+                    Some(Token::Ident(kw)) if kw.eq_ignore_ascii_case("is") => {
+                        let negated = self.peek_keyword("not");
+                        if negated {
+                            self.pos += 1;
+                        }
+                        self.expect_keyword("null")?;
+                        Ok(if negated { ScopeExpr::IsNotNull(field) } else { ScopeExpr::IsNull(field) })
+                    }
+                    Some(Token::Ident(kw)) if kw.eq_ignore_ascii_case("in") => {
+                        self.expect(Token::LParen)?;
+                        let mut values = vec![self.value()?];
+                        while self.peek() == Some(&Token::Comma) {
+                            self.pos += 1;
+                            values.push(self.value()?);
+                        }
+                        self.expect(Token::RParen)?;
+                        Ok(ScopeExpr::InList(field, values))
+                    }
+                    other => Err(format!("Toán tử không hợp lệ sau '{}': {:?}", field, other)),
+                }
+            }
+        }
+    }
+
+    fn field(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Ident(name)) if !KEYWORDS.contains(&name.to_lowercase().as_str()) => Ok(name.to_lowercase()),
+            other => Err(format!("Cần tên cột, gặp {:?}", other)),
+        }
+    }
+
+    fn value(&mut self) -> Result<ScopeValue, String> {
+        match self.next() {
+            Some(Token::Var(v)) if v == "user" => Ok(ScopeValue::User),
+            Some(Token::Var(v)) if v == "tenant" => Ok(ScopeValue::Tenant),
+            Some(Token::Var(v)) => Err(format!("Biến không hỗ trợ: ${}", v)),
+            Some(Token::Str(s)) => Ok(ScopeValue::Text(s)),
+            other => Err(format!("Cần giá trị ($user, $tenant, 'chuỗi'), gặp {:?}", other)),
+        }
+    }
+}
+
+/// Parse + kiểm tra cột theo whitelist (dùng lúc gán quyền / nạp quyền)
+pub fn parse_with_columns(input: &str, columns: &[&str]) -> Result<ScopeExpr, String> {
+    let expr = parse(input)?;
+    match expr.unknown_column(columns) {
+        Some(field) => Err(format!("Cột không được phép dùng trong scope: {}", field)),
+        None => Ok(expr),
+    }
+}
+
+/// ✅ Parse biểu thức scope, lỗi trả về mô tả để hiển thị cho người cấu hình quyền
+pub fn parse(input: &str) -> Result<ScopeExpr, String> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Err("Biểu thức scope rỗng".into());
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.expr()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("Thừa ký tự sau biểu thức: {:?}", parser.tokens[parser.pos]));
+    }
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> AuthUser {
+        AuthUser { user_id: Uuid::from_u128(1), tenant_id: Uuid::from_u128(2), perm_version: None }
+    }
+
+    fn sql(scope: &Scope, alias: &str) -> String {
+        let target = ScopeTarget { table: "loan_contract", columns: OWNERSHIP_COLUMNS };
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT 1 FROM loan_contract WHERE tenant_id = $0");
+        scope.push_filter(&mut qb, &target, alias, &user());
+        qb.sql().to_string()
+    }
+
+    #[test]
+    fn test_parse_assignee_or_shared() {
+        let expr = parse("assignee_id = $user OR $user IN shared_with").unwrap();
+        assert_eq!(
+            expr,
+            ScopeExpr::Or(
+                Box::new(ScopeExpr::Eq("assignee_id".into(), ScopeValue::User)),
+                Box::new(ScopeExpr::Contains("shared_with".into(), ScopeValue::User)),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_precedence_and_errors() {
+        let expr = parse("NOT state = 'draft' and (created_by = $user or state in ('active', 'closed'))").unwrap();
+        assert!(matches!(expr, ScopeExpr::And(ref a, _) if matches!(**a, ScopeExpr::Not(_))));
+
+        assert!(parse("").is_err());
+        assert!(parse("assignee_id = ").is_err());
+        assert!(parse("assignee_id = $me").is_err());
+        assert!(parse("state = 'x' OR").is_err());
+        assert!(parse("state = 'x'; DROP TABLE loan_contract").is_err());
+    }
+
+    #[test]
+    fn test_push_filter_sql() {
+        let scope = Scope::Any(vec![parse("assignee_id = $user OR $user IN shared_with").unwrap()]);
+        assert_eq!(
+            sql(&scope, "lc"),
+            "SELECT 1 FROM loan_contract WHERE tenant_id = $0 AND (((lc.assignee_id = $1 OR $2 = ANY(lc.shared_with))))"
+        );
+
+        assert_eq!(sql(&Scope::All, ""), "SELECT 1 FROM loan_contract WHERE tenant_id = $0");
+        assert!(sql(&Scope::Any(vec![]), "").ends_with(" AND FALSE"));
+
+        // cột ngoài whitelist → cả scope FALSE, kể cả khi bị phủ định hoặc OR với điều kiện hợp lệ
+        let unknown = Scope::Any(vec![parse("password_hash = 'x'").unwrap()]);
+        assert!(sql(&unknown, "").ends_with(" AND FALSE"));
+        let negated = Scope::Any(vec![parse("NOT password_hash = 'x'").unwrap()]);
+        assert_eq!(sql(&negated, ""), "SELECT 1 FROM loan_contract WHERE tenant_id = $0 AND FALSE");
+        let mixed = Scope::Any(vec![
+            parse("assignee_id = $user").unwrap(),
+            parse("state = 'draft' OR NOT password_hash = 'x'").unwrap(),
+        ]);
+        assert!(sql(&mixed, "").ends_with(" AND FALSE"));
+    }
+
+    #[test]
+    fn test_parse_with_columns() {
+        assert!(parse_with_columns("assignee_id = $user OR NOT state IN ('draft')", OWNERSHIP_COLUMNS).is_ok());
+        assert!(parse_with_columns("NOT password_hash = 'x'", OWNERSHIP_COLUMNS).is_err());
+        assert!(parse_with_columns("state = 'x' AND $user IN owners", OWNERSHIP_COLUMNS).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let a = Scope::Any(vec![parse("created_by = $user").unwrap()]);
+        let b = Scope::Any(vec![parse("assignee_id = $user").unwrap()]);
+        assert_eq!(a.clone().merge(b), Scope::Any(vec![
+            parse("created_by = $user").unwrap(),
+            parse("assignee_id = $user").unwrap(),
+        ]));
+        assert_eq!(a.merge(Scope::All), Scope::All);
+    }
+}
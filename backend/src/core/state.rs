@@ -1,28 +1,30 @@
 use std::sync::Arc;
-use crate::infra::{ telemetry::Telemetry, event_bus::EventPublisher, wasm_loader::ModuleRegistry};
+use crate::command_bus::CommandBus;
+use crate::core::iam::PermissionCache;
+use crate::infra::wasm_loader::ModuleRegistry;
 
 pub use crate::infra::db::ShardManager;
 
 #[derive(Clone)]
 pub struct AppState {
     pub shard: Arc<ShardManager>,
-    pub telemetry: Arc<Telemetry>,
-    pub event_publisher: Arc<dyn EventPublisher + Send + Sync>,
     pub module_registry: Arc<ModuleRegistry>, // Module registry cho WASM modules ngoài binary
+    pub command_bus: Arc<CommandBus>,          // Điều phối lệnh ghi qua middleware (phân quyền, audit, ...)
+    pub permissions: Arc<PermissionCache>,     // Cache quyền RBAC theo user
 }
 
 impl AppState {
     pub fn new(
         shard: Arc<ShardManager>,
-        telemetry: Arc<Telemetry>,
-        event_publisher: Arc<dyn EventPublisher + Send + Sync>,
         module_registry: Arc<ModuleRegistry>,
+        command_bus: Arc<CommandBus>,
+        permissions: Arc<PermissionCache>,
     ) -> Arc<Self> {
         Arc::new(Self {
             shard,
-            telemetry,
-            event_publisher,
             module_registry,
+            command_bus,
+            permissions,
         })
     }
 }
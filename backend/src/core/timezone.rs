@@ -0,0 +1,61 @@
+//! Múi giờ nghiệp vụ của tenant: quy đổi thời điểm (UTC) sang ngày nghiệp vụ.
+//! Dùng chung cho các module tính theo ngày (loan, sequence, ...).
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+/// Múi giờ mặc định khi tenant chưa cấu hình
+pub const DEFAULT_TIMEZONE: Tz = chrono_tz::Asia::Bangkok;
+
+/// Múi giờ từ cấu hình tenant, sai tên → mặc định
+pub fn parse_timezone(name: &str) -> Tz {
+    name.parse().unwrap_or(DEFAULT_TIMEZONE)
+}
+
+/// Ngày nghiệp vụ của 1 thời điểm theo múi giờ
+pub fn business_date(dt_utc: DateTime<Utc>, tz: Tz) -> NaiveDate {
+    dt_utc.with_timezone(&tz).date_naive()
+}
+
+/// Thời điểm cuối ngày nghiệp vụ `date` (23:59:59 giờ địa phương) theo UTC
+pub fn end_of_business_day(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    let next = date.succ_opt().unwrap_or(date).and_hms_opt(0, 0, 0).unwrap_or_default();
+    let start_of_next = tz
+        .from_local_datetime(&next)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&next));
+    start_of_next - chrono::Duration::seconds(1)
+}
+
+/// Múi giờ nghiệp vụ của tenant (bảng `tenant`)
+pub async fn tenant_timezone<'e>(executor: impl PgExecutor<'e>, tenant_id: Uuid) -> sqlx::Result<Tz> {
+    let name = sqlx::query_scalar!("SELECT timezone FROM tenant WHERE tenant_id = $1", tenant_id)
+        .fetch_optional(executor)
+        .await?;
+    Ok(name.map(|n| parse_timezone(&n)).unwrap_or(DEFAULT_TIMEZONE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(y: i32, m: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, day).unwrap()
+    }
+
+    #[test]
+    fn timezone_fallback() {
+        assert_eq!(parse_timezone("Asia/Ho_Chi_Minh"), chrono_tz::Asia::Ho_Chi_Minh);
+        assert_eq!(parse_timezone("Mars/Olympus"), DEFAULT_TIMEZONE);
+    }
+
+    #[test]
+    fn end_of_day_in_tenant_timezone() {
+        let eod = end_of_business_day(d(2025, 3, 10), chrono_tz::Asia::Bangkok);
+        assert_eq!(eod, Utc.with_ymd_and_hms(2025, 3, 10, 16, 59, 59).unwrap());
+        assert_eq!(business_date(eod, chrono_tz::Asia::Bangkok), d(2025, 3, 10));
+        assert_eq!(business_date(eod + chrono::Duration::seconds(1), chrono_tz::Asia::Bangkok), d(2025, 3, 11));
+    }
+}
@@ -1 +1,409 @@
 // event_handler: xử lý bất đồng bộ từ event bus
+//
+// Luồng: outbox relay → EventDispatcher ghi 1 dòng `event_handler_inbox` cho mỗi handler đăng ký
+// → HandlerWorker chạy handler, retry với backoff, quá số lần thử thì chuyển `event_dead_letter`.
+// Khoá chính (tenant_id, handler_name, event_id) đảm bảo mỗi handler xử lý 1 event đúng 1 lần
+// dù relay gửi lặp (at-least-once).
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use sqlx::PgPool;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::core::state::AppState;
+use crate::infra::db::ShardManager;
+use crate::infra::event_bus::{EventEnvelope, EventPublisher};
+use crate::infra::outbox::{retry_delay_secs, DomainEvent};
+
+/// Ngữ cảnh truyền cho handler
+pub struct EventContext<'a> {
+    pub state: &'a Arc<AppState>,
+    pub envelope: &'a EventEnvelope,
+}
+
+impl EventContext<'_> {
+    pub fn tenant_id(&self) -> Uuid {
+        self.envelope.tenant_id
+    }
+
+    /// Pool của shard chứa tenant phát sinh event
+    pub async fn pool(&self) -> Result<&PgPool, String> {
+        self.state
+            .shard
+            .get_pool_for_tenant(&self.envelope.tenant_id)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Subscriber cho 1 loại domain event (E = enum event của module, vd: LoanEvent)
+#[async_trait]
+pub trait EventHandler<E>: Send + Sync + 'static {
+    /// Tên duy nhất, dùng làm khoá idempotency → không đổi tên handler đã chạy production
+    fn name(&self) -> &'static str;
+
+    /// Chỉ nhận các event_type này (rỗng = mọi event của topic)
+    fn event_types(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Số lần thử tối đa trước khi chuyển dead-letter
+    fn max_attempts(&self) -> i32 {
+        5
+    }
+
+    async fn handle(&self, ctx: &EventContext<'_>, event: E) -> Result<(), String>;
+}
+
+/// Handler đã xoá kiểu event (để registry chứa nhiều loại event)
+#[async_trait]
+trait ErasedHandler: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn max_attempts(&self) -> i32;
+    fn accepts(&self, envelope: &EventEnvelope) -> bool;
+    async fn dispatch(&self, ctx: &EventContext<'_>) -> Result<(), String>;
+}
+
+struct Typed<E, H> {
+    handler: H,
+    _event: PhantomData<fn() -> E>,
+}
+
+#[async_trait]
+impl<E, H> ErasedHandler for Typed<E, H>
+where
+    E: DomainEvent + DeserializeOwned + Send + 'static,
+    H: EventHandler<E>,
+{
+    fn name(&self) -> &'static str {
+        self.handler.name()
+    }
+
+    fn max_attempts(&self) -> i32 {
+        self.handler.max_attempts()
+    }
+
+    fn accepts(&self, envelope: &EventEnvelope) -> bool {
+        let types = self.handler.event_types();
+        envelope.topic == E::TOPIC && (types.is_empty() || types.contains(&envelope.event_type.as_str()))
+    }
+
+    async fn dispatch(&self, ctx: &EventContext<'_>) -> Result<(), String> {
+        let event: E = serde_json::from_value(ctx.envelope.payload.clone())
+            .map_err(|e| format!("Payload {} không hợp lệ: {}", ctx.envelope.event_type, e))?;
+        self.handler.handle(ctx, event).await
+    }
+}
+
+/// Danh sách handler đăng ký theo loại event
+#[derive(Default)]
+pub struct EventHandlerRegistry {
+    handlers: Vec<Arc<dyn ErasedHandler>>,
+}
+
+impl EventHandlerRegistry {
+    /// Đăng ký handler cho event E
+    pub fn subscribe<E, H>(&mut self, handler: H) -> &mut Self
+    where
+        E: DomainEvent + DeserializeOwned + Send + 'static,
+        H: EventHandler<E>,
+    {
+        assert!(
+            self.find(handler.name()).is_none(),
+            "Event handler '{}' đã được đăng ký",
+            handler.name()
+        );
+        self.handlers.push(Arc::new(Typed { handler, _event: PhantomData }));
+        self
+    }
+
+    fn find(&self, name: &str) -> Option<&Arc<dyn ErasedHandler>> {
+        self.handlers.iter().find(|h| h.name() == name)
+    }
+
+    /// Tên các handler nhận event này
+    fn matching(&self, envelope: &EventEnvelope) -> Vec<String> {
+        self.handlers
+            .iter()
+            .filter(|h| h.accepts(envelope))
+            .map(|h| h.name().to_string())
+            .collect()
+    }
+}
+
+/// Handler của các module
+pub fn build_registry() -> EventHandlerRegistry {
+    let mut registry = EventHandlerRegistry::default();
+    crate::module::loan::event_handler::register(&mut registry);
+    crate::module::invoice_link::event_handler::register(&mut registry);
+    registry
+}
+
+/// Nhận event từ outbox relay và xếp vào inbox của từng handler (cùng shard với tenant)
+pub struct EventDispatcher {
+    shard: Arc<ShardManager>,
+    registry: Arc<EventHandlerRegistry>,
+}
+
+impl EventDispatcher {
+    pub fn new(shard: Arc<ShardManager>, registry: Arc<EventHandlerRegistry>) -> Self {
+        Self { shard, registry }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for EventDispatcher {
+    async fn publish(&self, event: &EventEnvelope) -> Result<(), String> {
+        let handlers = self.registry.matching(event);
+        if handlers.is_empty() {
+            return Ok(());
+        }
+
+        let pool = self
+            .shard
+            .get_pool_for_tenant(&event.tenant_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let body = serde_json::to_value(event).map_err(|e| e.to_string())?;
+
+        // Relay gửi lại cùng event → ON CONFLICT bỏ qua, không chạy handler lần 2
+        sqlx::query!(
+            r#"
+            INSERT INTO event_handler_inbox (tenant_id, handler_name, event_id, event)
+            SELECT $1, h, $3, $4 FROM UNNEST($2::text[]) AS h
+            ON CONFLICT (tenant_id, handler_name, event_id) DO NOTHING
+            "#,
+            event.tenant_id,
+            &handlers,
+            event.id,
+            body
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+}
+
+/// Worker nền: chạy handler cho các dòng inbox đến hạn ở mọi shard
+pub struct HandlerWorker {
+    state: Arc<AppState>,
+    registry: Arc<EventHandlerRegistry>,
+    batch_size: i64,
+}
+
+impl HandlerWorker {
+    pub fn new(state: Arc<AppState>, registry: Arc<EventHandlerRegistry>) -> Self {
+        Self { state, registry, batch_size: 50 }
+    }
+
+    /// Chạy vòng lặp theo chu kỳ `every`
+    pub fn spawn(self, every: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                while self.run_once().await >= self.batch_size as usize {}
+            }
+        });
+    }
+
+    /// Xử lý 1 lượt trên tất cả shard, trả về số dòng inbox đã xử lý (thành công hoặc lỗi)
+    pub async fn run_once(&self) -> usize {
+        let mut processed = 0;
+        for (shard_id, pool) in self.state.shard.distinct_shards() {
+            match self.run_pool(shard_id, pool).await {
+                Ok(n) => processed += n,
+                Err(e) => tracing::warn!("⚠️ Event handler worker lỗi: {}", e),
+            }
+        }
+        processed
+    }
+
+    async fn run_pool(&self, shard_id: &str, pool: &PgPool) -> Result<usize, sqlx::Error> {
+        // Tenant đang chuyển shard chờ tới khi xong; inbox cũ còn giữ ở shard nguồn sau khi chuyển → bỏ qua
+        let due = sqlx::query_scalar!(
+            "SELECT DISTINCT tenant_id FROM event_handler_inbox WHERE status = 'pending' AND next_attempt_at <= now()"
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut tenants = Vec::with_capacity(due.len());
+        for tenant_id in due {
+            if self.state.shard.owns_active_tenant(&tenant_id, shard_id).await.unwrap_or(false) {
+                tenants.push(tenant_id);
+            }
+        }
+        if tenants.is_empty() {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        while count < self.batch_size as usize && self.run_next(pool, &tenants).await? {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Xử lý 1 dòng inbox đến hạn trong transaction riêng: kết quả của từng event được commit ngay,
+    /// event sau lỗi không làm chạy lại side effect (vd: auto-send HTTP) của các event trước
+    async fn run_next(&self, pool: &PgPool, tenants: &[Uuid]) -> Result<bool, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        // SKIP LOCKED: nhiều instance cùng chạy không xử lý trùng
+        let row = sqlx::query!(
+            r#"
+            SELECT tenant_id, handler_name, event_id, event, attempts
+            FROM event_handler_inbox
+            WHERE status = 'pending' AND next_attempt_at <= now() AND tenant_id = ANY($1)
+            ORDER BY created_at, event_id
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            tenants
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(false);
+        };
+
+        let (result, max_attempts) = match (
+            self.registry.find(&row.handler_name),
+            serde_json::from_value::<EventEnvelope>(row.event.clone()),
+        ) {
+            (Some(handler), Ok(envelope)) => {
+                let ctx = EventContext { state: &self.state, envelope: &envelope };
+                (handler.dispatch(&ctx).await, handler.max_attempts())
+            }
+            (None, _) => (Err(format!("Handler '{}' không còn được đăng ký", row.handler_name)), 0),
+            (_, Err(e)) => (Err(format!("Event không đọc được: {}", e)), 0),
+        };
+
+        match result {
+            Ok(()) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE event_handler_inbox
+                    SET status = 'done', attempts = attempts + 1, last_error = NULL, processed_at = now()
+                    WHERE tenant_id = $1 AND handler_name = $2 AND event_id = $3
+                    "#,
+                    row.tenant_id,
+                    row.handler_name,
+                    row.event_id
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            Err(err) if row.attempts + 1 >= max_attempts => {
+                tracing::error!(
+                    "💀 Handler '{}' bỏ cuộc với event {} sau {} lần: {}",
+                    row.handler_name, row.event_id, row.attempts + 1, err
+                );
+                sqlx::query!(
+                    r#"
+                    INSERT INTO event_dead_letter (tenant_id, id, handler_name, event_id, event, error, attempts)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7)
+                    "#,
+                    row.tenant_id,
+                    Uuid::new_v4(),
+                    row.handler_name,
+                    row.event_id,
+                    row.event,
+                    err,
+                    row.attempts + 1
+                )
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query!(
+                    r#"
+                    UPDATE event_handler_inbox
+                    SET status = 'dead', attempts = attempts + 1, last_error = $4, processed_at = now()
+                    WHERE tenant_id = $1 AND handler_name = $2 AND event_id = $3
+                    "#,
+                    row.tenant_id,
+                    row.handler_name,
+                    row.event_id,
+                    err
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            Err(err) => {
+                let delay = retry_delay_secs(row.attempts);
+                tracing::warn!(
+                    "⚠️ Handler '{}' lỗi với event {} (lần {}): {}",
+                    row.handler_name, row.event_id, row.attempts + 1, err
+                );
+                sqlx::query!(
+                    r#"
+                    UPDATE event_handler_inbox
+                    SET attempts = attempts + 1,
+                        last_error = $4,
+                        next_attempt_at = now() + make_interval(secs => $5)
+                    WHERE tenant_id = $1 AND handler_name = $2 AND event_id = $3
+                    "#,
+                    row.tenant_id,
+                    row.handler_name,
+                    row.event_id,
+                    err,
+                    delay as f64
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::loan::event::LoanEvent;
+    use chrono::Utc;
+
+    struct OnlyCreated;
+
+    #[async_trait]
+    impl EventHandler<LoanEvent> for OnlyCreated {
+        fn name(&self) -> &'static str {
+            "test.only_created"
+        }
+
+        fn event_types(&self) -> &'static [&'static str] {
+            &["LoanCreated"]
+        }
+
+        async fn handle(&self, _ctx: &EventContext<'_>, _event: LoanEvent) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn envelope(topic: &str, event_type: &str) -> EventEnvelope {
+        EventEnvelope {
+            id: Uuid::new_v4(),
+            tenant_id: Uuid::nil(),
+            topic: topic.into(),
+            event_type: event_type.into(),
+            aggregate_id: Uuid::new_v4(),
+            payload: serde_json::Value::Null,
+            occurred_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn registry_matches_topic_and_event_type() {
+        let mut registry = EventHandlerRegistry::default();
+        registry.subscribe(OnlyCreated);
+
+        assert_eq!(registry.matching(&envelope("loan", "LoanCreated")), vec!["test.only_created"]);
+        assert!(registry.matching(&envelope("loan", "LoanDeleted")).is_empty());
+        assert!(registry.matching(&envelope("invoice", "LoanCreated")).is_empty());
+    }
+}
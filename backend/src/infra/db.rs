@@ -1,38 +1,108 @@
-use sqlx::PgPool;
-use std::collections::HashMap;
-use std::sync::Arc;
+use axum::http::StatusCode;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use uuid::Uuid;
 
+/// Shard mặc định (meta DB: tenant, enterprise, company, module…)
+pub const SYSTEM_SHARD: &str = "admin-cluster";
+
 /// Quản lý ánh xạ tenant_id → shard_id → pool
-/// - Trong giai đoạn đầu: dạng đơn shard (mock)
-/// - Sau mở rộng: HashMap shard_id → pool
-#[derive(Clone)]
+/// - Catalog shard_id → DATABASE_URL đọc từ config (env `SHARD_URLS`)
+/// - shard_id của tenant tra từ bảng `tenant` (meta DB) và cache trong RAM
+/// - Tenant / shard không có trong meta DB hoặc catalog → `RoutingError`, không rơi về pool mặc định
 pub struct ShardManager {
-    pool: PgPool, // Legacy: single pool mặc định
+    pool: PgPool, // Pool mặc định = meta DB (DATABASE_URL)
 
-    // 👇 Để chuẩn bị mở rộng multi-shard
+    /// Catalog shard_id → pool
     pub pools: HashMap<String, PgPool>,
+
+    /// shard_id → URL (nhận biết 2 shard có cùng 1 DB vật lý)
+    urls: HashMap<String, String>,
+
+    /// Cache routing tenant_id → shard_id
+    tenant_shards: RwLock<HashMap<Uuid, String>>,
 }
 
 impl ShardManager {
-    /// Khởi tạo ShardManager từ 1 URL (single-shard mode)
-    pub async fn new_from_url(database_url: &str) -> Arc<Self> {
+    /// Khởi tạo từ config:
+    /// - `DATABASE_URL`: meta DB + shard mặc định
+    /// - `SHARD_URLS`: danh sách `shard_id=url`, phân tách bởi dấu phẩy
+    ///   (vd: `cluster1=postgres://...,cluster2=postgres://...`)
+    pub async fn from_env() -> Arc<Self> {
+        let db_url = env::var("DATABASE_URL").expect("⚠️ DATABASE_URL chưa được cấu hình");
+        let catalog = env::var("SHARD_URLS")
+            .map(|raw| parse_shard_catalog(&raw))
+            .unwrap_or_default();
+
+        let shard = Self::new_with_catalog(&db_url, catalog).await;
+        if let Err(e) = shard.reload_tenant_shards().await {
+            tracing::warn!("⚠️ Không nạp được bảng routing tenant → shard: {}", e);
+        }
+        shard
+    }
+
+    /// Khởi tạo với catalog shard_id → URL tường minh
+    pub async fn new_with_catalog(database_url: &str, catalog: HashMap<String, String>) -> Arc<Self> {
         let pool = PgPool::connect(database_url)
             .await
             .expect("❌ Không kết nối được DB shard");
-    
-        let pools = HashMap::from([
+
+        // Alias legacy: các shard_id cũ đều trỏ về DB mặc định
+        let mut pools = HashMap::from([
             ("default".to_string(), pool.clone()),
             ("cluster1".to_string(), pool.clone()),
-            ("admin-cluster".to_string(), pool.clone()), // 👈 THÊM DÒNG NÀY
+            (SYSTEM_SHARD.to_string(), pool.clone()),
         ]);
-    
-        Arc::new(Self { pool, pools })
+        let mut urls: HashMap<String, String> = pools
+            .keys()
+            .map(|id| (id.clone(), database_url.to_string()))
+            .collect();
+
+        for (shard_id, url) in catalog {
+            urls.insert(shard_id.clone(), url.clone());
+            if url == database_url {
+                pools.insert(shard_id, pool.clone());
+                continue;
+            }
+            // Lazy connect: shard chưa sẵn sàng không chặn khởi động server
+            match PgPoolOptions::new().connect_lazy(&url) {
+                Ok(p) => {
+                    tracing::info!("🗄️ Đăng ký shard '{}'", shard_id);
+                    pools.insert(shard_id, p);
+                }
+                Err(e) => tracing::error!("❌ URL shard '{}' không hợp lệ: {}", shard_id, e),
+            }
+        }
+
+        Arc::new(Self {
+            pool,
+            pools,
+            urls,
+            tenant_shards: RwLock::new(HashMap::new()),
+        })
     }
 
-    /// Lấy pool từ tenant_id (dùng routing logic ngoài, hiện mock đơn shard)
-    pub fn get_pool_for_tenant(&self, _tenant_id: &Uuid) -> &PgPool {
-        &self.pool
+    /// Lấy pool của tenant: ưu tiên cache routing, miss thì tra bảng `tenant` ở meta DB.
+    /// Tenant nil → pool mặc định (meta DB). Tenant không tồn tại / shard không có trong catalog → lỗi,
+    /// không bao giờ lặng lẽ rơi về pool mặc định.
+    pub async fn get_pool_for_tenant(&self, tenant_id: &Uuid) -> Result<&PgPool, RoutingError> {
+        if tenant_id.is_nil() {
+            return Ok(&self.pool);
+        }
+
+        let shard_id = self
+            .resolve_shard_id(tenant_id)
+            .await?
+            .ok_or(RoutingError::UnknownTenant(*tenant_id))?;
+
+        self.pools.get(&shard_id).ok_or_else(|| {
+            tracing::warn!("⚠️ Shard '{}' của tenant {} không có trong catalog", shard_id, tenant_id);
+            RoutingError::UnknownShard(shard_id)
+        })
     }
 
     /// Lấy pool theo shard_id (cho khởi tạo tenant)
@@ -44,4 +114,200 @@ impl ShardManager {
     pub fn get_pool_for_system(&self) -> &PgPool {
         &self.pool
     }
+
+    /// Mỗi DB vật lý 1 pool (dùng cho job nền quét toàn bộ shard)
+    pub fn distinct_pools(&self) -> Vec<&PgPool> {
+        self.distinct_shards().into_iter().map(|(_, pool)| pool).collect()
+    }
+
+    /// Như `distinct_pools` nhưng kèm shard_id đại diện của DB đó
+    pub fn distinct_shards(&self) -> Vec<(&str, &PgPool)> {
+        let mut seen = HashSet::new();
+        let mut ids: Vec<&String> = self.pools.keys().collect();
+        ids.sort();
+        ids.into_iter()
+            .filter(|id| seen.insert(self.urls.get(*id)))
+            .filter_map(|id| self.pools.get(id).map(|pool| (id.as_str(), pool)))
+            .collect()
+    }
+
+    /// Hai shard_id có trỏ về cùng 1 DB vật lý không
+    pub fn same_database(&self, a: &str, b: &str) -> bool {
+        matches!((self.urls.get(a), self.urls.get(b)), (Some(x), Some(y)) if x == y)
+    }
+
+    /// Tra shard_id của tenant: ưu tiên cache, miss thì đọc bảng `tenant` rồi cache lại
+    pub async fn resolve_shard_id(&self, tenant_id: &Uuid) -> Result<Option<String>, sqlx::Error> {
+        if let Some(shard_id) = self.cached_shard_id(tenant_id) {
+            return Ok(Some(shard_id));
+        }
+
+        let shard_id: Option<String> = sqlx::query_scalar("SELECT shard_id FROM tenant WHERE tenant_id = $1")
+            .bind(tenant_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        if let Some(ref shard_id) = shard_id {
+            self.register_tenant(*tenant_id, shard_id);
+        }
+        Ok(shard_id)
+    }
+
+    /// Nạp lại toàn bộ bảng routing tenant → shard từ meta DB
+    pub async fn reload_tenant_shards(&self) -> Result<usize, sqlx::Error> {
+        let rows: Vec<(Uuid, String)> = sqlx::query_as("SELECT tenant_id, shard_id FROM tenant")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let count = rows.len();
+        if let Ok(mut map) = self.tenant_shards.write() {
+            *map = rows.into_iter().collect();
+        }
+
+        Ok(count)
+    }
+
+    /// Đọc lại routing + trạng thái chặn ghi của 1 tenant từ meta DB (gọi mỗi request ghi / mỗi lượt job).
+    /// Fence nằm ở meta DB nên instance nào cũng thấy ngay, không phải chờ `spawn_refresh`.
+    /// Trả về tenant có đang bị chặn ghi không.
+    pub async fn refresh_tenant(&self, tenant_id: &Uuid) -> Result<bool, sqlx::Error> {
+        let row: Option<(String, bool)> = sqlx::query_as(
+            r#"
+            SELECT t.shard_id,
+                   EXISTS (
+                       SELECT 1 FROM tenant_shard_migration m
+                       WHERE m.tenant_id = t.tenant_id
+                         AND m.status IN ('fenced', 'copying', 'verifying', 'switching')
+                   )
+            FROM tenant t
+            WHERE t.tenant_id = $1
+            "#,
+        )
+        .bind(tenant_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((shard_id, fenced)) = row else {
+            self.invalidate_tenant(tenant_id);
+            return Ok(false);
+        };
+
+        self.register_tenant(*tenant_id, &shard_id);
+        Ok(fenced)
+    }
+
+    /// Job nền quét DB của `shard_id` có được xử lý dữ liệu của tenant không: tenant không bị fence
+    /// chuyển shard và DB này đúng là shard chủ (bản sao cũ còn giữ ở shard nguồn sau khi chuyển → không)
+    pub async fn owns_active_tenant(&self, tenant_id: &Uuid, shard_id: &str) -> Result<bool, sqlx::Error> {
+        if self.refresh_tenant(tenant_id).await? {
+            return Ok(false);
+        }
+        Ok(matches!(self.resolve_shard_id(tenant_id).await?, Some(owner) if self.same_database(&owner, shard_id)))
+    }
+
+    /// Chạy nền: định kỳ nạp lại routing (đồng bộ khi nhiều instance cùng chạy)
+    pub fn spawn_refresh(self: &Arc<Self>, every: Duration) {
+        let shard = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            ticker.tick().await; // bỏ tick đầu (đã nạp lúc khởi động)
+            loop {
+                ticker.tick().await;
+                if let Err(e) = shard.reload_tenant_shards().await {
+                    tracing::warn!("⚠️ Refresh routing tenant → shard lỗi: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Ghi nhận tenant thuộc shard nào (gọi sau khi tạo tenant)
+    pub fn register_tenant(&self, tenant_id: Uuid, shard_id: &str) {
+        if let Ok(mut map) = self.tenant_shards.write() {
+            map.insert(tenant_id, shard_id.to_string());
+        }
+    }
+
+    /// Xoá cache routing của tenant (gọi sau khi đổi tenant.shard_id)
+    pub fn invalidate_tenant(&self, tenant_id: &Uuid) {
+        if let Ok(mut map) = self.tenant_shards.write() {
+            map.remove(tenant_id);
+        }
+    }
+
+    fn cached_shard_id(&self, tenant_id: &Uuid) -> Option<String> {
+        self.tenant_shards.read().ok()?.get(tenant_id).cloned()
+    }
+}
+
+/// Lỗi định tuyến tenant → shard
+#[derive(Debug)]
+pub enum RoutingError {
+    /// Tenant không có trong meta DB
+    UnknownTenant(Uuid),
+    /// shard_id của tenant không có trong catalog `SHARD_URLS`
+    UnknownShard(String),
+    Db(sqlx::Error),
+}
+
+impl fmt::Display for RoutingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoutingError::UnknownTenant(id) => write!(f, "Không tìm thấy tenant {}", id),
+            RoutingError::UnknownShard(id) => write!(f, "Không tìm thấy shard '{}'", id),
+            RoutingError::Db(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RoutingError {}
+
+impl RoutingError {
+    /// HTTP status cho các handler trả lỗi dạng `StatusCode`
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            RoutingError::UnknownTenant(_) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<sqlx::Error> for RoutingError {
+    fn from(e: sqlx::Error) -> Self {
+        RoutingError::Db(e)
+    }
+}
+
+/// Cho các hàm truy vấn trả `sqlx::Error` (tenant không tồn tại ≈ không có dòng)
+impl From<RoutingError> for sqlx::Error {
+    fn from(e: RoutingError) -> Self {
+        match e {
+            RoutingError::UnknownTenant(_) => sqlx::Error::RowNotFound,
+            RoutingError::UnknownShard(_) => sqlx::Error::Configuration(e.to_string().into()),
+            RoutingError::Db(e) => e,
+        }
+    }
+}
+
+/// Parse `shard_id=url,shard_id=url` → HashMap
+fn parse_shard_catalog(raw: &str) -> HashMap<String, String> {
+    raw.split(',')
+        .filter_map(|entry| {
+            let (id, url) = entry.split_once('=')?;
+            let (id, url) = (id.trim(), url.trim());
+            (!id.is_empty() && !url.is_empty()).then(|| (id.to_string(), url.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_shard_catalog;
+
+    #[test]
+    fn parse_catalog_keeps_url_query() {
+        let map = parse_shard_catalog("cluster2=postgres://u:p@h2/db?sslmode=disable, ,bad,cluster3 = postgres://h3/db");
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["cluster2"], "postgres://u:p@h2/db?sslmode=disable");
+        assert_eq!(map["cluster3"], "postgres://h3/db");
+    }
 }
@@ -1,6 +1,205 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Message chuẩn gửi qua event bus (tương thích Kafka/NATS)
+/// - Kafka: topic = `topic`, key = `aggregate_id` (giữ thứ tự theo aggregate)
+/// - NATS: subject = `topic.event_type`
+/// - `id` dùng để consumer khử trùng lặp (at-least-once)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub topic: String,
+    pub event_type: String,
+    pub aggregate_id: Uuid,
+    pub payload: serde_json::Value,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl EventEnvelope {
+    /// Partition key (Kafka)
+    pub fn key(&self) -> String {
+        self.aggregate_id.to_string()
+    }
+
+    /// Subject (NATS)
+    pub fn subject(&self) -> String {
+        format!("{}.{}", self.topic, self.event_type)
+    }
+}
+
 /// Interface cho các hệ thống Event Bus (Kafka, NATS,...)
 /// Mỗi implementation cụ thể sẽ cài `publish` khác nhau.
+/// Trả lỗi để outbox relay biết mà retry.
+#[async_trait]
 pub trait EventPublisher: Send + Sync {
     /// Gửi message tới topic tương ứng
-    fn publish(&self, topic: &str, payload: &[u8]);
+    async fn publish(&self, event: &EventEnvelope) -> Result<(), String>;
+}
+
+/// Chọn backend theo config `EVENT_BUS` = log | file | memory (mặc định: log)
+pub fn publisher_from_env() -> Arc<dyn EventPublisher + Send + Sync> {
+    match env::var("EVENT_BUS").unwrap_or_default().as_str() {
+        "file" => {
+            let path = env::var("EVENT_BUS_FILE").unwrap_or_else(|_| "logs/events.jsonl".to_string());
+            tracing::info!("📡 Event bus: file ({})", path);
+            Arc::new(FilePublisher::new(path))
+        }
+        "memory" => {
+            tracing::info!("📡 Event bus: in-process");
+            Arc::new(InMemoryPublisher::new())
+        }
+        _ => {
+            tracing::info!("📡 Event bus: log");
+            Arc::new(LogPublisher)
+        }
+    }
+}
+
+/// Backend chỉ ghi log (dev)
+pub struct LogPublisher;
+
+#[async_trait]
+impl EventPublisher for LogPublisher {
+    async fn publish(&self, event: &EventEnvelope) -> Result<(), String> {
+        tracing::info!("🌀 [EVENT] {} key={} id={} {}", event.subject(), event.key(), event.id, event.payload);
+        Ok(())
+    }
+}
+
+/// Backend ghi JSON lines ra file (mỗi dòng 1 event)
+pub struct FilePublisher {
+    path: String,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl FilePublisher {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for FilePublisher {
+    async fn publish(&self, event: &EventEnvelope) -> Result<(), String> {
+        let mut line = serde_json::to_vec(event).map_err(|e| e.to_string())?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| format!("Không mở được {}: {}", self.path, e))?;
+        file.write_all(&line).await.map_err(|e| e.to_string())?;
+        file.flush().await.map_err(|e| e.to_string())
+    }
+}
+
+/// Gửi cùng 1 event tới nhiều backend (vd: Kafka + handler nội bộ).
+/// Backend nào lỗi thì trả lỗi để relay gửi lại (backend phía sau phải idempotent theo event id).
+pub struct FanoutPublisher {
+    targets: Vec<Arc<dyn EventPublisher + Send + Sync>>,
+}
+
+impl FanoutPublisher {
+    pub fn new(targets: Vec<Arc<dyn EventPublisher + Send + Sync>>) -> Self {
+        Self { targets }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for FanoutPublisher {
+    async fn publish(&self, event: &EventEnvelope) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for target in &self.targets {
+            if let Err(e) = target.publish(event).await {
+                errors.push(e);
+            }
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors.join("; ")) }
+    }
+}
+
+/// Backend in-process: giữ lại event đã publish + phát cho subscriber (dùng cho test)
+pub struct InMemoryPublisher {
+    events: Mutex<Vec<EventEnvelope>>,
+    sender: broadcast::Sender<EventEnvelope>,
+}
+
+impl InMemoryPublisher {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(1024);
+        Self {
+            events: Mutex::new(Vec::new()),
+            sender,
+        }
+    }
+
+    /// Nhận event mới publish từ thời điểm subscribe
+    #[cfg(test)]
+    pub fn subscribe(&self) -> broadcast::Receiver<EventEnvelope> {
+        self.sender.subscribe()
+    }
+
+    /// Toàn bộ event đã publish
+    #[cfg(test)]
+    pub fn events(&self) -> Vec<EventEnvelope> {
+        self.events.lock().map(|e| e.clone()).unwrap_or_default()
+    }
+}
+
+impl Default for InMemoryPublisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl EventPublisher for InMemoryPublisher {
+    async fn publish(&self, event: &EventEnvelope) -> Result<(), String> {
+        self.events
+            .lock()
+            .map_err(|e| e.to_string())?
+            .push(event.clone());
+        // Không có subscriber cũng không phải lỗi
+        let _ = self.sender.send(event.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn in_memory_publisher_keeps_and_broadcasts_events() {
+        let bus = InMemoryPublisher::new();
+        let mut rx = bus.subscribe();
+        let event = EventEnvelope {
+            id: Uuid::new_v4(),
+            tenant_id: Uuid::nil(),
+            topic: "loan".into(),
+            event_type: "LoanCreated".into(),
+            aggregate_id: Uuid::new_v4(),
+            payload: serde_json::json!({ "contract_number": "LOAN-1" }),
+            occurred_at: Utc::now(),
+        };
+
+        bus.publish(&event).await.unwrap();
+
+        assert_eq!(bus.events().len(), 1);
+        assert_eq!(rx.recv().await.unwrap().id, event.id);
+        assert_eq!(event.subject(), "loan.LoanCreated");
+    }
 }
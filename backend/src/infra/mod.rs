@@ -1,4 +1,4 @@
 pub mod db;
 pub mod event_bus;
-pub mod telemetry;
+pub mod outbox;
 pub mod wasm_loader;
\ No newline at end of file
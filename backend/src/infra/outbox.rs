@@ -0,0 +1,223 @@
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::infra::db::ShardManager;
+use crate::infra::event_bus::{EventEnvelope, EventPublisher};
+
+/// Domain event có thể ghi vào outbox
+pub trait DomainEvent: Serialize {
+    /// Topic (Kafka topic / tiền tố NATS subject), vd: "loan"
+    const TOPIC: &'static str;
+    /// Tên event, vd: "LoanCreated"
+    fn event_type(&self) -> &'static str;
+    /// Aggregate phát sinh event (partition key)
+    fn aggregate_id(&self) -> Uuid;
+}
+
+/// Event đã serialize, chờ ghi vào outbox (vd: event do command bus gom lại)
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    pub topic: &'static str,
+    pub event_type: &'static str,
+    pub aggregate_id: Uuid,
+    pub payload: serde_json::Value,
+}
+
+impl OutboxEvent {
+    pub fn from_domain<E: DomainEvent>(event: &E) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            topic: E::TOPIC,
+            event_type: event.event_type(),
+            aggregate_id: event.aggregate_id(),
+            payload: serde_json::to_value(event)?,
+        })
+    }
+}
+
+/// Ghi event vào outbox trong transaction của command.
+/// Event chỉ được relay gửi đi khi transaction commit thành công.
+pub async fn enqueue<E: DomainEvent>(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    event: &E,
+) -> Result<Uuid, sqlx::Error> {
+    let event = OutboxEvent::from_domain(event).map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
+    enqueue_event(conn, tenant_id, &event).await
+}
+
+/// Như `enqueue` nhưng nhận event đã serialize
+pub async fn enqueue_event(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    event: &OutboxEvent,
+) -> Result<Uuid, sqlx::Error> {
+    let id = Uuid::new_v4();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO event_outbox (tenant_id, id, topic, event_type, aggregate_id, payload)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        tenant_id,
+        id,
+        event.topic,
+        event.event_type,
+        event.aggregate_id,
+        event.payload,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(id)
+}
+
+/// Relay nền: đọc outbox ở mọi shard và gửi qua EventPublisher
+pub struct OutboxRelay {
+    shard: Arc<ShardManager>,
+    publisher: Arc<dyn EventPublisher + Send + Sync>,
+    batch_size: i64,
+}
+
+impl OutboxRelay {
+    pub fn new(shard: Arc<ShardManager>, publisher: Arc<dyn EventPublisher + Send + Sync>) -> Self {
+        Self { shard, publisher, batch_size: 100 }
+    }
+
+    /// Chạy vòng lặp relay theo chu kỳ `every`
+    pub fn spawn(self, every: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                // Còn việc thì xử lý tiếp ngay, không chờ tick sau
+                // (mỗi lượt chỉ gửi event sớm nhất chưa publish của từng aggregate)
+                while self.relay_once().await > 0 {}
+            }
+        });
+    }
+
+    /// Relay 1 lượt trên tất cả shard, trả về số event đã gửi thành công
+    pub async fn relay_once(&self) -> usize {
+        let mut sent = 0;
+        for (shard_id, pool) in self.shard.distinct_shards() {
+            match self.relay_pool(shard_id, pool).await {
+                Ok(n) => sent += n,
+                Err(e) => tracing::warn!("⚠️ Outbox relay lỗi: {}", e),
+            }
+        }
+        sent
+    }
+
+    async fn relay_pool(&self, shard_id: &str, pool: &PgPool) -> Result<usize, sqlx::Error> {
+        // Chỉ gửi event của tenant mà DB này đang là shard chủ: tenant đang chuyển shard chờ tới khi xong,
+        // outbox cũ còn giữ ở shard nguồn sau khi chuyển không bị gửi lần 2 (bản copy ở shard đích sẽ gửi)
+        let pending = sqlx::query_scalar!("SELECT DISTINCT tenant_id FROM event_outbox WHERE published_at IS NULL")
+            .fetch_all(pool)
+            .await?;
+        let mut tenants = Vec::with_capacity(pending.len());
+        for tenant_id in pending {
+            if self.shard.owns_active_tenant(&tenant_id, shard_id).await.unwrap_or(false) {
+                tenants.push(tenant_id);
+            }
+        }
+        if tenants.is_empty() {
+            return Ok(0);
+        }
+
+        let mut tx = pool.begin().await?;
+
+        // SKIP LOCKED: nhiều instance cùng chạy relay không gửi trùng batch.
+        // Aggregate còn event trước chưa publish (đang chờ retry / instance khác đang giữ) → chưa gửi
+        let rows = sqlx::query!(
+            r#"
+            SELECT o.tenant_id, o.id, o.topic, o.event_type, o.aggregate_id, o.payload, o.created_at, o.attempts
+            FROM event_outbox o
+            WHERE o.published_at IS NULL AND o.next_attempt_at <= now()
+              AND o.tenant_id = ANY($2)
+              AND NOT EXISTS (
+                  SELECT 1 FROM event_outbox e
+                  WHERE e.tenant_id = o.tenant_id
+                    AND e.aggregate_id = o.aggregate_id
+                    AND e.published_at IS NULL
+                    AND e.seq < o.seq
+              )
+            ORDER BY o.seq
+            LIMIT $1
+            FOR UPDATE OF o SKIP LOCKED
+            "#,
+            self.batch_size,
+            &tenants
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut sent = 0;
+        for row in rows {
+            let event = EventEnvelope {
+                id: row.id,
+                tenant_id: row.tenant_id,
+                topic: row.topic,
+                event_type: row.event_type,
+                aggregate_id: row.aggregate_id,
+                payload: row.payload,
+                occurred_at: row.created_at,
+            };
+
+            match self.publisher.publish(&event).await {
+                Ok(()) => {
+                    sqlx::query!(
+                        "UPDATE event_outbox SET published_at = now(), last_error = NULL WHERE tenant_id = $1 AND id = $2",
+                        event.tenant_id,
+                        event.id
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                    sent += 1;
+                }
+                Err(err) => {
+                    let delay = retry_delay_secs(row.attempts);
+                    tracing::warn!("⚠️ Publish event {} lỗi (lần {}): {}", event.id, row.attempts + 1, err);
+                    sqlx::query!(
+                        r#"
+                        UPDATE event_outbox
+                        SET attempts = attempts + 1,
+                            last_error = $3,
+                            next_attempt_at = now() + make_interval(secs => $4)
+                        WHERE tenant_id = $1 AND id = $2
+                        "#,
+                        event.tenant_id,
+                        event.id,
+                        err,
+                        delay as f64
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+
+        tx.commit().await?;
+        Ok(sent)
+    }
+}
+
+/// Backoff luỹ thừa: 1s, 2s, 4s ... tối đa 5 phút
+pub(crate) fn retry_delay_secs(attempts: i32) -> i64 {
+    let exp = attempts.clamp(0, 16) as u32;
+    (1_i64 << exp).min(300)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::retry_delay_secs;
+
+    #[test]
+    fn retry_delay_is_capped() {
+        assert_eq!(retry_delay_secs(0), 1);
+        assert_eq!(retry_delay_secs(3), 8);
+        assert_eq!(retry_delay_secs(20), 300);
+    }
+}
@@ -5,7 +5,10 @@ use tower_http::cors::{CorsLayer, Any};
 
 use api::router::build_router;
 use core::state::AppState;
-use infra::{db::ShardManager, telemetry::Telemetry, event_bus::EventPublisher, wasm_loader::ModuleRegistry};
+use infra::{db::ShardManager, event_bus::{self, FanoutPublisher}, outbox::OutboxRelay, wasm_loader::ModuleRegistry};
+use event_handler::{EventDispatcher, HandlerWorker};
+use command_bus::CommandBus;
+use crate::core::iam::PermissionCache;
 // log file
 use tracing_appender::rolling;
 use tracing_appender::non_blocking;
@@ -22,14 +25,6 @@ mod command_bus;
 mod query_bus;
 mod event_handler;
 
-/// Dummy event bus để demo (sẽ thay bằng Kafka/NATS sau)
-struct DummyBus;
-impl EventPublisher for DummyBus {
-    fn publish(&self, topic: &str, payload: &[u8]) {
-        println!("🌀 [EVENT] {topic}: {:?}", payload);
-    }
-}
-
 #[tokio::main]
 async fn main() {
     dotenv().ok();
@@ -48,15 +43,35 @@ async fn main() {
         .with(EnvFilter::from_default_env())
         .init();
 
+    // 🔑 Khoá ký JWT (JWT_ALG / JWT_SECRET / JWT_PRIVATE_KEY ...) – sai cấu hình thì dừng sớm
+    if let Err(e) = core::jwt::init() {
+        panic!("❌ Cấu hình JWT không hợp lệ: {}", e);
+    }
 
-    // 🧪 Đọc DATABASE_URL và khởi tạo ShardManager (hiện chỉ có 1 shard duy nhất)
-    let db_url = env::var("DATABASE_URL").expect("⚠️ DATABASE_URL chưa được cấu hình");
-    let shard = ShardManager::new_from_url(&db_url)
-        .await;
+    // 🧪 Khởi tạo ShardManager: DATABASE_URL (meta DB) + catalog SHARD_URLS
+    let shard = ShardManager::from_env().await;
+    let refresh_secs = env::var("SHARD_ROUTING_REFRESH_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(60);
+    shard.spawn_refresh(std::time::Duration::from_secs(refresh_secs));
 
     // 📦 Các thành phần hệ thống phụ trợ
-    let telemetry = Telemetry::new();
-    let event_publisher = Arc::new(DummyBus);
+    let event_publisher = event_bus::publisher_from_env();
+
+    // 📤 Outbox relay: gửi domain event đã commit ra event bus
+    let relay_ms = env::var("OUTBOX_RELAY_INTERVAL_MS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(1000);
+    // Relay gửi song song: event bus ngoài + inbox của event handler nội bộ
+    let handler_registry = Arc::new(event_handler::build_registry());
+    let relay_publisher = Arc::new(FanoutPublisher::new(vec![
+        event_publisher,
+        Arc::new(EventDispatcher::new(shard.clone(), handler_registry.clone())),
+    ]));
+    OutboxRelay::new(shard.clone(), relay_publisher)
+        .spawn(std::time::Duration::from_millis(relay_ms));
 
     // 🎯 Module Registry - Load WASM modules ngoài binary
     let module_registry = ModuleRegistry::new();
@@ -82,7 +97,33 @@ async fn main() {
     let module_registry = Arc::new(module_registry);
 
     // 🧠 AppState
-    let app_state = AppState::new(shard.clone(), telemetry, event_publisher, module_registry);
+    let permissions = Arc::new(PermissionCache::from_env());
+    let command_bus = Arc::new(CommandBus::standard(permissions.clone()));
+    let app_state = AppState::new(shard.clone(), module_registry, command_bus, permissions);
+
+    // 🔁 Worker chạy event handler (retry + dead-letter)
+    let handler_ms = env::var("EVENT_HANDLER_INTERVAL_MS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(1000);
+    HandlerWorker::new(app_state.clone(), handler_registry)
+        .spawn(std::time::Duration::from_millis(handler_ms));
+
+    // 📉 Giám sát LTV hợp đồng vay theo ngưỡng của tenant
+    let ltv_secs = env::var("LTV_MONITOR_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(3600);
+    module::loan::job::LtvMonitor::new(shard.clone())
+        .spawn(std::time::Duration::from_secs(ltv_secs));
+
+    // 📸 Snapshot loan_report cuối ngày nghiệp vụ + chạy bù
+    let snapshot_secs = env::var("LOAN_REPORT_SNAPSHOT_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(300);
+    module::loan::job::ReportSnapshotJob::new(shard.clone())
+        .spawn(std::time::Duration::from_secs(snapshot_secs));
 
     // 🌐 CORS middleware để frontend gọi được
     let cors = CorsLayer::new()
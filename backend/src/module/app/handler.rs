@@ -14,7 +14,7 @@ pub async fn get_modules_status(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
 ) -> Result<Json<Vec<ModuleStatusDto>>, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
     let rows = sqlx::query!(
         r#"
@@ -72,7 +72,7 @@ pub async fn install_module(
     auth: AuthUser,
     Path(module_name): Path<String>,
 ) -> Result<(), AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
     sqlx::query!(
         r#"
@@ -96,7 +96,7 @@ pub async fn uninstall_module(
     auth: AuthUser,
     Path(module_name): Path<String>,
 ) -> Result<(), AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
     sqlx::query!(
         "DELETE FROM tenant_module
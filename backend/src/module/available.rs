@@ -18,7 +18,7 @@ pub async fn get_available_modules(
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<Vec<AvailableModule>>, (axum::http::StatusCode, String)> {
     // 👉 Lấy pool từ ShardManager (dùng nil() nếu là bảng toàn cục)
-    let pool = state.shard.get_pool_for_tenant(&Uuid::nil());
+    let pool = state.shard.get_pool_for_system();
 
     let rows = sqlx::query_as!(
         AvailableModule,
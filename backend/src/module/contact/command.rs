@@ -1,5 +1,12 @@
+use async_trait::async_trait;
 use uuid::Uuid;
-use sqlx::{Pool, Postgres};
+use sqlx::PgConnection;
+
+use crate::command_bus::{Command, CommandContext};
+use crate::core::error::AppError;
+use crate::core::scope::Scope;
+use super::event::ContactEvent;
+use super::query;
 
 #[derive(Debug)]
 pub struct CreateContactDto {
@@ -83,12 +90,10 @@ fn build_tags_cached(tags: &Option<Vec<String>>) -> Option<String> {
 /* ========== commands ========== */
 
 pub async fn create_contact(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     dto: CreateContactDto,
 ) -> Result<Uuid, sqlx::Error> {
-    let mut tx = pool.begin().await?;
-
     let name = build_fallback_name(&dto.name, &dto.display_name, &dto.email, &dto.phone);
     let display_name = norm_str(&dto.display_name).unwrap_or_else(|| name.clone());
     let tags_cached = build_tags_cached(&dto.tags);
@@ -127,23 +132,20 @@ pub async fn create_contact(
         dto.assignee_id,                                       // $20
         &dto.shared_with,                                      // $21  (uuid[])
     )
-    .execute(&mut *tx)
+    .execute(&mut *conn)
     .await?;
 
     // TODO: upsert bảng tag + link nếu có
 
-    tx.commit().await?;
     Ok(id)
 }
 
 pub async fn update_contact(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     id: Uuid,
     dto: UpdateContactDto,
 ) -> Result<(), sqlx::Error> {
-    let mut tx = pool.begin().await?;
-
     // Lấy dữ liệu hiện tại để fallback hợp lệ
     let current = sqlx::query!(
         r#"
@@ -154,7 +156,7 @@ pub async fn update_contact(
         tenant_id,
         id
     )
-    .fetch_one(&mut *tx)
+    .fetch_one(&mut *conn)
     .await?;
 
     // current.name (non-null trong schema) -> wrap Some(...)
@@ -207,29 +209,103 @@ pub async fn update_contact(
         norm_str(&dto.notes),                  // $17
         tags_cached                            // $18
     )
-    .execute(&mut *tx)
+    .execute(&mut *conn)
     .await?;
 
     // TODO (tuỳ schema): cập nhật bảng tag + link
 
-    tx.commit().await?;
     Ok(())
 }
 
+/// Xoá contact, trả về true nếu có dòng bị xoá
 pub async fn delete_contact(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     id: Uuid,
-) -> Result<(), sqlx::Error> {
+) -> Result<bool, sqlx::Error> {
     // Nếu có bảng link tag thì xoá trước (tuỳ schema)
     // sqlx::query!("DELETE FROM contact_tag_link WHERE tenant_id=$1 AND contact_id=$2", tenant_id, id)
-    //    .execute(pool).await?;
+    //    .execute(&mut *conn).await?;
 
-    sqlx::query!(
+    let deleted = sqlx::query!(
         "DELETE FROM contact WHERE tenant_id = $1 AND id = $2",
         tenant_id, id
     )
-    .execute(pool)
+    .execute(conn)
     .await?;
-    Ok(())
+
+    Ok(deleted.rows_affected() > 0)
+}
+
+/* ========== command bus ========== */
+
+// Lỗi SQL trả 400 kèm message (giữ hành vi cũ của handler)
+fn to_app_error(e: sqlx::Error) -> AppError {
+    AppError::bad_request(e.to_string())
+}
+
+pub struct CreateContact {
+    pub dto: CreateContactDto,
+}
+
+#[async_trait]
+impl Command for CreateContact {
+    type Output = Uuid;
+    const NAME: &'static str = "contact.create";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("contact", "create"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Uuid, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let id = create_contact(ctx.conn(), tenant_id, self.dto).await.map_err(to_app_error)?;
+        ctx.emit(ContactEvent::ContactCreated { contact_id: id })?;
+        Ok(id)
+    }
+}
+
+pub struct UpdateContact {
+    pub id: Uuid,
+    pub dto: UpdateContactDto,
+    /// Scope ABAC của quyền `contact.update`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for UpdateContact {
+    type Output = ();
+    const NAME: &'static str = "contact.update";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("contact", "update"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, self.id, "error.contact.not_found").await?;
+        update_contact(ctx.conn(), tenant_id, self.id, self.dto)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => AppError::not_found_i18n(&ctx.i18n, "error.contact.not_found"),
+                e => to_app_error(e),
+            })?;
+        ctx.emit(ContactEvent::ContactUpdated { contact_id: self.id })
+    }
+}
+
+pub struct DeleteContact {
+    pub id: Uuid,
+    /// Scope ABAC của quyền `contact.delete`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for DeleteContact {
+    type Output = ();
+    const NAME: &'static str = "contact.delete";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("contact", "delete"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, self.id, "error.contact.not_found").await?;
+        if delete_contact(ctx.conn(), tenant_id, self.id).await.map_err(to_app_error)? {
+            ctx.emit(ContactEvent::ContactDeleted { contact_id: self.id })?;
+        }
+        Ok(())
+    }
 }
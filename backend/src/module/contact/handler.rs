@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Path, Query, State},
+    extract::{Extension, Path, Query, State},
     http::{HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
@@ -8,7 +8,8 @@ use serde_json::json;
 use uuid::Uuid;
 use std::sync::Arc;
 
-use crate::core::{auth::AuthUser, state::AppState, error::AppError, i18n::I18n};
+use crate::core::{auth::AuthUser, scope::Scope, state::AppState, error::AppError, i18n::I18n};
+use crate::command_bus;
 
 use super::{
     command,
@@ -57,9 +58,10 @@ pub async fn get_metadata(headers: HeaderMap) -> Result<impl IntoResponse, AppEr
 pub async fn create_contact(
     State(state): State<Arc<AppState>>,
     auth: AuthUser, // dùng FromRequestParts đã có
+    headers: HeaderMap,
     Json(input): Json<CreateContactInput>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
     // ✅ Normalize ở BE để tránh 500 do CHECK
     let dto = command::CreateContactDto {
@@ -86,10 +88,7 @@ pub async fn create_contact(
         shared_with: input.shared_with.unwrap_or_default(),
     };
 
-    let id = command::create_contact(pool, auth.tenant_id, dto)
-        .await
-        // create_contact không chắc trả sqlx::Error → không dùng AppError::from
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    let id = command_bus::dispatch(&state, &auth, &i18n, command::CreateContact { dto }).await?;
 
     Ok(Json(json!({ "id": id })))
 }
@@ -100,10 +99,12 @@ pub async fn create_contact(
 pub async fn update_contact(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(input): Json<UpdateContactInput>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
     let dto = command::UpdateContactDto {
         is_company: input.is_company,
@@ -124,9 +125,7 @@ pub async fn update_contact(
         tags:         input.tags,
     };
 
-    command::update_contact(pool, auth.tenant_id, id, dto)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    command_bus::dispatch(&state, &auth, &i18n, command::UpdateContact { id, dto, scope }).await?;
 
     Ok(Json(json!({ "id": id, "ok": true })))
 }
@@ -137,13 +136,13 @@ pub async fn update_contact(
 pub async fn delete_contact(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
 ) -> Result<StatusCode, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
-    command::delete_contact(pool, auth.tenant_id, id)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    command_bus::dispatch(&state, &auth, &i18n, command::DeleteContact { id, scope }).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -154,14 +153,20 @@ pub async fn delete_contact(
 pub async fn get_contact_by_id(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+    let i18n = I18n::from_headers(&headers);
 
-    let c = query::get_contact_by_id(pool, auth.tenant_id, id)
+    // không tồn tại hoặc ngoài scope → 404
+    let c = query::get_visible_contact(pool, &auth, &scope, id)
         .await
-        // hàm query thường trả sqlx::Error → dùng From
-        .map_err(AppError::from)?;
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::not_found_i18n(&i18n, "error.contact.not_found"),
+            e => AppError::from(e),
+        })?;
 
     Ok(Json(serde_json::to_value(c).unwrap()))
 }
@@ -172,13 +177,15 @@ pub async fn get_contact_by_id(
 pub async fn list_contacts(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Query(f): Query<DtoListFilter>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
     let items = query::list_contacts(
         pool,
-        auth.tenant_id,
+        &auth,
+        &scope,
         query::ListFilter {
             q:         f.q,
             is_company: f.is_company,
@@ -7,10 +7,36 @@ pub mod dto;
 pub mod metadata;
 
 pub mod event {
-    #[derive(Debug, Clone, Copy)]
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    use crate::infra::outbox::DomainEvent;
+
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+    #[serde(tag = "type")]
     pub enum ContactEvent {
-        ContactCreated,
-        ContactUpdated,
-        ContactDeleted,
+        ContactCreated { contact_id: Uuid },
+        ContactUpdated { contact_id: Uuid },
+        ContactDeleted { contact_id: Uuid },
+    }
+
+    impl DomainEvent for ContactEvent {
+        const TOPIC: &'static str = "contact";
+
+        fn event_type(&self) -> &'static str {
+            match self {
+                ContactEvent::ContactCreated { .. } => "ContactCreated",
+                ContactEvent::ContactUpdated { .. } => "ContactUpdated",
+                ContactEvent::ContactDeleted { .. } => "ContactDeleted",
+            }
+        }
+
+        fn aggregate_id(&self) -> Uuid {
+            match *self {
+                ContactEvent::ContactCreated { contact_id }
+                | ContactEvent::ContactUpdated { contact_id }
+                | ContactEvent::ContactDeleted { contact_id } => contact_id,
+            }
+        }
     }
 }
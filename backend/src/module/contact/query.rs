@@ -1,9 +1,12 @@
-use sqlx::{Pool, Postgres};
+use sqlx::{PgExecutor, Pool, Postgres, QueryBuilder};
 use serde::Serialize;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, Serialize)]
+use crate::core::auth::AuthUser;
+use crate::core::scope::{Scope, ScopeTarget, OWNERSHIP_COLUMNS};
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
 pub struct ContactListItem {
     pub id: Uuid,
     pub name: String,                 // luôn có giá trị hiển thị
@@ -26,86 +29,59 @@ pub struct ListFilter {
     pub offset: Option<i64>,
 }
 
+/// Bảng + cột được dùng trong scope ABAC của resource `contact`
+pub const SCOPE: ScopeTarget = ScopeTarget { table: "contact", columns: OWNERSHIP_COLUMNS };
+
+/// Danh sách liên hệ user được thấy theo scope của quyền `contact.read`
 pub async fn list_contacts(
     pool: &Pool<Postgres>,
-    tenant_id: Uuid,
+    auth: &AuthUser,
+    scope: &Scope,
     f: ListFilter,
 ) -> Result<Vec<ContactListItem>, sqlx::Error> {
     let limit = f.limit.unwrap_or(200).clamp(1, 500);
     let offset = f.offset.unwrap_or(0).max(0);
 
     let q = f.q.unwrap_or_default();
-    let has_q = !q.trim().is_empty();
 
-    if has_q {
+    let mut qb = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT
+            id,
+            COALESCE(NULLIF(name,''), display_name, email, phone, '(không tên)') AS name,
+            display_name,
+            email,
+            phone,
+            is_company,
+            NULLIF(tags_cached,'') AS tags,
+            state,
+            created_at,
+            updated_at,
+            national_id
+        FROM contact
+        WHERE tenant_id = "#,
+    );
+    qb.push_bind(auth.tenant_id);
+
+    if let Some(is_company) = f.is_company {
+        qb.push(" AND is_company = ").push_bind(is_company);
+    }
+
+    if !q.trim().is_empty() {
         let like = format!("%{}%", q.trim().to_lowercase());
-        let rows = sqlx::query_as!(
-            ContactListItem,
-            r#"
-            SELECT
-                id,
-                COALESCE(NULLIF(name,''), display_name, email, phone, '(không tên)') AS "name!: String",
-                display_name,
-                email,
-                phone,
-                is_company,
-                NULLIF(tags_cached,'') AS "tags?: String",
-                state,
-                created_at,
-                updated_at,
-                national_id AS "national_id?: String"     -- 👈 THÊM
-            FROM contact
-            WHERE tenant_id = $1
-              AND ($2::bool IS NULL OR is_company = $2)
-              AND (
-                    lower(coalesce(name, ''))         LIKE $3
-                 OR lower(coalesce(display_name, '')) LIKE $3
-                 OR lower(coalesce(email, ''))        LIKE $3
-                 OR lower(coalesce(phone, ''))        LIKE $3
-              )
-            ORDER BY created_at DESC
-            LIMIT $4 OFFSET $5
-            "#,
-            tenant_id,
-            f.is_company,
-            like,
-            limit,
-            offset
-        )
-        .fetch_all(pool)
-        .await?;
-        Ok(rows)
-    } else {
-        let rows = sqlx::query_as!(
-            ContactListItem,
-            r#"
-            SELECT
-                id,
-                COALESCE(NULLIF(name,''), display_name, email, phone, '(không tên)') AS "name!: String",
-                display_name,
-                email,
-                phone,
-                is_company,
-                NULLIF(tags_cached,'') AS "tags?: String",
-                state,
-                created_at,
-                updated_at,
-                national_id AS "national_id?: String"     -- 👈 THÊM
-            FROM contact
-            WHERE tenant_id = $1
-              AND ($2::bool IS NULL OR is_company = $2)
-            ORDER BY created_at DESC
-            LIMIT $3 OFFSET $4
-            "#,
-            tenant_id,
-            f.is_company,
-            limit,
-            offset
-        )
-        .fetch_all(pool)
-        .await?;
-        Ok(rows)
+        qb.push(" AND (lower(coalesce(name, '')) LIKE ").push_bind(like.clone());
+        qb.push(" OR lower(coalesce(display_name, '')) LIKE ").push_bind(like.clone());
+        qb.push(" OR lower(coalesce(email, '')) LIKE ").push_bind(like.clone());
+        qb.push(" OR lower(coalesce(phone, '')) LIKE ").push_bind(like);
+        qb.push(")");
     }
+
+    scope.push_filter(&mut qb, &SCOPE, "", auth);
+
+    qb.push(" ORDER BY created_at DESC LIMIT ").push_bind(limit);
+    qb.push(" OFFSET ").push_bind(offset);
+
+    qb.build_query_as::<ContactListItem>().fetch_all(pool).await
 }
 
 #[derive(Debug, Serialize)]
@@ -131,8 +107,21 @@ pub struct ContactDetail {
     pub updated_at: Option<DateTime<Utc>>,
 }
 
-pub async fn get_contact_by_id(
+/// Chi tiết liên hệ nếu nằm trong scope, ngoài scope → RowNotFound
+pub async fn get_visible_contact(
     pool: &Pool<Postgres>,
+    auth: &AuthUser,
+    scope: &Scope,
+    id: Uuid,
+) -> Result<ContactDetail, sqlx::Error> {
+    if !scope.is_visible(pool, &SCOPE, auth, id).await? {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    get_contact_by_id(pool, auth.tenant_id, id).await
+}
+
+pub async fn get_contact_by_id<'e>(
+    executor: impl PgExecutor<'e>,
     tenant_id: Uuid,
     id: Uuid,
 ) -> Result<ContactDetail, sqlx::Error> {
@@ -165,7 +154,7 @@ pub async fn get_contact_by_id(
         tenant_id,
         id
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
 
     Ok(row)
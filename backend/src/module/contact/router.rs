@@ -3,6 +3,7 @@ use axum::routing::delete;
 use std::sync::Arc;
 
 use crate::core::{state::AppState, auth::jwt_auth};
+use crate::core::iam::RequirePermission;
 use super::handler;
 
 pub fn routes() -> Router<Arc<AppState>> {
@@ -11,11 +12,11 @@ pub fn routes() -> Router<Arc<AppState>> {
         .nest(
             "/contact",
             Router::new()
-                .route("/create", post(handler::create_contact))
-                .route("/list", get(handler::list_contacts))
-                .route("/:id", get(handler::get_contact_by_id))
-                .route("/:id/update", post(handler::update_contact))
-                .route("/:id", delete(handler::delete_contact))
+                .route("/create", post(handler::create_contact).route_layer(RequirePermission::new("contact", "create")))
+                .route("/list", get(handler::list_contacts).route_layer(RequirePermission::new("contact", "read")))
+                .route("/:id", get(handler::get_contact_by_id).route_layer(RequirePermission::new("contact", "read")))
+                .route("/:id/update", post(handler::update_contact).route_layer(RequirePermission::new("contact", "update")))
+                .route("/:id", delete(handler::delete_contact).route_layer(RequirePermission::new("contact", "delete")))
                 .layer(middleware::from_fn(jwt_auth)),
         )
 }
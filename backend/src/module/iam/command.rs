@@ -21,4 +21,7 @@ pub struct CreateRoleCommand {
 pub struct AssignPermissionsCommand {
     pub role_id: Uuid,
     pub permission_ids: Vec<Uuid>,
+    /// Scope ABAC áp cho các quyền này, vd: `assignee_id = $user OR $user IN shared_with`
+    #[serde(default)]
+    pub scope: Option<String>,
 }
\ No newline at end of file
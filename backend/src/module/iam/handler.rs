@@ -13,21 +13,30 @@ pub async fn assign_role(
     State(state): State<Arc<AppState>>,
     Json(cmd): Json<AssignRoleCommand>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let pool = state.shard.get_pool_for_tenant(&cmd.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&cmd.tenant_id)
+        .await
+        .map_err(|e| (e.status_code(), e.to_string()))?;
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
 
-    let res = sqlx::query!(
+    let mut tx = pool.begin().await.map_err(internal)?;
+    sqlx::query!(
         "INSERT INTO user_roles (user_id, role_id, tenant_id) VALUES ($1, $2, $3)",
         cmd.user_id,
         cmd.role_id,
         cmd.tenant_id
     )
-    .execute(pool)
-    .await;
+    .execute(&mut *tx)
+    .await
+    .map_err(internal)?;
+    // Token cấp trước đó mang perm_ver cũ → bị từ chối, client phải refresh
+    crate::core::iam::bump_user_version(&mut *tx, cmd.tenant_id, cmd.user_id)
+        .await
+        .map_err(internal)?;
+    tx.commit().await.map_err(internal)?;
 
-    match res {
-        Ok(_) => Ok(Json(serde_json::json!({ "status": "ok" }))),
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
-    }
+    // 🔄 Quyền của user thay đổi → bỏ cache
+    state.permissions.invalidate_user(cmd.tenant_id, cmd.user_id);
+    Ok(Json(serde_json::json!({ "status": "ok" })))
 }
 
 /// ✅ Trả danh sách tất cả quyền hệ thống (GET /iam/permissions)
@@ -35,7 +44,7 @@ pub async fn assign_role(
 pub async fn list_permissions(
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<Vec<Permission>>, (StatusCode, String)> {
-    let pool = state.shard.get_pool_for_tenant(&uuid::Uuid::nil());
+    let pool = state.shard.get_pool_for_system();
     let rows = sqlx::query_as!(Permission, r#"
         SELECT id, resource, action, label FROM permissions ORDER BY resource, action
     "#)
@@ -52,7 +61,9 @@ pub async fn list_roles(
     Extension(user): Extension<AuthUser>,
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<Vec<Role>>, (StatusCode, String)> {
-    let pool = state.shard.get_pool_for_tenant(&user.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&user.tenant_id)
+        .await
+        .map_err(|e| (e.status_code(), e.to_string()))?;
     let rows = sqlx::query_as!(Role, r#"
         SELECT id, tenant_id, name, module FROM roles WHERE tenant_id = $1 ORDER BY name
     "#, user.tenant_id)
@@ -70,7 +81,9 @@ pub async fn create_role(
     State(state): State<Arc<AppState>>,
     Json(cmd): Json<CreateRoleCommand>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let pool = state.shard.get_pool_for_tenant(&user.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&user.tenant_id)
+        .await
+        .map_err(|e| (e.status_code(), e.to_string()))?;
     let role_id = uuid::Uuid::new_v4();
 
     sqlx::query!(
@@ -93,19 +106,36 @@ pub async fn assign_permissions_to_role(
     State(state): State<Arc<AppState>>,
     Json(cmd): Json<AssignPermissionsCommand>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let pool = state.shard.get_pool_for_tenant(&uuid::Uuid::nil());
+    let pool = state.shard.get_pool_for_system();
+
+    // Scope rỗng = không giới hạn; sai cú pháp / cột ngoài whitelist → 400 để người cấu hình sửa
+    let scope = cmd.scope.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    if let Some(expr) = scope {
+        crate::core::scope::parse_with_columns(expr, crate::core::scope::OWNERSHIP_COLUMNS)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("Scope không hợp lệ: {}", e)))?;
+    }
 
     for perm_id in &cmd.permission_ids {
         sqlx::query!(
-            "INSERT INTO role_permissions (role_id, permission_id) VALUES ($1, $2)",
+            r#"
+            INSERT INTO role_permissions (role_id, permission_id, scope) VALUES ($1, $2, $3)
+            ON CONFLICT (role_id, permission_id) DO UPDATE SET scope = EXCLUDED.scope
+            "#,
             cmd.role_id,
-            perm_id
+            perm_id,
+            scope
         )
         .execute(pool)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     }
 
+    // 🔄 Role có thể gán cho nhiều user → tăng phiên bản quyền của họ + xoá toàn bộ cache quyền
+    crate::core::iam::bump_role_version(&state.shard, cmd.role_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    state.permissions.invalidate_all();
+
     Ok(Json(serde_json::json!({ "status": "ok" })))
 }
 
@@ -116,8 +146,10 @@ pub async fn my_modules(
     Extension(user): Extension<AuthUser>,
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<Vec<String>>, (StatusCode, String)> {
-    let pool_tenant = state.shard.get_pool_for_tenant(&user.tenant_id);
-    let pool_global = state.shard.get_pool_for_tenant(&Uuid::nil());
+    let pool_tenant = state.shard.get_pool_for_tenant(&user.tenant_id)
+        .await
+        .map_err(|e| (e.status_code(), e.to_string()))?;
+    let pool_global = state.shard.get_pool_for_system();
 
     // 👑 admin?
     let is_admin = sqlx::query_scalar::<_, bool>(
@@ -211,7 +243,9 @@ pub async fn my_permissions(
     Extension(user): Extension<AuthUser>,
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<Vec<EffectivePermission>>, (StatusCode, String)> {
-    let pool = state.shard.get_pool_for_tenant(&user.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&user.tenant_id)
+        .await
+        .map_err(|e| (e.status_code(), e.to_string()))?;
 
     let rows = sqlx::query!(
         r#"
@@ -255,7 +289,7 @@ pub struct AvailableModule {
 pub async fn available_modules(
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<Vec<AvailableModule>>, (StatusCode, String)> {
-    let pool = state.shard.get_pool_for_tenant(&Uuid::nil()); // global
+    let pool = state.shard.get_pool_for_system(); // global
 
     // 👇 Đọc từ available_module, alias về key/label để FE dùng như cũ
     let rows = sqlx::query_as::<_, AvailableModule>(
@@ -280,7 +314,7 @@ pub async fn create_permission(
     Json(req): Json<CreatePermissionReq>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     // permissions là global => dùng shard "nil"
-    let pool = state.shard.get_pool_for_tenant(&Uuid::nil());
+    let pool = state.shard.get_pool_for_system();
 
     // Dùng ON CONFLICT DO UPDATE để luôn RETURNING id (kể cả khi đã tồn tại)
     let id = sqlx::query_scalar!(
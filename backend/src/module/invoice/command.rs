@@ -1,9 +1,22 @@
+use std::collections::HashMap;
+
 use uuid::Uuid;
-use sqlx::{Pool, Postgres};
+use sqlx::{PgConnection, PgExecutor};
 use chrono::NaiveDate;
 use sqlx::types::BigDecimal;
 use serde_json::Value;
 
+use async_trait::async_trait;
+
+use crate::command_bus::{Command, CommandContext};
+use crate::core::error::AppError;
+use crate::core::i18n::I18n;
+use crate::core::scope::Scope;
+use crate::module::sequence;
+use super::event::InvoiceEvent;
+use super::posting::{self, EntryKind, LineTax, ProductLine};
+use super::query;
+
 #[derive(Debug)]
 pub struct CreateInvoiceDto {
     pub journal_id: Uuid,
@@ -84,7 +97,7 @@ pub struct UpdateInvoiceLineDto {
 
 /// Get or create default journal for tenant
 async fn get_or_create_default_journal(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     user_id: Uuid,
 ) -> Result<Uuid, sqlx::Error> {
@@ -98,7 +111,7 @@ async fn get_or_create_default_journal(
         "#,
         tenant_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut *conn)
     .await?;
 
     if let Some(j) = journal {
@@ -119,7 +132,7 @@ async fn get_or_create_default_journal(
         "#,
         tenant_id, journal_id, user_id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await;
 
     // Get the journal (either newly created or existing)
@@ -131,7 +144,7 @@ async fn get_or_create_default_journal(
         "#,
         tenant_id
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *conn)
     .await?;
     
     Ok(existing.id)
@@ -146,61 +159,109 @@ fn get_default_currency_id() -> Uuid {
 
 /// Get or create default account for revenue (for invoice lines)
 async fn get_or_create_default_revenue_account(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    user_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    get_or_create_default_account(conn, tenant_id, user_id, ("income", "income"), ("400000", "Product Sales")).await
+}
+
+/// Get or create default receivable account (Nợ phải thu khi ghi sổ hóa đơn)
+async fn get_or_create_default_receivable_account(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    user_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    get_or_create_default_account(conn, tenant_id, user_id, ("asset_receivable", "asset"), ("121000", "Account Receivable")).await
+}
+
+/// Get or create default payable account (Có phải trả khi ghi sổ hóa đơn mua)
+async fn get_or_create_default_payable_account(
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     user_id: Uuid,
 ) -> Result<Uuid, sqlx::Error> {
-    // Try to get existing revenue account
+    get_or_create_default_account(conn, tenant_id, user_id, ("liability_payable", "liability"), ("211000", "Account Payable")).await
+}
+
+/// Get or create default deductible tax account (thuế đầu vào của hóa đơn mua)
+async fn get_or_create_default_tax_paid_account(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    user_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    get_or_create_default_account(conn, tenant_id, user_id, ("asset_current", "asset"), ("131000", "Tax Paid")).await
+}
+
+/// Get or create default tax payable account (thuế không cấu hình tài khoản trên repartition)
+async fn get_or_create_default_tax_account(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    user_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    get_or_create_default_account(conn, tenant_id, user_id, ("liability_current", "liability"), ("251000", "Tax Payable")).await
+}
+
+/// Tài khoản đầu tiên (theo mã) thuộc `account_type`, chưa có thì tạo với `code` / `name`
+async fn get_or_create_default_account(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    user_id: Uuid,
+    (account_type, internal_group): (&str, &str),
+    (code, name): (&str, &str),
+) -> Result<Uuid, sqlx::Error> {
+    // Try to get existing account of this type
     let account = sqlx::query!(
         r#"
         SELECT id FROM account_account
-        WHERE tenant_id = $1 AND account_type = 'income' AND deprecated = FALSE
+        WHERE tenant_id = $1 AND account_type = $2 AND deprecated = FALSE
         ORDER BY code, created_at
         LIMIT 1
         "#,
-        tenant_id
+        tenant_id, account_type
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut *conn)
     .await?;
 
     if let Some(acc) = account {
         return Ok(acc.id);
     }
 
-    // Create default revenue account if not exists
+    // Create default account if not exists
     let account_id = Uuid::new_v4();
     let _ = sqlx::query!(
         r#"
         INSERT INTO account_account (
-            tenant_id, id, code, name, account_type, internal_group, created_by
+            tenant_id, id, code, name, account_type, internal_group, reconcile, created_by
         ) VALUES (
-            $1, $2, '400000', 'Product Sales', 'income', 'income', $3
+            $1, $2, $3, $4, $5, $6, $7, $8
         )
         ON CONFLICT (tenant_id, code) DO NOTHING
         "#,
-        tenant_id, account_id, user_id
+        tenant_id, account_id, code, name, account_type, internal_group,
+        account_type == "asset_receivable", user_id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await;
 
     // Get the account (either newly created or existing)
     let existing = sqlx::query!(
         r#"
         SELECT id FROM account_account
-        WHERE tenant_id = $1 AND code = '400000'
+        WHERE tenant_id = $1 AND code = $2
         LIMIT 1
         "#,
-        tenant_id
+        tenant_id, code
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *conn)
     .await?;
     
     Ok(existing.id)
 }
 
-/// Create a new invoice
+/// Create a new invoice (inside the caller's transaction)
 pub async fn create_invoice(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     dto: CreateInvoiceDto,
 ) -> Result<Uuid, sqlx::Error> {
@@ -210,7 +271,7 @@ pub async fn create_invoice(
     let journal_id = if dto.journal_id != Uuid::nil() {
         dto.journal_id
     } else {
-        get_or_create_default_journal(pool, tenant_id, dto.created_by).await?
+        get_or_create_default_journal(&mut *conn, tenant_id, dto.created_by).await?
     };
     
     // Get currency (use from DTO if provided, otherwise use default)
@@ -220,8 +281,11 @@ pub async fn create_invoice(
         get_default_currency_id()
     };
     
-    // Generate invoice name/sequence (simplified - should use proper sequence)
-    let invoice_name = format!("INV/{}", chrono::Utc::now().format("%Y/%m/%d"));
+    // Get default account for invoice lines (if needed)
+    let default_account_id = get_or_create_default_revenue_account(&mut *conn, tenant_id, dto.created_by).await?;
+
+    // Hóa đơn nháp chưa có số, số cấp lúc ghi sổ (confirm_invoice)
+    let invoice_name = "/";
     
     sqlx::query!(
         r#"
@@ -254,12 +318,9 @@ pub async fn create_invoice(
         dto.narration,
         dto.created_by, dto.assignee_id, &dto.shared_with
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
-    // Get default account for invoice lines (if needed)
-    let default_account_id = get_or_create_default_revenue_account(pool, tenant_id, dto.created_by).await?;
-
     // Create invoice lines
     for (idx, line) in dto.invoice_lines.iter().enumerate() {
         let line_id = Uuid::new_v4();
@@ -314,7 +375,7 @@ pub async fn create_invoice(
             account_id,
             price_subtotal, price_total
         )
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
 
         // Create tax relations
@@ -327,24 +388,28 @@ pub async fn create_invoice(
                 "#,
                 tenant_id, line_id, tax_id
             )
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
         }
     }
 
     // Recalculate totals
-    recalculate_invoice_totals(pool, tenant_id, invoice_id).await?;
+    recalculate_invoice_totals(conn, tenant_id, invoice_id).await?;
 
     Ok(invoice_id)
 }
 
-/// Update invoice
+/// Update invoice (chỉ hoá đơn nháp; số / sổ của hoá đơn đã cấp số còn được trigger khoá).
+/// Gọi trong transaction: dòng hoá đơn bị khoá từ lúc kiểm tra nháp tới khi commit, ghi sổ song song phải chờ
 pub async fn update_invoice(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
+    i18n: &I18n,
     tenant_id: Uuid,
     invoice_id: Uuid,
     dto: UpdateInvoiceDto,
-) -> Result<(), sqlx::Error> {
+) -> Result<(), AppError> {
+    ensure_draft(&mut *conn, i18n, tenant_id, invoice_id).await?;
+
     let mut query = sqlx::QueryBuilder::new("UPDATE account_move SET ");
 
     let mut has_updates = false;
@@ -451,81 +516,371 @@ pub async fn update_invoice(
         query.push(" AND id = ");
         query.push_bind(invoice_id);
 
-        query.build().execute(pool).await?;
+        query.build().execute(&mut *conn).await.map_err(to_app_error)?;
     }
 
     // Sync invoice lines if provided (always sync even if no other fields changed)
     if let Some(ref lines) = dto.invoice_lines {
-        sync_invoice_lines(pool, tenant_id, invoice_id, lines).await?;
+        sync_invoice_lines(&mut *conn, tenant_id, invoice_id, lines).await.map_err(to_app_error)?;
     }
 
     Ok(())
 }
 
-/// Confirm/Post invoice
+/// Hoá đơn phải còn ở trạng thái nháp mới được sửa – khoá dòng `account_move` (FOR UPDATE)
+/// để không bị ghi sổ / huỷ xen giữa lúc kiểm tra và lúc ghi của transaction hiện tại
+async fn ensure_draft<'e>(
+    executor: impl PgExecutor<'e>,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    invoice_id: Uuid,
+) -> Result<(), AppError> {
+    let state = sqlx::query_scalar!(
+        "SELECT state FROM account_move WHERE tenant_id = $1 AND id = $2 FOR UPDATE",
+        tenant_id, invoice_id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(to_app_error)?;
+    match state.as_deref() {
+        Some("draft") => Ok(()),
+        Some(_) => Err(AppError::bad_request_i18n(i18n, "error.invoice.not_draft")),
+        None => Err(AppError::not_found_i18n(i18n, "error.invoice.not_found")),
+    }
+}
+
+/// Confirm/Post invoice, trả về true nếu hóa đơn chuyển từ draft sang posted.
+/// Số hóa đơn cấp lúc ghi sổ (cùng transaction): sổ có ký hiệu → dãy của ký hiệu `invoice.series.{ký hiệu}`
+/// (dùng chung cho mọi sổ cùng ký hiệu), không có → dãy của sổ `invoice.{sổ}`.
+/// Số phải liền sau số lớn nhất đã cấp trong cùng nhóm, nếu không thì từ chối ghi sổ (không để lủng số).
+/// Số đã cấp bị khoá bởi trigger `account_move_lock_sequence`.
 pub async fn confirm_invoice(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
+    i18n: &I18n,
     tenant_id: Uuid,
     invoice_id: Uuid,
-    _user_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+    posted_by: Uuid,
+) -> Result<bool, AppError> {
+    let posted = sqlx::query!(
         r#"
         UPDATE account_move
         SET state = 'posted', posted_before = true, updated_at = now()
         WHERE tenant_id = $1 AND id = $2 AND state = 'draft'
+        RETURNING journal_id
         "#,
         tenant_id, invoice_id
     )
-    .execute(pool)
-    .await?;
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(to_app_error)?;
+    let Some(posted) = posted else {
+        return Ok(false);
+    };
 
+    let journal = sqlx::query!(
+        "SELECT code, invoice_series FROM account_journal WHERE tenant_id = $1 AND id = $2",
+        tenant_id, posted.journal_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(to_app_error)?;
+    let series = journal.invoice_series.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    let code = match series {
+        Some(series) => format!("{}.series.{}", sequence::model::INVOICE, series),
+        None => format!("{}.{}", sequence::model::INVOICE, journal.code),
+    };
+
+    let number = sequence::command::next_number(&mut *conn, tenant_id, &code, chrono::Utc::now(), series, Some(&journal.code))
+        .await
+        .map_err(to_app_error)?;
+    let group = format!("{}/{}", code, number.period_key);
+
+    // Số mới phải liền sau số lớn nhất đã cấp của nhóm (counter bị sửa tay / lệch dữ liệu → lủng số)
+    let last = sqlx::query_scalar!(
+        r#"
+        SELECT MAX(sequence_number) FROM account_move
+        WHERE tenant_id = $1 AND sequence_prefix = $2
+        "#,
+        tenant_id, group
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(to_app_error)?;
+    if number.number != i64::from(last.unwrap_or(0)) + 1 {
+        return Err(AppError::bad_request_i18n(i18n, "error.invoice.sequence_gap"));
+    }
+    // sequence_number là INT4 → counter vượt i32 thì từ chối thay vì cắt số
+    let sequence_number = i32::try_from(number.number)
+        .map_err(|_| AppError::bad_request_i18n(i18n, "error.invoice.sequence_overflow"))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE account_move
+        SET name = $3, sequence_prefix = $4, sequence_number = $5, invoice_series = $6
+        WHERE tenant_id = $1 AND id = $2
+        "#,
+        tenant_id, invoice_id, number.name, group, sequence_number, series
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(to_app_error)?;
+
+    post_journal_entries(&mut *conn, i18n, tenant_id, invoice_id, posted_by).await?;
+    Ok(true)
+}
+
+/// 📒 Sinh bút toán kép cho hóa đơn vừa ghi sổ (xem `posting::build`): ghi Nợ/Có lên dòng sản phẩm,
+/// thêm dòng thuế + dòng phải thu / phải trả theo `move_type` (ẩn khỏi tab dòng hóa đơn), cập nhật lại tổng tiền.
+/// Tổng Nợ ≠ tổng Có trên các dòng đã ghi → từ chối ghi sổ.
+async fn post_journal_entries(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    invoice_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), AppError> {
+    let mv = sqlx::query!(
+        r#"
+        SELECT name, move_type, journal_id, currency_id, COALESCE(commercial_partner_id, partner_id) AS partner_id,
+               date, invoice_date, invoice_date_due
+        FROM account_move
+        WHERE tenant_id = $1 AND id = $2
+        "#,
+        tenant_id, invoice_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(to_app_error)?;
+    let move_type = posting::MoveType::parse(&mv.move_type)
+        .ok_or_else(|| AppError::bad_request_i18n(i18n, "error.invoice.unsupported_move_type"))?;
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, account_id AS "account_id!",
+               COALESCE(quantity, 1)::numeric AS "quantity!",
+               COALESCE(price_subtotal, 0)::numeric AS "subtotal!",
+               COALESCE(price_total, 0)::numeric AS "total!"
+        FROM account_move_line
+        WHERE tenant_id = $1 AND move_id = $2 AND account_id IS NOT NULL
+          AND (display_type IS NULL OR display_type NOT IN ('line_section', 'line_subsection', 'line_note'))
+          AND NOT COALESCE(exclude_from_invoice_tab, false)
+        ORDER BY COALESCE(sequence, 0), id
+        "#,
+        tenant_id, invoice_id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(to_app_error)?;
+    let line_ids: Vec<Uuid> = rows.iter().map(|r| r.id).collect();
+
+    let mut taxes: HashMap<Uuid, Vec<LineTax>> = HashMap::new();
+    let tax_rows = sqlx::query!(
+        r#"
+        SELECT r.move_line_id, t.id, t.name, t.amount_type, t.amount,
+               (SELECT rl.account_id FROM account_tax_repartition_line rl
+                WHERE rl.tenant_id = t.tenant_id AND rl.invoice_tax_id = t.id
+                  AND rl.repartition_type = 'tax' AND rl.account_id IS NOT NULL
+                ORDER BY rl.sequence LIMIT 1) AS account_id
+        FROM account_move_line_tax_rel r
+        JOIN account_tax t ON t.tenant_id = r.tenant_id AND t.id = r.tax_id
+        WHERE r.tenant_id = $1 AND r.move_line_id = ANY($2)
+        ORDER BY t.sequence, t.name
+        "#,
+        tenant_id, &line_ids
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(to_app_error)?;
+    for t in tax_rows {
+        taxes.entry(t.move_line_id).or_default().push(LineTax {
+            tax_id: t.id,
+            name: t.name,
+            amount_type: t.amount_type,
+            amount: t.amount,
+            account_id: t.account_id,
+        });
+    }
+
+    let lines: Vec<ProductLine> = rows
+        .into_iter()
+        .map(|r| ProductLine {
+            line_id: r.id,
+            account_id: r.account_id,
+            taxes: taxes.remove(&r.id).unwrap_or_default(),
+            manual_tax: &r.total - &r.subtotal,
+            quantity: r.quantity,
+            subtotal: r.subtotal,
+        })
+        .collect();
+
+    let (partner_account, tax_account) = if move_type.is_sale() {
+        (
+            get_or_create_default_receivable_account(&mut *conn, tenant_id, user_id).await,
+            get_or_create_default_tax_account(&mut *conn, tenant_id, user_id).await,
+        )
+    } else {
+        (
+            get_or_create_default_payable_account(&mut *conn, tenant_id, user_id).await,
+            get_or_create_default_tax_paid_account(&mut *conn, tenant_id, user_id).await,
+        )
+    };
+    let entries = posting::build(
+        move_type,
+        &lines,
+        partner_account.map_err(to_app_error)?,
+        tax_account.map_err(to_app_error)?,
+    );
+
+    for entry in &entries {
+        let balance = entry.balance();
+        match &entry.kind {
+            EntryKind::Revenue { line_id } => {
+                // Tổng tiền dòng theo thuế thực tế khi ghi sổ
+                let tax_total = lines.iter().find(|l| l.line_id == *line_id).map(ProductLine::tax_total);
+                sqlx::query!(
+                    r#"
+                    UPDATE account_move_line
+                    SET debit = $3, credit = $4, balance = $5, amount_currency = $5,
+                        price_total = price_subtotal + COALESCE($6::numeric, 0),
+                        journal_id = $7, partner_id = $8, move_name = $9, parent_state = 'posted',
+                        date = $10, invoice_date = $11, updated_at = now()
+                    WHERE tenant_id = $1 AND id = $2
+                    "#,
+                    tenant_id, line_id, entry.debit, entry.credit, balance, tax_total,
+                    mv.journal_id, mv.partner_id, mv.name, mv.date, mv.invoice_date
+                )
+                .execute(&mut *conn)
+                .await
+                .map_err(to_app_error)?;
+            }
+            EntryKind::Tax { tax_id, name, base } => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO account_move_line (
+                        tenant_id, id, move_id, account_id, journal_id, currency_id, partner_id,
+                        name, move_name, parent_state, sequence, date, invoice_date,
+                        debit, credit, balance, amount_currency, amount_residual,
+                        tax_line_id, tax_base_amount, exclude_from_invoice_tab
+                    ) VALUES (
+                        $1, $2, $3, $4, $5, $6, $7,
+                        $8, $9, 'posted', 9000, $10, $11,
+                        $12, $13, $14, $14, 0,
+                        $15, $16, true
+                    )
+                    "#,
+                    tenant_id, Uuid::new_v4(), invoice_id, entry.account_id, mv.journal_id, mv.currency_id, mv.partner_id,
+                    name.as_deref(), mv.name, mv.date, mv.invoice_date,
+                    entry.debit, entry.credit, balance,
+                    *tax_id, base
+                )
+                .execute(&mut *conn)
+                .await
+                .map_err(to_app_error)?;
+            }
+            EntryKind::Partner => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO account_move_line (
+                        tenant_id, id, move_id, account_id, journal_id, currency_id, partner_id,
+                        name, move_name, parent_state, sequence, date, invoice_date, date_maturity,
+                        debit, credit, balance, amount_currency, amount_residual, amount_residual_currency,
+                        exclude_from_invoice_tab
+                    ) VALUES (
+                        $1, $2, $3, $4, $5, $6, $7,
+                        $8, $9, 'posted', 9999, $10, $11, $12,
+                        $13, $14, $15, $15, $15, $15,
+                        true
+                    )
+                    "#,
+                    tenant_id, Uuid::new_v4(), invoice_id, entry.account_id, mv.journal_id, mv.currency_id, mv.partner_id,
+                    mv.name, mv.name, mv.date, mv.invoice_date, mv.invoice_date_due,
+                    entry.debit, entry.credit, balance
+                )
+                .execute(&mut *conn)
+                .await
+                .map_err(to_app_error)?;
+            }
+        }
+    }
+
+    recalculate_invoice_totals(&mut *conn, tenant_id, invoice_id).await.map_err(to_app_error)?;
+
+    // Kiểm tra lại trên dữ liệu đã ghi (kể cả dòng bút toán có sẵn)
+    let totals = sqlx::query!(
+        r#"
+        SELECT COALESCE(SUM(debit), 0)::numeric AS "debit!", COALESCE(SUM(credit), 0)::numeric AS "credit!"
+        FROM account_move_line
+        WHERE tenant_id = $1 AND move_id = $2
+        "#,
+        tenant_id, invoice_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(to_app_error)?;
+    if totals.debit != totals.credit {
+        return Err(AppError::bad_request_i18n(i18n, "error.invoice.unbalanced"));
+    }
     Ok(())
 }
 
-/// Cancel invoice
+/// Cancel invoice: bút toán đã sinh khi ghi sổ chuyển sang `parent_state = 'cancel'`
+/// (ra khỏi sổ cái, hết công nợ), số hóa đơn đã cấp giữ nguyên
 pub async fn cancel_invoice(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+) -> Result<bool, sqlx::Error> {
+    let cancelled = sqlx::query!(
         r#"
         UPDATE account_move
-        SET state = 'cancel', updated_at = now()
-        WHERE tenant_id = $1 AND id = $2
+        SET state = 'cancel', amount_residual = 0, amount_residual_signed = 0, updated_at = now()
+        WHERE tenant_id = $1 AND id = $2 AND state <> 'cancel'
         "#,
         tenant_id, invoice_id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
+    if cancelled.rows_affected() == 0 {
+        return Ok(false);
+    }
 
-    Ok(())
+    sqlx::query!(
+        r#"
+        UPDATE account_move_line
+        SET parent_state = 'cancel', amount_residual = 0, amount_residual_currency = 0, updated_at = now()
+        WHERE tenant_id = $1 AND move_id = $2
+        "#,
+        tenant_id, invoice_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(true)
 }
 
 /// Delete invoice
 pub async fn delete_invoice(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
-) -> Result<(), sqlx::Error> {
+) -> Result<bool, sqlx::Error> {
     // Only allow deletion of draft invoices
-    sqlx::query!(
+    let deleted = sqlx::query!(
         r#"
         DELETE FROM account_move
         WHERE tenant_id = $1 AND id = $2 AND state = 'draft'
         "#,
         tenant_id, invoice_id
     )
-    .execute(pool)
+    .execute(conn)
     .await?;
 
-    Ok(())
+    Ok(deleted.rows_affected() > 0)
 }
 
 /// Add invoice line
 pub async fn add_invoice_line(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
     dto: CreateInvoiceLineDto,
@@ -537,7 +892,7 @@ pub async fn add_invoice_line(
         "SELECT currency_id FROM account_move WHERE tenant_id = $1 AND id = $2",
         tenant_id, invoice_id
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *conn)
     .await?;
 
     let quantity = dto.quantity.as_ref().map(|q| q.clone()).unwrap_or_else(|| BigDecimal::from(1));
@@ -573,7 +928,7 @@ pub async fn add_invoice_line(
         dto.account_id,
         price_subtotal, price_total
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     // Create tax relations
@@ -586,19 +941,19 @@ pub async fn add_invoice_line(
             "#,
             tenant_id, line_id, tax_id
         )
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
     }
 
     // Recalculate totals
-    recalculate_invoice_totals(pool, tenant_id, invoice_id).await?;
+    recalculate_invoice_totals(&mut *conn, tenant_id, invoice_id).await?;
 
     Ok(line_id)
 }
 
 /// Sync invoice lines (add/update/delete)
 async fn sync_invoice_lines(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
     lines: &[UpdateInvoiceLineDto],
@@ -608,17 +963,17 @@ async fn sync_invoice_lines(
         "SELECT currency_id FROM account_move WHERE tenant_id = $1 AND id = $2",
         tenant_id, invoice_id
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *conn)
     .await?;
 
-    let default_account_id = get_or_create_default_revenue_account(pool, tenant_id, Uuid::nil()).await?;
+    let default_account_id = get_or_create_default_revenue_account(&mut *conn, tenant_id, Uuid::nil()).await?;
 
     // Get existing line IDs
     let existing_lines = sqlx::query!(
-        "SELECT id FROM account_move_line WHERE tenant_id = $1 AND move_id = $2",
+        "SELECT id FROM account_move_line WHERE tenant_id = $1 AND move_id = $2 AND NOT COALESCE(exclude_from_invoice_tab, false)",
         tenant_id, invoice_id
     )
-    .fetch_all(pool)
+    .fetch_all(&mut *conn)
     .await?;
 
     let existing_ids: std::collections::HashSet<Uuid> = existing_lines.iter().map(|l| l.id).collect();
@@ -640,7 +995,7 @@ async fn sync_invoice_lines(
             "#,
             tenant_id, invoice_id, &ids_to_delete
         )
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
     }
 
@@ -650,7 +1005,7 @@ async fn sync_invoice_lines(
 
         if let Some(line_id) = line.id {
             // Update existing line (without recalculating totals)
-            update_invoice_line_only(pool, tenant_id, invoice_id, line_id, line, invoice.currency_id, default_account_id).await?;
+            update_invoice_line_only(&mut *conn, tenant_id, invoice_id, line_id, line, default_account_id).await?;
         } else {
             // Create new line
             let line_id = Uuid::new_v4();
@@ -701,7 +1056,7 @@ async fn sync_invoice_lines(
                 account_id,
                 price_subtotal, price_total
             )
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
 
             // Create tax relations
@@ -715,7 +1070,7 @@ async fn sync_invoice_lines(
                         "#,
                         tenant_id, line_id, tax_id
                     )
-                    .execute(pool)
+                    .execute(&mut *conn)
                     .await?;
                 }
             }
@@ -723,19 +1078,18 @@ async fn sync_invoice_lines(
     }
 
     // Recalculate totals ONCE at the end
-    recalculate_invoice_totals(pool, tenant_id, invoice_id).await?;
+    recalculate_invoice_totals(&mut *conn, tenant_id, invoice_id).await?;
 
     Ok(())
 }
 
 /// Update invoice line only (without recalculating totals)
 async fn update_invoice_line_only(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
     line_id: Uuid,
     dto: &UpdateInvoiceLineDto,
-    currency_id: Uuid,
     default_account_id: Uuid,
 ) -> Result<(), sqlx::Error> {
     // Calculate amounts
@@ -776,7 +1130,7 @@ async fn update_invoice_line_only(
             price_total = $9,
             sequence = COALESCE($10, sequence),
             display_type = $11
-        WHERE tenant_id = $12 AND id = $13 AND move_id = $14
+        WHERE tenant_id = $12 AND id = $13 AND move_id = $14 AND NOT COALESCE(exclude_from_invoice_tab, false)
         "#,
         dto.product_id, dto.product_uom_id, dto.name.as_deref(),
         dto.quantity, dto.price_unit, dto.discount,
@@ -784,7 +1138,7 @@ async fn update_invoice_line_only(
         dto.sequence, dto.display_type.as_deref(),
         tenant_id, line_id, invoice_id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     // Update tax relations - always delete existing first
@@ -792,7 +1146,7 @@ async fn update_invoice_line_only(
         "DELETE FROM account_move_line_tax_rel WHERE tenant_id = $1 AND move_line_id = $2",
         tenant_id, line_id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     // Add new tax relations if provided
@@ -806,7 +1160,7 @@ async fn update_invoice_line_only(
                 "#,
                 tenant_id, line_id, tax_id
             )
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
         }
     }
@@ -816,12 +1170,11 @@ async fn update_invoice_line_only(
 
 /// Update invoice line (full update with all fields)
 async fn update_invoice_line_full(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
     line_id: Uuid,
     dto: &UpdateInvoiceLineDto,
-    currency_id: Uuid,
     default_account_id: Uuid,
 ) -> Result<(), sqlx::Error> {
     // Calculate amounts
@@ -862,7 +1215,7 @@ async fn update_invoice_line_full(
             price_total = $9,
             sequence = COALESCE($10, sequence),
             display_type = $11
-        WHERE tenant_id = $12 AND id = $13 AND move_id = $14
+        WHERE tenant_id = $12 AND id = $13 AND move_id = $14 AND NOT COALESCE(exclude_from_invoice_tab, false)
         "#,
         dto.product_id, dto.product_uom_id, dto.name.as_deref(),
         dto.quantity, dto.price_unit, dto.discount,
@@ -870,7 +1223,7 @@ async fn update_invoice_line_full(
         dto.sequence, dto.display_type.as_deref(),
         tenant_id, line_id, invoice_id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     // Update tax relations - always delete existing first
@@ -878,7 +1231,7 @@ async fn update_invoice_line_full(
         "DELETE FROM account_move_line_tax_rel WHERE tenant_id = $1 AND move_line_id = $2",
         tenant_id, line_id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     // Add new tax relations if provided
@@ -892,7 +1245,7 @@ async fn update_invoice_line_full(
                 "#,
                 tenant_id, line_id, tax_id
             )
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
         }
     }
@@ -902,33 +1255,25 @@ async fn update_invoice_line_full(
 
 /// Update invoice line
 pub async fn update_invoice_line(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
     line_id: Uuid,
     dto: UpdateInvoiceLineDto,
 ) -> Result<(), sqlx::Error> {
-    // Get currency and default account from invoice
-    let invoice = sqlx::query!(
-        "SELECT currency_id FROM account_move WHERE tenant_id = $1 AND id = $2",
-        tenant_id, invoice_id
-    )
-    .fetch_one(pool)
-    .await?;
+    let default_account_id = get_or_create_default_revenue_account(&mut *conn, tenant_id, Uuid::nil()).await?;
 
-    let default_account_id = get_or_create_default_revenue_account(pool, tenant_id, Uuid::nil()).await?;
-
-    update_invoice_line_full(pool, tenant_id, invoice_id, line_id, &dto, invoice.currency_id, default_account_id).await?;
+    update_invoice_line_full(&mut *conn, tenant_id, invoice_id, line_id, &dto, default_account_id).await?;
 
     // Recalculate totals
-    recalculate_invoice_totals(pool, tenant_id, invoice_id).await?;
+    recalculate_invoice_totals(&mut *conn, tenant_id, invoice_id).await?;
 
     Ok(())
 }
 
 /// Delete invoice line
 pub async fn delete_invoice_line(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
     line_id: Uuid,
@@ -936,22 +1281,22 @@ pub async fn delete_invoice_line(
     sqlx::query!(
         r#"
         DELETE FROM account_move_line
-        WHERE tenant_id = $1 AND id = $2 AND move_id = $3
+        WHERE tenant_id = $1 AND id = $2 AND move_id = $3 AND NOT COALESCE(exclude_from_invoice_tab, false)
         "#,
         tenant_id, line_id, invoice_id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     // Recalculate totals
-    recalculate_invoice_totals(pool, tenant_id, invoice_id).await?;
+    recalculate_invoice_totals(&mut *conn, tenant_id, invoice_id).await?;
 
     Ok(())
 }
 
 /// Recalculate invoice totals
 async fn recalculate_invoice_totals(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
 ) -> Result<(), sqlx::Error> {
@@ -968,7 +1313,7 @@ async fn recalculate_invoice_totals(
         "#,
         tenant_id, invoice_id
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *conn)
     .await?;
 
     sqlx::query!(
@@ -989,9 +1334,239 @@ async fn recalculate_invoice_totals(
         totals.amount_untaxed, totals.amount_tax, totals.amount_total,
         tenant_id, invoice_id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     Ok(())
 }
 
+
+/* ========== Command bus ========== */
+
+// Lỗi SQL trả 400 kèm message (giữ hành vi cũ của handler)
+fn to_app_error(e: sqlx::Error) -> AppError {
+    AppError::bad_request(e.to_string())
+}
+
+pub struct CreateInvoice {
+    pub dto: CreateInvoiceDto,
+}
+
+#[async_trait]
+impl Command for CreateInvoice {
+    type Output = Uuid;
+    const NAME: &'static str = "invoice.create";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice", "create"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Uuid, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let invoice_id = create_invoice(ctx.conn(), tenant_id, self.dto).await.map_err(to_app_error)?;
+        ctx.emit(InvoiceEvent::InvoiceCreated { invoice_id })?;
+        Ok(invoice_id)
+    }
+}
+
+pub struct ConfirmInvoice {
+    pub invoice_id: Uuid,
+    /// Scope ABAC của quyền `invoice.confirm`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for ConfirmInvoice {
+    type Output = ();
+    const NAME: &'static str = "invoice.confirm";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice", "confirm"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let (tenant_id, invoice_id) = (ctx.tenant_id(), self.invoice_id);
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, invoice_id, "error.invoice.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        let posted_by = ctx.user_id();
+        if confirm_invoice(ctx.conn(), &i18n, tenant_id, invoice_id, posted_by).await? {
+            ctx.emit(InvoiceEvent::InvoicePosted { invoice_id, posted_by })?;
+        }
+        Ok(())
+    }
+}
+
+/// Đặt ký hiệu hóa đơn của sổ nhật ký – hóa đơn ghi sổ sau đó đánh số theo dãy của ký hiệu mới,
+/// hóa đơn đã cấp số giữ nguyên
+pub struct SetJournalSeries {
+    pub journal_id: Uuid,
+    pub invoice_series: Option<String>,
+}
+
+#[async_trait]
+impl Command for SetJournalSeries {
+    type Output = ();
+    const NAME: &'static str = "invoice.set_journal_series";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("sequence", "manage"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        let valid = |s: &str| s.len() <= 20 && s.chars().all(|c| c.is_ascii_alphanumeric());
+        if self.invoice_series.as_deref().map(str::trim).is_some_and(|s| !valid(s)) {
+            return Err(AppError::bad_request_i18n(i18n, "error.invoice.invalid_series"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        let series = self.invoice_series.as_deref().map(str::trim).filter(|s| !s.is_empty());
+        let updated = sqlx::query!(
+            "UPDATE account_journal SET invoice_series = $3, updated_at = now() WHERE tenant_id = $1 AND id = $2",
+            tenant_id, self.journal_id, series
+        )
+        .execute(ctx.conn())
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(AppError::not_found_i18n(&ctx.i18n, "error.invoice.journal_not_found"));
+        }
+        Ok(())
+    }
+}
+
+/// Sửa hoá đơn nháp (kèm đồng bộ dòng nếu có `invoice_lines`)
+pub struct UpdateInvoice {
+    pub invoice_id: Uuid,
+    pub dto: UpdateInvoiceDto,
+    /// Scope ABAC của quyền `invoice.update`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for UpdateInvoice {
+    type Output = ();
+    const NAME: &'static str = "invoice.update";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice", "update"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let (tenant_id, invoice_id) = (ctx.tenant_id(), self.invoice_id);
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, invoice_id, "error.invoice.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        update_invoice(ctx.conn(), &i18n, tenant_id, invoice_id, self.dto).await?;
+        ctx.emit(InvoiceEvent::InvoiceUpdated { invoice_id })?;
+        Ok(())
+    }
+}
+
+/// Thêm dòng vào hoá đơn nháp, trả về id dòng mới
+pub struct AddInvoiceLine {
+    pub invoice_id: Uuid,
+    pub dto: CreateInvoiceLineDto,
+    /// Scope ABAC của quyền `invoice.update`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for AddInvoiceLine {
+    type Output = Uuid;
+    const NAME: &'static str = "invoice.add_line";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice", "update"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Uuid, AppError> {
+        let (tenant_id, invoice_id) = (ctx.tenant_id(), self.invoice_id);
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, invoice_id, "error.invoice.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        ensure_draft(ctx.conn(), &i18n, tenant_id, invoice_id).await?;
+        let line_id = add_invoice_line(ctx.conn(), tenant_id, invoice_id, self.dto).await.map_err(to_app_error)?;
+        ctx.emit(InvoiceEvent::InvoiceUpdated { invoice_id })?;
+        Ok(line_id)
+    }
+}
+
+/// Sửa 1 dòng của hoá đơn nháp
+pub struct UpdateInvoiceLine {
+    pub invoice_id: Uuid,
+    pub line_id: Uuid,
+    pub dto: UpdateInvoiceLineDto,
+    /// Scope ABAC của quyền `invoice.update`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for UpdateInvoiceLine {
+    type Output = ();
+    const NAME: &'static str = "invoice.update_line";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice", "update"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let (tenant_id, invoice_id) = (ctx.tenant_id(), self.invoice_id);
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, invoice_id, "error.invoice.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        ensure_draft(ctx.conn(), &i18n, tenant_id, invoice_id).await?;
+        update_invoice_line(ctx.conn(), tenant_id, invoice_id, self.line_id, self.dto).await.map_err(to_app_error)?;
+        ctx.emit(InvoiceEvent::InvoiceUpdated { invoice_id })?;
+        Ok(())
+    }
+}
+
+/// Xoá 1 dòng của hoá đơn nháp
+pub struct DeleteInvoiceLine {
+    pub invoice_id: Uuid,
+    pub line_id: Uuid,
+    /// Scope ABAC của quyền `invoice.update`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for DeleteInvoiceLine {
+    type Output = ();
+    const NAME: &'static str = "invoice.delete_line";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice", "update"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let (tenant_id, invoice_id) = (ctx.tenant_id(), self.invoice_id);
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, invoice_id, "error.invoice.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        ensure_draft(ctx.conn(), &i18n, tenant_id, invoice_id).await?;
+        delete_invoice_line(ctx.conn(), tenant_id, invoice_id, self.line_id).await.map_err(to_app_error)?;
+        ctx.emit(InvoiceEvent::InvoiceUpdated { invoice_id })?;
+        Ok(())
+    }
+}
+
+pub struct CancelInvoice {
+    pub invoice_id: Uuid,
+    /// Scope ABAC của quyền `invoice.cancel`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for CancelInvoice {
+    type Output = ();
+    const NAME: &'static str = "invoice.cancel";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice", "cancel"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let (tenant_id, invoice_id) = (ctx.tenant_id(), self.invoice_id);
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, invoice_id, "error.invoice.not_found").await?;
+        if cancel_invoice(ctx.conn(), tenant_id, invoice_id).await.map_err(to_app_error)? {
+            ctx.emit(InvoiceEvent::InvoiceCancelled { invoice_id })?;
+        }
+        Ok(())
+    }
+}
+
+pub struct DeleteInvoice {
+    pub invoice_id: Uuid,
+    /// Scope ABAC của quyền `invoice.delete`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for DeleteInvoice {
+    type Output = ();
+    const NAME: &'static str = "invoice.delete";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice", "delete"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let (tenant_id, invoice_id) = (ctx.tenant_id(), self.invoice_id);
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, invoice_id, "error.invoice.not_found").await?;
+        if delete_invoice(ctx.conn(), tenant_id, invoice_id).await.map_err(to_app_error)? {
+            ctx.emit(InvoiceEvent::InvoiceDeleted { invoice_id })?;
+        }
+        Ok(())
+    }
+}
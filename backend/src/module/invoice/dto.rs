@@ -223,3 +223,9 @@ pub struct ListInvoiceFilter {
     pub offset: Option<i64>,
 }
 
+/// Dùng cho POST /invoice/journals/:journal_id/series
+#[derive(Debug, Deserialize)]
+pub struct JournalSeriesInput {
+    /// Ký hiệu hóa đơn (vd 1C26TAA), bỏ trống = không dùng ký hiệu
+    pub invoice_series: Option<String>,
+}
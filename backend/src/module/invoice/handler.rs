@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Path, Query, State},
+    extract::{Extension, Path, Query, State},
     response::IntoResponse,
     Json,
 };
@@ -7,15 +7,17 @@ use axum::http::{StatusCode, HeaderMap};
 use serde_json::json;
 use uuid::Uuid;
 use std::sync::Arc;
+use sqlx::PgPool;
 
-use crate::core::{auth::AuthUser, state::AppState, error::AppError, i18n::I18n};
+use crate::core::{auth::AuthUser, scope::Scope, state::AppState, error::AppError, i18n::I18n};
+use crate::command_bus;
 
 use super::{
     command,
     query,
     dto::{
         CreateInvoiceInput, UpdateInvoiceInput, CreateInvoiceLineInput, UpdateInvoiceLineInput,
-        ListInvoiceFilter,
+        JournalSeriesInput, ListInvoiceFilter,
     },
     metadata::invoice_form_schema,
 };
@@ -34,9 +36,10 @@ pub async fn get_metadata(headers: HeaderMap) -> Result<impl IntoResponse, AppEr
 pub async fn create_invoice(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    headers: HeaderMap,
     Json(input): Json<CreateInvoiceInput>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
     let dto = command::CreateInvoiceDto {
         journal_id: input.journal_id,
@@ -73,9 +76,7 @@ pub async fn create_invoice(
         shared_with: input.shared_with.unwrap_or_default(),
     };
 
-    let id = command::create_invoice(pool, auth.tenant_id, dto)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    let id = command_bus::dispatch(&state, &auth, &i18n, command::CreateInvoice { dto }).await?;
 
     Ok(Json(json!({ "id": id })))
 }
@@ -86,11 +87,12 @@ pub async fn create_invoice(
 pub async fn list_invoices(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Query(filter): Query<ListInvoiceFilter>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
-    let invoices = query::list_invoices(pool, auth.tenant_id, filter)
+    let invoices = query::list_invoices(pool, &auth, &scope, filter)
         .await
         .map_err(|e| AppError::internal(e.to_string()))?;
 
@@ -103,11 +105,14 @@ pub async fn list_invoices(
 pub async fn get_invoice_by_id(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(id): Path<Uuid>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
-    let invoice = query::get_invoice_by_id(pool, auth.tenant_id, id)
+    ensure_visible(pool, &auth, &scope, id).await?;
+
+    let invoice = query::get_invoice_by_id(&mut *pool.acquire().await?, auth.tenant_id, id)
         .await
         .map_err(|e| AppError::internal(e.to_string()))?;
 
@@ -123,10 +128,12 @@ pub async fn get_invoice_by_id(
 pub async fn update_invoice(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(input): Json<UpdateInvoiceInput>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
     let dto = command::UpdateInvoiceDto {
         journal_id: input.journal_id,
@@ -163,9 +170,7 @@ pub async fn update_invoice(
         }).collect()),
     };
 
-    command::update_invoice(pool, auth.tenant_id, id, dto)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    command_bus::dispatch(&state, &auth, &i18n, command::UpdateInvoice { invoice_id: id, dto, scope }).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -176,13 +181,36 @@ pub async fn update_invoice(
 pub async fn confirm_invoice(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
-    command::confirm_invoice(pool, auth.tenant_id, id, auth.user_id)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    command_bus::dispatch(&state, &auth, &i18n, command::ConfirmInvoice { invoice_id: id, scope }).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// -------------------------
+/// Set journal invoice series (ký hiệu hóa đơn)
+/// -------------------------
+pub async fn set_journal_series(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(journal_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(input): Json<JournalSeriesInput>,
+) -> Result<impl IntoResponse, AppError> {
+    let i18n = I18n::from_headers(&headers);
+
+    command_bus::dispatch(
+        &state,
+        &auth,
+        &i18n,
+        command::SetJournalSeries { journal_id, invoice_series: input.invoice_series },
+    )
+    .await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -193,13 +221,13 @@ pub async fn confirm_invoice(
 pub async fn cancel_invoice(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
-    command::cancel_invoice(pool, auth.tenant_id, id)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    command_bus::dispatch(&state, &auth, &i18n, command::CancelInvoice { invoice_id: id, scope }).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -210,13 +238,13 @@ pub async fn cancel_invoice(
 pub async fn delete_invoice(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
-    command::delete_invoice(pool, auth.tenant_id, id)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    command_bus::dispatch(&state, &auth, &i18n, command::DeleteInvoice { invoice_id: id, scope }).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -227,10 +255,12 @@ pub async fn delete_invoice(
 pub async fn add_invoice_line(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(input): Json<CreateInvoiceLineInput>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
     let dto = command::CreateInvoiceLineDto {
         product_id: input.product_id,
@@ -247,9 +277,8 @@ pub async fn add_invoice_line(
         analytic_distribution: input.analytic_distribution,
     };
 
-    let line_id = command::add_invoice_line(pool, auth.tenant_id, id, dto)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    let line_id =
+        command_bus::dispatch(&state, &auth, &i18n, command::AddInvoiceLine { invoice_id: id, dto, scope }).await?;
 
     Ok(Json(json!({ "id": line_id })))
 }
@@ -260,10 +289,12 @@ pub async fn add_invoice_line(
 pub async fn update_invoice_line(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path((id, line_id)): Path<(Uuid, Uuid)>,
+    headers: HeaderMap,
     Json(input): Json<UpdateInvoiceLineInput>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
     let dto = command::UpdateInvoiceLineDto {
         id: input.id,
@@ -281,9 +312,7 @@ pub async fn update_invoice_line(
         analytic_distribution: input.analytic_distribution,
     };
 
-    command::update_invoice_line(pool, auth.tenant_id, id, line_id, dto)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    command_bus::dispatch(&state, &auth, &i18n, command::UpdateInvoiceLine { invoice_id: id, line_id, dto, scope }).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -294,14 +323,22 @@ pub async fn update_invoice_line(
 pub async fn delete_invoice_line(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path((id, line_id)): Path<(Uuid, Uuid)>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
 
-    command::delete_invoice_line(pool, auth.tenant_id, id, line_id)
-        .await
-        .map_err(|e| AppError::bad_request(e.to_string()))?;
+    command_bus::dispatch(&state, &auth, &i18n, command::DeleteInvoiceLine { invoice_id: id, line_id, scope }).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Hoá đơn ngoài scope ABAC → 404 như không tồn tại
+async fn ensure_visible(pool: &PgPool, auth: &AuthUser, scope: &Scope, id: Uuid) -> Result<(), AppError> {
+    if scope.is_visible(pool, &query::SCOPE, auth, id).await? {
+        Ok(())
+    } else {
+        Err(AppError::not_found("Invoice not found"))
+    }
+}
@@ -5,15 +5,46 @@ pub mod query;
 pub mod model;
 pub mod dto;
 pub mod metadata;
+pub mod posting;
 
 pub mod event {
-    #[derive(Debug, Clone, Copy)]
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    use crate::infra::outbox::DomainEvent;
+
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+    #[serde(tag = "type")]
     pub enum InvoiceEvent {
-        InvoiceCreated,
-        InvoiceUpdated,
-        InvoicePosted,
-        InvoiceCancelled,
-        InvoiceDeleted,
+        InvoiceCreated { invoice_id: Uuid },
+        InvoiceUpdated { invoice_id: Uuid },
+        InvoicePosted { invoice_id: Uuid, posted_by: Uuid },
+        InvoiceCancelled { invoice_id: Uuid },
+        InvoiceDeleted { invoice_id: Uuid },
+    }
+
+    impl DomainEvent for InvoiceEvent {
+        const TOPIC: &'static str = "invoice";
+
+        fn event_type(&self) -> &'static str {
+            match self {
+                InvoiceEvent::InvoiceCreated { .. } => "InvoiceCreated",
+                InvoiceEvent::InvoiceUpdated { .. } => "InvoiceUpdated",
+                InvoiceEvent::InvoicePosted { .. } => "InvoicePosted",
+                InvoiceEvent::InvoiceCancelled { .. } => "InvoiceCancelled",
+                InvoiceEvent::InvoiceDeleted { .. } => "InvoiceDeleted",
+            }
+        }
+
+        fn aggregate_id(&self) -> Uuid {
+            match *self {
+                InvoiceEvent::InvoiceCreated { invoice_id }
+                | InvoiceEvent::InvoiceUpdated { invoice_id }
+                | InvoiceEvent::InvoicePosted { invoice_id, .. }
+                | InvoiceEvent::InvoiceCancelled { invoice_id }
+                | InvoiceEvent::InvoiceDeleted { invoice_id } => invoice_id,
+            }
+        }
     }
 }
 
@@ -0,0 +1,271 @@
+//! Bút toán kép khi ghi sổ hóa đơn: hóa đơn bán Nợ phải thu khách hàng / Có doanh thu (từng dòng sản phẩm)
+//! và Có thuế phải nộp (gộp theo thuế và tài khoản thuế); hóa đơn mua ghi ngược lại vào phải trả,
+//! hóa đơn trả lại (refund) đảo chiều hóa đơn gốc.
+use std::collections::BTreeMap;
+
+use sqlx::types::BigDecimal;
+use uuid::Uuid;
+
+/// Thuế gắn với dòng hóa đơn (bảng `account_tax`, tài khoản lấy từ repartition `tax`)
+#[derive(Debug, Clone)]
+pub struct LineTax {
+    pub tax_id: Uuid,
+    pub name: String,
+    /// percent / fixed / division
+    pub amount_type: String,
+    pub amount: BigDecimal,
+    /// None = tài khoản thuế mặc định
+    pub account_id: Option<Uuid>,
+}
+
+impl LineTax {
+    /// Tiền thuế của 1 dòng, làm tròn 2 chữ số thập phân
+    pub fn compute(&self, base: &BigDecimal, quantity: &BigDecimal) -> BigDecimal {
+        let hundred = BigDecimal::from(100);
+        let value = match self.amount_type.as_str() {
+            "fixed" => &self.amount * quantity,
+            // Thuế tính trên giá đã gồm thuế: base × rate / (100 − rate)
+            "division" if self.amount < hundred => base * &self.amount / (&hundred - &self.amount),
+            "division" => BigDecimal::from(0),
+            _ => base * &self.amount / &hundred,
+        };
+        value.round(2)
+    }
+}
+
+/// Dòng sản phẩm của hóa đơn
+#[derive(Debug, Clone)]
+pub struct ProductLine {
+    pub line_id: Uuid,
+    /// Tài khoản doanh thu
+    pub account_id: Uuid,
+    pub quantity: BigDecimal,
+    pub subtotal: BigDecimal,
+    pub taxes: Vec<LineTax>,
+    /// Thuế nhập theo `tax_rate` khi dòng không gắn `account_tax` (price_total − price_subtotal)
+    pub manual_tax: BigDecimal,
+}
+
+impl ProductLine {
+    /// Tổng thuế của dòng theo từng thuế (hoặc thuế nhập tay)
+    fn tax_amounts(&self) -> Vec<(Option<&LineTax>, BigDecimal)> {
+        if self.taxes.is_empty() {
+            vec![(None, self.manual_tax.clone())]
+        } else {
+            self.taxes.iter().map(|t| (Some(t), t.compute(&self.subtotal, &self.quantity))).collect()
+        }
+    }
+
+    pub fn tax_total(&self) -> BigDecimal {
+        self.tax_amounts().into_iter().map(|(_, v)| v).sum()
+    }
+}
+
+/// Loại hóa đơn (`account_move.move_type`) quyết định chiều Nợ/Có và tài khoản công nợ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveType {
+    OutInvoice,
+    OutRefund,
+    OutReceipt,
+    InInvoice,
+    InRefund,
+    InReceipt,
+}
+
+impl MoveType {
+    /// None = bút toán thường (`entry`) hoặc loại không hỗ trợ
+    pub fn parse(move_type: &str) -> Option<Self> {
+        match move_type {
+            "out_invoice" => Some(MoveType::OutInvoice),
+            "out_refund" => Some(MoveType::OutRefund),
+            "out_receipt" => Some(MoveType::OutReceipt),
+            "in_invoice" => Some(MoveType::InInvoice),
+            "in_refund" => Some(MoveType::InRefund),
+            "in_receipt" => Some(MoveType::InReceipt),
+            _ => None,
+        }
+    }
+
+    /// Bán (phải thu khách hàng) hay mua (phải trả nhà cung cấp)
+    pub fn is_sale(&self) -> bool {
+        matches!(self, MoveType::OutInvoice | MoveType::OutRefund | MoveType::OutReceipt)
+    }
+
+    /// Dòng công nợ ghi Nợ (bán, mua trả lại) hay ghi Có (mua, bán trả lại)
+    fn partner_debit(&self) -> bool {
+        matches!(self, MoveType::OutInvoice | MoveType::OutReceipt | MoveType::InRefund)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryKind {
+    /// Ghi Có doanh thu ngay trên dòng sản phẩm
+    Revenue { line_id: Uuid },
+    /// Dòng thuế (None = thuế nhập tay)
+    Tax { tax_id: Option<Uuid>, name: Option<String>, base: BigDecimal },
+    /// Phải thu (hóa đơn bán) / phải trả (hóa đơn mua)
+    Partner,
+}
+
+/// 1 dòng bút toán (Nợ hoặc Có)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub kind: EntryKind,
+    pub account_id: Uuid,
+    pub debit: BigDecimal,
+    pub credit: BigDecimal,
+}
+
+impl Entry {
+    /// Số dương → Nợ, số âm → Có
+    fn signed(kind: EntryKind, account_id: Uuid, balance: BigDecimal) -> Self {
+        let zero = BigDecimal::from(0);
+        let (debit, credit) = if balance >= zero { (balance, zero) } else { (zero, -balance) };
+        Entry { kind, account_id, debit, credit }
+    }
+
+    /// debit − credit
+    pub fn balance(&self) -> BigDecimal {
+        &self.debit - &self.credit
+    }
+}
+
+/// Thuế cộng dồn theo (thuế, tài khoản)
+#[derive(Default)]
+struct TaxTotal {
+    name: Option<String>,
+    amount: BigDecimal,
+    base: BigDecimal,
+}
+
+/// ✅ Lập bút toán cho hóa đơn: hóa đơn bán Có doanh thu từng dòng, Có thuế gộp theo (thuế, tài khoản),
+/// Nợ phải thu = tổng cộng; các loại khác đổi chiều theo `move_type`. Bỏ qua dòng thuế bằng 0.
+pub fn build(move_type: MoveType, lines: &[ProductLine], partner_account: Uuid, default_tax_account: Uuid) -> Vec<Entry> {
+    let zero = BigDecimal::from(0);
+    // số dương → ghi Nợ dòng công nợ, Có dòng sản phẩm / thuế
+    let sign = |v: BigDecimal| if move_type.partner_debit() { v } else { -v };
+    let mut entries = Vec::new();
+    let mut taxes: BTreeMap<(Option<Uuid>, Uuid), TaxTotal> = BTreeMap::new();
+    let mut total = BigDecimal::from(0);
+
+    for line in lines {
+        entries.push(Entry::signed(EntryKind::Revenue { line_id: line.line_id }, line.account_id, sign(-line.subtotal.clone())));
+        total += &line.subtotal;
+
+        for (tax, amount) in line.tax_amounts() {
+            if amount == zero {
+                continue;
+            }
+            let account = tax.and_then(|t| t.account_id).unwrap_or(default_tax_account);
+            let slot = taxes.entry((tax.map(|t| t.tax_id), account)).or_default();
+            slot.name = tax.map(|t| t.name.clone());
+            slot.amount += &amount;
+            slot.base += &line.subtotal;
+            total += amount;
+        }
+    }
+
+    for ((tax_id, account_id), TaxTotal { name, amount, base }) in taxes {
+        entries.push(Entry::signed(EntryKind::Tax { tax_id, name, base }, account_id, sign(-amount)));
+    }
+    entries.push(Entry::signed(EntryKind::Partner, partner_account, sign(total)));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(v: &str) -> BigDecimal {
+        BigDecimal::from_str(v).unwrap()
+    }
+
+    fn vat(id: u128, rate: &str) -> LineTax {
+        LineTax {
+            tax_id: Uuid::from_u128(id),
+            name: format!("VAT {rate}%"),
+            amount_type: "percent".into(),
+            amount: dec(rate),
+            account_id: None,
+        }
+    }
+
+    fn is_balanced(entries: &[Entry]) -> bool {
+        entries.iter().map(Entry::balance).sum::<BigDecimal>() == BigDecimal::from(0)
+    }
+
+    fn line(id: u128, subtotal: &str, taxes: Vec<LineTax>) -> ProductLine {
+        ProductLine {
+            line_id: Uuid::from_u128(id),
+            account_id: Uuid::from_u128(400),
+            quantity: dec("1"),
+            subtotal: dec(subtotal),
+            taxes,
+            manual_tax: BigDecimal::from(0),
+        }
+    }
+
+    #[test]
+    fn receivable_equals_revenue_plus_tax() {
+        let (receivable, tax_account) = (Uuid::from_u128(131), Uuid::from_u128(333));
+        let lines = [
+            line(1, "1000000", vec![vat(10, "10")]),
+            line(2, "500000", vec![vat(10, "10")]),
+            line(3, "200000", vec![]),
+        ];
+        let entries = build(MoveType::OutInvoice, &lines, receivable, tax_account);
+        assert!(is_balanced(&entries));
+
+        // 3 dòng doanh thu + 1 dòng thuế gộp + phải thu
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[3].credit, dec("150000"));
+        assert_eq!(entries[3].account_id, tax_account);
+        assert!(matches!(&entries[3].kind, EntryKind::Tax { base, .. } if *base == dec("1500000")));
+        assert_eq!(entries[4].kind, EntryKind::Partner);
+        assert_eq!(entries[4].debit, dec("1850000"));
+        assert_eq!(lines[0].tax_total(), dec("100000"));
+    }
+
+    #[test]
+    fn tax_types_and_negative_lines() {
+        let base = dec("1000000");
+        let qty = dec("3");
+        let mut tax = vat(1, "10");
+        assert_eq!(tax.compute(&base, &qty), dec("100000"));
+        tax.amount_type = "fixed".into();
+        tax.amount = dec("2500");
+        assert_eq!(tax.compute(&base, &qty), dec("7500"));
+        tax.amount_type = "division".into();
+        tax.amount = dec("20");
+        assert_eq!(tax.compute(&base, &qty), dec("250000"));
+
+        // Dòng giảm giá âm → ghi Nợ doanh thu, vẫn cân
+        let mut discount = line(2, "-100000", vec![]);
+        discount.manual_tax = dec("-10000");
+        let entries = build(MoveType::OutInvoice, &[line(1, "1000000", vec![vat(1, "10")]), discount], Uuid::nil(), Uuid::nil());
+        assert!(is_balanced(&entries));
+        assert_eq!(entries[1].debit, dec("100000"));
+        assert_eq!(entries.last().unwrap().debit, dec("990000"));
+    }
+
+    #[test]
+    fn direction_follows_move_type() {
+        let lines = [line(1, "1000000", vec![vat(10, "10")])];
+        let (partner, tax_account) = (Uuid::from_u128(331), Uuid::from_u128(133));
+
+        // Hóa đơn mua: Nợ chi phí + thuế, Có phải trả
+        let bill = build(MoveType::InInvoice, &lines, partner, tax_account);
+        assert!(is_balanced(&bill));
+        assert_eq!(bill[0].debit, dec("1000000"));
+        assert_eq!(bill[1].debit, dec("100000"));
+        assert_eq!(bill[2].credit, dec("1100000"));
+
+        // Trả lại hàng bán: đảo chiều hóa đơn bán
+        let refund = build(MoveType::OutRefund, &lines, partner, tax_account);
+        let invoice = build(MoveType::OutInvoice, &lines, partner, tax_account);
+        assert!(refund.iter().zip(&invoice).all(|(r, i)| r.debit == i.credit && r.credit == i.debit));
+        assert_eq!(build(MoveType::InRefund, &lines, partner, tax_account), invoice);
+        assert!(MoveType::parse("entry").is_none());
+    }
+}
@@ -1,13 +1,20 @@
 use uuid::Uuid;
-use sqlx::{Pool, Postgres, Row};
+use sqlx::{PgConnection, Pool, Postgres, QueryBuilder, Row};
 use sqlx::types::BigDecimal;
 
+use crate::core::auth::AuthUser;
+use crate::core::scope::{Scope, ScopeTarget, OWNERSHIP_COLUMNS};
+
 use super::dto::{InvoiceDto, InvoiceLineDto, ListInvoiceFilter};
 
-/// List invoices with filters
+/// Bảng + cột được dùng trong scope ABAC của resource `invoice`
+pub const SCOPE: ScopeTarget = ScopeTarget { table: "account_move", columns: OWNERSHIP_COLUMNS };
+
+/// List invoices with filters (chỉ các hoá đơn trong scope của `invoice.read`)
 pub async fn list_invoices(
     pool: &Pool<Postgres>,
-    tenant_id: Uuid,
+    auth: &AuthUser,
+    scope: &Scope,
     filter: ListInvoiceFilter,
 ) -> Result<Vec<InvoiceDto>, sqlx::Error> {
     let limit = filter.limit.unwrap_or(100).clamp(1, 500);
@@ -15,7 +22,7 @@ pub async fn list_invoices(
 
     // Simplified query - filter in application layer for now
     // Use query and map manually to avoid type inference issues
-    let rows = sqlx::query(
+    let mut qb = QueryBuilder::<Postgres>::new(
         r#"
         SELECT 
             am.id, am.tenant_id,
@@ -33,16 +40,14 @@ pub async fn list_invoices(
             am.narration,
             am.created_at, am.updated_at, am.created_by, am.assignee_id
         FROM account_move am
-        WHERE am.tenant_id = $1
-        ORDER BY am.date DESC, am.created_at DESC
-        LIMIT $2 OFFSET $3
-        "#
-    )
-    .bind(tenant_id)
-    .bind(limit as i64)
-    .bind(offset as i64)
-    .fetch_all(pool)
-    .await?;
+        WHERE am.tenant_id = "#,
+    );
+    qb.push_bind(auth.tenant_id);
+    scope.push_filter(&mut qb, &SCOPE, "am", auth);
+    qb.push(" ORDER BY am.date DESC, am.created_at DESC LIMIT ").push_bind(limit);
+    qb.push(" OFFSET ").push_bind(offset);
+
+    let rows = qb.build().fetch_all(pool).await?;
 
     let mut invoices = Vec::new();
     for row in rows {
@@ -85,7 +90,7 @@ pub async fn list_invoices(
 
 /// Get invoice by ID with lines
 pub async fn get_invoice_by_id(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
 ) -> Result<Option<InvoiceDto>, sqlx::Error> {
@@ -113,12 +118,12 @@ pub async fn get_invoice_by_id(
     )
     .bind(tenant_id)
     .bind(invoice_id)
-    .fetch_optional(pool)
+    .fetch_optional(&mut *conn)
     .await?;
 
     if let Some(row) = row_opt {
         // Get invoice lines
-        let lines = get_invoice_lines(pool, tenant_id, invoice_id).await?;
+        let lines = get_invoice_lines(&mut *conn, tenant_id, invoice_id).await?;
 
         let invoice = InvoiceDto {
             id: row.try_get("id")?,
@@ -161,7 +166,7 @@ pub async fn get_invoice_by_id(
 
 /// Get invoice lines for an invoice
 pub async fn get_invoice_lines(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     invoice_id: Uuid,
 ) -> Result<Vec<InvoiceLineDto>, sqlx::Error> {
@@ -179,12 +184,13 @@ pub async fn get_invoice_lines(
             aml.sequence
         FROM account_move_line aml
         WHERE aml.tenant_id = $1 AND aml.move_id = $2
+            AND NOT COALESCE(aml.exclude_from_invoice_tab, false) -- dòng thuế / phải thu sinh khi ghi sổ
         ORDER BY COALESCE(aml.sequence, 0), aml.id
         "#
     )
     .bind(tenant_id)
     .bind(invoice_id)
-    .fetch_all(pool)
+    .fetch_all(&mut *conn)
     .await?;
 
     // Get tax IDs for each line
@@ -196,7 +202,7 @@ pub async fn get_invoice_lines(
             "SELECT tax_id FROM account_move_line_tax_rel WHERE tenant_id = $1 AND move_line_id = $2",
             tenant_id, line_id
         )
-        .fetch_all(pool)
+        .fetch_all(&mut *conn)
         .await?
         .into_iter()
         .map(|r| r.tax_id)
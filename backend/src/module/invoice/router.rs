@@ -3,6 +3,7 @@ use axum::routing::{delete, put};
 use std::sync::Arc;
 
 use crate::core::{state::AppState, auth::jwt_auth};
+use crate::core::iam::RequirePermission;
 use super::handler;
 
 pub fn routes() -> Router<Arc<AppState>> {
@@ -11,17 +12,18 @@ pub fn routes() -> Router<Arc<AppState>> {
         .nest(
             "/invoice",
             Router::new()
-                .route("/create", post(handler::create_invoice))
-                .route("/list", get(handler::list_invoices))
-                .route("/:id", get(handler::get_invoice_by_id))
-                .route("/:id/update", put(handler::update_invoice))
-                .route("/:id/confirm", post(handler::confirm_invoice))
-                .route("/:id/cancel", post(handler::cancel_invoice))
-                .route("/:id", delete(handler::delete_invoice))
+                .route("/create", post(handler::create_invoice).route_layer(RequirePermission::new("invoice", "create")))
+                .route("/list", get(handler::list_invoices).route_layer(RequirePermission::new("invoice", "read")))
+                .route("/:id", get(handler::get_invoice_by_id).route_layer(RequirePermission::new("invoice", "read")))
+                .route("/:id/update", put(handler::update_invoice).route_layer(RequirePermission::new("invoice", "update")))
+                .route("/:id/confirm", post(handler::confirm_invoice).route_layer(RequirePermission::new("invoice", "confirm")))
+                .route("/:id/cancel", post(handler::cancel_invoice).route_layer(RequirePermission::new("invoice", "cancel")))
+                .route("/journals/:journal_id/series", post(handler::set_journal_series).route_layer(RequirePermission::new("sequence", "manage")))
+                .route("/:id", delete(handler::delete_invoice).route_layer(RequirePermission::new("invoice", "delete")))
                 // Invoice lines
-                .route("/:id/line", post(handler::add_invoice_line))
-                .route("/:id/line/:line_id", put(handler::update_invoice_line))
-                .route("/:id/line/:line_id", delete(handler::delete_invoice_line))
+                .route("/:id/line", post(handler::add_invoice_line).route_layer(RequirePermission::new("invoice", "update")))
+                .route("/:id/line/:line_id", put(handler::update_invoice_line).route_layer(RequirePermission::new("invoice", "update")))
+                .route("/:id/line/:line_id", delete(handler::delete_invoice_line).route_layer(RequirePermission::new("invoice", "update")))
                 .layer(middleware::from_fn(jwt_auth)),
         )
 }
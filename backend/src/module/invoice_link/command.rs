@@ -1,17 +1,20 @@
 use uuid::Uuid;
-use sqlx::{Pool, Postgres};
+use sqlx::PgConnection;
 use serde_json::json;
 use tracing::{error, info, warn};
 use anyhow::Result;
 use chrono::{Utc, Duration, DateTime};
 use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
+use async_trait::async_trait;
 
 use super::{
     model::{InvoiceLinkStatus, ProviderCredentials},
     dto::{LinkProviderInput, SendInvoiceToProviderInput},
     invoice_link_viettel,
 };
+use crate::command_bus::{Command, CommandContext};
+use crate::core::error::AppError;
 use crate::module::invoice::query as invoice_query;
 use crate::module::contact::query as contact_query;
 
@@ -49,7 +52,7 @@ fn get_token_expiry(token: &str) -> Option<DateTime<Utc>> {
 /// Kiểm tra và refresh token nếu cần
 /// Trả về access_token mới (hoặc token cũ nếu còn hạn)
 async fn ensure_valid_token(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     credentials: &mut ProviderCredentials,
 ) -> Result<String, sqlx::Error> {
     // Kiểm tra xem token có tồn tại và còn hạn không
@@ -137,7 +140,7 @@ async fn ensure_valid_token(
         credentials.id,
         credentials.tenant_id,
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     info!("Token refreshed successfully for credential {}", credentials.id);
@@ -151,7 +154,7 @@ async fn ensure_valid_token(
 
 /// Link provider với tenant (lưu credentials)
 pub async fn link_provider(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     user_id: Uuid,
     input: LinkProviderInput,
@@ -226,7 +229,7 @@ pub async fn link_provider(
             tenant_id,
             input.provider,
         )
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
     }
 
@@ -240,7 +243,7 @@ pub async fn link_provider(
         input.provider,
         user_id,
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut *conn)
     .await?;
 
     // Lấy thời gian hết hạn từ token JWT
@@ -274,7 +277,7 @@ pub async fn link_provider(
             record.id,
             tenant_id,
         )
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
 
         record.id
@@ -299,7 +302,7 @@ pub async fn link_provider(
             Utc::now(),
             Utc::now(),
         )
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
 
         id
@@ -309,15 +312,16 @@ pub async fn link_provider(
     Ok(credential_id)
 }
 
-/// Gửi hóa đơn đến provider
+/// Gửi hóa đơn đến provider, trả về id của invoice_link.
+/// Provider từ chối → link ghi trạng thái `failed` kèm lỗi (vẫn trả về id để xem kết quả)
 pub async fn send_invoice_to_provider(
-    pool: &Pool<Postgres>,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     user_id: Uuid,
     input: SendInvoiceToProviderInput,
 ) -> Result<Uuid, sqlx::Error> {
     // 1. Lấy invoice từ database
-    let invoice = invoice_query::get_invoice_by_id(pool, tenant_id, input.invoice_id)
+    let invoice = invoice_query::get_invoice_by_id(&mut *conn, tenant_id, input.invoice_id)
         .await?
         .ok_or_else(|| sqlx::Error::RowNotFound)?;
 
@@ -334,7 +338,7 @@ pub async fn send_invoice_to_provider(
             tenant_id,
             input.provider,
         )
-        .fetch_optional(pool)
+        .fetch_optional(&mut *conn)
         .await?
     } else {
         // Ưu tiên lấy credentials mặc định, nếu không có thì lấy mới nhất
@@ -350,7 +354,7 @@ pub async fn send_invoice_to_provider(
             tenant_id,
             input.provider,
         )
-        .fetch_optional(pool)
+        .fetch_optional(&mut *conn)
         .await?
     }
     .ok_or_else(|| {
@@ -359,7 +363,7 @@ pub async fn send_invoice_to_provider(
     })?;
 
     // 2.5. Đảm bảo token còn hạn, nếu không thì refresh
-    let access_token = ensure_valid_token(pool, &mut credentials)
+    let access_token = ensure_valid_token(&mut *conn, &mut credentials)
         .await
         .map_err(|e| {
             error!("Failed to ensure valid token: {:?}", e);
@@ -368,7 +372,7 @@ pub async fn send_invoice_to_provider(
 
     // 2.6. Lấy thông tin contact nếu invoice có partner_id
     let contact_info = if let Some(partner_id) = invoice.partner_id {
-        contact_query::get_contact_by_id(pool, tenant_id, partner_id)
+        contact_query::get_contact_by_id(&mut *conn, tenant_id, partner_id)
             .await
             .ok()
     } else {
@@ -392,7 +396,7 @@ pub async fn send_invoice_to_provider(
         chrono::Utc::now(),
         chrono::Utc::now(),
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     // 4. Gửi invoice đến provider với retry logic
@@ -454,7 +458,7 @@ pub async fn send_invoice_to_provider(
                                 credentials.id,
                                 credentials.tenant_id,
                             )
-                            .execute(pool)
+                            .execute(&mut *conn)
                             .await?;
                             
                             info!("Token refreshed after 401 error, retrying create invoice...");
@@ -510,7 +514,7 @@ pub async fn send_invoice_to_provider(
                 link_id,
                 tenant_id,
             )
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
 
             info!("Invoice {} sent to {} successfully", input.invoice_id, input.provider);
@@ -534,11 +538,50 @@ pub async fn send_invoice_to_provider(
                 link_id,
                 tenant_id,
             )
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
 
-            Err(sqlx::Error::RowNotFound)
+            Ok(link_id)
         }
     }
 }
 
+/* ========== Command bus ========== */
+
+/// Liên kết provider HĐĐT với tenant (POST /invoice-link/providers/link), trả về id credentials
+pub struct LinkProvider {
+    pub input: LinkProviderInput,
+}
+
+#[async_trait]
+impl Command for LinkProvider {
+    type Output = Uuid;
+    const NAME: &'static str = "invoice_link.link_provider";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice_link", "manage"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Uuid, AppError> {
+        let (tenant_id, user_id) = (ctx.tenant_id(), ctx.user_id());
+        link_provider(ctx.conn(), tenant_id, user_id, self.input)
+            .await
+            .map_err(|e| AppError::bad_request(format!("Không thể liên kết với provider: {}", e)))
+    }
+}
+
+/// Gửi hóa đơn tới provider (POST /invoice-link/send), trả về id invoice_link
+pub struct SendInvoiceToProvider {
+    pub input: SendInvoiceToProviderInput,
+}
+
+#[async_trait]
+impl Command for SendInvoiceToProvider {
+    type Output = Uuid;
+    const NAME: &'static str = "invoice_link.send";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("invoice_link", "send"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Uuid, AppError> {
+        let (tenant_id, user_id) = (ctx.tenant_id(), ctx.user_id());
+        send_invoice_to_provider(ctx.conn(), tenant_id, user_id, self.input)
+            .await
+            .map_err(|e| AppError::bad_request(format!("Failed to send invoice: {}", e)))
+    }
+}
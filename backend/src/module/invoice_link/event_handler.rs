@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::event_handler::{EventContext, EventHandler, EventHandlerRegistry};
+use crate::module::invoice::event::InvoiceEvent;
+use crate::module::invoice_link::{command, dto::SendInvoiceToProviderInput, query};
+
+/// Tự động gửi hóa đơn đã ghi sổ tới nhà cung cấp HĐĐT mặc định của tenant
+pub struct AutoSendInvoiceToProvider;
+
+#[async_trait]
+impl EventHandler<InvoiceEvent> for AutoSendInvoiceToProvider {
+    fn name(&self) -> &'static str {
+        "invoice_link.auto_send"
+    }
+
+    fn event_types(&self) -> &'static [&'static str] {
+        &["InvoicePosted"]
+    }
+
+    async fn handle(&self, ctx: &EventContext<'_>, event: InvoiceEvent) -> Result<(), String> {
+        let InvoiceEvent::InvoicePosted { invoice_id, posted_by } = event else {
+            return Ok(());
+        };
+        let pool = ctx.pool().await?;
+        let tenant_id = ctx.tenant_id();
+
+        // Tenant chưa chọn provider mặc định → không tự gửi
+        let Some(credential) = sqlx::query!(
+            r#"
+            SELECT id, provider
+            FROM invoice_link_provider_credentials
+            WHERE tenant_id = $1 AND is_default = true AND is_active = true
+            ORDER BY updated_at DESC
+            LIMIT 1
+            "#,
+            tenant_id
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        else {
+            return Ok(());
+        };
+
+        // Đã gửi (hoặc đang gửi) thì bỏ qua
+        let already_sent = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM invoice_link
+                WHERE tenant_id = $1 AND invoice_id = $2 AND status IN ('pending', 'linked')
+            ) AS "exists!"
+            "#,
+            tenant_id,
+            invoice_id
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        if already_sent {
+            return Ok(());
+        }
+
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+        let link_id = command::send_invoice_to_provider(
+            &mut conn,
+            tenant_id,
+            posted_by,
+            SendInvoiceToProviderInput {
+                invoice_id,
+                provider: credential.provider.clone(),
+                credential_id: Some(credential.id),
+            },
+        )
+        .await
+        .map_err(|e| format!("Gửi hóa đơn {} tới {} lỗi: {}", invoice_id, credential.provider, e))?;
+
+        // Provider từ chối → báo lỗi để worker thử lại (link `failed` không chặn lần gửi sau)
+        if let Some(link) = query::get_invoice_link_by_id(pool, tenant_id, link_id).await.map_err(|e| e.to_string())? {
+            if link.status == "failed" {
+                return Err(format!(
+                    "Gửi hóa đơn {} tới {} lỗi: {}",
+                    invoice_id,
+                    credential.provider,
+                    link.error_message.unwrap_or_default()
+                ));
+            }
+        }
+
+        info!("Invoice {} auto-sent to {} (link {})", invoice_id, credential.provider, link_id);
+        Ok(())
+    }
+}
+
+pub fn register(registry: &mut EventHandlerRegistry) {
+    registry.subscribe(AutoSendInvoiceToProvider);
+}
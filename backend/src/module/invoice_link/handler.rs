@@ -1,5 +1,6 @@
 use axum::{
     extract::{Path, Query, State},
+    http::HeaderMap,
     response::IntoResponse,
     Json,
 };
@@ -7,7 +8,8 @@ use serde_json::json;
 use uuid::Uuid;
 use std::sync::Arc;
 
-use crate::core::{auth::AuthUser, state::AppState, error::AppError};
+use crate::command_bus;
+use crate::core::{auth::AuthUser, state::AppState, error::AppError, i18n::I18n};
 use super::{
     command,
     query,
@@ -96,84 +98,57 @@ pub async fn get_provider_form_fields(
 #[axum::debug_handler]
 pub async fn link_provider(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     auth: AuthUser,
     Json(input): Json<LinkProviderInput>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
-
-    match command::link_provider(pool, auth.tenant_id, auth.user_id, input.clone()).await {
-        Ok(credential_id) => {
-            Ok(Json(LinkProviderResponse {
-                success: true,
-                message: format!("Đã liên kết thành công với {}", input.provider),
-                credential_id: Some(credential_id),
-                provider: input.provider,
-            }))
-        }
-        Err(e) => {
-            Err(AppError::bad_request(format!("Không thể liên kết với provider: {}", e)))
-        }
-    }
+    let i18n = I18n::from_headers(&headers);
+    let provider = input.provider.clone();
+
+    let credential_id = command_bus::dispatch(&state, &auth, &i18n, command::LinkProvider { input }).await?;
+
+    Ok(Json(LinkProviderResponse {
+        success: true,
+        message: format!("Đã liên kết thành công với {}", provider),
+        credential_id: Some(credential_id),
+        provider,
+    }))
 }
 
 /// Gửi hóa đơn đến provider
 #[axum::debug_handler]
 pub async fn send_invoice_to_provider(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     auth: AuthUser,
     Json(input): Json<SendInvoiceToProviderInput>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
-
-    match command::send_invoice_to_provider(pool, auth.tenant_id, auth.user_id, input.clone()).await {
-        Ok(link_id) => {
-            // Lấy thông tin link vừa tạo
-            let link = query::get_invoice_link_by_id(pool, auth.tenant_id, link_id)
-                .await
-                .map_err(|e| AppError::internal(e.to_string()))?;
-
-            match link {
-                Some(l) => {
-                    let status = l.status.clone();
-                    Ok(Json(SendInvoiceResponse {
-                        link_id: l.id,
-                        status: status.clone(),
-                        provider_invoice_id: l.provider_invoice_id,
-                        provider_invoice_number: l.provider_invoice_number,
-                        message: if status == "linked" {
-                            Some(format!("Hóa đơn đã được gửi thành công đến {}", input.provider))
-                        } else if status == "failed" {
-                            l.error_message
-                        } else {
-                            Some("Đang xử lý...".to_string())
-                        },
-                    }))
-                },
-                None => Err(AppError::internal("Failed to retrieve invoice link")),
-            }
-        }
-        Err(e) => {
-            // Nếu có lỗi, vẫn trả về link_id nếu đã tạo được record
-            if let Ok(link) = query::get_latest_invoice_link_by_invoice_id(
-                pool,
-                auth.tenant_id,
-                input.invoice_id,
-            )
-            .await
-            {
-                if let Some(l) = link {
-                    return Ok(Json(SendInvoiceResponse {
-                        link_id: l.id,
-                        status: l.status,
-                        provider_invoice_id: l.provider_invoice_id,
-                        provider_invoice_number: l.provider_invoice_number,
-                        message: l.error_message,
-                    }));
-                }
-            }
-            Err(AppError::bad_request(format!("Failed to send invoice: {}", e)))
-        }
-    }
+    let i18n = I18n::from_headers(&headers);
+    let provider = input.provider.clone();
+
+    let link_id = command_bus::dispatch(&state, &auth, &i18n, command::SendInvoiceToProvider { input }).await?;
+
+    // Lấy thông tin link vừa tạo
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+    let link = query::get_invoice_link_by_id(pool, auth.tenant_id, link_id)
+        .await
+        .map_err(|e| AppError::internal(e.to_string()))?
+        .ok_or_else(|| AppError::internal("Failed to retrieve invoice link"))?;
+
+    let status = link.status.clone();
+    Ok(Json(SendInvoiceResponse {
+        link_id: link.id,
+        status: status.clone(),
+        provider_invoice_id: link.provider_invoice_id,
+        provider_invoice_number: link.provider_invoice_number,
+        message: if status == "linked" {
+            Some(format!("Hóa đơn đã được gửi thành công đến {}", provider))
+        } else if status == "failed" {
+            link.error_message
+        } else {
+            Some("Đang xử lý...".to_string())
+        },
+    }))
 }
 
 /// Lấy danh sách invoice links
@@ -182,7 +157,7 @@ pub async fn list_invoice_links(
     auth: AuthUser,
     Query(filter): Query<ListInvoiceLinkFilter>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
     let links = query::list_invoice_links(pool, auth.tenant_id, filter)
         .await
@@ -197,7 +172,7 @@ pub async fn get_invoice_link_by_id(
     auth: AuthUser,
     Path(id): Path<Uuid>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
     let link = query::get_invoice_link_by_id(pool, auth.tenant_id, id)
         .await
@@ -215,7 +190,7 @@ pub async fn get_invoice_link_by_invoice_id(
     auth: AuthUser,
     Path(invoice_id): Path<Uuid>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
     let link = query::get_latest_invoice_link_by_invoice_id(pool, auth.tenant_id, invoice_id)
         .await
@@ -232,7 +207,7 @@ pub async fn list_provider_credentials(
     State(state): State<Arc<AppState>>,
     auth: AuthUser,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
 
     let credentials = query::list_provider_credentials(pool, auth.tenant_id)
         .await
@@ -4,6 +4,7 @@ pub mod command;
 pub mod query;
 pub mod model;
 pub mod dto;
+pub mod event_handler;
 
 // Sub-modules cho các provider
 pub mod invoice_link_viettel;
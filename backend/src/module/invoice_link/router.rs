@@ -2,6 +2,7 @@ use axum::{Router, routing::{get, post}, middleware};
 use std::sync::Arc;
 
 use crate::core::{state::AppState, auth::jwt_auth};
+use crate::core::iam::RequirePermission;
 use super::handler;
 
 pub fn routes() -> Router<Arc<AppState>> {
@@ -10,15 +11,15 @@ pub fn routes() -> Router<Arc<AppState>> {
             "/invoice-link",
             Router::new()
                 // Provider management
-                .route("/providers", get(handler::list_providers))
-                .route("/providers/:provider/form-fields", get(handler::get_provider_form_fields))
-                .route("/providers/link", post(handler::link_provider))
-                .route("/providers/credentials", get(handler::list_provider_credentials))
+                .route("/providers", get(handler::list_providers).route_layer(RequirePermission::new("invoice_link", "read")))
+                .route("/providers/:provider/form-fields", get(handler::get_provider_form_fields).route_layer(RequirePermission::new("invoice_link", "read")))
+                .route("/providers/link", post(handler::link_provider).route_layer(RequirePermission::new("invoice_link", "manage")))
+                .route("/providers/credentials", get(handler::list_provider_credentials).route_layer(RequirePermission::new("invoice_link", "read")))
                 // Invoice linking
-                .route("/send", post(handler::send_invoice_to_provider))
-                .route("/list", get(handler::list_invoice_links))
-                .route("/:id", get(handler::get_invoice_link_by_id))
-                .route("/invoice/:invoice_id", get(handler::get_invoice_link_by_invoice_id))
+                .route("/send", post(handler::send_invoice_to_provider).route_layer(RequirePermission::new("invoice_link", "send")))
+                .route("/list", get(handler::list_invoice_links).route_layer(RequirePermission::new("invoice_link", "read")))
+                .route("/:id", get(handler::get_invoice_link_by_id).route_layer(RequirePermission::new("invoice_link", "read")))
+                .route("/invoice/:invoice_id", get(handler::get_invoice_link_by_invoice_id).route_layer(RequirePermission::new("invoice_link", "read")))
                 .layer(middleware::from_fn(jwt_auth)),
         )
 }
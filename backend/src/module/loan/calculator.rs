@@ -1,11 +1,12 @@
 use chrono::{DateTime, NaiveDate, Utc};
-use chrono_tz::Asia::Bangkok;
+use serde::Serialize;
 use crate::module::loan::model::{LoanContract, LoanTransaction};
+use crate::module::loan::convention::business_date;
+use crate::module::loan::delinquency;
+use crate::module::loan::waterfall::{Applied, Split};
 
 #[inline]
 fn clamp_zero(x: f64) -> f64 { if x < 0.0 { 0.0 } else { x } }
-#[inline]
-fn biz_date(dt_utc: DateTime<Utc>) -> NaiveDate { dt_utc.with_timezone(&Bangkok).date_naive() }
 
 pub fn calculate_interest_fields(contract: &mut LoanContract, txs: &mut [LoanTransaction]) {
     calculate_interest_fields_as_of(contract, txs, Utc::now());
@@ -21,32 +22,55 @@ pub fn calculate_interest_fields_as_of(
     let mut accrued_interest_unpaid: f64 = 0.0;
     let mut total_paid_interest: i64 = 0;
     let mut total_paid_principal: i64 = 0; // 👈 mới
+    let mut penalty_paid: f64 = 0.0;
+    let mut storage_paid: f64 = 0.0;
+    // (ngày, gốc + lãi đã phân bổ) → đối chiếu với lịch trả nợ
+    let mut payments: Vec<(NaiveDate, i64)> = Vec::new();
+
+    // ngày nghiệp vụ theo múi giờ tenant, lãi theo quy ước của hợp đồng + lãi suất từng giai đoạn cơ cấu
+    let tz = contract.tz();
+    let convention = contract.convention();
+    let rates = contract.rate_timeline();
 
-    let mut prev_date: NaiveDate = biz_date(contract.date_start);
-    let daily_rate: f64 = (contract.interest_rate as f64) / 100.0 / 365.0;
+    let start_local: NaiveDate = business_date(contract.date_start, tz);
+    let mut prev_date = start_local;
 
-    let mut today_local = biz_date(as_of);
+    // 🏬 Phí lưu kho cộng dồn từng ngày theo giá trị tài sản cầm cố của ngày đó + phí cố định
+    let storage = contract.storage_fee();
+    let storage_accrued_to = |d: NaiveDate| storage.accrue(start_local, d);
+
+    let mut today_local = business_date(as_of, tz);
     if let Some(end) = contract.date_end {
-        let end_local = biz_date(end);
+        let end_local = business_date(end, tz);
         if today_local > end_local { today_local = end_local; }
     }
 
     // đảm bảo order ổn định
     txs.sort_by_key(|tx| (tx.date, tx.id));
     let mut stop_at: Option<NaiveDate> = None;
+    let mut credit: i64 = 0;
 
     for tx in txs.iter_mut() {
-        let cur = biz_date(tx.date);
-
         // reset projection per-tx
         tx.principal_applied = 0;
         tx.interest_applied  = 0;
+        tx.penalty_applied   = 0;
+        tx.fee_applied       = 0;
+
+        // giao dịch sau thời điểm tính → chưa xảy ra
+        if tx.date > as_of { break; }
+        // sau tất toán chỉ còn phí trả trước hạn thu kèm
+        if stop_at.is_some() {
+            if tx.transaction_type == "prepayment_fee" { tx.fee_applied = tx.amount.abs(); }
+            continue;
+        }
+        let cur = business_date(tx.date, tz);
 
         let days = (cur - prev_date).num_days().max(0);
         tx.days_from_prev = days as i32;
 
         // tính lãi dồn tới ngày txn
-        let interest = principal * daily_rate * (days as f64);
+        let interest = rates.accrue(&convention, principal, accrued_interest_unpaid, prev_date, cur);
         accumulated_interest_total += interest;
         accrued_interest_unpaid += interest;
 
@@ -76,6 +100,44 @@ pub fn calculate_interest_fields_as_of(
                 tx.interest_applied = pay_i.round() as i64;         // 👈 projection
                 total_paid_interest += tx.interest_applied;          // field có sẵn
             }
+            "penalty" => {
+                // trả lãi phạt, phần vượt bị chặn khi tính current_penalty
+                penalty_paid += amt.abs();
+                tx.penalty_applied = tx.amount.abs();
+            }
+            "storage_fee" => {
+                storage_paid += amt.abs();
+                tx.fee_applied = tx.amount.abs();
+            }
+            "repayment" => {
+                // khoản trả gộp: chia vào phạt / phí / lãi / gốc theo thứ tự phân bổ của hợp đồng
+                let overdue = delinquency::assess(&contract.schedule, &payments, cur, contract.penalty_rate, convention.day_count);
+                let due = Split {
+                    penalty: clamp_zero(overdue.penalty_accrued - penalty_paid).round() as i64,
+                    fees: clamp_zero(storage_accrued_to(cur) - storage_paid).round() as i64,
+                    interest: clamp_zero(accrued_interest_unpaid).round() as i64,
+                    principal: principal.round() as i64,
+                };
+                let Applied { split: applied, excess } = contract.repayment_waterfall.apply(tx.amount.abs(), &due);
+                // phần vượt tổng nghĩa vụ không mất đi: ghi có cho khách
+                credit += excess;
+
+                penalty_paid += applied.penalty as f64;
+                storage_paid += applied.fees as f64;
+                accrued_interest_unpaid -= applied.interest as f64;
+                principal = clamp_zero(principal - applied.principal as f64);
+
+                tx.penalty_applied   = applied.penalty;
+                tx.fee_applied       = applied.fees;
+                tx.interest_applied  = applied.interest;
+                tx.principal_applied = applied.principal;
+                total_paid_interest  += applied.interest;
+                total_paid_principal += applied.principal;
+            }
+            "prepayment_fee" => {
+                // phí trả trước hạn: thu riêng, không trừ vào nghĩa vụ nợ
+                tx.fee_applied = tx.amount.abs();
+            }
             "liquidation" | "settlement" => {
                 // trả lãi treo trước
                 let mut pay_left = amt.abs();
@@ -92,7 +154,15 @@ pub fn calculate_interest_fields_as_of(
 
                     tx.principal_applied = applied_principal.round() as i64; // 👈 projection
                     total_paid_principal += tx.principal_applied;            // 👈 cộng dồn
+                    pay_left -= applied_principal;
                 }
+                // tiếp theo phí lưu kho tới ngày tất toán, còn dư → trả lãi phạt
+                let applied_storage = pay_left.max(0.0).min(clamp_zero(storage_accrued_to(cur) - storage_paid));
+                storage_paid += applied_storage;
+                pay_left -= applied_storage;
+                penalty_paid += pay_left.max(0.0);
+                tx.fee_applied = applied_storage.round() as i64;
+                tx.penalty_applied = pay_left.max(0.0).round() as i64;
                 stop_at = Some(cur);
             }
             _ => {}
@@ -100,117 +170,83 @@ pub fn calculate_interest_fields_as_of(
 
         tx.principal_balance = principal.round() as i64;
         prev_date = cur;
-
-        if stop_at.is_some() { break; }
+        if tx.principal_applied + tx.interest_applied > 0 {
+            payments.push((cur, tx.principal_applied + tx.interest_applied));
+        }
     }
 
     if stop_at.is_none() && today_local > prev_date {
-        let tail_days = (today_local - prev_date).num_days();
-        let tail_interest = principal * daily_rate * (tail_days as f64);
+        let tail_interest = rates.accrue(&convention, principal, accrued_interest_unpaid, prev_date, today_local);
         accumulated_interest_total += tail_interest;
         accrued_interest_unpaid += tail_interest;
     }
 
+    // ⏰ Quá hạn theo lịch trả nợ: đã tất toán / hết dư nợ thì dừng tính phạt tại giao dịch cuối
+    let paid_off = stop_at.is_some() || (principal <= 0.0 && total_paid_principal > 0);
+    let until = if paid_off { prev_date } else { today_local };
+    let overdue = delinquency::assess(&contract.schedule, &payments, until, contract.penalty_rate, convention.day_count);
+    // 🏬 Phí lưu kho dừng phát sinh khi đã tất toán / hết dư nợ
+    let storage_accrued = storage_accrued_to(until);
+
     contract.current_principal    = principal.round() as i64;
     contract.accumulated_interest = accumulated_interest_total.round() as i64;
     contract.current_interest     = clamp_zero(accrued_interest_unpaid).round() as i64;
     contract.total_paid_interest  = total_paid_interest;
 
+    contract.days_past_due   = if paid_off { 0 } else { overdue.days_past_due };
+    contract.current_penalty = clamp_zero(overdue.penalty_accrued - penalty_paid).round() as i64;
+
+    contract.storage_fee         = storage_accrued.round() as i64;
+    contract.current_storage_fee = clamp_zero(storage_accrued - storage_paid).round() as i64;
+    contract.credit_balance      = credit;
+
     // 👇 gán projection tổng “gốc đã trả” để FE hiển thị
     contract.total_paid_principal = total_paid_principal;
     // 👇 Thêm dòng này để BE trả luôn số tiền còn phải trả
-    contract.payoff_due = contract.current_principal + contract.current_interest;
+    contract.payoff_due = contract.current_principal + contract.current_interest + contract.current_penalty
+        + contract.current_storage_fee;
+}
+
+/// Bảng kê số tiền tất toán tại 1 thời điểm
+#[derive(Debug, Clone, Serialize)]
+pub struct SettlementQuote {
+    pub as_of: DateTime<Utc>,
+    pub principal: i64,
+    pub unpaid_interest: i64,
+    /// Lãi phạt quá hạn chưa trả
+    pub late_penalty: i64,
+    pub storage_fee: i64,
+    /// Phí trả trước hạn: ghi thành giao dịch `prepayment_fee` riêng, không nằm trong `total`
+    pub prepayment_penalty: i64,
+    /// Số tiền tất toán khoản nợ (giao dịch `settlement`)
+    pub total: i64,
+    /// Tổng tiền khách phải nộp = `total` + phí trả trước hạn
+    pub amount_due: i64,
 }
 
-/// Tính số tiền cần trả để tất toán tại thời điểm `as_of`,
-/// dựa trên trạng thái hợp đồng + dãy giao dịch đã diễn ra TRƯỚC thời điểm tất toán.
-/// Công thức:
-/// amount = current_principal + current_interest
-///        + storage_fee_for_period (nếu có)
-pub fn settlement_quote_as_of(
+/// Bảng kê tất toán tại `as_of`, dựa trên các giao dịch đã diễn ra TRƯỚC thời điểm tất toán.
+/// Không làm thay đổi hợp đồng gốc (tính trên bản clone).
+pub fn settlement_breakdown_as_of(
     contract: &LoanContract,
     txs_prefix: &mut [LoanTransaction],
     as_of: DateTime<Utc>,
-) -> i64 {
-    // Clone để tính toán "what-if" không làm bẩn state gốc
+) -> SettlementQuote {
     let mut c = contract.clone();
     calculate_interest_fields_as_of(&mut c, txs_prefix, as_of);
 
-    // ✅ Bỏ phần tính phí lưu kho theo collateral_value
-    let amount = c.current_principal + c.current_interest;
-
-    amount.max(0)
-}
-
-
-pub fn principal_paid_as_of(
-    contract: &crate::module::loan::model::LoanContract,
-    txs: &[LoanTransaction],
-    as_of: DateTime<Utc>,
-) -> i64 {
-    // Sắp theo thời gian để tính đúng trình tự
-    let mut items: Vec<&LoanTransaction> = txs.iter().collect();
-    items.sort_by_key(|tx| (tx.date, tx.id));
-
-    let daily_rate = (contract.interest_rate as f64) / 100.0 / 365.0;
-    let mut principal: f64 = 0.0;
-    let mut accrued_interest_unpaid: f64 = 0.0;
-    let mut paid_principal_total: i64 = 0;
-
-    // hàm ngày business theo Asia/Bangkok, giống file gốc
-    fn biz_date_local(dt_utc: DateTime<Utc>) -> chrono::NaiveDate {
-        use chrono_tz::Asia::Bangkok;
-        dt_utc.with_timezone(&Bangkok).date_naive()
+    let prepayment_penalty = contract
+        .prepayment_penalty
+        .amount(c.current_principal, contract.date_start, contract.maturity(), as_of);
+    let total = c.payoff_due.max(0);
+
+    SettlementQuote {
+        as_of,
+        principal: c.current_principal,
+        unpaid_interest: c.current_interest,
+        late_penalty: c.current_penalty,
+        storage_fee: c.current_storage_fee,
+        prepayment_penalty,
+        total,
+        amount_due: total + prepayment_penalty,
     }
-
-    let mut prev_date = biz_date_local(contract.date_start);
-    // dừng ở as_of (nếu giao dịch sau as_of thì bỏ qua)
-    for tx in items {
-        if tx.date > as_of { break; }
-
-        let cur = biz_date_local(tx.date);
-        let days = (cur - prev_date).num_days().max(0) as f64;
-
-        // cộng lãi dồn đến ngày giao dịch
-        accrued_interest_unpaid += principal * daily_rate * days;
-
-        match tx.transaction_type.as_str() {
-            "disbursement" | "additional" => {
-                principal += tx.amount as f64;
-                principal = clamp_zero(principal);
-            }
-            "principal" => {
-                let p = (tx.amount as f64).abs();
-                let applied = p.min(principal);
-                principal -= applied;
-                principal = clamp_zero(principal);
-                paid_principal_total += applied.round() as i64;
-            }
-            "interest" => {
-                let pay = (tx.amount as f64).abs();
-                let applied = pay.min(clamp_zero(accrued_interest_unpaid));
-                accrued_interest_unpaid -= applied;
-            }
-            "liquidation" | "settlement" => {
-                let mut pay_left = (tx.amount as f64).abs();
-                // trả lãi trước
-                let applied_int = pay_left.min(clamp_zero(accrued_interest_unpaid));
-                accrued_interest_unpaid -= applied_int;
-                pay_left -= applied_int;
-                // phần còn lại trừ vào gốc
-                if pay_left > 0.0 {
-                    let applied_principal = pay_left.min(principal);
-                    principal -= applied_principal;
-                    principal = clamp_zero(principal);
-                    paid_principal_total += applied_principal.round() as i64;
-                }
-                // tất toán là điểm dừng logic — có thể break nếu muốn
-            }
-            _ => {}
-        }
-
-        prev_date = cur;
-    }
-
-    paid_principal_total
-}
\ No newline at end of file
+}
@@ -1,14 +1,32 @@
-use sqlx::{PgPool, PgConnection};
+use std::collections::HashMap;
+
+use sqlx::{Arguments, PgPool, PgConnection, postgres::PgArguments};
 use uuid::Uuid;
-use chrono::{DateTime, Utc, Datelike};
-use crate::module::loan::dto::CreateContractInput;
-use crate::module::loan::model::LoanContract;
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::module::loan::dto::{
+    CompleteLiquidationInput, CreateContractInput, LiquidationProceedsInput, LtvThresholdInput, RateIndexInput,
+    RateIndexValueInput, LoanProductInput, RefinanceInput, RepaymentWaterfallInput, ReportBackfillInput, RestructureInput, SeizeCollateralInput, TransactionInput,
+};
+use crate::module::loan::model::{LoanContract, LoanReport};
 use crate::module::loan::model::LoanTransaction;
-use crate::module::loan::calculator::{settlement_quote_as_of, calculate_interest_fields, calculate_interest_fields_as_of};
+use crate::module::loan::calculator::{settlement_breakdown_as_of, calculate_interest_fields, calculate_interest_fields_as_of, SettlementQuote};
 use crate::module::loan::query;
+use crate::module::loan::convention::{self, InterestConvention};
+use crate::module::loan::liquidation::{self, Liquidation, LiquidationAsset};
+use crate::module::loan::product::{self, LoanProduct};
+use crate::module::loan::restructure::TermChange;
+use crate::module::loan::state::LoanState;
+use crate::module::loan::waterfall::Waterfall;
+use crate::module::loan::schedule::{self, Installment, RepaymentPlan, ScheduleTerms};
+use crate::module::sequence;
+use sqlx::types::Json;
 use crate::core::error::{AppError, ErrorResponse};
 use crate::core::i18n::I18n;
-use crate::module::loan::dto::CreateCollateralDto;
+use crate::core::scope::Scope;
+use crate::module::loan::event::LoanEvent;
+use crate::command_bus::{Command, CommandContext};
+use async_trait::async_trait;
+use serde::Serialize;
 
 // epoch seconds -> DateTime<Utc>
 fn epoch_to_utc(ts: i64) -> Result<DateTime<Utc>, sqlx::Error> {
@@ -16,24 +34,89 @@ fn epoch_to_utc(ts: i64) -> Result<DateTime<Utc>, sqlx::Error> {
         .ok_or_else(|| sqlx::Error::Protocol("Invalid timestamp".into()))
 }
 
+/// Tạo hợp đồng + giao dịch (chạy trong transaction của caller)
 pub async fn create_contract(
-    pool: &PgPool,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
-    input: CreateContractInput,
+    mut input: CreateContractInput,
+    actor: Uuid,
 ) -> Result<LoanContract, AppError> {
     // Defense in depth: validate again (validation should be done in handler, but this is extra safety)
     let i18n = I18n::default(); // Use default language in command layer
     if input.transactions.is_empty() {
         return Err(AppError::bad_request_i18n(&i18n, "error.loan.transactions_empty"));
     }
+    if input.prepayment_penalty.is_some_and(|p| !p.is_valid()) {
+        return Err(AppError::bad_request_i18n(&i18n, "error.loan.invalid_prepayment_penalty"));
+    }
 
-    let mut tx = pool.begin().await?;
+    // 👉 Theo sản phẩm vay: điền mặc định cho trường bỏ trống, điều khoản phải nằm trong khung
+    let product = match input.product_id {
+        Some(product_id) => {
+            let product = active_product(&mut *conn, &i18n, tenant_id, product_id).await?;
+            product.fill_defaults(&mut input);
+            product.check(&input).map_err(|key| AppError::bad_request_i18n(&i18n, key))?;
+            Some(product)
+        }
+        None => None,
+    };
+    let (Some(interest_rate), Some(term_months)) = (input.interest_rate, input.term_months) else {
+        return Err(AppError::bad_request_i18n(&i18n, "error.loan.terms_required"));
+    };
+
+    // Tài sản cầm cố kèm hợp đồng, phải đủ các loại sản phẩm yêu cầu
+    let asset_ids = input.collateral_asset_ids.clone().unwrap_or_default();
+    let asset_types = sqlx::query_scalar!(
+        "SELECT asset_type FROM collateral_assets WHERE tenant_id = $1 AND asset_id = ANY($2)",
+        tenant_id,
+        &asset_ids
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    if asset_types.len() != asset_ids.len() {
+        return Err(AppError::bad_request_i18n(&i18n, "error.loan.collateral_not_found"));
+    }
+    // 1 tài sản chỉ bảo đảm cho 1 hợp đồng tại 1 thời điểm (`uq_asset_active_once`)
+    let already_pledged = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM loan_collateral
+            WHERE tenant_id = $1 AND asset_id = ANY($2) AND status = 'active' AND released_at IS NULL
+        ) AS "exists!"
+        "#,
+        tenant_id,
+        &asset_ids
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if already_pledged {
+        return Err(AppError::bad_request_i18n(&i18n, "error.loan.collateral_already_pledged"));
+    }
+    if let Some(p) = &product {
+        let missing = p.missing_collateral(&asset_types);
+        if !missing.is_empty() {
+            let types = missing.join(", ");
+            let params = HashMap::from([("types", types.as_str())]);
+            return Err(AppError::bad_request(i18n.t_with_params("error.loan.product_collateral_required", &params)));
+        }
+    }
+
+    ensure_rate_index(&mut *conn, &i18n, tenant_id, input.rate_index.as_deref()).await?;
+    let waterfall = repayment_waterfall(&mut *conn, &i18n, tenant_id, input.repayment_waterfall.as_deref())
+        .await?
+        .unwrap_or_default();
 
-    let contract_number = generate_contract_number_monthly(
-        tx.as_mut(),
+    // Số hợp đồng theo dãy `loan_contract` của tenant, tiền tố theo sản phẩm vay (nếu có)
+    let contract_number = sequence::command::next_number(
+        &mut *conn,
         tenant_id,
+        sequence::model::LOAN_CONTRACT,
+        Utc::now(),
+        product.as_ref().and_then(|p| p.number_prefix.as_deref()),
         None,
-    ).await?;
+    )
+    .await?
+    .name;
 
     let storage_fee_rate = input.storage_fee_rate.unwrap_or(0.0);
     let storage_fee = input.storage_fee.unwrap_or(0);
@@ -44,59 +127,36 @@ pub async fn create_contract(
     let total_settlement_amount = input.total_settlement_amount.unwrap_or(0);
     let shared_with = input.shared_with.as_deref().unwrap_or(&[]);
 
-    // 👉 Tự động xác định trạng thái ban đầu
-    let mut state = "Nháp".to_string();
-    if !input.transactions.is_empty() {
-        state = "Hoạt động".to_string();
-        for t in &input.transactions {
-            match t.transaction_type.as_str() {
-                "liquidation" => {
-                    state = "Đã thanh lý".to_string();
-                    break;
-                }
-                "settlement" => {
-                    state = "Đã tất toán".to_string();
-                    // vẫn tiếp tục vòng lặp để ưu tiên "liquidated" nếu có
-                }
-                _ => {}
-            }
-        }
-    }
+    // 👉 Trạng thái ban đầu suy ra từ sổ giao dịch (không nhận từ FE)
+    let state = LoanState::from_ledger(input.transactions.iter().map(|t| t.transaction_type.as_str()));
 
-    let contract = sqlx::query_as!(
-        LoanContract,
+    let contract_id = sqlx::query_scalar!(
         r#"
         INSERT INTO loan_contract (
             tenant_id, contact_id, contract_number, interest_rate, term_months,
             date_start, date_end,
             storage_fee_rate, storage_fee, current_principal, current_interest,
             accumulated_interest, total_paid_interest, total_settlement_amount,
-            state, created_by, assignee_id, shared_with
+            state, created_by, assignee_id, shared_with, penalty_rate,
+            day_count, compounding, storage_fee_flat, prepayment_penalty,
+            rate_index, rate_margin, repayment_waterfall, product_id
         )
         VALUES (
             $1, $2, $3, $4, $5,
             $6, $7,
             $8, $9, $10, $11,
             $12, $13, $14,
-            $15, $16, $17, $18
+            $15, $16, $17, $18, $19,
+            $20, $21, $22, $23,
+            $24, $25, $26, $27
         )
-        RETURNING
-            id, tenant_id, contact_id, contract_number,
-            interest_rate, term_months,
-            date_start, date_end,
-            storage_fee_rate, storage_fee,
-            current_principal, current_interest,
-            accumulated_interest, total_paid_interest, total_settlement_amount,
-            state, created_at, updated_at,
-            created_by, assignee_id, shared_with,
-            0::int8 AS "total_paid_principal!",
-            0::int8 AS "payoff_due!"
+        RETURNING id
         "#,
         tenant_id,
         input.contact_id,
         contract_number,
-        input.interest_rate,
-        input.term_months,
+        interest_rate,
+        term_months,
         input.date_start,
         input.date_end,
         storage_fee_rate,
@@ -106,16 +166,51 @@ pub async fn create_contract(
         accumulated_interest,
         total_paid_interest,
         total_settlement_amount,
-        state,
+        state.as_str(),
         input.created_by,
         input.assignee_id,
-        shared_with
+        shared_with,
+        input.penalty_rate.unwrap_or(0.0),
+        input.day_count.unwrap_or_default() as _,
+        input.compounding.unwrap_or_default() as _,
+        input.storage_fee_flat.unwrap_or(0),
+        Json(input.prepayment_penalty.unwrap_or_default()) as _,
+        input.rate_index.as_deref().filter(|c| !c.is_empty()),
+        input.rate_margin.unwrap_or(0.0),
+        Json(waterfall) as _,
+        input.product_id
     )
-    .fetch_one(tx.as_mut())
+    .fetch_one(&mut *conn)
     .await?;
 
+    // Tài sản bảo đảm cho hợp đồng từ ngày bắt đầu (phí lưu kho tính từ đó)
+    if !asset_ids.is_empty() {
+        sqlx::query!(
+            r#"
+            INSERT INTO loan_collateral (tenant_id, contract_id, asset_id, pledge_value, status, pledged_at, created_by)
+            SELECT a.tenant_id, $2, a.asset_id, a.value_estimate, 'active', $3, $4
+            FROM collateral_assets a
+            WHERE a.tenant_id = $1 AND a.asset_id = ANY($5)
+            "#,
+            tenant_id,
+            contract_id,
+            input.date_start,
+            actor,
+            &asset_ids
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    let mut contract = query::get_contract_by_id(&mut *conn, tenant_id, contract_id).await?;
+
+    record_state_change(&mut *conn, tenant_id, contract.id, None, state, None, Some(actor)).await?;
+
+    // Lịch trả nợ sinh trước giao dịch để snapshot tính được quá hạn / lãi phạt
+    let rows = save_schedule(&mut *conn, &contract, &input).await?;
+    contract.schedule = Json(rows);
+
     // ✅ Lấy transactions hiện tại để tính toán chính xác
-    let existing_txs = query::get_transactions_by_contract(pool, tenant_id, contract.id).await.unwrap_or_default();
+    let existing_txs = query::get_transactions_by_contract(&mut *conn, tenant_id, contract.id).await.unwrap_or_default();
 
     // ✅ Tính toán current_interest CHƯA có transactions mới
     let mut temp_contract = contract.clone();
@@ -127,6 +222,10 @@ pub async fn create_contract(
     // Lưu giao dịch
     let mut prefix: Vec<LoanTransaction> = Vec::new();
     for t in input.transactions.iter() {
+        // Phí trả trước hạn sinh kèm giao dịch tất toán, không nhận từ FE
+        if t.transaction_type == "prepayment_fee" {
+            continue;
+        }
         let date_parsed = epoch_to_utc(t.date)?;
 
         // ✅ Tính snapshot tại thời điểm giao dịch dựa trên prefix (đã xử lý trước đó)
@@ -134,29 +233,14 @@ pub async fn create_contract(
         let mut snapshot_prefix = prefix.clone();
         calculate_interest_fields_as_of(&mut snapshot_contract, &mut snapshot_prefix, date_parsed);
 
+        let mut prepayment_fee = 0;
         let computed_amount = if t.transaction_type == "settlement" {
-            settlement_quote_as_of(&contract, &mut prefix, date_parsed)
+            let quote = settlement_breakdown_as_of(&contract, &mut prefix, date_parsed);
+            prepayment_fee = quote.prepayment_penalty;
+            quote.total
         } else {
             // ✅ Validation: Kiểm tra số tiền không vượt quá snapshot hiện tại
-            match t.transaction_type.as_str() {
-                "interest" => {
-                    if t.amount > snapshot_contract.current_interest {
-                        return Err(AppError::Validation(ErrorResponse {
-                            code: "interest_exceeded",
-                            message: format!("Số tiền thu lãi ({}) vượt quá tổng lãi đến hiện tại ({})", t.amount, snapshot_contract.accumulated_interest),
-                        }));
-                    }
-                }
-                "principal" => {
-                    if t.amount > snapshot_contract.current_principal {
-                        return Err(AppError::Validation(ErrorResponse {
-                            code: "principal_exceeded", 
-                            message: format!("Số tiền thu gốc ({}) vượt quá gốc hiện tại ({})", t.amount, snapshot_contract.current_principal),
-                        }));
-                    }
-                }
-                _ => {}
-            }
+            check_amount_within_snapshot(&t.transaction_type, t.amount, &snapshot_contract)?;
             t.amount
         };
 
@@ -180,8 +264,11 @@ pub async fn create_contract(
             input.assignee_id,
             shared_with
         )
-        .execute(tx.as_mut())
+        .execute(&mut *conn)
         .await?;
+        if prepayment_fee > 0 {
+            record_prepayment_fee(&mut *conn, &contract, prepayment_fee, date_parsed, input.created_by).await?;
+        }
 
         prefix.push(LoanTransaction {
             id: Uuid::new_v4(),
@@ -198,75 +285,168 @@ pub async fn create_contract(
             principal_balance: 0,
             principal_applied: 0,
             interest_applied: 0,
+            penalty_applied: 0,
+            fee_applied: 0,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         });
     }
 
-    tx.commit().await?;
     Ok(contract)
 }
 
+/// Ghi phí trả trước hạn thu kèm tất toán thành giao dịch `prepayment_fee` riêng
+async fn record_prepayment_fee(
+    conn: &mut PgConnection,
+    contract: &LoanContract,
+    amount: i64,
+    date: DateTime<Utc>,
+    created_by: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO loan_transaction (
+            contract_id, tenant_id, contact_id,
+            transaction_type, amount, "date", note,
+            created_by, assignee_id, shared_with
+        )
+        VALUES ($1, $2, $3, 'prepayment_fee', $4, $5, $6, $7, $8, $9)
+        "#,
+        contract.id,
+        contract.tenant_id,
+        contract.contact_id,
+        amount,
+        date,
+        "Phí trả nợ trước hạn",
+        created_by,
+        contract.assignee_id,
+        contract.shared_with.as_deref().unwrap_or(&[])
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Số tiền thu theo từng loại không được vượt quá số đang nợ tại thời điểm giao dịch
+fn check_amount_within_snapshot(transaction_type: &str, amount: i64, snapshot: &LoanContract) -> Result<(), AppError> {
+    match transaction_type {
+        "interest" => {
+            if amount > snapshot.current_interest {
+                return Err(AppError::Validation(ErrorResponse {
+                    code: "interest_exceeded",
+                    message: format!("Số tiền thu lãi ({}) vượt quá tổng lãi đến hiện tại ({})", amount, snapshot.accumulated_interest),
+                }));
+            }
+        }
+        "penalty" => {
+            if amount > snapshot.current_penalty {
+                return Err(AppError::Validation(ErrorResponse {
+                    code: "penalty_exceeded",
+                    message: format!("Số tiền thu phạt ({}) vượt quá lãi phạt hiện tại ({})", amount, snapshot.current_penalty),
+                }));
+            }
+        }
+        "storage_fee" => {
+            if amount > snapshot.current_storage_fee {
+                return Err(AppError::Validation(ErrorResponse {
+                    code: "storage_fee_exceeded",
+                    message: format!("Số tiền thu phí lưu kho ({}) vượt quá phí lưu kho hiện tại ({})", amount, snapshot.current_storage_fee),
+                }));
+            }
+        }
+        "principal" => {
+            if amount > snapshot.current_principal {
+                return Err(AppError::Validation(ErrorResponse {
+                    code: "principal_exceeded",
+                    message: format!("Số tiền thu gốc ({}) vượt quá gốc hiện tại ({})", amount, snapshot.current_principal),
+                }));
+            }
+        }
+        "repayment" => {
+            if amount > snapshot.payoff_due {
+                return Err(AppError::Validation(ErrorResponse {
+                    code: "repayment_exceeded",
+                    message: format!("Số tiền trả nợ ({}) vượt quá tổng số tiền còn phải trả ({})", amount, snapshot.payoff_due),
+                }));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Cập nhật hợp đồng + ghi lại toàn bộ giao dịch (chạy trong transaction của caller)
 pub async fn update_contract(
-    pool: &PgPool,
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     contract_id: Uuid,
-    input: CreateContractInput,
+    mut input: CreateContractInput,
+    actor: Uuid,
 ) -> Result<LoanContract, AppError> {
     // Defense in depth: validate again (validation should be done in handler, but this is extra safety)
     let i18n = I18n::default(); // Use default language in command layer
     if input.transactions.is_empty() {
         return Err(AppError::bad_request_i18n(&i18n, "error.loan.transactions_empty"));
     }
-
-    let mut tx = pool.begin().await?;
+    if input.prepayment_penalty.is_some_and(|p| !p.is_valid()) {
+        return Err(AppError::bad_request_i18n(&i18n, "error.loan.invalid_prepayment_penalty"));
+    }
     let shared_with = input.shared_with.as_deref().unwrap_or(&[]);
 
-    // 👉 Tự động cập nhật trạng thái theo giao dịch
-    let mut state = "Nháp".to_string();
-    if !input.transactions.is_empty() {
-        state = "Hoạt động".to_string();
-        for t in &input.transactions {
-            match t.transaction_type.as_str() {
-                "liquidation" => {
-                    state = "Đã thanh lý".to_string();
-                    break;
-                }
-                "settlement" => {
-                    state = "Đã tất toán".to_string();
-                    // vẫn tiếp tục vòng lặp để ưu tiên "liquidated" nếu có
-                }
-                _ => {}
-            }
+    ensure_rate_index(&mut *conn, &i18n, tenant_id, input.rate_index.as_deref()).await?;
+    let waterfall = repayment_waterfall(&mut *conn, &i18n, tenant_id, input.repayment_waterfall.as_deref()).await?;
+
+    // 👉 Hợp đồng theo sản phẩm: điều khoản mới vẫn phải nằm trong khung (sản phẩm không đổi)
+    let product_id = sqlx::query_scalar!(
+        "SELECT product_id FROM loan_contract WHERE tenant_id = $1 AND id = $2",
+        tenant_id,
+        contract_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .flatten();
+    if let Some(product_id) = product_id {
+        if let Some(product) = query::get_product(&mut *conn, tenant_id, product_id).await? {
+            product.check(&input).map_err(|key| AppError::bad_request_i18n(&i18n, key))?;
         }
     }
 
-    let updated = sqlx::query_as!(
-        LoanContract,
+    // 👉 Trạng thái theo sổ giao dịch mới, phải là chuyển trạng thái hợp lệ
+    let current = lock_state(&mut *conn, tenant_id, contract_id).await?
+        .ok_or_else(|| AppError::not_found_i18n(&i18n, "error.loan.not_found"))?;
+    let derived = LoanState::from_ledger(input.transactions.iter().map(|t| t.transaction_type.as_str()));
+    let state = match (current, derived) {
+        // còn dư nợ: giữ nguyên active/overdue, quá hạn do đồng bộ theo lịch trả nợ
+        (LoanState::Active | LoanState::Overdue, LoanState::Active) => current,
+        _ => derived,
+    };
+    if state != current && !current.can_transition_to(state) {
+        return Err(AppError::bad_request_i18n(&i18n, "error.loan.invalid_state_transition"));
+    }
+
+    sqlx::query!(
         r#"
         UPDATE loan_contract
         SET
             contact_id = $1,
-            interest_rate = $2,
-            term_months = $3,
+            interest_rate = COALESCE($2, interest_rate),
+            term_months = COALESCE($3, term_months),
             date_start = $4,
             date_end = $5,
             assignee_id = $6,
             shared_with = $7,
             state = $8,
+            penalty_rate = COALESCE($11, penalty_rate),
+            day_count = COALESCE($12, day_count),
+            compounding = COALESCE($13, compounding),
+            storage_fee_rate = COALESCE($14, storage_fee_rate),
+            storage_fee_flat = COALESCE($15, storage_fee_flat),
+            prepayment_penalty = COALESCE($16, prepayment_penalty),
+            rate_index = CASE WHEN $17::text IS NULL THEN rate_index ELSE NULLIF($17, '') END,
+            rate_margin = COALESCE($18, rate_margin),
+            repayment_waterfall = COALESCE($19, repayment_waterfall),
             updated_at = NOW()
         WHERE id = $9 AND tenant_id = $10
-        RETURNING
-            id, tenant_id, contact_id, contract_number,
-            interest_rate, term_months,
-            date_start, date_end,
-            storage_fee_rate, storage_fee,
-            current_principal, current_interest,
-            accumulated_interest, total_paid_interest, total_settlement_amount,
-            state, created_at, updated_at,
-            created_by, assignee_id, shared_with,
-            0::int8 AS "total_paid_principal!",
-            0::int8 AS "payoff_due!"
         "#,
         input.contact_id,
         input.interest_rate,
@@ -275,22 +455,42 @@ pub async fn update_contract(
         input.date_end,
         input.assignee_id,
         shared_with,
-        state,
+        state.as_str(),
         contract_id,
         tenant_id,
+        input.penalty_rate,
+        input.day_count as _,
+        input.compounding as _,
+        input.storage_fee_rate,
+        input.storage_fee_flat,
+        input.prepayment_penalty.map(Json) as _,
+        input.rate_index,
+        input.rate_margin,
+        waterfall.map(Json) as _,
     )
-    .fetch_one(tx.as_mut())
+    .execute(&mut *conn)
     .await?;
+    let mut updated = query::get_contract_by_id(&mut *conn, tenant_id, contract_id).await?;
+
+    if state != current {
+        record_state_change(&mut *conn, tenant_id, contract_id, Some(current), state, None, Some(actor)).await?;
+    }
+
+    // Lịch trả nợ theo điều khoản sau cập nhật
+    input.interest_rate = Some(updated.interest_rate);
+    input.term_months = Some(updated.term_months);
+    let rows = save_schedule(&mut *conn, &updated, &input).await?;
+    updated.schedule = Json(rows);
 
     // ✅ Lấy transactions hiện tại TRƯỚC KHI xóa để tính toán chính xác
-    let existing_txs = query::get_transactions_by_contract(pool, tenant_id, contract_id).await.unwrap_or_default();
+    let existing_txs = query::get_transactions_by_contract(&mut *conn, tenant_id, contract_id).await.unwrap_or_default();
 
     sqlx::query!(
         "DELETE FROM loan_transaction WHERE contract_id = $1 AND tenant_id = $2",
         contract_id,
         tenant_id
     )
-    .execute(tx.as_mut())
+    .execute(&mut *conn)
     .await?;
     
     // ✅ Tính toán current_interest CHƯA có transactions mới
@@ -302,6 +502,10 @@ pub async fn update_contract(
 
     let mut prefix: Vec<LoanTransaction> = Vec::new();
     for t in input.transactions.iter() {
+        // Phí trả trước hạn sinh kèm giao dịch tất toán, không nhận từ FE
+        if t.transaction_type == "prepayment_fee" {
+            continue;
+        }
         let date_parsed = epoch_to_utc(t.date)?;
 
         // ✅ Snapshot theo prefix đã xử lý trong lần cập nhật này
@@ -309,29 +513,14 @@ pub async fn update_contract(
         let mut snapshot_prefix = prefix.clone();
         calculate_interest_fields_as_of(&mut snapshot_contract, &mut snapshot_prefix, date_parsed);
 
+        let mut prepayment_fee = 0;
         let computed_amount = if t.transaction_type == "settlement" {
-            settlement_quote_as_of(&updated, &mut prefix, date_parsed)
+            let quote = settlement_breakdown_as_of(&updated, &mut prefix, date_parsed);
+            prepayment_fee = quote.prepayment_penalty;
+            quote.total
         } else {
             // ✅ Validation dựa trên snapshot hiện tại (không dùng giao dịch cũ trong DB)
-            match t.transaction_type.as_str() {
-                "interest" => {
-                    if t.amount > snapshot_contract.current_interest {
-                        return Err(AppError::Validation(ErrorResponse {
-                            code: "interest_exceeded",
-                            message: format!("Số tiền thu lãi ({}) vượt quá tổng lãi đến hiện tại ({})", t.amount, snapshot_contract.accumulated_interest),
-                        }));
-                    }
-                }
-                "principal" => {
-                    if t.amount > snapshot_contract.current_principal {
-                        return Err(AppError::Validation(ErrorResponse {
-                            code: "principal_exceeded", 
-                            message: format!("Số tiền thu gốc ({}) vượt quá gốc hiện tại ({})", t.amount, snapshot_contract.current_principal),
-                        }));
-                    }
-                }
-                _ => {}
-            }
+            check_amount_within_snapshot(&t.transaction_type, t.amount, &snapshot_contract)?;
             t.amount
         };
 
@@ -355,8 +544,11 @@ pub async fn update_contract(
             input.assignee_id,
             shared_with
         )
-        .execute(tx.as_mut())
+        .execute(&mut *conn)
         .await?;
+        if prepayment_fee > 0 {
+            record_prepayment_fee(&mut *conn, &updated, prepayment_fee, date_parsed, input.created_by).await?;
+        }
 
         prefix.push(LoanTransaction {
             id: Uuid::new_v4(),
@@ -373,114 +565,1898 @@ pub async fn update_contract(
             principal_balance: 0,
             principal_applied: 0,
             interest_applied: 0,
+            penalty_applied: 0,
+            fee_applied: 0,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         });
     }
 
-    tx.commit().await?;
     Ok(updated)
 }
 
+/// Dựng lịch trả nợ từ điều khoản trong input (dùng chung cho validate + lưu)
+pub fn build_schedule(
+    input: &CreateContractInput,
+    convention: InterestConvention,
+    tz: chrono_tz::Tz,
+) -> Result<Vec<Installment>, schedule::ScheduleError> {
+    schedule::generate(&ScheduleTerms {
+        plan: input.repayment_plan,
+        principal: input.principal,
+        interest_rate: input.interest_rate.unwrap_or_default(),
+        term_months: input.term_months.unwrap_or_default(),
+        start: convention::business_date(input.date_start, tz),
+        custom: &input.custom_schedule,
+        convention,
+    })
+}
 
-pub async fn delete_contract(
-    pool: &PgPool,
+/// Sản phẩm vay đang kích hoạt của tenant
+async fn active_product(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    product_id: Uuid,
+) -> Result<LoanProduct, AppError> {
+    let product = query::get_product(conn, tenant_id, product_id)
+        .await?
+        .ok_or_else(|| AppError::bad_request_i18n(i18n, "error.loan.product_not_found"))?;
+    if !product.active {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.product_inactive"));
+    }
+    Ok(product)
+}
+
+/// Thứ tự phân bổ theo mã cấu hình của tenant (None = không đổi / mặc định)
+async fn repayment_waterfall(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    code: Option<&str>,
+) -> Result<Option<Waterfall>, AppError> {
+    let Some(code) = code.filter(|c| !c.is_empty()) else {
+        return Ok(None);
+    };
+    let steps = sqlx::query_scalar!(
+        r#"SELECT steps AS "steps: Json<Waterfall>" FROM loan_repayment_waterfall WHERE tenant_id = $1 AND code = $2"#,
+        tenant_id,
+        code
+    )
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| AppError::bad_request_i18n(i18n, "error.loan.waterfall_not_found"))?;
+    Ok(Some(steps.0))
+}
+
+/// Chỉ số lãi suất tham chiếu phải tồn tại trong tenant ("" = bỏ thả nổi)
+async fn ensure_rate_index(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    code: Option<&str>,
+) -> Result<(), AppError> {
+    let Some(code) = code.filter(|c| !c.is_empty()) else {
+        return Ok(());
+    };
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM loan_rate_index WHERE tenant_id = $1 AND code = $2) AS "exists!""#,
+        tenant_id,
+        code
+    )
+    .fetch_one(conn)
+    .await?;
+    if !exists {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.rate_index_not_found"));
+    }
+    Ok(())
+}
+
+/// Sinh lại + lưu lịch trả nợ của hợp đồng (chạy trong transaction của caller)
+pub async fn save_schedule(
+    conn: &mut PgConnection,
+    contract: &LoanContract,
+    input: &CreateContractInput,
+) -> Result<Vec<Installment>, AppError> {
+    let (tenant_id, contract_id) = (contract.tenant_id, contract.id);
+    let rows = build_schedule(input, contract.convention(), contract.tz())
+        .map_err(|e| AppError::bad_request_i18n(&I18n::default(), e.i18n_key()))?;
+
+    sqlx::query!(
+        "UPDATE loan_contract SET repayment_plan = $3 WHERE tenant_id = $1 AND id = $2",
+        tenant_id,
+        contract_id,
+        input.repayment_plan.as_str()
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    write_schedule(conn, tenant_id, contract_id, &rows).await?;
+    Ok(rows)
+}
+
+/// Thay toàn bộ các kỳ đã lưu của hợp đồng
+async fn write_schedule(
+    conn: &mut PgConnection,
     tenant_id: Uuid,
     contract_id: Uuid,
+    rows: &[Installment],
 ) -> sqlx::Result<()> {
     sqlx::query!(
+        "DELETE FROM loan_schedule WHERE tenant_id = $1 AND contract_id = $2",
+        tenant_id,
+        contract_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO loan_schedule (
+            tenant_id, contract_id, seq, due_date,
+            principal, interest, total_due, remaining_balance
+        )
+        SELECT $1, $2, * FROM UNNEST($3::int4[], $4::date[], $5::int8[], $6::int8[], $7::int8[], $8::int8[])
+        "#,
+        tenant_id,
+        contract_id,
+        &rows.iter().map(|r| r.seq).collect::<Vec<_>>(),
+        &rows.iter().map(|r| r.due_date).collect::<Vec<_>>(),
+        &rows.iter().map(|r| r.principal).collect::<Vec<_>>(),
+        &rows.iter().map(|r| r.interest).collect::<Vec<_>>(),
+        &rows.iter().map(|r| r.total_due).collect::<Vec<_>>(),
+        &rows.iter().map(|r| r.remaining_balance).collect::<Vec<_>>(),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+
+/// Xoá hợp đồng, trả về true nếu có dòng bị xoá
+pub async fn delete_contract(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+) -> sqlx::Result<bool> {
+    let deleted = sqlx::query!(
         "DELETE FROM loan_contract WHERE id = $1 AND tenant_id = $2",
         contract_id,
         tenant_id
     )
-    .execute(pool)
+    .execute(conn)
+    .await?;
+
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// Khoá dòng hợp đồng và đọc trạng thái hiện tại (chạy trong transaction của caller)
+async fn lock_state(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+) -> sqlx::Result<Option<LoanState>> {
+    sqlx::query_scalar!(
+        r#"SELECT state AS "state: LoanState" FROM loan_contract WHERE tenant_id = $1 AND id = $2 FOR UPDATE"#,
+        tenant_id,
+        contract_id
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Ghi 1 dòng lịch sử chuyển trạng thái (`changed_by` None = hệ thống)
+async fn record_state_change(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+    from: Option<LoanState>,
+    to: LoanState,
+    reason: Option<&str>,
+    changed_by: Option<Uuid>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO loan_state_history (tenant_id, contract_id, from_state, to_state, reason, changed_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        tenant_id,
+        contract_id,
+        from.map(|s| s.as_str()),
+        to.as_str(),
+        reason,
+        changed_by
+    )
+    .execute(conn)
     .await?;
     Ok(())
 }
 
-pub async fn create_collateral(
+/// ✅ Chuyển trạng thái thủ công (kích hoạt, xoá nợ…) – kiểm tra bảng chuyển trạng thái
+pub async fn transition_state(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+    to: LoanState,
+    reason: Option<&str>,
+    actor: Uuid,
+) -> Result<LoanState, AppError> {
+    let from = lock_state(&mut *conn, tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(i18n, "error.loan.not_found"))?;
+    if !from.can_transition_to(to) {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_state_transition"));
+    }
+
+    sqlx::query!(
+        "UPDATE loan_contract SET state = $3, updated_at = NOW() WHERE tenant_id = $1 AND id = $2",
+        tenant_id,
+        contract_id,
+        to.as_str()
+    )
+    .execute(&mut *conn)
+    .await?;
+    record_state_change(conn, tenant_id, contract_id, Some(from), to, reason, Some(actor)).await?;
+    Ok(from)
+}
+
+/// ⏰ Đồng bộ active ⇄ overdue theo số ngày quá hạn (calculator đã tính).
+/// Trả về trạng thái mới nếu có thay đổi.
+pub async fn sync_overdue_state(
     pool: &PgPool,
+    contract: &LoanContract,
+) -> sqlx::Result<Option<LoanState>> {
+    let next = match contract.state {
+        LoanState::Active if contract.days_past_due > 0 => LoanState::Overdue,
+        LoanState::Overdue if contract.days_past_due == 0 => LoanState::Active,
+        _ => return Ok(None),
+    };
+
+    let mut tx = pool.begin().await?;
+    let changed = sqlx::query!(
+        "UPDATE loan_contract SET state = $4, updated_at = NOW() WHERE tenant_id = $1 AND id = $2 AND state = $3",
+        contract.tenant_id,
+        contract.id,
+        contract.state.as_str(),
+        next.as_str()
+    )
+    .execute(&mut *tx)
+    .await?;
+    if changed.rows_affected() == 0 {
+        return Ok(None);
+    }
+    let reason = format!("days_past_due={}", contract.days_past_due);
+    record_state_change(&mut tx, contract.tenant_id, contract.id, Some(contract.state), next, Some(&reason), None).await?;
+    tx.commit().await?;
+    Ok(Some(next))
+}
+
+/// Phương thức trả nợ đã lưu của hợp đồng
+async fn repayment_plan(
+    conn: &mut PgConnection,
     tenant_id: Uuid,
-    input: CreateCollateralDto,
     contract_id: Uuid,
-    created_by: Uuid,
-) -> Result<(), AppError> {
-    // Tạo tài sản vào bảng collateral_assets (nếu cần)
-    let asset = sqlx::query!(
+) -> sqlx::Result<RepaymentPlan> {
+    let plan = sqlx::query_scalar!(
+        "SELECT repayment_plan FROM loan_contract WHERE tenant_id = $1 AND id = $2",
+        tenant_id,
+        contract_id
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(RepaymentPlan::parse(&plan))
+}
+
+/// ✅ Cơ cấu lại hợp đồng (chạy trong transaction của caller).
+/// Lãi tới ngày hiệu lực vẫn tính theo điều khoản cũ; từ ngày hiệu lực áp dụng lãi suất / kỳ hạn mới.
+/// Lịch trả nợ giữ các kỳ đã đến hạn, các kỳ còn lại sinh lại trên dư nợ gốc tại ngày hiệu lực.
+pub async fn restructure_contract(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+    input: &RestructureInput,
+    actor: Uuid,
+) -> Result<TermChange, AppError> {
+    let state = lock_state(&mut *conn, tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(i18n, "error.loan.not_found"))?;
+    if !matches!(state, LoanState::Active | LoanState::Overdue) {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.restructure_not_allowed"));
+    }
+
+    let contract = query::get_contract_by_id(&mut *conn, tenant_id, contract_id).await?;
+    let after_last_change = contract
+        .term_changes
+        .last()
+        .is_none_or(|c| input.effective_date > c.effective_date);
+    if input.effective_date < contract.date_start || !after_last_change {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_effective_date"));
+    }
+    let interest_rate = input.interest_rate.unwrap_or(contract.interest_rate);
+    let term_months = input.term_months.unwrap_or(contract.term_months);
+
+    // Dư nợ gốc tại ngày hiệu lực
+    let mut txs = query::get_transactions_by_contract(&mut *conn, tenant_id, contract_id).await?;
+    let mut at_effective = contract.clone();
+    calculate_interest_fields_as_of(&mut at_effective, &mut txs, input.effective_date);
+
+    // Giữ các kỳ đã đến hạn, sinh lại phần còn lại theo chu kỳ cũ
+    let tz = contract.tz();
+    let effective_local = convention::business_date(input.effective_date, tz);
+    let mut rows: Vec<Installment> = contract
+        .schedule
+        .iter()
+        .filter(|i| i.due_date <= effective_local)
+        .cloned()
+        .collect();
+    if at_effective.current_principal > 0 {
+        let plan = match repayment_plan(&mut *conn, tenant_id, contract_id).await? {
+            RepaymentPlan::Custom => RepaymentPlan::EqualPrincipal,
+            plan => plan,
+        };
+        let anchor = rows
+            .last()
+            .map(|i| i.due_date)
+            .unwrap_or_else(|| convention::business_date(contract.date_start, tz));
+        let kept = rows.len() as i32;
+        // Kỳ hạn mới phải dài hơn số kỳ đã đến hạn để còn kỳ trả phần gốc còn lại
+        if term_months - kept <= 0 {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.restructure_term_too_short"));
+        }
+        let tail = schedule::generate(&ScheduleTerms {
+            plan,
+            principal: at_effective.current_principal,
+            interest_rate,
+            term_months: term_months - kept,
+            start: anchor,
+            custom: &[],
+            convention: contract.convention(),
+        })
+        .map_err(|e| AppError::bad_request_i18n(i18n, e.i18n_key()))?;
+        rows.extend(tail.into_iter().map(|i| Installment { seq: i.seq + kept, ..i }));
+    }
+
+    let change = sqlx::query_as!(
+        TermChange,
         r#"
-        INSERT INTO collateral_assets (
-            tenant_id, asset_id, asset_type, description, value_estimate, status, created_by, created_at
+        INSERT INTO loan_term_change (
+            tenant_id, contract_id, effective_date,
+            previous_interest_rate, interest_rate,
+            previous_term_months, term_months,
+            reason, created_by
         )
-        VALUES ($1, gen_random_uuid(), $2, $3, $4, $5, $6, NOW())
-        RETURNING asset_id
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING id, effective_date, previous_interest_rate, interest_rate,
+                  previous_term_months, term_months, reason, created_by, created_at
         "#,
         tenant_id,
-        input.asset_type,
-        input.description,
-        input.value_estimate,
-        input.status.unwrap_or("available".to_string()),
-        created_by
+        contract_id,
+        input.effective_date,
+        contract.interest_rate,
+        interest_rate,
+        contract.term_months,
+        term_months,
+        input.reason,
+        actor
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *conn)
     .await?;
 
-    // Ghi liên kết vào bảng loan_collateral
+    // Điều khoản hiện hành của hợp đồng = điều khoản mới; ngày đáo hạn dời theo kỳ hạn
     sqlx::query!(
         r#"
-        INSERT INTO loan_collateral (
-            tenant_id, contract_id, asset_id, status, created_by, created_at
-        )
-        VALUES ($1, $2, $3, 'active', $4, NOW())
+        UPDATE loan_contract
+        SET interest_rate = $3,
+            term_months = $4,
+            date_end = CASE WHEN date_end IS NULL THEN NULL ELSE date_start + make_interval(months => $4) END,
+            updated_at = NOW()
+        WHERE tenant_id = $1 AND id = $2
         "#,
         tenant_id,
         contract_id,
-        asset.asset_id,
-        created_by
+        interest_rate,
+        term_months
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
-    Ok(())
+    write_schedule(conn, tenant_id, contract_id, &rows).await?;
+    Ok(change)
+}
+
+/// Kết quả tái cấp vốn
+#[derive(Debug, Serialize)]
+pub struct Refinancing {
+    /// Hợp đồng cũ (đã tất toán)
+    pub settled_contract_id: Uuid,
+    /// Hợp đồng mới nhận toàn bộ số tiền tất toán làm gốc
+    pub contract: LoanContract,
+    pub settlement: SettlementQuote,
+    /// Trạng thái của hợp đồng cũ trước khi tất toán
+    #[serde(skip)]
+    pub settled_from: LoanState,
 }
 
-/// Sinh số HĐ dạng LOAN-{tenant}-{YYYYMM}-{000001}, reset theo **tháng hiện tại**
-pub async fn generate_contract_number_monthly(
+/// ✅ Tái cấp vốn (chạy trong transaction của caller): tất toán hợp đồng cũ bằng giao dịch `settlement`,
+/// mở hợp đồng mới cùng khách hàng với gốc = số tiền tất toán, chuyển tài sản cầm cố sang hợp đồng mới.
+pub async fn refinance_contract(
     conn: &mut PgConnection,
+    i18n: &I18n,
     tenant_id: Uuid,
-    tenant_code: Option<String>,
-) -> Result<String, sqlx::Error> {
-    // 🕒 Lấy ngày hiện tại theo UTC.
-    // Nếu muốn theo giờ VN (Asia/Bangkok), xem chú thích bên dưới.
-    let d = Utc::now().date_naive();
-    let period_ym: i32 = d.year() * 100 + (d.month() as i32);
-
-    // UPSERT counter cho (tenant_id, period_ym)
-    let no: i64 = sqlx::query_scalar(
+    contract_id: Uuid,
+    input: &RefinanceInput,
+    actor: Uuid,
+) -> Result<Refinancing, AppError> {
+    let state = lock_state(&mut *conn, tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(i18n, "error.loan.not_found"))?;
+    if !state.can_transition_to(LoanState::Settled) {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_state_transition"));
+    }
+
+    let old = query::get_contract_by_id(&mut *conn, tenant_id, contract_id).await?;
+    let mut txs = query::get_transactions_by_contract(&mut *conn, tenant_id, contract_id).await?;
+    let date = input.date.unwrap_or_else(Utc::now);
+    if date < old.date_start || txs.iter().any(|t| t.date > date) {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_effective_date"));
+    }
+    let settlement = settlement_breakdown_as_of(&old, &mut txs, date);
+
+    // Tài sản cầm cố tiếp tục bảo đảm cho khoản nợ mới: đóng kỳ cầm cố của hợp đồng cũ tại ngày tái cấp vốn
+    let asset_ids = sqlx::query_scalar!(
         r#"
-        INSERT INTO loan_counters_monthly (tenant_id, period_ym, counter, updated_at)
-        VALUES ($1, $2, 1, now())
-        ON CONFLICT (tenant_id, period_ym) DO UPDATE
-          SET counter = loan_counters_monthly.counter + 1,
-              updated_at = now()
-        RETURNING counter
-        "#
-    )
-    .bind(tenant_id)
-    .bind(period_ym)
-    .fetch_one(conn)
+        UPDATE loan_collateral SET status = 'released', released_at = $3
+        WHERE tenant_id = $1 AND contract_id = $2 AND status = 'active'
+        RETURNING asset_id
+        "#,
+        tenant_id,
+        old.id,
+        date
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // 👉 Hợp đồng mới: giữ điều khoản cũ trừ khi được đổi
+    let repayment_plan = match input.repayment_plan {
+        Some(plan) => plan,
+        None => match repayment_plan(&mut *conn, tenant_id, contract_id).await? {
+            RepaymentPlan::Custom => RepaymentPlan::EqualPrincipal,
+            plan => plan,
+        },
+    };
+    let new_input = CreateContractInput {
+        contact_id: old.contact_id,
+        product_id: old.product_id,
+        principal: settlement.total,
+        interest_rate: Some(input.interest_rate.unwrap_or(old.interest_rate)),
+        term_months: Some(input.term_months.unwrap_or(old.term_months)),
+        date_start: date,
+        date_end: None,
+        storage_fee_rate: Some(old.storage_fee_rate),
+        storage_fee: None,
+        storage_fee_flat: Some(old.storage_fee_flat),
+        penalty_rate: Some(old.penalty_rate),
+        prepayment_penalty: Some(old.prepayment_penalty.0),
+        rate_index: old.rate_index.clone(),
+        rate_margin: Some(old.rate_margin),
+        repayment_waterfall: None,
+        day_count: Some(old.day_count),
+        compounding: Some(old.compounding),
+        current_principal: None,
+        current_interest: None,
+        accumulated_interest: None,
+        total_paid_interest: None,
+        total_settlement_amount: None,
+        created_by: Some(actor),
+        assignee_id: old.assignee_id,
+        shared_with: old.shared_with.clone(),
+        collateral_asset_ids: Some(asset_ids),
+        repayment_plan,
+        custom_schedule: Vec::new(),
+        transactions: vec![TransactionInput {
+            date: date.timestamp(),
+            transaction_type: "disbursement".to_string(),
+            amount: settlement.total,
+            note: Some(format!("Tái cấp vốn từ {}", old.contract_number)),
+            days_from_prev: None,
+            interest_for_period: None,
+            accumulated_interest: None,
+            principal_balance: None,
+        }],
+    };
+    let mut contract = create_contract(&mut *conn, tenant_id, new_input, actor).await?;
+
+    // Giữ thứ tự phân bổ của hợp đồng cũ
+    sqlx::query!(
+        "UPDATE loan_contract SET refinanced_from = $3, repayment_waterfall = $4 WHERE tenant_id = $1 AND id = $2",
+        tenant_id,
+        contract.id,
+        old.id,
+        Json(&old.repayment_waterfall.0) as _
+    )
+    .execute(&mut *conn)
+    .await?;
+    contract.refinanced_from = Some(old.id);
+    contract.repayment_waterfall = old.repayment_waterfall.clone();
+
+    // Tất toán hợp đồng cũ
+    sqlx::query!(
+        r#"
+        INSERT INTO loan_transaction (
+            contract_id, tenant_id, contact_id,
+            transaction_type, amount, "date", note,
+            created_by, assignee_id, shared_with
+        )
+        VALUES ($1, $2, $3, 'settlement', $4, $5, $6, $7, $8, $9)
+        "#,
+        old.id,
+        tenant_id,
+        old.contact_id,
+        settlement.total,
+        date,
+        format!("Tái cấp vốn sang {}", contract.contract_number),
+        actor,
+        old.assignee_id,
+        old.shared_with.as_deref().unwrap_or(&[])
+    )
+    .execute(&mut *conn)
     .await?;
+    // Phí trả trước hạn thu riêng, không cộng vào gốc của hợp đồng mới
+    if settlement.prepayment_penalty > 0 {
+        record_prepayment_fee(&mut *conn, &old, settlement.prepayment_penalty, date, Some(actor)).await?;
+    }
+
+    let reason = input
+        .reason
+        .clone()
+        .unwrap_or_else(|| format!("refinanced_to={}", contract.contract_number));
+    let settled_from =
+        transition_state(&mut *conn, i18n, tenant_id, old.id, LoanState::Settled, Some(&reason), actor).await?;
+
+    Ok(Refinancing { settled_contract_id: old.id, contract, settlement, settled_from })
+}
 
-    // code tenant: ưu tiên tenant_code, fallback 5 ký tự đầu UUID
-    let code: String = tenant_code.unwrap_or_else(|| tenant_id.to_string()[..5].to_string());
-    Ok(format!("LOAN-{}-{:04}{:02}-{:06}", code, d.year(), d.month(), no))
+/// Đợt thanh lý của hợp đồng đang chờ bán tài sản
+async fn open_liquidation(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+) -> Result<Liquidation, AppError> {
+    let liquidation = query::get_liquidation(conn, tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(i18n, "error.loan.liquidation_not_found"))?;
+    if liquidation.status != "seized" {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.liquidation_completed"));
+    }
+    Ok(liquidation)
 }
 
-/*
-🔁 Nếu muốn THÁNG/TZ theo Việt Nam thay vì UTC:
-1) Thêm vào Cargo.toml:
-   chrono-tz = "0.8"
+/// ✅ Thu giữ tài sản cầm cố để thanh lý (chạy trong transaction của caller).
+/// Hợp đồng vẫn giữ trạng thái hiện tại cho tới khi hoàn tất thanh lý.
+pub async fn seize_collateral(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+    input: &SeizeCollateralInput,
+    actor: Uuid,
+) -> Result<Liquidation, AppError> {
+    let state = lock_state(&mut *conn, tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(i18n, "error.loan.not_found"))?;
+    if !state.can_transition_to(LoanState::Liquidated) {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_state_transition"));
+    }
+    if query::get_liquidation(&mut *conn, tenant_id, contract_id).await?.is_some() {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.liquidation_exists"));
+    }
 
-2) Thay 2 dòng trong hàm trên:
-   use chrono_tz::Asia::Bangkok;
-   let d = Utc::now().with_timezone(&Bangkok).date_naive();
-*/
+    // Tài sản đang cầm cố cho hợp đồng
+    let pledged = sqlx::query_scalar!(
+        r#"
+        SELECT a.asset_id
+        FROM loan_collateral lc
+        JOIN collateral_assets a ON a.tenant_id = lc.tenant_id AND a.asset_id = lc.asset_id
+        WHERE lc.tenant_id = $1 AND lc.contract_id = $2 AND lc.status = 'active'
+          AND a.status NOT IN ('seized', 'sold', 'disposed')
+        FOR UPDATE OF a
+        "#,
+        tenant_id,
+        contract_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut asset_ids = match &input.asset_ids {
+        Some(ids) => {
+            if ids.iter().any(|id| !pledged.contains(id)) {
+                return Err(AppError::bad_request_i18n(i18n, "error.loan.collateral_not_pledged"));
+            }
+            ids.clone()
+        }
+        None => pledged,
+    };
+    asset_ids.sort();
+    asset_ids.dedup();
+    if asset_ids.is_empty() {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.no_collateral_to_seize"));
+    }
+
+    let liquidation_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO loan_liquidation (tenant_id, contract_id, reason, seized_by)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+        "#,
+        tenant_id,
+        contract_id,
+        input.reason,
+        actor
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // Chụp lại giá trị ước tính lúc thu giữ
+    sqlx::query!(
+        r#"
+        INSERT INTO loan_liquidation_asset (tenant_id, liquidation_id, asset_id, value_estimate)
+        SELECT tenant_id, $2, asset_id, value_estimate
+        FROM collateral_assets
+        WHERE tenant_id = $1 AND asset_id = ANY($3)
+        "#,
+        tenant_id,
+        liquidation_id,
+        &asset_ids
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE collateral_assets SET status = 'seized' WHERE tenant_id = $1 AND asset_id = ANY($2)",
+        tenant_id,
+        &asset_ids
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    query::get_liquidation(conn, tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(i18n, "error.loan.liquidation_not_found"))
+}
+
+/// ✅ Ghi nhận tiền bán 1 tài sản đã thu giữ (chạy trong transaction của caller)
+pub async fn record_liquidation_proceeds(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+    input: &LiquidationProceedsInput,
+    actor: Uuid,
+) -> Result<LiquidationAsset, AppError> {
+    lock_state(&mut *conn, tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(i18n, "error.loan.not_found"))?;
+    let liquidation = open_liquidation(&mut *conn, i18n, tenant_id, contract_id).await?;
+
+    let asset = sqlx::query_as!(
+        LiquidationAsset,
+        r#"
+        UPDATE loan_liquidation_asset
+        SET proceeds = $4, sold_at = $5, note = $6, recorded_by = $7, recorded_at = now()
+        WHERE tenant_id = $1 AND liquidation_id = $2 AND asset_id = $3
+        RETURNING asset_id, value_estimate, proceeds, sold_at, note, recorded_by, recorded_at
+        "#,
+        tenant_id,
+        liquidation.id,
+        input.asset_id,
+        input.amount,
+        input.sold_at.unwrap_or_else(Utc::now),
+        input.note,
+        actor
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::bad_request_i18n(i18n, "error.loan.collateral_not_seized"))?;
+
+    sqlx::query!(
+        "UPDATE collateral_assets SET status = 'sold' WHERE tenant_id = $1 AND asset_id = $2",
+        tenant_id,
+        input.asset_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(asset)
+}
+
+/// Kết quả hoàn tất thanh lý
+#[derive(Debug, Serialize)]
+pub struct LiquidationOutcome {
+    pub liquidation: Liquidation,
+    /// liquidated (đủ bù nợ) | written_off (còn thiếu)
+    pub state: LoanState,
+    /// Trạng thái hợp đồng trước khi thanh lý
+    #[serde(skip)]
+    pub previous_state: LoanState,
+}
+
+/// ✅ Hoàn tất thanh lý (chạy trong transaction của caller): toàn bộ tài sản thu giữ phải có tiền bán.
+/// Tiền bán được ghi thành 1 giao dịch `liquidation` (tối đa bằng nghĩa vụ tại ngày thanh lý),
+/// tiền thừa → khoản phải trả khách hàng, tiền thiếu → hợp đồng chuyển sang xoá nợ.
+pub async fn complete_liquidation(
+    conn: &mut PgConnection,
+    i18n: &I18n,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+    input: &CompleteLiquidationInput,
+    actor: Uuid,
+) -> Result<LiquidationOutcome, AppError> {
+    let state = lock_state(&mut *conn, tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(i18n, "error.loan.not_found"))?;
+    if !state.can_transition_to(LoanState::Liquidated) {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_state_transition"));
+    }
+    let liquidation = open_liquidation(&mut *conn, i18n, tenant_id, contract_id).await?;
+    let proceeds: Option<i64> = liquidation.assets.iter().map(|a| a.proceeds).sum();
+    let proceeds = proceeds.ok_or_else(|| AppError::bad_request_i18n(i18n, "error.loan.liquidation_proceeds_missing"))?;
+
+    let contract = query::get_contract_by_id(&mut *conn, tenant_id, contract_id).await?;
+    let mut txs = query::get_transactions_by_contract(&mut *conn, tenant_id, contract_id).await?;
+    let date = input.date.unwrap_or_else(Utc::now);
+    if date < contract.date_start || txs.iter().any(|t| t.date > date) {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_effective_date"));
+    }
+    let due = settlement_breakdown_as_of(&contract, &mut txs, date);
+    let allocation = liquidation::allocate(proceeds, &due);
+
+    // Giao dịch thu nợ (kể cả 0đ) để chốt lãi tại ngày thanh lý
+    sqlx::query!(
+        r#"
+        INSERT INTO loan_transaction (
+            contract_id, tenant_id, contact_id,
+            transaction_type, amount, "date", note,
+            created_by, assignee_id, shared_with
+        )
+        VALUES ($1, $2, $3, 'liquidation', $4, $5, $6, $7, $8, $9)
+        "#,
+        contract_id,
+        tenant_id,
+        contract.contact_id,
+        allocation.applied(),
+        date,
+        format!("Thanh lý {} tài sản cầm cố", liquidation.assets.len()),
+        actor,
+        contract.assignee_id,
+        contract.shared_with.as_deref().unwrap_or(&[])
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if allocation.surplus > 0 {
+        sqlx::query!(
+            r#"
+            INSERT INTO loan_contact_payable (tenant_id, contact_id, contract_id, liquidation_id, amount, note, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            tenant_id,
+            contract.contact_id,
+            contract_id,
+            liquidation.id,
+            allocation.surplus,
+            format!("Tiền thừa thanh lý {}", contract.contract_number),
+            actor
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE loan_liquidation SET
+            status = 'completed', liquidation_date = $3, proceeds_total = $4,
+            interest_allocated = $5, principal_allocated = $6, fees_allocated = $7,
+            surplus = $8, shortfall = $9, completed_by = $10, completed_at = now()
+        WHERE tenant_id = $1 AND id = $2
+        "#,
+        tenant_id,
+        liquidation.id,
+        date,
+        proceeds,
+        allocation.interest,
+        allocation.principal,
+        allocation.fees,
+        allocation.surplus,
+        allocation.shortfall,
+        actor
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // Tài sản đã bán → liquidated, tài sản cầm cố không bị thu giữ → trả lại (released)
+    let seized: Vec<Uuid> = liquidation.assets.iter().map(|a| a.asset_id).collect();
+    sqlx::query!(
+        r#"
+        UPDATE loan_collateral
+        SET status = CASE WHEN asset_id = ANY($3) THEN 'liquidated' ELSE 'released' END,
+            released_at = $4
+        WHERE tenant_id = $1 AND contract_id = $2 AND status = 'active'
+        "#,
+        tenant_id,
+        contract_id,
+        &seized,
+        date
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let (to, reason) = if allocation.shortfall > 0 {
+        (LoanState::WrittenOff, format!("liquidation shortfall={}", allocation.shortfall))
+    } else {
+        (LoanState::Liquidated, format!("liquidation surplus={}", allocation.surplus))
+    };
+    let reason = input.reason.clone().unwrap_or(reason);
+    let previous_state = transition_state(&mut *conn, i18n, tenant_id, contract_id, to, Some(&reason), actor).await?;
+
+    let liquidation = query::get_liquidation(conn, tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(i18n, "error.loan.liquidation_not_found"))?;
+    Ok(LiquidationOutcome { liquidation, state: to, previous_state })
+}
+
+/// Thay đổi hồi tố (lãi thả nổi / cơ cấu có ngày hiệu lực trong quá khứ): xếp yêu cầu chạy bù
+/// snapshot loan_report từ ngày hiệu lực tới ngày nghiệp vụ đã đóng gần nhất.
+/// Đã có yêu cầu đang chờ phủ khoảng này thì bỏ qua.
+pub async fn request_report_backfill_from(
+    pool: &PgPool,
+    tenant_id: Uuid,
+    effective_date: DateTime<Utc>,
+) -> sqlx::Result<()> {
+    let tz = query::tenant_timezone(pool, tenant_id).await?;
+    let from = convention::business_date(effective_date, tz);
+    let Some(to) = convention::business_date(Utc::now(), tz).pred_opt() else { return Ok(()) };
+    if from > to {
+        return Ok(());
+    }
+
+    // requested_by = nil: hệ thống tự yêu cầu
+    sqlx::query!(
+        r#"
+        INSERT INTO loan_report_backfill (tenant_id, date_from, date_to, next_date, requested_by)
+        SELECT $1, $2, $3, $2, $4
+        WHERE NOT EXISTS (
+            SELECT 1 FROM loan_report_backfill
+            WHERE tenant_id = $1 AND status = 'pending' AND next_date <= $2 AND date_to >= $3
+        )
+        "#,
+        tenant_id,
+        from,
+        to,
+        Uuid::nil()
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Tính lại snapshot loan_report ngày nghiệp vụ hôm nay cho 1 hợp đồng (upsert theo ngày)
+/// Hợp đồng không còn tồn tại thì bỏ qua.
+pub async fn refresh_loan_report(
+    pool: &PgPool,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+) -> sqlx::Result<()> {
+    let mut contract = match query::get_contract_by_id(pool, tenant_id, contract_id).await {
+        Ok(c) => c,
+        Err(sqlx::Error::RowNotFound) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let mut txs = query::get_transactions_by_contract(pool, tenant_id, contract_id).await?;
+
+    let as_of = Utc::now();
+    calculate_interest_fields_as_of(&mut contract, &mut txs, as_of);
+    store_transaction_projections(pool, tenant_id, &txs).await?;
+    if let Some(next) = sync_overdue_state(pool, &contract).await? {
+        contract.state = next;
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO loan_report (
+            tenant_id, contract_id, contact_id, date,
+            current_principal, current_interest, accumulated_interest,
+            total_paid_interest, total_paid_principal, current_storage_fee, payoff_due, state
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        ON CONFLICT (tenant_id, contract_id, date) DO UPDATE SET
+            current_principal    = EXCLUDED.current_principal,
+            current_interest     = EXCLUDED.current_interest,
+            accumulated_interest = EXCLUDED.accumulated_interest,
+            total_paid_interest  = EXCLUDED.total_paid_interest,
+            total_paid_principal = EXCLUDED.total_paid_principal,
+            current_storage_fee  = EXCLUDED.current_storage_fee,
+            payoff_due           = EXCLUDED.payoff_due,
+            state                = EXCLUDED.state
+        "#,
+        tenant_id,
+        contract.id,
+        contract.contact_id,
+        convention::business_date(as_of, contract.tz()),
+        contract.current_principal,
+        contract.current_interest,
+        contract.accumulated_interest,
+        contract.total_paid_interest,
+        contract.total_paid_principal,
+        contract.current_storage_fee,
+        contract.payoff_due,
+        contract.state.as_str(),
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Ghi projection calculator đã tính vào từng giao dịch (số ngày, lãi kỳ, dư nợ gốc, phần phân bổ)
+/// để báo cáo đọc thẳng từ `loan_transaction` mà không phải tính lại.
+pub async fn store_transaction_projections(
+    pool: &PgPool,
+    tenant_id: Uuid,
+    txs: &[LoanTransaction],
+) -> sqlx::Result<()> {
+    if txs.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<Uuid> = txs.iter().map(|t| t.id).collect();
+    let days: Vec<i32> = txs.iter().map(|t| t.days_from_prev).collect();
+    let interest_for_period: Vec<i64> = txs.iter().map(|t| t.interest_for_period).collect();
+    let accumulated: Vec<i64> = txs.iter().map(|t| t.accumulated_interest).collect();
+    let balance: Vec<i64> = txs.iter().map(|t| t.principal_balance).collect();
+    let principal: Vec<i64> = txs.iter().map(|t| t.principal_applied).collect();
+    let interest: Vec<i64> = txs.iter().map(|t| t.interest_applied).collect();
+    let penalty: Vec<i64> = txs.iter().map(|t| t.penalty_applied).collect();
+    let fee: Vec<i64> = txs.iter().map(|t| t.fee_applied).collect();
+
+    sqlx::query!(
+        r#"
+        UPDATE loan_transaction t SET
+            days_from_prev       = v.days_from_prev,
+            interest_for_period  = v.interest_for_period,
+            accumulated_interest = v.accumulated_interest,
+            principal_balance    = v.principal_balance,
+            principal_applied    = v.principal_applied,
+            interest_applied     = v.interest_applied,
+            penalty_applied      = v.penalty_applied,
+            fee_applied          = v.fee_applied
+        FROM UNNEST($2::uuid[], $3::int4[], $4::int8[], $5::int8[], $6::int8[], $7::int8[], $8::int8[], $9::int8[], $10::int8[])
+            AS v(id, days_from_prev, interest_for_period, accumulated_interest, principal_balance,
+                 principal_applied, interest_applied, penalty_applied, fee_applied)
+        WHERE t.tenant_id = $1 AND t.id = v.id
+        "#,
+        tenant_id,
+        &ids,
+        &days,
+        &interest_for_period,
+        &accumulated,
+        &balance,
+        &principal,
+        &interest,
+        &penalty,
+        &fee
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Upsert hàng loạt snapshot loan_report (trùng `(tenant_id, contract_id, date)` → ghi đè)
+pub async fn upsert_loan_reports(conn: &mut PgConnection, reports: &[LoanReport]) -> sqlx::Result<()> {
+    // 12 tham số / dòng, Postgres giới hạn 65535 tham số / câu lệnh
+    for chunk in reports.chunks(1000) {
+        let mut vals = String::new();
+        let mut args = PgArguments::default();
+        let mut i = 1;
+
+        for r in chunk {
+            if !vals.is_empty() {
+                vals.push_str(", ");
+            }
+            vals.push_str(&format!(
+                "(${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${}, ${})",
+                i, i + 1, i + 2, i + 3, i + 4, i + 5, i + 6, i + 7, i + 8, i + 9, i + 10, i + 11
+            ));
+
+            args.add(r.tenant_id);
+            args.add(r.contract_id);
+            args.add(r.contact_id);
+            args.add(r.date);
+            args.add(r.current_principal);
+            args.add(r.current_interest);
+            args.add(r.accumulated_interest);
+            args.add(r.total_paid_interest);
+            args.add(r.total_paid_principal);
+            args.add(r.current_storage_fee);
+            args.add(r.payoff_due);
+            args.add(r.state);
+
+            i += 12;
+        }
+
+        let sql = format!(
+            r#"
+WITH v(tenant_id, contract_id, contact_id, date,
+       current_principal, current_interest, accumulated_interest,
+       total_paid_interest, total_paid_principal, current_storage_fee, payoff_due, state) AS (
+  VALUES {vals}
+)
+INSERT INTO loan_report (
+  tenant_id, contract_id, contact_id, date,
+  current_principal, current_interest, accumulated_interest,
+  total_paid_interest, total_paid_principal, current_storage_fee, payoff_due, state
+)
+SELECT tenant_id, contract_id, contact_id, date,
+       current_principal, current_interest, accumulated_interest,
+       total_paid_interest, total_paid_principal, current_storage_fee, payoff_due, state
+FROM v
+ON CONFLICT (tenant_id, contract_id, date) DO UPDATE SET
+  current_principal     = EXCLUDED.current_principal,
+  current_interest      = EXCLUDED.current_interest,
+  accumulated_interest  = EXCLUDED.accumulated_interest,
+  total_paid_interest   = EXCLUDED.total_paid_interest,
+  total_paid_principal  = EXCLUDED.total_paid_principal,
+  current_storage_fee   = EXCLUDED.current_storage_fee,
+  payoff_due            = EXCLUDED.payoff_due,
+  state                 = EXCLUDED.state
+"#,
+            vals = vals
+        );
+
+        sqlx::query_with(&sql, args).execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+/// 📸 Snapshot loan_report mọi hợp đồng của tenant tại cuối ngày nghiệp vụ `date`
+/// (ngày chưa kết thúc thì tính tới hiện tại). Chạy lại cùng ngày chỉ ghi đè nên an toàn.
+/// Trạng thái lấy theo lịch sử chuyển trạng thái tại thời điểm đó; hợp đồng chưa bắt đầu thì bỏ qua.
+pub async fn snapshot_loan_reports(conn: &mut PgConnection, tenant_id: Uuid, date: NaiveDate) -> sqlx::Result<usize> {
+    let tz = query::tenant_timezone(&mut *conn, tenant_id).await?;
+    let as_of = convention::end_of_business_day(date, tz).min(Utc::now());
+
+    let contracts = query::list_contracts(&mut *conn, tenant_id).await?;
+    let mut tx_map: HashMap<Uuid, Vec<LoanTransaction>> = HashMap::new();
+    for tx in query::get_transactions_by_tenant(&mut *conn, tenant_id).await? {
+        tx_map.entry(tx.contract_id).or_default().push(tx);
+    }
+    let states: HashMap<Uuid, LoanState> = query::get_states_as_of(&mut *conn, tenant_id, as_of).await?.into_iter().collect();
+
+    let reports: Vec<LoanReport> = contracts
+        .into_iter()
+        .filter(|c| c.date_start <= as_of)
+        .map(|mut contract| {
+            let mut txs = tx_map.remove(&contract.id).unwrap_or_default();
+            calculate_interest_fields_as_of(&mut contract, &mut txs, as_of);
+            LoanReport {
+                tenant_id,
+                contract_id: contract.id,
+                contact_id: contract.contact_id,
+                date,
+                current_principal: Some(contract.current_principal),
+                current_interest: Some(contract.current_interest),
+                accumulated_interest: Some(contract.accumulated_interest),
+                total_paid_interest: Some(contract.total_paid_interest),
+                total_paid_principal: Some(contract.total_paid_principal),
+                current_storage_fee: Some(contract.current_storage_fee),
+                payoff_due: Some(contract.payoff_due),
+                state: states.get(&contract.id).copied().unwrap_or(contract.state),
+            }
+        })
+        .collect();
+
+    upsert_loan_reports(&mut *conn, &reports).await?;
+    Ok(reports.len())
+}
+
+/* ========== Command bus ========== */
+
+/// Tạo hợp đồng (POST /loan/create)
+pub struct CreateContract {
+    pub input: CreateContractInput,
+}
+
+#[async_trait]
+impl Command for CreateContract {
+    type Output = LoanContract;
+    const NAME: &'static str = "loan.create_contract";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "create"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        if self.input.transactions.is_empty() {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.transactions_empty"));
+        }
+        // Theo sản phẩm: lãi suất / kỳ hạn có thể lấy mặc định → lịch trả nợ kiểm tra khi xử lý
+        if self.input.product_id.is_none() {
+            if self.input.interest_rate.is_none() || self.input.term_months.is_none() {
+                return Err(AppError::bad_request_i18n(i18n, "error.loan.terms_required"));
+            }
+            build_schedule(&self.input, self.input.convention(), convention::DEFAULT_TIMEZONE)
+                .map_err(|e| AppError::bad_request_i18n(i18n, e.i18n_key()))?;
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<LoanContract, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let count = self.input.transactions.len();
+        let actor = ctx.user_id();
+        let contract = create_contract(ctx.conn(), tenant_id, self.input, actor).await?;
+
+        ctx.emit(LoanEvent::LoanCreated {
+            contract_id: contract.id,
+            contact_id: contract.contact_id,
+            contract_number: contract.contract_number.clone(),
+        })?;
+        ctx.emit(LoanEvent::LoanTransactionsRecorded { contract_id: contract.id, count })?;
+        Ok(contract)
+    }
+}
+
+/// Cập nhật hợp đồng (POST /loan/:id/update)
+pub struct UpdateContract {
+    pub contract_id: Uuid,
+    pub input: CreateContractInput,
+    /// Scope ABAC của quyền `loan.update`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for UpdateContract {
+    type Output = LoanContract;
+    const NAME: &'static str = "loan.update_contract";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "update"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        if self.input.transactions.is_empty() {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.transactions_empty"));
+        }
+        // Bỏ trống lãi suất / kỳ hạn = giữ nguyên → lịch trả nợ kiểm tra khi xử lý
+        if self.input.interest_rate.is_some() && self.input.term_months.is_some() {
+            build_schedule(&self.input, self.input.convention(), convention::DEFAULT_TIMEZONE)
+                .map_err(|e| AppError::bad_request_i18n(i18n, e.i18n_key()))?;
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<LoanContract, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let contract_id = self.contract_id;
+        let count = self.input.transactions.len();
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, contract_id, "error.loan.not_found").await?;
+        let actor = ctx.user_id();
+        let updated = update_contract(ctx.conn(), tenant_id, contract_id, self.input, actor).await?;
+
+        ctx.emit(LoanEvent::LoanUpdated { contract_id })?;
+        ctx.emit(LoanEvent::LoanTransactionsRecorded { contract_id, count })?;
+        Ok(updated)
+    }
+}
+
+/// Xoá hợp đồng (DELETE /loan/:id)
+pub struct DeleteContract {
+    pub contract_id: Uuid,
+    /// Scope ABAC của quyền `loan.delete`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for DeleteContract {
+    type Output = ();
+    const NAME: &'static str = "loan.delete_contract";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "delete"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, self.contract_id, "error.loan.not_found").await?;
+        if delete_contract(ctx.conn(), tenant_id, self.contract_id).await? {
+            ctx.emit(LoanEvent::LoanDeleted { contract_id: self.contract_id })?;
+        }
+        Ok(())
+    }
+}
+
+/// Kích hoạt hợp đồng nháp (POST /loan/:id/activate)
+pub struct ActivateContract {
+    pub contract_id: Uuid,
+    pub reason: Option<String>,
+    /// Scope ABAC của quyền `loan.update`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for ActivateContract {
+    type Output = LoanState;
+    const NAME: &'static str = "loan.activate_contract";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "update"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<LoanState, AppError> {
+        change_state(ctx, self.contract_id, LoanState::Active, self.reason, &self.scope).await
+    }
+}
+
+/// Xoá nợ hợp đồng (POST /loan/:id/write-off)
+pub struct WriteOffContract {
+    pub contract_id: Uuid,
+    pub reason: Option<String>,
+    /// Scope ABAC của quyền `loan.write_off`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for WriteOffContract {
+    type Output = LoanState;
+    const NAME: &'static str = "loan.write_off_contract";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "write_off"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<LoanState, AppError> {
+        change_state(ctx, self.contract_id, LoanState::WrittenOff, self.reason, &self.scope).await
+    }
+}
+
+async fn change_state(
+    ctx: &mut CommandContext,
+    contract_id: Uuid,
+    to: LoanState,
+    reason: Option<String>,
+    scope: &Scope,
+) -> Result<LoanState, AppError> {
+    let tenant_id = ctx.tenant_id();
+    let actor = ctx.user_id();
+    ctx.ensure_in_scope(scope, &query::SCOPE, contract_id, "error.loan.not_found").await?;
+    let i18n = ctx.i18n.clone();
+    let from = transition_state(ctx.conn(), &i18n, tenant_id, contract_id, to, reason.as_deref(), actor).await?;
+    ctx.emit(LoanEvent::LoanStateChanged { contract_id, from, to })?;
+    Ok(to)
+}
+
+/// Cơ cấu lại lãi suất / kỳ hạn (POST /loan/:id/restructure)
+pub struct RestructureContract {
+    pub contract_id: Uuid,
+    pub input: RestructureInput,
+    /// Scope ABAC của quyền `loan.update`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for RestructureContract {
+    type Output = TermChange;
+    const NAME: &'static str = "loan.restructure_contract";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "update"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        validate_new_terms(i18n, self.input.interest_rate, self.input.term_months)
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<TermChange, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let actor = ctx.user_id();
+        let contract_id = self.contract_id;
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, contract_id, "error.loan.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        let change = restructure_contract(ctx.conn(), &i18n, tenant_id, contract_id, &self.input, actor).await?;
+
+        ctx.emit(LoanEvent::LoanRestructured { contract_id, effective_date: change.effective_date })?;
+        Ok(change)
+    }
+}
+
+/// Tái cấp vốn sang hợp đồng mới (POST /loan/:id/refinance)
+pub struct RefinanceContract {
+    pub contract_id: Uuid,
+    pub input: RefinanceInput,
+    /// Scope ABAC của quyền `loan.refinance`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for RefinanceContract {
+    type Output = Refinancing;
+    const NAME: &'static str = "loan.refinance_contract";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "refinance"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        validate_new_terms(i18n, self.input.interest_rate, self.input.term_months)
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Refinancing, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let actor = ctx.user_id();
+        let contract_id = self.contract_id;
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, contract_id, "error.loan.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        let result = refinance_contract(ctx.conn(), &i18n, tenant_id, contract_id, &self.input, actor).await?;
+        let new_id = result.contract.id;
+
+        ctx.emit(LoanEvent::LoanStateChanged { contract_id, from: result.settled_from, to: LoanState::Settled })?;
+        ctx.emit(LoanEvent::LoanTransactionsRecorded { contract_id, count: 1 })?;
+        ctx.emit(LoanEvent::LoanCreated {
+            contract_id: new_id,
+            contact_id: result.contract.contact_id,
+            contract_number: result.contract.contract_number.clone(),
+        })?;
+        ctx.emit(LoanEvent::LoanTransactionsRecorded { contract_id: new_id, count: 1 })?;
+        ctx.emit(LoanEvent::LoanRefinanced { contract_id, new_contract_id: new_id })?;
+        Ok(result)
+    }
+}
+
+/// Lãi suất không âm, kỳ hạn > 0 (nếu có đổi)
+fn validate_new_terms(i18n: &I18n, interest_rate: Option<f64>, term_months: Option<i32>) -> Result<(), AppError> {
+    if interest_rate.is_some_and(|r| !r.is_finite() || r < 0.0) {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_interest_rate"));
+    }
+    if term_months.is_some_and(|t| t <= 0) {
+        return Err(AppError::bad_request_i18n(i18n, "error.loan.schedule_invalid_term"));
+    }
+    Ok(())
+}
+
+/// Tạo chỉ số lãi suất tham chiếu của tenant (POST /loan/rate-index)
+pub struct CreateRateIndex {
+    pub input: RateIndexInput,
+}
+
+#[async_trait]
+impl Command for CreateRateIndex {
+    type Output = ();
+    const NAME: &'static str = "loan.create_rate_index";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "manage_rates"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        if self.input.code.trim().is_empty() || self.input.name.trim().is_empty() {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.rate_index_invalid"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        let created = sqlx::query!(
+            r#"
+            INSERT INTO loan_rate_index (tenant_id, code, name)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (tenant_id, code) DO NOTHING
+            "#,
+            tenant_id,
+            self.input.code.trim(),
+            self.input.name.trim()
+        )
+        .execute(ctx.conn())
+        .await?;
+        if created.rows_affected() == 0 {
+            return Err(AppError::bad_request_i18n(&ctx.i18n, "error.loan.rate_index_exists"));
+        }
+        Ok(())
+    }
+}
+
+/// Ghi giá trị chỉ số từ 1 ngày hiệu lực (POST /loan/rate-index/:code/values).
+/// Các hợp đồng thả nổi đang dư nợ theo chỉ số này được tính lại báo cáo.
+pub struct SetRateIndexValue {
+    pub code: String,
+    pub input: RateIndexValueInput,
+}
+
+#[async_trait]
+impl Command for SetRateIndexValue {
+    type Output = usize;
+    const NAME: &'static str = "loan.set_rate_index_value";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "manage_rates"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        if !self.input.rate.is_finite() {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_interest_rate"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<usize, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let actor = ctx.user_id();
+        let i18n = ctx.i18n.clone();
+        let conn = ctx.conn();
+        ensure_rate_index(&mut *conn, &i18n, tenant_id, Some(&self.code)).await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO loan_rate_index_value (tenant_id, index_code, effective_date, rate, created_by)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (tenant_id, index_code, effective_date) DO UPDATE SET
+                rate = EXCLUDED.rate,
+                created_by = EXCLUDED.created_by,
+                created_at = now()
+            "#,
+            tenant_id,
+            self.code,
+            self.input.effective_date,
+            self.input.rate,
+            actor
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let affected = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM loan_contract
+            WHERE tenant_id = $1 AND rate_index = $2 AND state IN ('active', 'overdue')
+            "#,
+            tenant_id,
+            self.code
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        for &contract_id in &affected {
+            ctx.emit(LoanEvent::LoanRateChanged { contract_id, effective_date: self.input.effective_date })?;
+        }
+        Ok(affected.len())
+    }
+}
+
+/// Thu giữ tài sản cầm cố để thanh lý (POST /loan/:id/collaterals/seize)
+pub struct SeizeCollateral {
+    pub contract_id: Uuid,
+    pub input: SeizeCollateralInput,
+    /// Scope ABAC của quyền `loan.liquidate`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for SeizeCollateral {
+    type Output = Liquidation;
+    const NAME: &'static str = "loan.seize_collateral";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "liquidate"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Liquidation, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let actor = ctx.user_id();
+        let contract_id = self.contract_id;
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, contract_id, "error.loan.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        let liquidation = seize_collateral(ctx.conn(), &i18n, tenant_id, contract_id, &self.input, actor).await?;
+
+        ctx.emit(LoanEvent::LoanCollateralSeized {
+            contract_id,
+            liquidation_id: liquidation.id,
+            asset_count: liquidation.assets.len(),
+        })?;
+        Ok(liquidation)
+    }
+}
+
+/// Ghi nhận tiền bán tài sản đã thu giữ (POST /loan/:id/collaterals/proceeds)
+pub struct RecordLiquidationProceeds {
+    pub contract_id: Uuid,
+    pub input: LiquidationProceedsInput,
+    /// Scope ABAC của quyền `loan.liquidate`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for RecordLiquidationProceeds {
+    type Output = LiquidationAsset;
+    const NAME: &'static str = "loan.record_liquidation_proceeds";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "liquidate"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        if self.input.amount < 0 {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.invalid_proceeds"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<LiquidationAsset, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let actor = ctx.user_id();
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, self.contract_id, "error.loan.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        record_liquidation_proceeds(ctx.conn(), &i18n, tenant_id, self.contract_id, &self.input, actor).await
+    }
+}
+
+/// Hoàn tất thanh lý và phân bổ tiền bán (POST /loan/:id/collaterals/liquidate)
+pub struct CompleteLiquidation {
+    pub contract_id: Uuid,
+    pub input: CompleteLiquidationInput,
+    /// Scope ABAC của quyền `loan.liquidate`
+    pub scope: Scope,
+}
+
+#[async_trait]
+impl Command for CompleteLiquidation {
+    type Output = LiquidationOutcome;
+    const NAME: &'static str = "loan.complete_liquidation";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "liquidate"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<LiquidationOutcome, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let actor = ctx.user_id();
+        let contract_id = self.contract_id;
+        ctx.ensure_in_scope(&self.scope, &query::SCOPE, contract_id, "error.loan.not_found").await?;
+        let i18n = ctx.i18n.clone();
+        let outcome = complete_liquidation(ctx.conn(), &i18n, tenant_id, contract_id, &self.input, actor).await?;
+
+        ctx.emit(LoanEvent::LoanTransactionsRecorded { contract_id, count: 1 })?;
+        ctx.emit(LoanEvent::LoanStateChanged { contract_id, from: outcome.previous_state, to: outcome.state })?;
+        ctx.emit(LoanEvent::LoanLiquidated {
+            contract_id,
+            liquidation_id: outcome.liquidation.id,
+            surplus: outcome.liquidation.surplus,
+            shortfall: outcome.liquidation.shortfall,
+        })?;
+        Ok(outcome)
+    }
+}
+
+/// Đặt ngưỡng LTV của tenant, trùng mã thì ghi đè (POST /loan/ltv-thresholds)
+pub struct SetLtvThreshold {
+    pub input: LtvThresholdInput,
+}
+
+#[async_trait]
+impl Command for SetLtvThreshold {
+    type Output = ();
+    const NAME: &'static str = "loan.set_ltv_threshold";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "manage_ltv"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        if self.input.code.trim().is_empty() || self.input.name.trim().is_empty() {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.ltv_threshold_invalid"));
+        }
+        if !self.input.ltv.is_finite() || self.input.ltv <= 0.0 {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.ltv_threshold_invalid"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        sqlx::query!(
+            r#"
+            INSERT INTO loan_ltv_threshold (tenant_id, code, name, ltv)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (tenant_id, code) DO UPDATE SET name = EXCLUDED.name, ltv = EXCLUDED.ltv
+            "#,
+            tenant_id,
+            self.input.code.trim(),
+            self.input.name.trim(),
+            self.input.ltv
+        )
+        .execute(ctx.conn())
+        .await?;
+        Ok(())
+    }
+}
+
+/// Xoá ngưỡng LTV (DELETE /loan/ltv-thresholds/:code) – cờ đang gắn theo ngưỡng này bị xoá theo
+pub struct DeleteLtvThreshold {
+    pub code: String,
+}
+
+#[async_trait]
+impl Command for DeleteLtvThreshold {
+    type Output = ();
+    const NAME: &'static str = "loan.delete_ltv_threshold";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "manage_ltv"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        let deleted = sqlx::query!(
+            "DELETE FROM loan_ltv_threshold WHERE tenant_id = $1 AND code = $2",
+            tenant_id,
+            self.code
+        )
+        .execute(ctx.conn())
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(AppError::not_found_i18n(&ctx.i18n, "error.loan.ltv_threshold_not_found"));
+        }
+        Ok(())
+    }
+}
+
+/// Tạo sản phẩm vay (POST /loan/products)
+pub struct CreateLoanProduct {
+    pub input: LoanProductInput,
+}
+
+#[async_trait]
+impl Command for CreateLoanProduct {
+    type Output = Uuid;
+    const NAME: &'static str = "loan.create_product";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "manage_products"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        if !product::valid_config(&self.input) {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.product_invalid"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Uuid, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let actor = ctx.user_id();
+        let i18n = ctx.i18n.clone();
+        repayment_waterfall(ctx.conn(), &i18n, tenant_id, self.input.repayment_waterfall.as_deref()).await?;
+
+        let input = self.input;
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO loan_product (
+                tenant_id, code, name, description,
+                min_rate, max_rate, default_rate,
+                min_term_months, max_term_months, default_term_months,
+                min_principal, max_principal, day_count, compounding, required_collateral_types,
+                storage_fee_rate, storage_fee_flat, penalty_rate, prepayment_penalty,
+                repayment_waterfall, number_prefix, active, created_by
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23)
+            ON CONFLICT (tenant_id, code) DO NOTHING
+            RETURNING id
+            "#,
+            tenant_id,
+            input.code.trim(),
+            input.name.trim(),
+            input.description,
+            input.min_rate,
+            input.max_rate,
+            input.default_rate,
+            input.min_term_months,
+            input.max_term_months,
+            input.default_term_months,
+            input.min_principal,
+            input.max_principal,
+            input.day_count.unwrap_or_default() as _,
+            input.compounding.unwrap_or_default() as _,
+            &input.required_collateral_types,
+            input.storage_fee_rate.unwrap_or(0.0),
+            input.storage_fee_flat.unwrap_or(0),
+            input.penalty_rate.unwrap_or(0.0),
+            Json(input.prepayment_penalty.unwrap_or_default()) as _,
+            input.repayment_waterfall.as_deref().filter(|c| !c.is_empty()),
+            input.number_prefix.as_deref().map(str::trim).filter(|p| !p.is_empty()),
+            input.active.unwrap_or(true),
+            actor
+        )
+        .fetch_optional(ctx.conn())
+        .await?
+        .ok_or_else(|| AppError::bad_request_i18n(&ctx.i18n, "error.loan.product_exists"))?;
+        Ok(id)
+    }
+}
+
+/// Cập nhật sản phẩm vay (POST /loan/products/:id) – chỉ áp dụng cho hợp đồng tạo / cập nhật sau đó,
+/// ngừng bán bằng `active = false` (không xoá sản phẩm đã có hợp đồng)
+pub struct UpdateLoanProduct {
+    pub product_id: Uuid,
+    pub input: LoanProductInput,
+}
+
+#[async_trait]
+impl Command for UpdateLoanProduct {
+    type Output = ();
+    const NAME: &'static str = "loan.update_product";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "manage_products"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        if !product::valid_config(&self.input) {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.product_invalid"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        let i18n = ctx.i18n.clone();
+        repayment_waterfall(ctx.conn(), &i18n, tenant_id, self.input.repayment_waterfall.as_deref()).await?;
+
+        let input = self.input;
+        let code_taken = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM loan_product WHERE tenant_id = $1 AND code = $2 AND id <> $3) AS "exists!""#,
+            tenant_id,
+            input.code.trim(),
+            self.product_id
+        )
+        .fetch_one(ctx.conn())
+        .await?;
+        if code_taken {
+            return Err(AppError::bad_request_i18n(&i18n, "error.loan.product_exists"));
+        }
+
+        let updated = sqlx::query!(
+            r#"
+            UPDATE loan_product SET
+                code = $3, name = $4, description = $5,
+                min_rate = $6, max_rate = $7, default_rate = $8,
+                min_term_months = $9, max_term_months = $10, default_term_months = $11,
+                min_principal = $12, max_principal = $13,
+                day_count = $14, compounding = $15, required_collateral_types = $16,
+                storage_fee_rate = $17, storage_fee_flat = $18, penalty_rate = $19, prepayment_penalty = $20,
+                repayment_waterfall = $21, number_prefix = $22, active = $23,
+                updated_at = now()
+            WHERE tenant_id = $1 AND id = $2
+            "#,
+            tenant_id,
+            self.product_id,
+            input.code.trim(),
+            input.name.trim(),
+            input.description,
+            input.min_rate,
+            input.max_rate,
+            input.default_rate,
+            input.min_term_months,
+            input.max_term_months,
+            input.default_term_months,
+            input.min_principal,
+            input.max_principal,
+            input.day_count.unwrap_or_default() as _,
+            input.compounding.unwrap_or_default() as _,
+            &input.required_collateral_types,
+            input.storage_fee_rate.unwrap_or(0.0),
+            input.storage_fee_flat.unwrap_or(0),
+            input.penalty_rate.unwrap_or(0.0),
+            Json(input.prepayment_penalty.unwrap_or_default()) as _,
+            input.repayment_waterfall.as_deref().filter(|c| !c.is_empty()),
+            input.number_prefix.as_deref().map(str::trim).filter(|p| !p.is_empty()),
+            input.active.unwrap_or(true)
+        )
+        .execute(ctx.conn())
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(AppError::not_found_i18n(&i18n, "error.loan.product_not_found"));
+        }
+        Ok(())
+    }
+}
+
+/// Đặt thứ tự phân bổ khoản trả gộp, trùng mã thì ghi đè (POST /loan/repayment-waterfalls).
+/// Hợp đồng đã tạo giữ bản sao cũ, chỉ hợp đồng mới / cập nhật sau đó dùng thứ tự mới.
+pub struct SetRepaymentWaterfall {
+    pub input: RepaymentWaterfallInput,
+}
+
+#[async_trait]
+impl Command for SetRepaymentWaterfall {
+    type Output = ();
+    const NAME: &'static str = "loan.set_repayment_waterfall";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "manage_products"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        if self.input.code.trim().is_empty() || self.input.name.trim().is_empty() {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.waterfall_invalid"));
+        }
+        if Waterfall::new(self.input.steps.clone()).is_none() {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.waterfall_invalid"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        let steps = Waterfall::new(self.input.steps)
+            .ok_or_else(|| AppError::bad_request_i18n(&ctx.i18n, "error.loan.waterfall_invalid"))?;
+        sqlx::query!(
+            r#"
+            INSERT INTO loan_repayment_waterfall (tenant_id, code, name, steps)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (tenant_id, code) DO UPDATE SET name = EXCLUDED.name, steps = EXCLUDED.steps
+            "#,
+            tenant_id,
+            self.input.code.trim(),
+            self.input.name.trim(),
+            Json(steps) as _
+        )
+        .execute(ctx.conn())
+        .await?;
+        Ok(())
+    }
+}
+
+/// Xoá thứ tự phân bổ (DELETE /loan/repayment-waterfalls/:code) – không ảnh hưởng hợp đồng đã tạo
+pub struct DeleteRepaymentWaterfall {
+    pub code: String,
+}
+
+#[async_trait]
+impl Command for DeleteRepaymentWaterfall {
+    type Output = ();
+    const NAME: &'static str = "loan.delete_repayment_waterfall";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "manage_products"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        let deleted = sqlx::query!(
+            "DELETE FROM loan_repayment_waterfall WHERE tenant_id = $1 AND code = $2",
+            tenant_id,
+            self.code
+        )
+        .execute(ctx.conn())
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(AppError::not_found_i18n(&ctx.i18n, "error.loan.waterfall_not_found"));
+        }
+        Ok(())
+    }
+}
+
+/// Yêu cầu chạy bù snapshot loan_report cho 1 khoảng ngày (POST /loan/report/backfill).
+/// Job snapshot xử lý dần từng ngày, server khởi động lại thì chạy tiếp từ `next_date`.
+pub struct RequestReportBackfill {
+    pub input: ReportBackfillInput,
+}
+
+/// Khoảng tối đa của 1 yêu cầu chạy bù
+const MAX_BACKFILL_DAYS: i64 = 366;
+
+#[async_trait]
+impl Command for RequestReportBackfill {
+    type Output = Uuid;
+    const NAME: &'static str = "loan.request_report_backfill";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "update"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        let days = (self.input.date_to - self.input.date_from).num_days();
+        if !(0..MAX_BACKFILL_DAYS).contains(&days) {
+            return Err(AppError::bad_request_i18n(i18n, "error.loan.report_backfill_invalid_range"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<Uuid, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let actor = ctx.user_id();
+        let tz = query::tenant_timezone(&mut *ctx.conn(), tenant_id).await?;
+        if self.input.date_to > convention::business_date(Utc::now(), tz) {
+            return Err(AppError::bad_request_i18n(&ctx.i18n, "error.loan.report_backfill_invalid_range"));
+        }
+
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO loan_report_backfill (tenant_id, date_from, date_to, next_date, requested_by)
+            VALUES ($1, $2, $3, $2, $4)
+            RETURNING id
+            "#,
+            tenant_id,
+            self.input.date_from,
+            self.input.date_to,
+            actor
+        )
+        .fetch_one(ctx.conn())
+        .await?;
+        Ok(id)
+    }
+}
+
+/// Tính & ghi loan_report mọi hợp đồng của tenant cho ngày nghiệp vụ hôm nay (POST /loan/report/pivot-now),
+/// trả về số hợp đồng đã snapshot
+pub struct SnapshotLoanReports;
+
+#[async_trait]
+impl Command for SnapshotLoanReports {
+    type Output = usize;
+    const NAME: &'static str = "loan.snapshot_reports";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("loan", "update"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<usize, AppError> {
+        let tenant_id = ctx.tenant_id();
+        let tz = query::tenant_timezone(&mut *ctx.conn(), tenant_id).await?;
+        let today = convention::business_date(Utc::now(), tz);
+        let count = snapshot_loan_reports(ctx.conn(), tenant_id, today)
+            .await
+            .map_err(|e| AppError::internal(format!("CTE upsert loan_report lỗi: {:?}", e)))?;
+        Ok(count)
+    }
+}
@@ -0,0 +1,152 @@
+//! Quy ước tính lãi của hợp đồng: cơ sở ngày (day-count) + cách ghép lãi (compounding),
+//! và múi giờ nghiệp vụ để quy đổi thời điểm giao dịch sang ngày.
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+pub use crate::core::timezone::{business_date, end_of_business_day, parse_timezone, DEFAULT_TIMEZONE};
+
+/// Cơ sở tính ngày
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "text")]
+pub enum DayCount {
+    /// Số ngày thực tế / 365
+    #[default]
+    #[serde(rename = "act_365")]
+    #[sqlx(rename = "act_365")]
+    Act365,
+    /// Số ngày thực tế / 360
+    #[serde(rename = "act_360")]
+    #[sqlx(rename = "act_360")]
+    Act360,
+    /// Số ngày thực tế / số ngày của từng năm (ISDA)
+    #[serde(rename = "act_act")]
+    #[sqlx(rename = "act_act")]
+    ActAct,
+    /// 30/360 (US bond basis)
+    #[serde(rename = "30_360")]
+    #[sqlx(rename = "30_360")]
+    Thirty360,
+}
+
+/// Cách ghép lãi
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Compounding {
+    /// Lãi đơn trên dư nợ gốc
+    #[default]
+    Simple,
+    /// Ghép lãi theo ngày (lãi chưa trả nhập gốc)
+    Daily,
+    /// Ghép lãi theo tháng
+    Monthly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct InterestConvention {
+    pub day_count: DayCount,
+    pub compounding: Compounding,
+}
+
+impl DayCount {
+    /// Phần năm giữa 2 ngày (to <= from → 0)
+    pub fn year_fraction(&self, from: NaiveDate, to: NaiveDate) -> f64 {
+        if to <= from {
+            return 0.0;
+        }
+        let days = (to - from).num_days() as f64;
+        match self {
+            DayCount::Act365 => days / 365.0,
+            DayCount::Act360 => days / 360.0,
+            DayCount::ActAct => {
+                // chia đoạn theo từng năm dương lịch
+                let mut total = 0.0;
+                let mut cur = from;
+                while cur < to {
+                    let next_year = NaiveDate::from_ymd_opt(cur.year() + 1, 1, 1).unwrap_or(to);
+                    let end = next_year.min(to);
+                    total += (end - cur).num_days() as f64 / days_in_year(cur.year());
+                    cur = end;
+                }
+                total
+            }
+            DayCount::Thirty360 => {
+                let mut d1 = from.day() as i64;
+                let mut d2 = to.day() as i64;
+                if d1 == 31 {
+                    d1 = 30;
+                }
+                if d2 == 31 && d1 == 30 {
+                    d2 = 30;
+                }
+                let days = 360 * (to.year() - from.year()) as i64
+                    + 30 * (to.month() as i64 - from.month() as i64)
+                    + (d2 - d1);
+                days.max(0) as f64 / 360.0
+            }
+        }
+    }
+
+    /// Số ngày/năm dùng cho ghép lãi ngày
+    fn basis(&self) -> f64 {
+        match self {
+            DayCount::Act360 | DayCount::Thirty360 => 360.0,
+            _ => 365.0,
+        }
+    }
+}
+
+impl InterestConvention {
+    /// Lãi phát sinh trong [from, to).
+    /// Lãi đơn chỉ tính trên gốc; ghép lãi tính trên gốc + lãi chưa trả.
+    pub fn accrue(&self, principal: f64, unpaid_interest: f64, annual_rate_pct: f64, from: NaiveDate, to: NaiveDate) -> f64 {
+        let yf = self.day_count.year_fraction(from, to);
+        if yf <= 0.0 {
+            return 0.0;
+        }
+        let rate = annual_rate_pct / 100.0;
+        let periods = match self.compounding {
+            Compounding::Simple => return principal * rate * yf,
+            Compounding::Daily => self.day_count.basis(),
+            Compounding::Monthly => 12.0,
+        };
+        let base = principal + unpaid_interest.max(0.0);
+        base * ((1.0 + rate / periods).powf(periods * yf) - 1.0)
+    }
+}
+
+fn days_in_year(year: i32) -> f64 {
+    if NaiveDate::from_ymd_opt(year, 2, 29).is_some() { 366.0 } else { 365.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(y: i32, m: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, day).unwrap()
+    }
+
+    #[test]
+    fn day_count_fractions() {
+        let (a, b) = (d(2023, 12, 1), d(2024, 3, 1)); // 91 ngày, qua năm nhuận
+        assert!((DayCount::Act365.year_fraction(a, b) - 91.0 / 365.0).abs() < 1e-12);
+        assert!((DayCount::Act360.year_fraction(a, b) - 91.0 / 360.0).abs() < 1e-12);
+        assert!((DayCount::ActAct.year_fraction(a, b) - (31.0 / 365.0 + 60.0 / 366.0)).abs() < 1e-12);
+        assert!((DayCount::Thirty360.year_fraction(a, b) - 90.0 / 360.0).abs() < 1e-12);
+        assert_eq!(DayCount::Thirty360.year_fraction(d(2024, 1, 31), d(2024, 3, 31)), 60.0 / 360.0);
+    }
+
+    #[test]
+    fn compounding_exceeds_simple() {
+        let (a, b) = (d(2024, 1, 1), d(2025, 1, 1));
+        let simple = InterestConvention { day_count: DayCount::ActAct, compounding: Compounding::Simple };
+        let monthly = InterestConvention { compounding: Compounding::Monthly, ..simple };
+        let daily = InterestConvention { compounding: Compounding::Daily, ..simple };
+        assert!((simple.accrue(1_000.0, 0.0, 12.0, a, b) - 120.0).abs() < 1e-9);
+        assert!((monthly.accrue(1_000.0, 0.0, 12.0, a, b) - 126.825_030_131_97).abs() < 1e-6);
+        assert!(daily.accrue(1_000.0, 0.0, 12.0, a, b) > monthly.accrue(1_000.0, 0.0, 12.0, a, b));
+        // lãi chưa trả chỉ được ghép khi compounding
+        assert_eq!(simple.accrue(1_000.0, 50.0, 12.0, a, b), simple.accrue(1_000.0, 0.0, 12.0, a, b));
+    }
+}
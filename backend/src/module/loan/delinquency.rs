@@ -0,0 +1,169 @@
+//! Quá hạn (days-past-due), nhóm nợ và lãi phạt trên số tiền quá hạn.
+//! Số tiền đến hạn lấy từ lịch trả nợ (`loan_schedule`), số đã trả lấy từ phần
+//! `interest_applied` + `principal_applied` do `calculator` phân bổ.
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::module::loan::convention::DayCount;
+use crate::module::loan::schedule::Installment;
+
+/// Nhóm nợ theo số ngày quá hạn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum DpdBucket {
+    #[serde(rename = "current")]
+    Current,
+    #[serde(rename = "1-30")]
+    Dpd1To30,
+    #[serde(rename = "31-60")]
+    Dpd31To60,
+    #[serde(rename = "61-90")]
+    Dpd61To90,
+    #[serde(rename = "90+")]
+    Dpd90Plus,
+}
+
+impl DpdBucket {
+    pub const ALL: [DpdBucket; 5] = [
+        DpdBucket::Current,
+        DpdBucket::Dpd1To30,
+        DpdBucket::Dpd31To60,
+        DpdBucket::Dpd61To90,
+        DpdBucket::Dpd90Plus,
+    ];
+
+    pub fn from_days(days_past_due: i32) -> Self {
+        match days_past_due {
+            d if d <= 0 => DpdBucket::Current,
+            1..=30 => DpdBucket::Dpd1To30,
+            31..=60 => DpdBucket::Dpd31To60,
+            61..=90 => DpdBucket::Dpd61To90,
+            _ => DpdBucket::Dpd90Plus,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DpdBucket::Current => "current",
+            DpdBucket::Dpd1To30 => "1-30",
+            DpdBucket::Dpd31To60 => "31-60",
+            DpdBucket::Dpd61To90 => "61-90",
+            DpdBucket::Dpd90Plus => "90+",
+        }
+    }
+}
+
+/// Kết quả đánh giá quá hạn tại 1 thời điểm
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Delinquency {
+    /// Số ngày quá hạn của kỳ chưa trả cũ nhất
+    pub days_past_due: i32,
+    /// Tổng số tiền đến hạn chưa trả
+    pub overdue_amount: i64,
+    /// Lãi phạt phát sinh (chưa trừ phần đã trả)
+    pub penalty_accrued: f64,
+}
+
+/// ✅ Tính quá hạn + lãi phạt đến ngày `until`.
+/// `payments`: (ngày, số tiền gốc+lãi đã phân bổ) theo thứ tự thời gian.
+/// Lãi phạt (lãi đơn) = Σ số tiền quá hạn × `penalty_rate`%/năm × phần năm theo `day_count`,
+/// tính theo từng đoạn giữa các mốc (ngày đến hạn, ngày thanh toán).
+pub fn assess(
+    schedule: &[Installment],
+    payments: &[(NaiveDate, i64)],
+    until: NaiveDate,
+    penalty_rate: f64,
+    day_count: DayCount,
+) -> Delinquency {
+    if schedule.is_empty() {
+        return Delinquency::default();
+    }
+
+    // Mốc thay đổi: +đến hạn, −thanh toán
+    let mut events: Vec<(NaiveDate, i64)> = schedule
+        .iter()
+        .filter(|i| i.due_date <= until)
+        .map(|i| (i.due_date, i.total_due))
+        .chain(payments.iter().filter(|(d, _)| *d <= until).map(|(d, amt)| (*d, -amt)))
+        .collect();
+    events.sort_by_key(|(d, _)| *d);
+
+    let rate = penalty_rate / 100.0;
+    let mut balance: i64 = 0; // đến hạn − đã trả (âm = trả trước)
+    let mut penalty = 0.0;
+    let mut i = 0;
+    while i < events.len() {
+        let day = events[i].0;
+        while i < events.len() && events[i].0 == day {
+            balance += events[i].1;
+            i += 1;
+        }
+        let next = events.get(i).map(|(d, _)| *d).unwrap_or(until);
+        penalty += balance.max(0) as f64 * rate * day_count.year_fraction(day, next);
+    }
+
+    // Kỳ chưa trả đủ cũ nhất (phân bổ thanh toán theo FIFO)
+    let paid: i64 = payments.iter().filter(|(d, _)| *d <= until).map(|(_, amt)| amt).sum();
+    let mut cumulative = 0;
+    let oldest_unpaid = schedule.iter().find(|inst| {
+        cumulative += inst.total_due;
+        cumulative > paid
+    });
+    let days_past_due = match oldest_unpaid {
+        Some(inst) if inst.due_date < until => (until - inst.due_date).num_days() as i32,
+        _ => 0,
+    };
+
+    Delinquency { days_past_due, overdue_amount: balance.max(0), penalty_accrued: penalty }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(m: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, m, day).unwrap()
+    }
+
+    fn schedule() -> Vec<Installment> {
+        [(2, 1), (3, 1), (4, 1)]
+            .iter()
+            .enumerate()
+            .map(|(i, (m, day))| Installment {
+                seq: i as i32 + 1,
+                due_date: d(*m, *day),
+                principal: 1_000_000,
+                interest: 0,
+                total_due: 1_000_000,
+                remaining_balance: 2_000_000 - i as i64 * 1_000_000,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn buckets() {
+        assert_eq!(DpdBucket::from_days(0), DpdBucket::Current);
+        assert_eq!(DpdBucket::from_days(30), DpdBucket::Dpd1To30);
+        assert_eq!(DpdBucket::from_days(31), DpdBucket::Dpd31To60);
+        assert_eq!(DpdBucket::from_days(90), DpdBucket::Dpd61To90);
+        assert_eq!(DpdBucket::from_days(91), DpdBucket::Dpd90Plus);
+    }
+
+    #[test]
+    fn paid_on_time_is_current() {
+        let payments = [(d(2, 1), 1_000_000), (d(3, 1), 1_000_000)];
+        let r = assess(&schedule(), &payments, d(3, 20), 36.5, DayCount::Act365);
+        assert_eq!(r, Delinquency { days_past_due: 0, overdue_amount: 0, penalty_accrued: 0.0 });
+    }
+
+    #[test]
+    fn late_installment_accrues_penalty_until_paid() {
+        // kỳ 1 trả trễ 10 ngày, kỳ 2 chưa trả
+        let payments = [(d(2, 11), 1_000_000)];
+        let r = assess(&schedule(), &payments, d(3, 21), 36.5, DayCount::Act365);
+        assert_eq!(r.days_past_due, 20);
+        assert_eq!(r.overdue_amount, 1_000_000);
+        // 36.5%/năm = 0.1%/ngày: 10 ngày (kỳ 1) + 20 ngày (kỳ 2) trên 1tr
+        assert!((r.penalty_accrued - 30_000.0).abs() < 1e-6);
+        assert_eq!(DpdBucket::from_days(r.days_past_due), DpdBucket::Dpd1To30);
+    }
+}
@@ -1,35 +1,87 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use sqlx::types::BigDecimal;
+use crate::module::loan::calculator::SettlementQuote;
+use crate::module::loan::convention::{Compounding, DayCount, InterestConvention};
+use crate::module::loan::prepayment::PrepaymentPenalty;
+use crate::module::loan::schedule::{CustomInstallment, RepaymentPlan};
+use crate::module::loan::waterfall::Bucket;
 
 // ================== LOAN ==================
 
 #[derive(Debug, Deserialize)]
 pub struct CreateContractInput {
     pub contact_id: Uuid,
+    /// Sản phẩm vay: điền mặc định cho các trường bỏ trống + kiểm tra khung (chỉ khi tạo)
+    #[serde(default)]
+    pub product_id: Option<Uuid>,
     pub principal: i64,
-    pub interest_rate: f64,
-    pub term_months: i32,
+    /// %/năm – bỏ trống khi tạo theo sản phẩm (lấy mặc định), khi cập nhật (giữ nguyên)
+    #[serde(default)]
+    pub interest_rate: Option<f64>,
+    #[serde(default)]
+    pub term_months: Option<i32>,
     pub date_start: DateTime<Utc>,
     pub date_end: Option<DateTime<Utc>>,
+    /// % giá trị tài sản cầm cố / ngày
     pub storage_fee_rate: Option<f64>,
     pub storage_fee: Option<i64>,
+    /// Phí lưu kho cố định / ngày
+    #[serde(default)]
+    pub storage_fee_flat: Option<i64>,
+    /// Lãi phạt %/năm trên số tiền quá hạn
+    #[serde(default)]
+    pub penalty_rate: Option<f64>,
+    /// Phí trả nợ trước hạn (mặc định không thu)
+    #[serde(default)]
+    pub prepayment_penalty: Option<PrepaymentPenalty>,
+    /// Lãi thả nổi: mã chỉ số tham chiếu của tenant ("" khi cập nhật = chuyển về lãi cố định).
+    /// `interest_rate` vẫn dùng để lập lịch và cho giai đoạn trước khi chỉ số có giá trị.
+    #[serde(default)]
+    pub rate_index: Option<String>,
+    /// Biên độ %/năm cộng vào chỉ số
+    #[serde(default)]
+    pub rate_margin: Option<f64>,
+    /// Mã thứ tự phân bổ khoản trả gộp của tenant (mặc định phạt → phí → lãi → gốc)
+    #[serde(default)]
+    pub repayment_waterfall: Option<String>,
+    /// Quy ước tính lãi (mặc định ACT/365, lãi đơn)
+    #[serde(default)]
+    pub day_count: Option<DayCount>,
+    #[serde(default)]
+    pub compounding: Option<Compounding>,
     pub current_principal: Option<i64>,
     pub current_interest: Option<i64>,
     pub accumulated_interest: Option<i64>,
     pub total_paid_interest: Option<i64>,
     pub total_settlement_amount: Option<i64>,
-    pub state: String,
     pub created_by: Option<Uuid>,
     pub assignee_id: Option<Uuid>,
     pub shared_with: Option<Vec<Uuid>>,
     pub collateral_asset_ids: Option<Vec<Uuid>>,
 
+    /// Phương thức trả nợ để sinh lịch (mặc định gốc đều)
+    #[serde(default)]
+    pub repayment_plan: RepaymentPlan,
+    /// Gốc từng kỳ khi `repayment_plan = custom`
+    #[serde(default)]
+    pub custom_schedule: Vec<CustomInstallment>,
+
     #[serde(default)]
     pub transactions: Vec<TransactionInput>,
 }
 
+impl CreateContractInput {
+    /// Quy ước lãi theo input, thiếu → mặc định
+    pub fn convention(&self) -> InterestConvention {
+        InterestConvention {
+            day_count: self.day_count.unwrap_or_default(),
+            compounding: self.compounding.unwrap_or_default(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct TransactionInput {
     pub date: i64, // epoch seconds client gửi
@@ -47,6 +99,49 @@ pub struct TransactionInput {
     pub principal_balance: Option<i64>,
 }
 
+/// Dùng cho POST /loan/:id/restructure – thiếu trường nào giữ nguyên điều khoản đó
+#[derive(Debug, Deserialize)]
+pub struct RestructureInput {
+    pub effective_date: DateTime<Utc>,
+    pub interest_rate: Option<f64>,
+    /// Tổng kỳ hạn mới tính từ ngày bắt đầu hợp đồng
+    pub term_months: Option<i32>,
+    pub reason: Option<String>,
+}
+
+/// Dùng cho POST /loan/:id/refinance – hợp đồng mới mặc định giữ điều khoản của hợp đồng cũ
+#[derive(Debug, Deserialize)]
+pub struct RefinanceInput {
+    /// Ngày tất toán HĐ cũ / giải ngân HĐ mới (mặc định: bây giờ)
+    pub date: Option<DateTime<Utc>>,
+    pub interest_rate: Option<f64>,
+    pub term_months: Option<i32>,
+    pub repayment_plan: Option<RepaymentPlan>,
+    pub reason: Option<String>,
+}
+
+/// Dùng cho POST /loan/rate-index
+#[derive(Debug, Deserialize)]
+pub struct RateIndexInput {
+    pub code: String,
+    pub name: String,
+}
+
+/// Dùng cho POST /loan/rate-index/:code/values – ghi đè nếu trùng ngày hiệu lực
+#[derive(Debug, Deserialize)]
+pub struct RateIndexValueInput {
+    pub effective_date: DateTime<Utc>,
+    /// %/năm
+    pub rate: f64,
+}
+
+/// Dùng cho POST /loan/report/backfill – chạy bù snapshot loan_report từ `date_from` tới `date_to` (ngày nghiệp vụ)
+#[derive(Debug, Deserialize)]
+pub struct ReportBackfillInput {
+    pub date_from: NaiveDate,
+    pub date_to: NaiveDate,
+}
+
 // === DTO trả ra Frontend (view) ===
 #[derive(Debug, Serialize)]
 pub struct ContractView {
@@ -68,6 +163,15 @@ pub struct ContractView {
     pub transactions: Vec<TransactionView>,
 }
 
+/// Báo giá tất toán kèm hợp đồng (POST /loan/:id/settlement-quote)
+#[derive(Debug, Serialize)]
+pub struct SettlementQuoteView {
+    pub contract_id: Uuid,
+    pub contract_number: String,
+    #[serde(flatten)]
+    pub quote: SettlementQuote,
+}
+
 #[derive(Debug, Serialize)]
 pub struct TransactionView {
     pub date: i64, // vẫn trả epoch/hoặc ISO tuỳ FE
@@ -109,3 +213,120 @@ pub struct CollateralAsset {
     pub created_by: Uuid,
     pub created_at: DateTime<Utc>,
 }
+
+/// Dùng cho POST /loan/collateral/:asset_id/valuations
+#[derive(Debug, Deserialize)]
+pub struct CreateValuationDto {
+    pub value: BigDecimal,
+    /// Mặc định: bây giờ
+    pub valuation_date: Option<DateTime<Utc>>,
+    pub appraiser: Option<String>,
+    /// manual/market/appraisal/auction... (mặc định manual)
+    pub method: Option<String>,
+    pub note: Option<String>,
+}
+
+/// 1 lần định giá tài sản (bảng `collateral_valuation`)
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct CollateralValuation {
+    pub id: Uuid,
+    pub asset_id: Uuid,
+    pub valuation_date: DateTime<Utc>,
+    pub value: BigDecimal,
+    pub appraiser: Option<String>,
+    pub method: String,
+    pub note: Option<String>,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Dùng cho POST /loan/ltv-thresholds – ghi đè nếu trùng mã
+#[derive(Debug, Deserialize)]
+pub struct LtvThresholdInput {
+    pub code: String,
+    pub name: String,
+    /// %
+    pub ltv: f64,
+}
+
+/// Dùng cho POST /loan/products và POST /loan/products/:id (ghi đè toàn bộ cấu hình)
+#[derive(Debug, Deserialize)]
+pub struct LoanProductInput {
+    pub code: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub min_rate: Option<f64>,
+    #[serde(default)]
+    pub max_rate: Option<f64>,
+    #[serde(default)]
+    pub default_rate: Option<f64>,
+    #[serde(default)]
+    pub min_term_months: Option<i32>,
+    #[serde(default)]
+    pub max_term_months: Option<i32>,
+    #[serde(default)]
+    pub default_term_months: Option<i32>,
+    #[serde(default)]
+    pub min_principal: Option<i64>,
+    #[serde(default)]
+    pub max_principal: Option<i64>,
+    #[serde(default)]
+    pub day_count: Option<DayCount>,
+    #[serde(default)]
+    pub compounding: Option<Compounding>,
+    #[serde(default)]
+    pub required_collateral_types: Vec<String>,
+    #[serde(default)]
+    pub storage_fee_rate: Option<f64>,
+    #[serde(default)]
+    pub storage_fee_flat: Option<i64>,
+    #[serde(default)]
+    pub penalty_rate: Option<f64>,
+    #[serde(default)]
+    pub prepayment_penalty: Option<PrepaymentPenalty>,
+    #[serde(default)]
+    pub repayment_waterfall: Option<String>,
+    #[serde(default)]
+    pub number_prefix: Option<String>,
+    #[serde(default)]
+    pub active: Option<bool>,
+}
+
+/// Dùng cho POST /loan/repayment-waterfalls – ghi đè nếu trùng mã
+#[derive(Debug, Deserialize)]
+pub struct RepaymentWaterfallInput {
+    pub code: String,
+    pub name: String,
+    /// Đủ 4 nhóm, mỗi nhóm 1 lần, vd `["principal","interest","fees","penalty"]`
+    pub steps: Vec<Bucket>,
+}
+
+// ================== LIQUIDATION ==================
+
+/// Dùng cho POST /loan/:id/collaterals/seize – không truyền `asset_ids` = thu giữ toàn bộ tài sản đang cầm cố
+#[derive(Debug, Deserialize)]
+pub struct SeizeCollateralInput {
+    pub asset_ids: Option<Vec<Uuid>>,
+    pub reason: Option<String>,
+}
+
+/// Dùng cho POST /loan/:id/collaterals/proceeds – ghi lại được cho tới khi hoàn tất thanh lý
+#[derive(Debug, Deserialize)]
+pub struct LiquidationProceedsInput {
+    pub asset_id: Uuid,
+    /// Số tiền bán tài sản
+    pub amount: i64,
+    /// Mặc định: bây giờ
+    pub sold_at: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+}
+
+/// Dùng cho POST /loan/:id/collaterals/liquidate
+#[derive(Debug, Deserialize)]
+pub struct CompleteLiquidationInput {
+    /// Ngày ghi nhận thu nợ từ tiền bán (mặc định: bây giờ)
+    pub date: Option<DateTime<Utc>>,
+    pub reason: Option<String>,
+}
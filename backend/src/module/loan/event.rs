@@ -1,6 +1,73 @@
-// Domain Event cho module Loan (sẽ dùng trong event bus nếu cần)
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use crate::infra::outbox::DomainEvent;
+use crate::module::loan::state::LoanState;
+
+// Domain Event cho module Loan (ghi vào outbox cùng transaction với command)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
 pub enum LoanEvent {
-    LoanCreated,
-    LoanApproved,
-    LoanClosed,
+    LoanCreated { contract_id: Uuid, contact_id: Uuid, contract_number: String },
+    LoanUpdated { contract_id: Uuid },
+    LoanDeleted { contract_id: Uuid },
+    LoanApproved { contract_id: Uuid },
+    LoanClosed { contract_id: Uuid },
+    /// Đã ghi thêm giao dịch cho hợp đồng (giải ngân / thu lãi / thu gốc)
+    LoanTransactionsRecorded { contract_id: Uuid, count: usize },
+    /// Chuyển trạng thái thủ công (kích hoạt, xoá nợ)
+    LoanStateChanged { contract_id: Uuid, from: LoanState, to: LoanState },
+    /// Đổi lãi suất / kỳ hạn có hiệu lực từ `effective_date`
+    LoanRestructured { contract_id: Uuid, effective_date: DateTime<Utc> },
+    /// Hợp đồng cũ đã tất toán và chuyển dư nợ sang `new_contract_id`
+    LoanRefinanced { contract_id: Uuid, new_contract_id: Uuid },
+    /// Chỉ số tham chiếu của hợp đồng thả nổi có giá trị mới từ `effective_date`
+    LoanRateChanged { contract_id: Uuid, effective_date: DateTime<Utc> },
+    /// Thu giữ tài sản cầm cố để thanh lý
+    LoanCollateralSeized { contract_id: Uuid, liquidation_id: Uuid, asset_count: usize },
+    /// Hoàn tất thanh lý: tiền thừa trả lại khách, tiền thiếu xoá nợ
+    LoanLiquidated { contract_id: Uuid, liquidation_id: Uuid, surplus: i64, shortfall: i64 },
+    /// Job giám sát LTV đổi mức cờ của hợp đồng (`threshold_code` None = hết vượt ngưỡng)
+    LoanLtvFlagged { contract_id: Uuid, threshold_code: Option<String>, ltv: Option<f64> },
+}
+
+impl DomainEvent for LoanEvent {
+    const TOPIC: &'static str = "loan";
+
+    fn event_type(&self) -> &'static str {
+        match self {
+            LoanEvent::LoanCreated { .. } => "LoanCreated",
+            LoanEvent::LoanUpdated { .. } => "LoanUpdated",
+            LoanEvent::LoanDeleted { .. } => "LoanDeleted",
+            LoanEvent::LoanApproved { .. } => "LoanApproved",
+            LoanEvent::LoanClosed { .. } => "LoanClosed",
+            LoanEvent::LoanTransactionsRecorded { .. } => "LoanTransactionsRecorded",
+            LoanEvent::LoanStateChanged { .. } => "LoanStateChanged",
+            LoanEvent::LoanRestructured { .. } => "LoanRestructured",
+            LoanEvent::LoanRefinanced { .. } => "LoanRefinanced",
+            LoanEvent::LoanRateChanged { .. } => "LoanRateChanged",
+            LoanEvent::LoanCollateralSeized { .. } => "LoanCollateralSeized",
+            LoanEvent::LoanLiquidated { .. } => "LoanLiquidated",
+            LoanEvent::LoanLtvFlagged { .. } => "LoanLtvFlagged",
+        }
+    }
+
+    fn aggregate_id(&self) -> Uuid {
+        match self {
+            LoanEvent::LoanCreated { contract_id, .. }
+            | LoanEvent::LoanUpdated { contract_id }
+            | LoanEvent::LoanDeleted { contract_id }
+            | LoanEvent::LoanApproved { contract_id }
+            | LoanEvent::LoanClosed { contract_id }
+            | LoanEvent::LoanTransactionsRecorded { contract_id, .. }
+            | LoanEvent::LoanStateChanged { contract_id, .. }
+            | LoanEvent::LoanRestructured { contract_id, .. }
+            | LoanEvent::LoanRefinanced { contract_id, .. }
+            | LoanEvent::LoanRateChanged { contract_id, .. }
+            | LoanEvent::LoanCollateralSeized { contract_id, .. }
+            | LoanEvent::LoanLiquidated { contract_id, .. }
+            | LoanEvent::LoanLtvFlagged { contract_id, .. } => *contract_id,
+        }
+    }
 }
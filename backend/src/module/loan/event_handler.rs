@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+
+use crate::event_handler::{EventContext, EventHandler, EventHandlerRegistry};
+use crate::module::loan::{command, event::LoanEvent};
+
+/// Tính lại snapshot loan_report khi hợp đồng có giao dịch mới, được cơ cấu lại hoặc đổi lãi thả nổi.
+/// Cơ cấu / đổi lãi có hiệu lực từ quá khứ → chạy bù snapshot các ngày đã đóng từ ngày hiệu lực.
+pub struct RecomputeLoanReport;
+
+#[async_trait]
+impl EventHandler<LoanEvent> for RecomputeLoanReport {
+    fn name(&self) -> &'static str {
+        "loan.recompute_report"
+    }
+
+    fn event_types(&self) -> &'static [&'static str] {
+        &["LoanTransactionsRecorded", "LoanRestructured", "LoanRateChanged"]
+    }
+
+    async fn handle(&self, ctx: &EventContext<'_>, event: LoanEvent) -> Result<(), String> {
+        let (contract_id, effective_date) = match event {
+            LoanEvent::LoanTransactionsRecorded { contract_id, .. } => (contract_id, None),
+            LoanEvent::LoanRestructured { contract_id, effective_date }
+            | LoanEvent::LoanRateChanged { contract_id, effective_date } => (contract_id, Some(effective_date)),
+            _ => return Ok(()),
+        };
+
+        let pool = ctx.pool().await?;
+        command::refresh_loan_report(pool, ctx.tenant_id(), contract_id)
+            .await
+            .map_err(|e| format!("Không tính lại được loan_report cho {}: {}", contract_id, e))?;
+
+        // Snapshot các ngày đã đóng kể từ ngày hiệu lực cũng phải tính lại
+        if let Some(effective_date) = effective_date {
+            command::request_report_backfill_from(pool, ctx.tenant_id(), effective_date)
+                .await
+                .map_err(|e| format!("Không xếp được chạy bù loan_report từ {}: {}", effective_date, e))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn register(registry: &mut EventHandlerRegistry) {
+    registry.subscribe(RecomputeLoanReport);
+}
@@ -1,10 +1,11 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{State, Json, Path},
+    extract::{Extension, State, Json, Path},
+    http::HeaderMap,
 };
 use uuid::Uuid;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 use sqlx::types::BigDecimal;
@@ -12,7 +13,15 @@ use sqlx::types::BigDecimal;
 use crate::core::auth::AuthUser;
 use crate::core::error::AppError;
 use crate::core::state::AppState;
+use crate::core::i18n::I18n;
+use crate::core::scope::Scope;
+use crate::command_bus;
+use crate::module::loan::command;
 use crate::module::loan::dto::{CreateCollateralDto,CollateralAsset};
+use crate::module::loan::dto::{CollateralValuation, CreateValuationDto};
+use crate::module::loan::dto::{CompleteLiquidationInput, LiquidationProceedsInput, SeizeCollateralInput};
+use crate::module::loan::liquidation::{Liquidation, LiquidationAsset};
+use crate::module::loan::query;
 
 
 
@@ -35,7 +44,7 @@ pub async fn list_collateral(
     State(state): State<Arc<AppState>>,
     user: AuthUser,
 ) -> Result<Json<Vec<CollateralAssetView>>, AppError> {
-    let pool: &PgPool = state.shard.get_pool_for_tenant(&user.tenant_id);
+    let pool: &PgPool = state.shard.get_pool_for_tenant(&user.tenant_id).await?;
 
     let items = sqlx::query_as!(
         CollateralAssetView,
@@ -75,9 +84,10 @@ pub async fn create_collateral(
     user: AuthUser,
     Json(payload): Json<CreateCollateralDto>,
 ) -> Result<Json<CollateralAsset>, AppError> {
-    let pool: &PgPool = state.shard.get_pool_for_tenant(&user.tenant_id);
+    let pool: &PgPool = state.shard.get_pool_for_tenant(&user.tenant_id).await?;
     let asset_id = Uuid::new_v4();
     let status = payload.status.unwrap_or_else(|| "available".to_string());
+    let mut tx = pool.begin().await?;
 
     // 1. Ghi vào bảng collateral_assets
     let rec = sqlx::query_as!(
@@ -99,9 +109,21 @@ pub async fn create_collateral(
         status,
         user.user_id
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *tx)
     .await?;
 
+    // Giá trị ban đầu = lần định giá đầu tiên
+    if let Some(value) = rec.value_estimate.clone() {
+        let initial = CreateValuationDto {
+            value,
+            valuation_date: Some(rec.created_at),
+            appraiser: None,
+            method: None,
+            note: None,
+        };
+        insert_valuation(&mut tx, user.tenant_id, asset_id, &initial, user.user_id).await?;
+    }
+
     // 2. Nếu FE truyền contract_id, ghi liên kết vào loan_collateral
     if let Some(contract_id) = payload.contract_id.clone() {
         sqlx::query!(
@@ -115,10 +137,11 @@ pub async fn create_collateral(
             asset_id,
             user.user_id
         )
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
     }
 
+    tx.commit().await?;
     Ok(Json(rec))
 }
 
@@ -127,7 +150,7 @@ pub async fn get_collaterals_by_contract(
     user: AuthUser,
     Path(contract_id): Path<Uuid>,
 ) -> Result<Json<Vec<CollateralAsset>>, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&user.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&user.tenant_id).await?;
 
     let items = sqlx::query_as!(
         CollateralAsset,
@@ -162,7 +185,7 @@ pub async fn add_collateral_to_contract(
     Path(contract_id): Path<Uuid>,
     Json(payload): Json<AddCollateralDto>,
 ) -> Result<(), AppError> {
-    let pool = state.shard.get_pool_for_tenant(&user.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&user.tenant_id).await?;
 
     sqlx::query!(
         r#"
@@ -195,7 +218,7 @@ pub async fn release_collateral_from_contract(
     Path(contract_id): Path<Uuid>,
     Json(payload): Json<ReleaseCollateralDto>,
 ) -> Result<(), AppError> {
-    let pool = state.shard.get_pool_for_tenant(&user.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&user.tenant_id).await?;
 
     sqlx::query!(
         r#"
@@ -232,7 +255,7 @@ pub async fn update_collateral(
     Path(asset_id): Path<Uuid>,
     Json(payload): Json<UpdateCollateralDto>,
 ) -> Result<Json<CollateralAsset>, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&user.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&user.tenant_id).await?;
 
     // Lấy bản ghi hiện tại để merge giá trị Option
     let existing = sqlx::query_as!(
@@ -256,6 +279,7 @@ pub async fn update_collateral(
     let new_owner_contact_id = payload.owner_contact_id.or(existing.owner_contact_id);
     let new_status = payload.status.unwrap_or(existing.status.clone());
 
+    let mut tx = pool.begin().await?;
     let updated = sqlx::query_as!(
         CollateralAsset,
         r#"
@@ -277,8 +301,186 @@ pub async fn update_collateral(
         new_owner_contact_id,
         new_status,
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *tx)
     .await?;
 
+    // Sửa giá trị trực tiếp → ghi thành 1 lần định giá thủ công
+    if let Some(value) = new_value_estimate.filter(|v| existing.value_estimate.as_ref() != Some(v)) {
+        let manual = CreateValuationDto { value, valuation_date: None, appraiser: None, method: None, note: None };
+        insert_valuation(&mut tx, user.tenant_id, asset_id, &manual, user.user_id).await?;
+    }
+
+    tx.commit().await?;
     Ok(Json(updated))
-}
\ No newline at end of file
+}
+
+// ================== ĐỊNH GIÁ ==================
+
+/// Ghi 1 lần định giá và cập nhật `value_estimate` theo lần định giá mới nhất
+async fn insert_valuation(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    asset_id: Uuid,
+    input: &CreateValuationDto,
+    created_by: Uuid,
+) -> Result<CollateralValuation, AppError> {
+    let valuation = sqlx::query_as!(
+        CollateralValuation,
+        r#"
+        INSERT INTO collateral_valuation (
+            tenant_id, asset_id, valuation_date, value, appraiser, method, note, created_by
+        ) VALUES ($1, $2, $3, $4, $5, COALESCE($6, 'manual'), $7, $8)
+        RETURNING id, asset_id, valuation_date, value, appraiser, method, note, created_by, created_at
+        "#,
+        tenant_id,
+        asset_id,
+        input.valuation_date.unwrap_or_else(Utc::now),
+        input.value,
+        input.appraiser,
+        input.method.as_deref().map(str::trim).filter(|m| !m.is_empty()),
+        input.note,
+        created_by
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE collateral_assets a
+        SET value_estimate = (
+            SELECT v.value FROM collateral_valuation v
+            WHERE v.tenant_id = a.tenant_id AND v.asset_id = a.asset_id
+            ORDER BY v.valuation_date DESC, v.created_at DESC
+            LIMIT 1
+        )
+        WHERE a.tenant_id = $1 AND a.asset_id = $2
+        "#,
+        tenant_id,
+        asset_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(valuation)
+}
+
+/// Lịch sử định giá của 1 tài sản, mới nhất trước (GET /loan/collateral/:asset_id/valuations)
+pub async fn list_valuations(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(asset_id): Path<Uuid>,
+) -> Result<Json<Vec<CollateralValuation>>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&user.tenant_id).await?;
+
+    let items = sqlx::query_as!(
+        CollateralValuation,
+        r#"
+        SELECT id, asset_id, valuation_date, value, appraiser, method, note, created_by, created_at
+        FROM collateral_valuation
+        WHERE tenant_id = $1 AND asset_id = $2
+        ORDER BY valuation_date DESC, created_at DESC
+        "#,
+        user.tenant_id,
+        asset_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Json(items))
+}
+
+/// Định giá lại tài sản (POST /loan/collateral/:asset_id/valuations)
+pub async fn add_valuation(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(asset_id): Path<Uuid>,
+    Json(payload): Json<CreateValuationDto>,
+) -> Result<Json<CollateralValuation>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&user.tenant_id).await?;
+    if payload.value < BigDecimal::from(0) {
+        return Err(AppError::bad_request("value must not be negative"));
+    }
+
+    let mut tx = pool.begin().await?;
+    let exists = sqlx::query_scalar!(
+        "SELECT 1 FROM collateral_assets WHERE tenant_id = $1 AND asset_id = $2 FOR UPDATE",
+        user.tenant_id,
+        asset_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if exists.is_none() {
+        return Err(AppError::bad_request("asset not found"));
+    }
+
+    let valuation = insert_valuation(&mut tx, user.tenant_id, asset_id, &payload, user.user_id).await?;
+    tx.commit().await?;
+    Ok(Json(valuation))
+}
+// ================== THANH LÝ ==================
+
+/// ✅ Thu giữ tài sản cầm cố để thanh lý (POST /loan/:id/collaterals/seize)
+pub async fn seize_collateral(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+    Json(input): Json<SeizeCollateralInput>,
+) -> Result<Json<Liquidation>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let result = command_bus::dispatch(&state, &auth, &i18n, command::SeizeCollateral { contract_id, input, scope }).await?;
+    Ok(Json(result))
+}
+
+/// ✅ Ghi nhận tiền bán tài sản đã thu giữ (POST /loan/:id/collaterals/proceeds)
+pub async fn record_liquidation_proceeds(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+    Json(input): Json<LiquidationProceedsInput>,
+) -> Result<Json<LiquidationAsset>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let result =
+        command_bus::dispatch(&state, &auth, &i18n, command::RecordLiquidationProceeds { contract_id, input, scope }).await?;
+    Ok(Json(result))
+}
+
+/// ✅ Hoàn tất thanh lý: phân bổ tiền bán lãi → gốc → phí (POST /loan/:id/collaterals/liquidate)
+pub async fn complete_liquidation(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+    Json(input): Json<CompleteLiquidationInput>,
+) -> Result<Json<command::LiquidationOutcome>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let result = command_bus::dispatch(&state, &auth, &i18n, command::CompleteLiquidation { contract_id, input, scope }).await?;
+    Ok(Json(result))
+}
+
+/// ✅ Hồ sơ thanh lý của hợp đồng: tài sản thu giữ, tiền bán, kết quả phân bổ (GET /loan/:id/collaterals/liquidation)
+pub async fn get_liquidation(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+) -> Result<Json<Liquidation>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+
+    query::get_visible_contract(pool, &auth, &scope, contract_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::not_found_i18n(&i18n, "error.loan.not_found"),
+            e => e.into(),
+        })?;
+    let liquidation = query::get_liquidation(pool, auth.tenant_id, contract_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(&i18n, "error.loan.liquidation_not_found"))?;
+    Ok(Json(liquidation))
+}
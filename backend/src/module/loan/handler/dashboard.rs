@@ -14,10 +14,12 @@ use tokio::sync::RwLock;
 use once_cell::sync::Lazy;
 
 use crate::core::auth::AuthUser;
+use crate::core::error::AppError;
 use crate::core::state::AppState;
 use crate::core::cache::{get_redis_client, is_redis_available};
 use crate::module::loan::{
     calculator,
+    delinquency::DpdBucket,
     query,
 };
 
@@ -106,7 +108,7 @@ async fn calculate_stats_from_transactions(
             }
 
             // Loan Repaid: principal + interest_applied (bao gồm cả lãi từ settlement/liquidation)
-            if matches!(tx.transaction_type.as_str(), "principal" | "interest" | "settlement" | "liquidation") {
+            if matches!(tx.transaction_type.as_str(), "principal" | "interest" | "penalty" | "storage_fee" | "prepayment_fee" | "repayment" | "settlement" | "liquidation") {
                 if tx.transaction_type == "settlement" || tx.transaction_type == "liquidation" {
                     // Với settlement/liquidation, lấy interest_applied (đã được calculator tách)
                     entry.1 += tx.interest_applied;
@@ -173,8 +175,8 @@ pub async fn get_loan_stats(
     State(state): State<Arc<AppState>>,
     Extension(auth): Extension<AuthUser>,
     Query(params): Query<StatsParams>,
-) -> Json<StatsResponse> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+) -> Result<Json<StatsResponse>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     
     // ✅ OPTIMIZATION: Redis Cache với fallback
     let cache_key = format!("stats_{}_{}_{}_{}", 
@@ -194,7 +196,7 @@ pub async fn get_loan_stats(
                 params.range.as_deref()
             ).await {
                 if let Ok(stats_response) = serde_json::from_value::<StatsResponse>(cached_data) {
-                    return Json(stats_response);
+                    return Ok(Json(stats_response));
                 }
             }
         }
@@ -205,7 +207,7 @@ pub async fn get_loan_stats(
         let cache = STATS_CACHE.read().await;
         if let Some((cached_response, cached_time)) = cache.get(&cache_key) {
             if cached_time.elapsed() < CACHE_TTL {
-                return Json(cached_response.clone());
+                return Ok(Json(cached_response.clone()));
             }
         }
     }
@@ -252,7 +254,7 @@ pub async fn get_loan_stats(
         }
     }
 
-    Json(result)
+    Ok(Json(result))
 }
 
 // Lấy từ pre-computed hoặc fallback to transactions
@@ -270,8 +272,8 @@ async fn calculate_stats_from_precomputed_or_fallback(
 pub async fn get_monthly_interest_income(
     State(state): State<Arc<AppState>>,
     Extension(auth): Extension<AuthUser>,
-) -> Json<serde_json::Value> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     
     // Tạo cache key
     let now = chrono::Utc::now();
@@ -286,7 +288,7 @@ pub async fn get_monthly_interest_income(
         let cache = MONTHLY_INTEREST_CACHE.read().await;
         if let Some((cached_response, cached_time)) = cache.get(&cache_key) {
             if cached_time.elapsed() < CACHE_TTL {
-                return Json(cached_response.clone());
+                return Ok(Json(cached_response.clone()));
             }
         }
     }
@@ -352,7 +354,7 @@ pub async fn get_monthly_interest_income(
         }
     }
 
-    Json(result)
+    Ok(Json(result))
 }
 
 // Helper functions
@@ -387,8 +389,8 @@ fn format_currency(amount: i64) -> String {
 pub async fn get_dashboard_stats(
     State(state): State<Arc<AppState>>,
     Extension(auth): Extension<AuthUser>,
-) -> Json<serde_json::Value> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     let tenant_id = auth.tenant_id;
     
     let now = chrono::Utc::now();
@@ -404,7 +406,7 @@ pub async fn get_dashboard_stats(
     if is_redis_available().await {
         if let Some(redis_client) = get_redis_client().await {
             if let Ok(Some(cached_data)) = redis_client.get_dashboard_stats(&tenant_id.to_string(), month, year).await {
-                return Json(cached_data);
+                return Ok(Json(cached_data));
             }
         }
     }
@@ -414,7 +416,7 @@ pub async fn get_dashboard_stats(
         let cache = MONTHLY_INTEREST_CACHE.read().await;
         if let Some((cached_response, cached_time)) = cache.get(&cache_key) {
             if cached_time.elapsed() < CACHE_TTL {
-                return Json(cached_response.clone());
+                return Ok(Json(cached_response.clone()));
             }
         }
     }
@@ -504,15 +506,15 @@ pub async fn get_dashboard_stats(
         cache.insert(cache_key, (result.clone(), Instant::now()));
     }
 
-    Json(result)
+    Ok(Json(result))
 }
 
 /// API lấy báo cáo hoạt động cho vay (thay thế Bandwidth Report) - OPTIMIZED
 pub async fn get_loan_activity_report(
     State(state): State<Arc<AppState>>,
     Extension(auth): Extension<AuthUser>,
-) -> Json<serde_json::Value> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     let tenant_id = auth.tenant_id;
     
     // Cache key cho loan activity report
@@ -523,7 +525,7 @@ pub async fn get_loan_activity_report(
         let cache = MONTHLY_INTEREST_CACHE.read().await;
         if let Some((cached_response, cached_time)) = cache.get(&cache_key) {
             if cached_time.elapsed() < CACHE_TTL {
-                return Json(cached_response.clone());
+                return Ok(Json(cached_response.clone()));
             }
         }
     }
@@ -578,30 +580,10 @@ pub async fn get_loan_activity_report(
             new_loans_amount += contract.current_principal;
         }
 
-        // Kiểm tra trạng thái quá hạn
-        let last_disbursement = transactions
-            .iter()
-            .filter(|tx| matches!(tx.transaction_type.as_str(), "disbursement" | "additional"))
-            .max_by_key(|tx| tx.date);
-
-        if let Some(disbursement) = last_disbursement {
-            let disbursement_date = disbursement.date.date_naive();
-            let due_date = disbursement_date + chrono::Duration::days(contract.term_months as i64 * 30);
-            let overdue_days = (today - due_date).num_days();
-            
-            if overdue_days > 0 {
-                overdue_count += 1;
-                // Tính số dư hiện tại cho hợp đồng quá hạn
-                let mut current_balance = contract.current_principal;
-                for tx in &transactions {
-                    match tx.transaction_type.as_str() {
-                        "principal" => current_balance -= tx.amount,
-                        "settlement" | "liquidation" => current_balance -= tx.amount - tx.interest_applied,
-                        _ => {}
-                    }
-                }
-                overdue_amount += current_balance.max(0);
-            }
+        // Kiểm tra trạng thái quá hạn (DPD theo lịch trả nợ, calculator đã tính)
+        if contract.days_past_due > 0 {
+            overdue_count += 1;
+            overdue_amount += contract.current_principal;
         }
 
         // ✅ OPTIMIZATION 4: Process transactions một lần cho cả statistics và chart
@@ -611,7 +593,7 @@ pub async fn get_loan_activity_report(
             // Đếm các giao dịch trong tháng cho statistics
             if tx_date.year() == current_year && tx_date.month() == current_month {
                 match tx.transaction_type.as_str() {
-                    "principal" | "interest" => {
+                    "principal" | "interest" | "penalty" | "storage_fee" | "prepayment_fee" | "repayment" => {
                         repayments_count += 1;
                         repayments_amount += tx.amount;
                     }
@@ -713,15 +695,15 @@ pub async fn get_loan_activity_report(
         cache.insert(cache_key, (result.clone(), Instant::now()));
     }
 
-    Json(result)
+    Ok(Json(result))
 }
 
 /// API lấy trạng thái hợp đồng vay (thay thế Projects Status)
 pub async fn get_contract_status(
     State(state): State<Arc<AppState>>,
     Extension(auth): Extension<AuthUser>,
-) -> Json<serde_json::Value> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     let tenant_id = auth.tenant_id;
     
     // Cache key cho contract status
@@ -732,7 +714,7 @@ pub async fn get_contract_status(
         let cache = MONTHLY_INTEREST_CACHE.read().await;
         if let Some((cached_response, cached_time)) = cache.get(&cache_key) {
             if cached_time.elapsed() < CACHE_TTL {
-                return Json(cached_response.clone());
+                return Ok(Json(cached_response.clone()));
             }
         }
     }
@@ -773,6 +755,9 @@ pub async fn get_contract_status(
                 "principal" => {
                     current_principal -= tx.amount;
                 }
+                "repayment" => {
+                    current_principal -= tx.principal_applied;
+                }
                 "settlement" | "liquidation" => {
                     current_principal -= tx.amount - tx.interest_applied;
                     is_settled = true;
@@ -804,21 +789,10 @@ pub async fn get_contract_status(
             };
             total_progress += progress_percentage;
 
-            // Tìm giao dịch giải ngân cuối cùng để tính ngày đáo hạn
-            let last_disbursement = transactions
-                .iter()
-                .filter(|tx| matches!(tx.transaction_type.as_str(), "disbursement" | "additional"))
-                .max_by_key(|tx| tx.date);
-
-            if let Some(disbursement) = last_disbursement {
-                let disbursement_date = disbursement.date.date_naive();
-                let due_date = disbursement_date + chrono::Duration::days(contract.term_months as i64 * 30);
-                let overdue_days = (today - due_date).num_days();
-
-                if overdue_days > 0 {
-                    overdue_contracts += 1;
-                    total_overdue_days += overdue_days;
-                }
+            // Quá hạn theo lịch trả nợ (calculator đã tính days_past_due)
+            if contract.days_past_due > 0 {
+                overdue_contracts += 1;
+                total_overdue_days += contract.days_past_due as i64;
             }
         }
     }
@@ -902,15 +876,15 @@ pub async fn get_contract_status(
         cache.insert(cache_key, (result.clone(), Instant::now()));
     }
 
-    Json(result)
+    Ok(Json(result))
 }
 
 /// API lấy top hợp đồng có lợi nhuận cao nhất (thay thế Top Sellers)
 pub async fn get_top_contracts(
     State(state): State<Arc<AppState>>,
     Extension(auth): Extension<AuthUser>,
-) -> Json<serde_json::Value> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     let tenant_id = auth.tenant_id;
     
     // Cache key cho top contracts
@@ -921,7 +895,7 @@ pub async fn get_top_contracts(
         let cache = MONTHLY_INTEREST_CACHE.read().await;
         if let Some((cached_response, cached_time)) = cache.get(&cache_key) {
             if cached_time.elapsed() < CACHE_TTL {
-                return Json(cached_response.clone());
+                return Ok(Json(cached_response.clone()));
             }
         }
     }
@@ -967,6 +941,9 @@ pub async fn get_top_contracts(
                 "principal" => {
                     principal_collected += tx.amount;
                 }
+                "repayment" => {
+                    principal_collected += tx.principal_applied;
+                }
                 "settlement" | "liquidation" => {
                     principal_collected += tx.amount - tx.interest_applied;
                 }
@@ -1045,15 +1022,48 @@ pub async fn get_top_contracts(
         cache.insert(cache_key, (result.clone(), Instant::now()));
     }
 
-    Json(result)
+    Ok(Json(result))
+}
+
+/// API hợp đồng vượt ngưỡng LTV (job giám sát LTV gắn cờ), đi kèm chất lượng danh mục
+pub async fn get_ltv_alerts(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<AuthUser>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+    let tenant_id = auth.tenant_id;
+
+    let thresholds = query::list_ltv_thresholds(pool, tenant_id).await.unwrap_or_default();
+    let alerts = query::list_ltv_alerts(pool, tenant_id).await.unwrap_or_default();
+
+    // Đếm + tổng dư nợ theo từng ngưỡng
+    let by_threshold: Vec<serde_json::Value> = thresholds
+        .iter()
+        .map(|t| {
+            let flagged = alerts.iter().filter(|a| a.threshold_code == t.code);
+            json!({
+                "code": t.code,
+                "name": t.name,
+                "ltv": t.ltv,
+                "count": flagged.clone().count(),
+                "principal": flagged.map(|a| a.principal).sum::<i64>(),
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "total_flagged": alerts.len(),
+        "thresholds": by_threshold,
+        "contracts": alerts,
+    })))
 }
 
 /// API lấy chất lượng danh mục cho vay (thay thế Customer Satisfaction)
 pub async fn get_loan_portfolio_quality(
     State(state): State<Arc<AppState>>,
     Extension(auth): Extension<AuthUser>,
-) -> Json<serde_json::Value> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     let tenant_id = auth.tenant_id;
     
     // Cache key cho portfolio quality
@@ -1064,20 +1074,17 @@ pub async fn get_loan_portfolio_quality(
         let cache = MONTHLY_INTEREST_CACHE.read().await;
         if let Some((cached_response, cached_time)) = cache.get(&cache_key) {
             if cached_time.elapsed() < CACHE_TTL {
-                return Json(cached_response.clone());
+                return Ok(Json(cached_response.clone()));
             }
         }
     }
 
     // Lấy tất cả hợp đồng đang hoạt động
     let contracts = query::list_contracts(pool, tenant_id).await.unwrap_or_default();
-    let today = chrono::Utc::now().date_naive();
-    
-    let mut excellent = 0i32;      // Đúng hạn
-    let mut very_good = 0i32;      // Quá hạn 1-7 ngày
-    let mut good = 0i32;           // Quá hạn 8-30 ngày  
-    let mut poor = 0i32;           // Quá hạn 31-90 ngày
-    let mut very_poor = 0i32;      // Quá hạn >90 ngày
+
+    // Đếm theo nhóm nợ DPD (current, 1-30, 31-60, 61-90, 90+)
+    let mut buckets: HashMap<DpdBucket, i32> = HashMap::new();
+    let mut bucket_principal: HashMap<DpdBucket, i64> = HashMap::new();
     let mut total_active = 0i32;
 
     for mut contract in contracts {
@@ -1089,55 +1096,23 @@ pub async fn get_loan_portfolio_quality(
             continue;
         }
 
-        // Tính toán để xác định trạng thái hợp đồng
+        // days_past_due + lãi phạt tính theo lịch trả nợ
         calculator::calculate_interest_fields(&mut contract, &mut transactions);
 
-        // Kiểm tra xem hợp đồng đã tất toán chưa
-        let mut is_settled = false;
-        let mut current_principal = contract.current_principal;
-        
-        for tx in &transactions {
-            match tx.transaction_type.as_str() {
-                "principal" => {
-                    current_principal -= tx.amount;
-                }
-                "settlement" | "liquidation" => {
-                    current_principal -= tx.amount - tx.interest_applied;
-                    is_settled = true;
-                }
-                _ => {}
-            }
+        // Chỉ tính hợp đồng còn dư nợ
+        let is_settled = transactions
+            .iter()
+            .any(|tx| matches!(tx.transaction_type.as_str(), "settlement" | "liquidation"));
+        if is_settled || contract.current_principal <= 0 {
+            continue;
         }
 
-        // Chỉ tính hợp đồng đang hoạt động
-        if !is_settled {
-            total_active += 1;
-
-            // Tìm giao dịch giải ngân cuối cùng để tính ngày đáo hạn
-            let last_disbursement = transactions
-                .iter()
-                .filter(|tx| matches!(tx.transaction_type.as_str(), "disbursement" | "additional"))
-                .max_by_key(|tx| tx.date);
-
-            if let Some(disbursement) = last_disbursement {
-                // Giả sử kỳ hạn là term_months từ ngày giải ngân cuối
-                let disbursement_date = disbursement.date.date_naive();
-                let due_date = disbursement_date + chrono::Duration::days(contract.term_months as i64 * 30);
-                let overdue_days = (today - due_date).num_days();
-
-                match overdue_days {
-                    x if x <= 0 => excellent += 1,      // Đúng hạn hoặc chưa đến hạn
-                    x if x <= 7 => very_good += 1,      // Quá hạn 1-7 ngày
-                    x if x <= 30 => good += 1,          // Quá hạn 8-30 ngày
-                    x if x <= 90 => poor += 1,          // Quá hạn 31-90 ngày
-                    _ => very_poor += 1,                 // Quá hạn >90 ngày
-                }
-            } else {
-                // Không có giao dịch giải ngân, coi như excellent
-                excellent += 1;
-            }
-        }
+        total_active += 1;
+        let bucket = DpdBucket::from_days(contract.days_past_due);
+        *buckets.entry(bucket).or_default() += 1;
+        *bucket_principal.entry(bucket).or_default() += contract.current_principal;
     }
+    let count = |b: DpdBucket| buckets.get(&b).copied().unwrap_or(0);
 
     // Tính phần trăm
     let calculate_percentage = |count: i32| -> f64 {
@@ -1150,7 +1125,11 @@ pub async fn get_loan_portfolio_quality(
 
     // Tính điểm chất lượng tổng thể (weighted score)
     let quality_score = if total_active > 0 {
-        let weighted_sum = excellent * 5 + very_good * 4 + good * 3 + poor * 2 + very_poor * 1;
+        let weighted_sum: i32 = DpdBucket::ALL
+            .iter()
+            .zip([5, 4, 3, 2, 1])
+            .map(|(b, weight)| count(*b) * weight)
+            .sum();
         (weighted_sum as f64 / total_active as f64 / 5.0) * 10.0 // Scale to 0-10
     } else {
         0.0
@@ -1159,38 +1138,18 @@ pub async fn get_loan_portfolio_quality(
     let result = serde_json::json!({
         "quality_score": format!("{:.1}", quality_score),
         "total_active_contracts": total_active,
-        "categories": [
-            {
-                "name": "Excellent",
-                "count": excellent,
-                "percentage": calculate_percentage(excellent) as i32,
-                "color": "success"
-            },
-            {
-                "name": "Very Good", 
-                "count": very_good,
-                "percentage": calculate_percentage(very_good) as i32,
-                "color": "info"
-            },
-            {
-                "name": "Good",
-                "count": good, 
-                "percentage": calculate_percentage(good) as i32,
-                "color": "warning"
-            },
-            {
-                "name": "Poor",
-                "count": poor,
-                "percentage": calculate_percentage(poor) as i32,
-                "color": "error"
-            },
-            {
-                "name": "Very Poor",
-                "count": very_poor,
-                "percentage": calculate_percentage(very_poor) as i32,
-                "color": "error"
-            }
-        ]
+        "categories": DpdBucket::ALL
+            .iter()
+            .zip(["success", "info", "warning", "error", "error"])
+            .map(|(b, color)| serde_json::json!({
+                "name": if *b == DpdBucket::Current { "Current".to_string() } else { format!("{} DPD", b.as_str()) },
+                "bucket": b,
+                "count": count(*b),
+                "percentage": calculate_percentage(count(*b)) as i32,
+                "principal": bucket_principal.get(b).copied().unwrap_or(0),
+                "color": color
+            }))
+            .collect::<Vec<_>>()
     });
 
     // Cache kết quả
@@ -1199,15 +1158,15 @@ pub async fn get_loan_portfolio_quality(
         cache.insert(cache_key, (result.clone(), Instant::now()));
     }
 
-    Json(result)
+    Ok(Json(result))
 }
 
 /// API lấy hoạt động gần đây (thay thế Users Activity)
 pub async fn get_recent_activities(
     State(state): State<Arc<AppState>>,
     Extension(auth): Extension<AuthUser>,
-) -> Json<serde_json::Value> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
     let tenant_id = auth.tenant_id;
     
     // Cache key cho recent activities
@@ -1218,7 +1177,7 @@ pub async fn get_recent_activities(
         let cache = MONTHLY_INTEREST_CACHE.read().await;
         if let Some((cached_response, cached_time)) = cache.get(&cache_key) {
             if cached_time.elapsed() < CACHE_TTL {
-                return Json(cached_response.clone());
+                return Ok(Json(cached_response.clone()));
             }
         }
     }
@@ -1289,6 +1248,16 @@ pub async fn get_recent_activities(
                 "interest", 
                 "warning"
             ),
+            "repayment" => (
+                "Trả nợ",
+                format!("{} trả nợ {} - HĐ {}", 
+                    tx.contact_name, 
+                    format_currency(tx.amount), 
+                    tx.contract_number
+                ),
+                "repayment",
+                "primary"
+            ),
             "settlement" => (
                 "Tất toán",
                 format!("{} tất toán {} - HĐ {}", 
@@ -1349,5 +1318,5 @@ pub async fn get_recent_activities(
         cache.insert(cache_key, (result.clone(), Instant::now()));
     }
 
-    Json(result)
+    Ok(Json(result))
 }
\ No newline at end of file
@@ -1,9 +1,11 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Extension, Path, Query, State},
     http::{StatusCode, HeaderMap},
     Json,
 };
 use std::sync::Arc;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
 use serde_json::json;
 use uuid::Uuid;
 use tracing::error;
@@ -12,13 +14,20 @@ use crate::core::auth::AuthUser;
 use crate::core::error::AppError;
 use crate::core::state::AppState;
 use crate::core::i18n::I18n;
+use crate::core::scope::Scope;
+use crate::command_bus;
 
 use crate::module::loan::{
     calculator,
     command,
-    dto::CreateContractInput,
+    delinquency::DpdBucket,
+    dto::{
+        CreateContractInput, LoanProductInput, LtvThresholdInput, RateIndexInput, RateIndexValueInput, RefinanceInput,
+        RepaymentWaterfallInput, RestructureInput, SettlementQuoteView,
+    },
     metadata::loan_form_schema,
     query,
+    state::LoanState,
 };
 
 pub async fn get_metadata(headers: HeaderMap) -> Result<Json<serde_json::Value>, StatusCode> {
@@ -32,14 +41,7 @@ pub async fn create_contract(
     auth: AuthUser,
     Json(mut input): Json<CreateContractInput>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    // ✅ validate sớm
     let i18n = I18n::from_headers(&headers);
-    tracing::info!("🌐 Handler language: {}, message: {}", i18n.language(), i18n.t("error.loan.transactions_empty"));
-    if input.transactions.is_empty() {
-        return Err(AppError::bad_request_i18n(&i18n, "error.loan.transactions_empty"));
-    }
-
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
 
     // 👇 IAM mặc định
     input.created_by = Some(auth.user_id);
@@ -49,17 +51,20 @@ pub async fn create_contract(
     input.shared_with.get_or_insert_with(|| vec![]);
 
     // 👇 tạo HĐ (contract_number tự sinh trong service)
-    let contract = command::create_contract(pool, auth.tenant_id, input).await?;
+    let contract = command_bus::dispatch(&state, &auth, &i18n, command::CreateContract { input }).await?;
     Ok(Json(json!({ "contract_id": contract.id })))
 }
 
 pub async fn list_contracts(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await.map_err(|e| e.status_code())?;
 
-    let contracts = query::list_contracts(pool, auth.tenant_id)
+    let contracts = query::list_visible_contracts(pool, &auth, &scope)
         .await
         .map_err(|e| {
             error!("❌ Lỗi query list_contracts: {:?}", e);
@@ -79,7 +84,8 @@ pub async fn list_contracts(
                 "term_months": c.term_months,
                 "date_start": c.date_start.format("%Y-%m-%d").to_string(),
                 "date_end": c.date_end.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default(),
-                "state": c.state
+                "state": c.state,
+                "state_label": i18n.t(c.state.i18n_key())
             })
         })
         .collect();
@@ -89,12 +95,15 @@ pub async fn list_contracts(
 
 pub async fn get_contract_by_id(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(contract_id): Path<Uuid>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+    let i18n = I18n::from_headers(&headers);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await.map_err(|e| e.status_code())?;
 
-    let mut contract = query::get_contract_by_id(pool, auth.tenant_id, contract_id)
+    let mut contract = query::get_visible_contract(pool, &auth, &scope, contract_id)
         .await
         .map_err(|_| StatusCode::NOT_FOUND)?;
 
@@ -107,8 +116,14 @@ pub async fn get_contract_by_id(
 
     // tính toán projection từ ledger
     calculator::calculate_interest_fields(&mut contract, &mut transactions);
+    let dpd_bucket = DpdBucket::from_days(contract.days_past_due);
+    let ltv = contract.ltv();
 
+    let state_label = i18n.t(contract.state.i18n_key());
     let mut value = serde_json::to_value(contract).unwrap();
+    value["state_label"] = json!(state_label);
+    value["dpd_bucket"] = json!(dpd_bucket);
+    value["ltv"] = json!(ltv);
     value["transactions"] = serde_json::to_value(transactions).unwrap();
 
     Ok(Json(value))
@@ -118,35 +133,384 @@ pub async fn update_contract(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(contract_id): Path<Uuid>,
     Json(input): Json<CreateContractInput>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    // ✅ validate policy
     let i18n = I18n::from_headers(&headers);
-    if input.transactions.is_empty() {
-        return Err(AppError::bad_request_i18n(&i18n, "error.loan.transactions_empty"));
-    }
-
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
 
     // contract_number immutable — logic nằm trong service
-    command::update_contract(pool, auth.tenant_id, contract_id, input).await?;
+    command_bus::dispatch(&state, &auth, &i18n, command::UpdateContract { contract_id, input, scope }).await?;
     Ok(Json(json!({ "updated": true })))
 }
 
 pub async fn delete_contract(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     auth: AuthUser,
+    Extension(scope): Extension<Scope>,
     Path(contract_id): Path<Uuid>,
-) -> Result<StatusCode, StatusCode> {
-    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id);
+) -> Result<StatusCode, AppError> {
+    let i18n = I18n::from_headers(&headers);
 
-    command::delete_contract(pool, auth.tenant_id, contract_id)
+    command_bus::dispatch(&state, &auth, &i18n, command::DeleteContract { contract_id, scope })
         .await
-        .map_err(|e| {
-            error!("❌ Lỗi delete_contract: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        .inspect_err(|e| error!("❌ Lỗi delete_contract: {:?}", e))?;
 
     Ok(StatusCode::NO_CONTENT)
-}
\ No newline at end of file
+}
+
+/// ✅ Lịch trả nợ dự kiến của hợp đồng (GET /loan/:id/schedule)
+pub async fn get_schedule(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+
+    let contract = query::get_visible_contract(pool, &auth, &scope, contract_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::not_found_i18n(&i18n, "error.loan.not_found"),
+            e => e.into(),
+        })?;
+    let (plan, installments) = query::get_schedule(pool, auth.tenant_id, contract_id).await?;
+
+    Ok(Json(json!({
+        "contract_id": contract.id,
+        "contract_number": contract.contract_number,
+        "repayment_plan": plan,
+        "interest_rate": contract.interest_rate,
+        "term_months": contract.term_months,
+        "total_principal": installments.iter().map(|i| i.principal).sum::<i64>(),
+        "total_interest": installments.iter().map(|i| i.interest).sum::<i64>(),
+        "installments": installments,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SettlementQuoteParams {
+    /// RFC3339 hoặc YYYY-MM-DD (tính hết ngày theo múi giờ tenant); mặc định: hiện tại
+    pub as_of: Option<String>,
+}
+
+/// ✅ Báo giá tất toán trước hạn (POST /loan/:id/settlement-quote?as_of=) – không ghi DB
+pub async fn settlement_quote(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+    Query(params): Query<SettlementQuoteParams>,
+) -> Result<Json<SettlementQuoteView>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+
+    let contract = query::get_visible_contract(pool, &auth, &scope, contract_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::not_found_i18n(&i18n, "error.loan.not_found"),
+            e => e.into(),
+        })?;
+
+    let as_of = match params.as_of.as_deref() {
+        None => Utc::now(),
+        Some(raw) => parse_as_of(raw, contract.tz())
+            .ok_or_else(|| AppError::bad_request_i18n(&i18n, "error.loan.invalid_as_of"))?,
+    };
+
+    let mut transactions = query::get_transactions_by_contract(pool, auth.tenant_id, contract_id).await?;
+    let quote = calculator::settlement_breakdown_as_of(&contract, &mut transactions, as_of);
+
+    Ok(Json(SettlementQuoteView { contract_id: contract.id, contract_number: contract.contract_number, quote }))
+}
+
+/// Thời điểm báo giá: RFC3339, hoặc ngày → cuối ngày đó theo múi giờ nghiệp vụ
+fn parse_as_of(raw: &str, tz: chrono_tz::Tz) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(raw) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    let next_day = NaiveDate::parse_from_str(raw, "%Y-%m-%d").ok()?.succ_opt()?;
+    let start = next_day.and_hms_opt(0, 0, 0)?.and_local_timezone(tz).earliest()?;
+    Some(start.with_timezone(&Utc) - chrono::Duration::seconds(1))
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct StateChangeInput {
+    pub reason: Option<String>,
+}
+
+/// ✅ Kích hoạt hợp đồng nháp (POST /loan/:id/activate)
+pub async fn activate_contract(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+    body: Option<Json<StateChangeInput>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let reason = body.and_then(|Json(b)| b.reason);
+    let to = command_bus::dispatch(&state, &auth, &i18n, command::ActivateContract { contract_id, reason, scope }).await?;
+    Ok(Json(json!({ "contract_id": contract_id, "state": to, "state_label": i18n.t(to.i18n_key()) })))
+}
+
+/// ✅ Xoá nợ hợp đồng đang hoạt động / quá hạn (POST /loan/:id/write-off)
+pub async fn write_off_contract(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+    body: Option<Json<StateChangeInput>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let reason = body.and_then(|Json(b)| b.reason);
+    let to = command_bus::dispatch(&state, &auth, &i18n, command::WriteOffContract { contract_id, reason, scope }).await?;
+    Ok(Json(json!({ "contract_id": contract_id, "state": to, "state_label": i18n.t(to.i18n_key()) })))
+}
+
+/// ✅ Lịch sử chuyển trạng thái (GET /loan/:id/state-history)
+pub async fn get_state_history(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+
+    query::get_visible_contract(pool, &auth, &scope, contract_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::not_found_i18n(&i18n, "error.loan.not_found"),
+            e => e.into(),
+        })?;
+    let rows = query::get_state_history(pool, auth.tenant_id, contract_id).await?;
+
+    let label = |s: Option<LoanState>| s.map(|s| i18n.t(s.i18n_key()));
+    let items: Vec<_> = rows
+        .into_iter()
+        .map(|h| {
+            json!({
+                "from_state": h.from_state,
+                "from_state_label": label(h.from_state),
+                "to_state": h.to_state,
+                "to_state_label": label(Some(h.to_state)),
+                "reason": h.reason,
+                "changed_by": h.changed_by,
+                "changed_at": h.changed_at,
+            })
+        })
+        .collect();
+    Ok(Json(json!(items)))
+}
+
+/// ✅ Cơ cấu lãi suất / kỳ hạn có hiệu lực từ 1 ngày (POST /loan/:id/restructure)
+pub async fn restructure_contract(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+    Json(input): Json<RestructureInput>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let change = command_bus::dispatch(&state, &auth, &i18n, command::RestructureContract { contract_id, input, scope }).await?;
+    Ok(Json(json!({ "contract_id": contract_id, "term_change": change })))
+}
+
+/// ✅ Các lần cơ cấu của hợp đồng (GET /loan/:id/term-changes)
+pub async fn get_term_changes(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+
+    query::get_visible_contract(pool, &auth, &scope, contract_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::not_found_i18n(&i18n, "error.loan.not_found"),
+            e => e.into(),
+        })?;
+    let rows = query::get_term_changes(pool, auth.tenant_id, contract_id).await?;
+    Ok(Json(json!(rows)))
+}
+
+/// ✅ Tái cấp vốn: tất toán HĐ cũ, mở HĐ mới mang dư nợ sang (POST /loan/:id/refinance)
+pub async fn refinance_contract(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Extension(scope): Extension<Scope>,
+    Path(contract_id): Path<Uuid>,
+    Json(input): Json<RefinanceInput>,
+) -> Result<Json<command::Refinancing>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let result = command_bus::dispatch(&state, &auth, &i18n, command::RefinanceContract { contract_id, input, scope }).await?;
+    Ok(Json(result))
+}
+
+/// ✅ Danh sách chỉ số lãi suất tham chiếu của tenant (GET /loan/rate-index)
+pub async fn list_rate_indexes(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+    let rows = query::list_rate_indexes(pool, auth.tenant_id).await?;
+    Ok(Json(json!(rows)))
+}
+
+/// ✅ Tạo chỉ số lãi suất tham chiếu (POST /loan/rate-index)
+pub async fn create_rate_index(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Json(input): Json<RateIndexInput>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let code = input.code.trim().to_string();
+    command_bus::dispatch(&state, &auth, &i18n, command::CreateRateIndex { input }).await?;
+    Ok(Json(json!({ "code": code })))
+}
+
+/// ✅ Ghi giá trị chỉ số từ ngày hiệu lực (POST /loan/rate-index/:code/values)
+pub async fn set_rate_index_value(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path(code): Path<String>,
+    Json(input): Json<RateIndexValueInput>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let affected = command_bus::dispatch(&state, &auth, &i18n, command::SetRateIndexValue { code: code.clone(), input }).await?;
+    Ok(Json(json!({ "code": code, "affected_contracts": affected })))
+}
+
+/// ✅ Ngưỡng LTV của tenant (GET /loan/ltv-thresholds)
+pub async fn list_ltv_thresholds(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+    let rows = query::list_ltv_thresholds(pool, auth.tenant_id).await?;
+    Ok(Json(json!(rows)))
+}
+
+/// ✅ Đặt ngưỡng LTV (POST /loan/ltv-thresholds)
+pub async fn set_ltv_threshold(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Json(input): Json<LtvThresholdInput>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let code = input.code.trim().to_string();
+    command_bus::dispatch(&state, &auth, &i18n, command::SetLtvThreshold { input }).await?;
+    Ok(Json(json!({ "code": code })))
+}
+
+/// ✅ Xoá ngưỡng LTV (DELETE /loan/ltv-thresholds/:code)
+pub async fn delete_ltv_threshold(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path(code): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    command_bus::dispatch(&state, &auth, &i18n, command::DeleteLtvThreshold { code: code.clone() }).await?;
+    Ok(Json(json!({ "code": code, "deleted": true })))
+}
+
+/// ✅ Danh sách thứ tự phân bổ khoản trả gộp (GET /loan/repayment-waterfalls)
+pub async fn list_repayment_waterfalls(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+    let rows = query::list_repayment_waterfalls(pool, auth.tenant_id).await?;
+    Ok(Json(json!(rows)))
+}
+
+/// ✅ Đặt thứ tự phân bổ (POST /loan/repayment-waterfalls)
+pub async fn set_repayment_waterfall(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Json(input): Json<RepaymentWaterfallInput>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let code = input.code.trim().to_string();
+    command_bus::dispatch(&state, &auth, &i18n, command::SetRepaymentWaterfall { input }).await?;
+    Ok(Json(json!({ "code": code })))
+}
+
+/// ✅ Xoá thứ tự phân bổ (DELETE /loan/repayment-waterfalls/:code)
+pub async fn delete_repayment_waterfall(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path(code): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    command_bus::dispatch(&state, &auth, &i18n, command::DeleteRepaymentWaterfall { code: code.clone() }).await?;
+    Ok(Json(json!({ "code": code, "deleted": true })))
+}
+
+/// ✅ Danh sách sản phẩm vay (GET /loan/products)
+pub async fn list_products(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+    let rows = query::list_products(pool, auth.tenant_id).await?;
+    Ok(Json(json!(rows)))
+}
+
+/// ✅ Chi tiết sản phẩm vay (GET /loan/products/:product_id)
+pub async fn get_product(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path(product_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+    let product = query::get_product(pool, auth.tenant_id, product_id)
+        .await?
+        .ok_or_else(|| AppError::not_found_i18n(&i18n, "error.loan.product_not_found"))?;
+    Ok(Json(json!(product)))
+}
+
+/// ✅ Tạo sản phẩm vay (POST /loan/products)
+pub async fn create_product(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Json(input): Json<LoanProductInput>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let id = command_bus::dispatch(&state, &auth, &i18n, command::CreateLoanProduct { input }).await?;
+    Ok(Json(json!({ "id": id })))
+}
+
+/// ✅ Cập nhật sản phẩm vay (POST /loan/products/:product_id)
+pub async fn update_product(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path(product_id): Path<Uuid>,
+    Json(input): Json<LoanProductInput>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    command_bus::dispatch(&state, &auth, &i18n, command::UpdateLoanProduct { product_id, input }).await?;
+    Ok(Json(json!({ "id": product_id })))
+}
@@ -0,0 +1,360 @@
+//! Job nền của module loan (chạy theo chu kỳ trên mọi shard / tenant)
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{NaiveDate, Utc};
+use sqlx::{Connection, PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::infra::db::ShardManager;
+use crate::infra::outbox;
+use crate::module::loan::{calculator, command, convention, event::LoanEvent, ltv, query, state::LoanState};
+
+/// 📉 Giám sát LTV: tính LTV các hợp đồng đang dư nợ của tenant có cấu hình ngưỡng,
+/// gắn cờ ngưỡng cao nhất bị vượt (`loan_ltv_alert`) và phát event khi mức cờ thay đổi.
+pub struct LtvMonitor {
+    shard: Arc<ShardManager>,
+}
+
+impl LtvMonitor {
+    pub fn new(shard: Arc<ShardManager>) -> Self {
+        Self { shard }
+    }
+
+    /// Chạy vòng lặp theo chu kỳ `every`
+    pub fn spawn(self, every: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                self.run_once().await;
+            }
+        });
+    }
+
+    /// Quét 1 lượt trên tất cả shard, trả về số hợp đồng đổi mức cờ
+    pub async fn run_once(&self) -> usize {
+        let mut changed = 0;
+        for (shard_id, pool) in self.shard.distinct_shards() {
+            match self.run_pool(shard_id, pool).await {
+                Ok(n) => changed += n,
+                Err(e) => tracing::warn!("⚠️ Giám sát LTV lỗi: {}", e),
+            }
+        }
+        changed
+    }
+
+    async fn run_pool(&self, shard_id: &str, pool: &PgPool) -> Result<usize, sqlx::Error> {
+        let tenants = sqlx::query_scalar!("SELECT DISTINCT tenant_id FROM loan_ltv_threshold")
+            .fetch_all(pool)
+            .await?;
+
+        let mut changed = 0;
+        for tenant_id in tenants {
+            // Tenant đang chuyển shard → để lượt sau; bản sao cũ còn sót trên shard cũ → không phải của pool này
+            if !self.shard.owns_active_tenant(&tenant_id, shard_id).await.unwrap_or(false) {
+                continue;
+            }
+            changed += check_tenant(pool, tenant_id).await?;
+        }
+        Ok(changed)
+    }
+}
+
+async fn check_tenant(pool: &PgPool, tenant_id: Uuid) -> Result<usize, sqlx::Error> {
+    // Nhiều instance cùng chạy job: chỉ 1 instance xử lý tenant trong 1 lượt,
+    // cờ hiện tại đọc lại trong transaction đang giữ khóa → không phát event trùng
+    let mut tx = pool.begin().await?;
+    let locked = sqlx::query_scalar!(
+        r#"SELECT pg_try_advisory_xact_lock(hashtext('loan_ltv_monitor'), hashtext($1::text)) AS "locked!""#,
+        tenant_id.to_string()
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if !locked {
+        return Ok(0);
+    }
+
+    let thresholds = query::list_ltv_thresholds(pool, tenant_id).await?;
+    let contracts = query::list_contracts(pool, tenant_id).await?;
+
+    let flagged: HashMap<Uuid, String> = sqlx::query!(
+        "SELECT contract_id, threshold_code FROM loan_ltv_alert WHERE tenant_id = $1",
+        tenant_id
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|r| (r.contract_id, r.threshold_code))
+    .collect();
+
+    let mut changed = 0;
+    for mut contract in contracts {
+        let level = if matches!(contract.state, LoanState::Active | LoanState::Overdue) {
+            let mut txs = query::get_transactions_by_contract(pool, tenant_id, contract.id).await?;
+            calculator::calculate_interest_fields(&mut contract, &mut txs);
+            contract.ltv().and_then(|v| ltv::breached(v, &thresholds).map(|t| (t, v)))
+        } else {
+            None
+        };
+        let previous = flagged.get(&contract.id);
+
+        match level {
+            Some((threshold, value)) => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO loan_ltv_alert (tenant_id, contract_id, threshold_code, ltv, principal, collateral_value)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT (tenant_id, contract_id) DO UPDATE SET
+                        flagged_at = CASE WHEN loan_ltv_alert.threshold_code = EXCLUDED.threshold_code
+                                          THEN loan_ltv_alert.flagged_at ELSE now() END,
+                        threshold_code = EXCLUDED.threshold_code,
+                        ltv = EXCLUDED.ltv,
+                        principal = EXCLUDED.principal,
+                        collateral_value = EXCLUDED.collateral_value,
+                        checked_at = now()
+                    "#,
+                    tenant_id,
+                    contract.id,
+                    threshold.code,
+                    value,
+                    contract.current_principal,
+                    contract.collateral_value
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                if previous != Some(&threshold.code) {
+                    let event = LoanEvent::LoanLtvFlagged {
+                        contract_id: contract.id,
+                        threshold_code: Some(threshold.code.clone()),
+                        ltv: Some(value),
+                    };
+                    outbox::enqueue(&mut tx, tenant_id, &event).await?;
+                    changed += 1;
+                }
+            }
+            None if previous.is_some() => {
+                sqlx::query!(
+                    "DELETE FROM loan_ltv_alert WHERE tenant_id = $1 AND contract_id = $2",
+                    tenant_id,
+                    contract.id
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                let event = LoanEvent::LoanLtvFlagged { contract_id: contract.id, threshold_code: None, ltv: contract.ltv() };
+                outbox::enqueue(&mut tx, tenant_id, &event).await?;
+                changed += 1;
+            }
+            None => {}
+        }
+    }
+    tx.commit().await?;
+
+    if changed > 0 {
+        tracing::info!("📉 [LTV] tenant={} đổi mức cờ {} hợp đồng", tenant_id, changed);
+    }
+    Ok(changed)
+}
+
+/// 📸 Snapshot loan_report cuối ngày nghiệp vụ cho mọi tenant + chạy bù theo yêu cầu.
+/// Ngày đã snapshot xong lưu ở `loan_report_snapshot_run`, tiến độ chạy bù ở `loan_report_backfill.next_date`
+/// → server khởi động lại chạy tiếp từ ngày còn thiếu. Upsert theo ngày nên chạy trùng không sao.
+pub struct ReportSnapshotJob {
+    shard: Arc<ShardManager>,
+}
+
+impl ReportSnapshotJob {
+    pub fn new(shard: Arc<ShardManager>) -> Self {
+        Self { shard }
+    }
+
+    /// Chạy vòng lặp theo chu kỳ `every`
+    pub fn spawn(self, every: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                self.run_once().await;
+            }
+        });
+    }
+
+    /// Chạy 1 lượt cho mọi tenant, trả về số ngày đã snapshot
+    pub async fn run_once(&self) -> usize {
+        let tenants = match sqlx::query!("SELECT tenant_id, timezone FROM tenant")
+            .fetch_all(self.shard.get_pool_for_system())
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::warn!("⚠️ Snapshot loan_report: không đọc được danh sách tenant: {}", e);
+                return 0;
+            }
+        };
+
+        let mut days = 0;
+        for t in tenants {
+            // Tenant đang chuyển shard (fence đọc từ meta DB) → để lượt sau
+            if self.shard.refresh_tenant(&t.tenant_id).await.unwrap_or(true) {
+                continue;
+            }
+            let pool = match self.shard.get_pool_for_tenant(&t.tenant_id).await {
+                Ok(pool) => pool,
+                Err(e) => {
+                    tracing::warn!("⚠️ Snapshot loan_report tenant={} không định tuyến được shard: {}", t.tenant_id, e);
+                    continue;
+                }
+            };
+            let tz = convention::parse_timezone(&t.timezone);
+            match snapshot_tenant(pool, t.tenant_id, convention::business_date(Utc::now(), tz)).await {
+                Ok(n) => days += n,
+                Err(e) => tracing::warn!("⚠️ Snapshot loan_report tenant={} lỗi: {}", t.tenant_id, e),
+            }
+        }
+        days
+    }
+}
+
+/// Snapshot các ngày đã kết thúc (trước `today`) chưa chạy, rồi xử lý các yêu cầu chạy bù.
+/// Nhiều instance cùng chạy job: advisory lock theo tenant (giữ trên 1 connection suốt lượt chạy),
+/// instance không lấy được khoá bỏ qua tenant trong lượt này.
+async fn snapshot_tenant(pool: &PgPool, tenant_id: Uuid, today: NaiveDate) -> Result<usize, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let locked = sqlx::query_scalar!(
+        r#"SELECT pg_try_advisory_lock(hashtext('loan_report_snapshot'), hashtext($1::text)) AS "locked!""#,
+        tenant_id.to_string()
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if !locked {
+        return Ok(0);
+    }
+
+    let result = snapshot_tenant_locked(&mut conn, tenant_id, today).await;
+
+    let unlocked = sqlx::query_scalar!(
+        r#"SELECT pg_advisory_unlock(hashtext('loan_report_snapshot'), hashtext($1::text)) AS "unlocked!""#,
+        tenant_id.to_string()
+    )
+    .fetch_one(&mut *conn)
+    .await;
+    if !matches!(unlocked, Ok(true)) {
+        // Không nhả được khoá session → đóng hẳn connection thay vì trả về pool
+        drop(conn.detach());
+    }
+    result
+}
+
+async fn snapshot_tenant_locked(conn: &mut PgConnection, tenant_id: Uuid, today: NaiveDate) -> Result<usize, sqlx::Error> {
+    let mut days = 0;
+
+    // Lần đầu chạy: chỉ snapshot ngày vừa kết thúc, lịch sử cũ dùng chạy bù
+    let Some(closed) = today.pred_opt() else { return Ok(0) };
+    let last = sqlx::query_scalar!("SELECT last_date FROM loan_report_snapshot_run WHERE tenant_id = $1", tenant_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    let mut date = last.and_then(|d| d.succ_opt()).unwrap_or(closed);
+    while date <= closed {
+        // Snapshot + đánh dấu ngày đã chạy trong cùng transaction
+        let mut tx = conn.begin().await?;
+        command::snapshot_loan_reports(&mut tx, tenant_id, date).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO loan_report_snapshot_run (tenant_id, last_date) VALUES ($1, $2)
+            ON CONFLICT (tenant_id) DO UPDATE SET last_date = EXCLUDED.last_date, updated_at = now()
+            "#,
+            tenant_id,
+            date
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        tracing::info!("📸 [LOAN_REPORT] tenant={} snapshot ngày {}", tenant_id, date);
+        days += 1;
+        date += chrono::Duration::days(1);
+    }
+
+    let pending = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM loan_report_backfill
+        WHERE tenant_id = $1 AND status = 'pending'
+        ORDER BY created_at
+        "#,
+        tenant_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for id in pending {
+        days += run_backfill(conn, tenant_id, id).await?;
+    }
+
+    Ok(days)
+}
+
+/// Chạy bù từng ngày của 1 yêu cầu: mỗi ngày nhận dòng yêu cầu bằng `FOR UPDATE SKIP LOCKED`
+/// rồi snapshot + lưu `next_date` trong cùng transaction (đang được xử lý ở nơi khác → bỏ qua)
+async fn run_backfill(conn: &mut PgConnection, tenant_id: Uuid, id: Uuid) -> Result<usize, sqlx::Error> {
+    let mut days = 0;
+    loop {
+        let mut tx = conn.begin().await?;
+        let Some(b) = sqlx::query!(
+            r#"
+            SELECT next_date, date_to FROM loan_report_backfill
+            WHERE tenant_id = $1 AND id = $2 AND status = 'pending'
+            FOR UPDATE SKIP LOCKED
+            "#,
+            tenant_id,
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(days);
+        };
+
+        if b.next_date > b.date_to {
+            sqlx::query!(
+                "UPDATE loan_report_backfill SET status = 'done', error = NULL, finished_at = now() WHERE tenant_id = $1 AND id = $2",
+                tenant_id,
+                id
+            )
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            tracing::info!("📸 [LOAN_REPORT] tenant={} chạy bù {} xong tới {}", tenant_id, id, b.date_to);
+            return Ok(days);
+        }
+
+        if let Err(e) = command::snapshot_loan_reports(&mut tx, tenant_id, b.next_date).await {
+            tx.rollback().await?;
+            sqlx::query!(
+                r#"
+                UPDATE loan_report_backfill
+                SET status = 'failed', error = $3, finished_at = now()
+                WHERE tenant_id = $1 AND id = $2 AND status = 'pending'
+                "#,
+                tenant_id,
+                id,
+                e.to_string()
+            )
+            .execute(&mut *conn)
+            .await?;
+            tracing::warn!("⚠️ Chạy bù loan_report {} tenant={} lỗi ngày {}: {}", id, tenant_id, b.next_date, e);
+            return Ok(days);
+        }
+        sqlx::query!(
+            "UPDATE loan_report_backfill SET next_date = $3 WHERE tenant_id = $1 AND id = $2",
+            tenant_id,
+            id,
+            b.next_date + chrono::Duration::days(1)
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        days += 1;
+    }
+}
@@ -0,0 +1,120 @@
+//! Thanh lý tài sản cầm cố: thu giữ tài sản → ghi nhận tiền bán từng tài sản → hoàn tất.
+//! Tiền bán được phân bổ theo thứ tự lãi → gốc → phí (lưu kho, lãi phạt), cùng thứ tự với
+//! giao dịch `liquidation` trong calculator. Tiền thừa thành khoản phải trả lại khách hàng,
+//! tiền thiếu để lại trên hợp đồng dưới dạng nợ đã xoá.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::{BigDecimal, Json};
+use uuid::Uuid;
+
+use crate::module::loan::calculator::SettlementQuote;
+
+/// 1 đợt thanh lý (bảng `loan_liquidation`)
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Liquidation {
+    pub id: Uuid,
+    pub contract_id: Uuid,
+    /// seized | completed
+    pub status: String,
+    pub reason: Option<String>,
+    pub seized_by: Uuid,
+    pub seized_at: DateTime<Utc>,
+    pub liquidation_date: Option<DateTime<Utc>>,
+    pub proceeds_total: i64,
+    pub interest_allocated: i64,
+    pub principal_allocated: i64,
+    pub fees_allocated: i64,
+    pub surplus: i64,
+    pub shortfall: i64,
+    pub completed_by: Option<Uuid>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub assets: Json<Vec<LiquidationAsset>>,
+    /// Khoản phải trả lại khách hàng (nếu có tiền thừa)
+    pub payable_id: Option<Uuid>,
+}
+
+/// Tài sản bị thu giữ + tiền bán (bảng `loan_liquidation_asset`)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LiquidationAsset {
+    pub asset_id: Uuid,
+    pub value_estimate: Option<BigDecimal>,
+    /// None = chưa bán
+    pub proceeds: Option<i64>,
+    pub sold_at: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+    pub recorded_by: Option<Uuid>,
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+/// Kết quả phân bổ tiền bán tài sản
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Allocation {
+    pub interest: i64,
+    pub principal: i64,
+    /// Phí lưu kho + lãi phạt
+    pub fees: i64,
+    /// Phần vượt quá nghĩa vụ → trả lại khách hàng
+    pub surplus: i64,
+    /// Nghĩa vụ chưa được bù đắp → xoá nợ
+    pub shortfall: i64,
+}
+
+impl Allocation {
+    /// Số tiền thực sự thu nợ (ghi thành giao dịch `liquidation`)
+    pub fn applied(&self) -> i64 {
+        self.interest + self.principal + self.fees
+    }
+}
+
+/// ✅ Phân bổ tiền bán theo thứ tự lãi → gốc → phí.
+/// Thanh lý do vi phạm nên không thu phí trả nợ trước hạn (`prepayment_penalty` bị bỏ qua).
+pub fn allocate(proceeds: i64, due: &SettlementQuote) -> Allocation {
+    let mut left = proceeds.max(0);
+    let mut take = |owed: i64| {
+        let paid = left.min(owed.max(0));
+        left -= paid;
+        paid
+    };
+    let interest = take(due.unpaid_interest);
+    let principal = take(due.principal);
+    let fees = take(due.storage_fee + due.late_penalty);
+    let surplus = left;
+
+    let owed = due.unpaid_interest.max(0) + due.principal.max(0) + (due.storage_fee + due.late_penalty).max(0);
+    Allocation { interest, principal, fees, surplus, shortfall: owed - (interest + principal + fees) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn due() -> SettlementQuote {
+        SettlementQuote {
+            as_of: Utc::now(),
+            principal: 10_000_000,
+            unpaid_interest: 500_000,
+            late_penalty: 120_000,
+            storage_fee: 80_000,
+            prepayment_penalty: 300_000,
+            total: 10_700_000,
+            amount_due: 11_000_000,
+        }
+    }
+
+    #[test]
+    fn shortfall_leaves_fees_then_principal_unpaid() {
+        let a = allocate(8_000_000, &due());
+        assert_eq!(a, Allocation { interest: 500_000, principal: 7_500_000, fees: 0, surplus: 0, shortfall: 2_700_000 });
+        assert_eq!(a.applied(), 8_000_000);
+
+        let a = allocate(10_600_000, &due());
+        assert_eq!((a.principal, a.fees, a.shortfall), (10_000_000, 100_000, 100_000));
+    }
+
+    #[test]
+    fn surplus_ignores_prepayment_penalty() {
+        let a = allocate(12_000_000, &due());
+        assert_eq!(a, Allocation { interest: 500_000, principal: 10_000_000, fees: 200_000, surplus: 1_300_000, shortfall: 0 });
+        assert_eq!(a.applied() + a.surplus, 12_000_000);
+    }
+}
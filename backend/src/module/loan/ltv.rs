@@ -0,0 +1,71 @@
+//! LTV (loan-to-value) = dư nợ gốc / giá trị tài sản đang cầm cố (%).
+//! Giá trị tài sản lấy theo lần định giá mới nhất (`collateral_valuation`);
+//! tenant tự đặt các ngưỡng, job giám sát gắn cờ hợp đồng vượt ngưỡng cao nhất.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Ngưỡng LTV của tenant (bảng `loan_ltv_threshold`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LtvThreshold {
+    pub code: String,
+    pub name: String,
+    /// %
+    pub ltv: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Hợp đồng đang bị gắn cờ vượt ngưỡng (bảng `loan_ltv_alert` + ngưỡng)
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct LtvAlert {
+    pub contract_id: Uuid,
+    pub contract_number: String,
+    pub contact_id: Uuid,
+    pub threshold_code: String,
+    pub threshold_name: String,
+    pub threshold_ltv: f64,
+    pub ltv: f64,
+    pub principal: i64,
+    pub collateral_value: i64,
+    /// Lần đầu vượt ngưỡng hiện tại
+    pub flagged_at: DateTime<Utc>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// LTV (%) – None khi hợp đồng không có tài sản cầm cố được định giá
+pub fn ratio(principal: i64, collateral_value: i64) -> Option<f64> {
+    (collateral_value > 0).then(|| principal.max(0) as f64 / collateral_value as f64 * 100.0)
+}
+
+/// Ngưỡng cao nhất mà `ltv` đạt tới (≥)
+pub fn breached(ltv: f64, thresholds: &[LtvThreshold]) -> Option<&LtvThreshold> {
+    thresholds
+        .iter()
+        .filter(|t| ltv >= t.ltv)
+        .max_by(|a, b| a.ltv.total_cmp(&b.ltv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn threshold(code: &str, ltv: f64) -> LtvThreshold {
+        LtvThreshold { code: code.into(), name: code.into(), ltv, created_at: Utc::now() }
+    }
+
+    #[test]
+    fn ratio_needs_collateral() {
+        assert_eq!(ratio(6_000_000, 10_000_000), Some(60.0));
+        assert_eq!(ratio(6_000_000, 0), None);
+        assert_eq!(ratio(-1, 10_000_000), Some(0.0));
+    }
+
+    #[test]
+    fn highest_threshold_wins() {
+        let thresholds = [threshold("margin_call", 80.0), threshold("warning", 70.0), threshold("liquidate", 95.0)];
+        assert_eq!(breached(65.0, &thresholds), None);
+        assert_eq!(breached(70.0, &thresholds).map(|t| t.code.as_str()), Some("warning"));
+        assert_eq!(breached(88.5, &thresholds).map(|t| t.code.as_str()), Some("margin_call"));
+        assert_eq!(breached(120.0, &thresholds).map(|t| t.code.as_str()), Some("liquidate"));
+    }
+}
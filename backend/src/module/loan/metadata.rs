@@ -1,5 +1,6 @@
 use serde_json::json;
 use crate::core::i18n::I18n;
+use crate::module::loan::state::LoanState;
 
 pub const DISPLAY_NAME: &str = "Loan";
 pub const DESCRIPTION: &str = "Quản lí cho vay";
@@ -11,10 +12,32 @@ pub fn loan_form_schema(i18n: &I18n) -> serde_json::Value {
                 { "name": "contact_id", "label": i18n.t("loan.field.customer"), "type": "select", "width": 12 },
                 { "name": "contract_number", "label": i18n.t("loan.field.contractNumber"), "type": "text", "width": 6, "disabled": true },
                 { "name": "interest_rate", "label": i18n.t("loan.field.interestRate"), "type": "number", "width": 6},
+                { "name": "rate_index", "label": i18n.t("loan.field.rateIndex"), "type": "text", "width": 6 },
+                { "name": "rate_margin", "label": i18n.t("loan.field.rateMargin"), "type": "number", "width": 6 },
                 { "name": "date_start", "label": i18n.t("loan.field.dateStart"), "type": "date", "width": 6  },
                 { "name": "date_end", "label": i18n.t("loan.field.dateEnd"), "type": "date", "width": 6  },
                 { "name": "term_months", "label": i18n.t("loan.field.termMonths"), "type": "number", "width": 6 },
-                { "name": "state", "label": i18n.t("loan.field.state"), "type": "text", "width": 6 , "disabled": true},
+                { "name": "penalty_rate", "label": i18n.t("loan.field.penaltyRate"), "type": "number", "width": 6 },
+                { "name": "storage_fee_rate", "label": i18n.t("loan.field.storageFeeRate"), "type": "number", "width": 6 },
+                { "name": "storage_fee_flat", "label": i18n.t("loan.field.storageFeeFlat"), "type": "number", "width": 6 },
+                { "name": "repayment_plan", "label": i18n.t("loan.field.repaymentPlan"), "type": "select", "width": 6, "options": json!([
+                    { "value": "equal_principal", "label": i18n.t("loan.repaymentPlan.equalPrincipal") },
+                    { "value": "annuity", "label": i18n.t("loan.repaymentPlan.annuity") },
+                    { "value": "interest_only_bullet", "label": i18n.t("loan.repaymentPlan.interestOnlyBullet") },
+                    { "value": "custom", "label": i18n.t("loan.repaymentPlan.custom") }
+                ])},
+                { "name": "day_count", "label": i18n.t("loan.field.dayCount"), "type": "select", "width": 6, "options": json!([
+                    { "value": "act_365", "label": "ACT/365" },
+                    { "value": "act_360", "label": "ACT/360" },
+                    { "value": "act_act", "label": "ACT/ACT" },
+                    { "value": "30_360", "label": "30/360" }
+                ])},
+                { "name": "compounding", "label": i18n.t("loan.field.compounding"), "type": "select", "width": 6, "options": json!([
+                    { "value": "simple", "label": i18n.t("loan.compounding.simple") },
+                    { "value": "daily", "label": i18n.t("loan.compounding.daily") },
+                    { "value": "monthly", "label": i18n.t("loan.compounding.monthly") }
+                ])},
+                { "name": "state", "label": i18n.t("loan.field.state"), "type": "select", "width": 6 , "disabled": true, "options": state_options(i18n) },
             ]
         },
         "list": {
@@ -63,8 +86,12 @@ pub fn loan_form_schema(i18n: &I18n) -> serde_json::Value {
                     { "value": "additional", "label": i18n.t("loan.notebook.transactionType.additional") },
                     { "value": "interest", "label": i18n.t("loan.notebook.transactionType.interest") },
                     { "value": "principal", "label": i18n.t("loan.notebook.transactionType.principal") },
+                    { "value": "penalty", "label": i18n.t("loan.notebook.transactionType.penalty") },
+                    { "value": "storage_fee", "label": i18n.t("loan.notebook.transactionType.storageFee") },
+                    { "value": "repayment", "label": i18n.t("loan.notebook.transactionType.repayment") },
                     { "value": "liquidation", "label": i18n.t("loan.notebook.transactionType.liquidation") },
-                    { "value": "settlement", "label": i18n.t("loan.notebook.transactionType.settlement") }
+                    { "value": "settlement", "label": i18n.t("loan.notebook.transactionType.settlement") },
+                    { "value": "prepayment_fee", "label": i18n.t("loan.notebook.transactionType.prepaymentFee") }
                 ])},
                 { "name": "amount", "label": i18n.t("loan.notebook.amount"), "type": "number" },
                 { "name": "days_from_prev", "label": i18n.t("loan.notebook.daysFromPrev"), "type": "compute" },
@@ -76,3 +103,11 @@ pub fn loan_form_schema(i18n: &I18n) -> serde_json::Value {
         }
     })
 }
+
+/// Nhãn trạng thái hợp đồng theo ngôn ngữ
+fn state_options(i18n: &I18n) -> serde_json::Value {
+    LoanState::ALL
+        .iter()
+        .map(|s| json!({ "value": s.as_str(), "label": i18n.t(s.i18n_key()) }))
+        .collect()
+}
@@ -7,3 +7,17 @@ pub mod dto;
 pub mod event;
 pub mod metadata;
 pub mod calculator;
+pub mod convention;
+pub mod schedule;
+pub mod delinquency;
+pub mod prepayment;
+pub mod rate;
+pub mod restructure;
+pub mod liquidation;
+pub mod ltv;
+pub mod storage;
+pub mod waterfall;
+pub mod product;
+pub mod job;
+pub mod state;
+pub mod event_handler;
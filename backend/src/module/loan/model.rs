@@ -2,7 +2,16 @@ use serde::{Serialize, Deserialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 use chrono::{DateTime, Utc, NaiveDate};
-use sqlx::types::BigDecimal;
+use sqlx::types::{BigDecimal, Json};
+use crate::module::loan::convention::{self, Compounding, DayCount, InterestConvention};
+use crate::module::loan::ltv;
+use crate::module::loan::prepayment::PrepaymentPenalty;
+use crate::module::loan::rate::{RateIndexValue, RateTimeline};
+use crate::module::loan::restructure::TermChange;
+use crate::module::loan::schedule::Installment;
+use crate::module::loan::state::LoanState;
+use crate::module::loan::storage::{Pledge, StorageFee};
+use crate::module::loan::waterfall::Waterfall;
 
 #[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
 pub struct LoanContract {
@@ -13,6 +22,11 @@ pub struct LoanContract {
 
     /// %/năm (ví dụ 18.0 = 18%/năm)
     pub interest_rate: f64,
+    /// Chỉ số lãi suất tham chiếu (NULL = lãi cố định)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_index: Option<String>,
+    /// Biên độ cộng thêm vào chỉ số (%/năm)
+    pub rate_margin: f64,
 
     pub term_months: i32,
     pub date_start: DateTime<Utc>,
@@ -24,8 +38,27 @@ pub struct LoanContract {
 
     // các cột dưới đây là NOT NULL ở DB
 
+    /// % giá trị tài sản cầm cố / ngày
     pub storage_fee_rate: f64,
+    /// Tổng phí lưu kho đã phát sinh (projection)
     pub storage_fee: i64,
+    /// Phí lưu kho cố định / ngày
+    pub storage_fee_flat: i64,
+    /// Tổng giá trị ước tính tài sản đang cầm cố (LTV)
+    pub collateral_value: i64,
+
+    /// Lãi phạt %/năm trên số tiền quá hạn
+    pub penalty_rate: f64,
+    /// Phí trả nợ trước hạn
+    pub prepayment_penalty: Json<PrepaymentPenalty>,
+    /// Thứ tự phân bổ khoản trả gộp (`repayment`)
+    pub repayment_waterfall: Json<Waterfall>,
+
+    /// Quy ước tính lãi
+    pub day_count: DayCount,
+    pub compounding: Compounding,
+    /// Múi giờ nghiệp vụ của tenant (IANA)
+    pub timezone: String,
 
     pub current_principal: i64,
     pub current_interest: i64,
@@ -34,15 +67,96 @@ pub struct LoanContract {
     pub total_settlement_amount: i64,
     pub total_paid_principal: i64, // projection
 
-    pub state: String,
+    pub state: LoanState,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub created_by: Uuid,
     pub assignee_id: Option<Uuid>,
     pub shared_with: Option<Vec<Uuid>>,
+    /// Hợp đồng cũ được tái cấp vốn sang hợp đồng này
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refinanced_from: Option<Uuid>,
+    /// Sản phẩm vay lúc tạo hợp đồng
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_id: Option<Uuid>,
 
     #[sqlx(skip)]
     pub payoff_due: i64, // projection: số tiền còn phải trả
+
+    #[sqlx(skip)]
+    pub current_penalty: i64, // projection: lãi phạt chưa trả
+    #[sqlx(skip)]
+    pub days_past_due: i32,   // projection: số ngày quá hạn
+    #[sqlx(skip)]
+    pub current_storage_fee: i64, // projection: phí lưu kho chưa trả
+    #[sqlx(skip)]
+    pub credit_balance: i64, // projection: tiền trả gộp vượt nghĩa vụ, ghi có cho khách
+
+    /// Lịch trả nợ (nạp kèm hợp đồng để tính quá hạn)
+    #[serde(skip)]
+    pub schedule: Json<Vec<Installment>>,
+    /// Các lần cơ cấu lãi suất / kỳ hạn (nạp kèm để tính lãi theo từng giai đoạn)
+    #[serde(skip)]
+    pub term_changes: Json<Vec<TermChange>>,
+    /// Giá trị chỉ số tham chiếu (chỉ nạp khi lãi thả nổi)
+    #[serde(skip)]
+    pub rate_index_values: Json<Vec<RateIndexValue>>,
+    /// Lịch sử cầm cố + định giá tài sản (nạp kèm để tính phí lưu kho theo ngày)
+    #[serde(skip)]
+    pub collateral_pledges: Json<Vec<Pledge>>,
+}
+
+impl LoanContract {
+    pub fn convention(&self) -> InterestConvention {
+        InterestConvention { day_count: self.day_count, compounding: self.compounding }
+    }
+
+    pub fn tz(&self) -> chrono_tz::Tz {
+        convention::parse_timezone(&self.timezone)
+    }
+
+    /// Lãi suất theo thời gian (sau các lần cơ cấu / theo chỉ số thả nổi)
+    pub fn rate_timeline(&self) -> RateTimeline {
+        let timeline = RateTimeline::new(self.interest_rate, &self.term_changes, self.tz());
+        match self.rate_index {
+            Some(_) => timeline.with_index(&self.rate_index_values, self.rate_margin, self.tz()),
+            None => timeline,
+        }
+    }
+
+    /// Phí lưu kho theo tài sản đã / đang cầm cố
+    pub fn storage_fee(&self) -> StorageFee<'_> {
+        StorageFee {
+            rate: self.storage_fee_rate,
+            flat: self.storage_fee_flat,
+            pledges: &self.collateral_pledges,
+            tz: self.tz(),
+        }
+    }
+
+    /// LTV (%) trên dư nợ gốc đã tính – None khi không có tài sản cầm cố
+    pub fn ltv(&self) -> Option<f64> {
+        ltv::ratio(self.current_principal, self.collateral_value)
+    }
+
+    /// Ngày đáo hạn: `date_end`, không có thì tính theo kỳ hạn
+    pub fn maturity(&self) -> Option<DateTime<Utc>> {
+        self.date_end.or_else(|| {
+            u32::try_from(self.term_months)
+                .ok()
+                .and_then(|m| self.date_start.checked_add_months(chrono::Months::new(m)))
+        })
+    }
+}
+
+/// Chỉ số lãi suất tham chiếu của tenant (bảng `loan_rate_index`)
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct RateIndex {
+    pub code: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Các giá trị theo ngày hiệu lực
+    pub values: Json<Vec<RateIndexValue>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
@@ -52,7 +166,7 @@ pub struct LoanTransaction {
     pub tenant_id: Uuid,
     pub contact_id: Uuid,
 
-    /// disbursement | additional | interest | principal | liquidation | settlement
+    /// disbursement | additional | interest | principal | penalty | storage_fee | repayment | liquidation | settlement
     pub transaction_type: String,
 
     /// Số tiền dương (UI nhập dương)
@@ -73,10 +187,11 @@ pub struct LoanTransaction {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 
-    #[sqlx(skip)]
-    pub principal_applied: i64, // projection
-    #[sqlx(skip)]
-    pub interest_applied: i64,  // projection
+    // projection: phần tiền đã phân bổ vào gốc / lãi / lãi phạt / phí lưu kho
+    pub principal_applied: i64,
+    pub interest_applied: i64,
+    pub penalty_applied: i64,
+    pub fee_applied: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
@@ -103,8 +218,25 @@ pub struct LoanReport {
     pub accumulated_interest: Option<i64>,
     pub total_paid_interest: Option<i64>,
     pub total_paid_principal: Option<i64>,
+    pub current_storage_fee: Option<i64>,
     pub payoff_due: Option<i64>,
-    pub state: String,
+    pub state: LoanState,
+}
+
+/// Yêu cầu chạy bù snapshot loan_report (bảng `loan_report_backfill`)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ReportBackfill {
+    pub id: Uuid,
+    pub date_from: NaiveDate,
+    pub date_to: NaiveDate,
+    /// Ngày tiếp theo cần chạy (> date_to khi đã xong)
+    pub next_date: NaiveDate,
+    /// pending | done | failed
+    pub status: String,
+    pub error: Option<String>,
+    pub requested_by: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
 }
 
 #[derive(sqlx::FromRow)]
@@ -136,8 +268,20 @@ pub struct LoanReportView {
     pub accumulated_interest: Option<i64>,
     pub total_paid_interest: Option<i64>,
     pub total_paid_principal: Option<i64>,
+    pub current_storage_fee: Option<i64>,
     pub payoff_due: Option<i64>,
     pub state: String,
     pub contract_number: String,
     pub contact_name: String,
-}
\ No newline at end of file
+}
+
+/// 1 lần chuyển trạng thái hợp đồng
+#[derive(Debug, Clone, sqlx::FromRow, serde::Serialize)]
+pub struct LoanStateHistory {
+    pub from_state: Option<LoanState>,
+    pub to_state: LoanState,
+    pub reason: Option<String>,
+    /// None = hệ thống tự chuyển
+    pub changed_by: Option<Uuid>,
+    pub changed_at: DateTime<Utc>,
+}
@@ -0,0 +1,98 @@
+//! Phí trả nợ trước hạn (prepayment penalty) cấu hình theo hợp đồng.
+//! Chỉ áp dụng khi tất toán trước ngày đáo hạn; có thể miễn sau N tháng kể từ ngày giải ngân.
+use chrono::{DateTime, Months, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Cách tính phí trả trước
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PrepaymentRule {
+    /// Không thu phí
+    #[default]
+    None,
+    /// % trên dư nợ gốc còn lại
+    Percent { rate: f64 },
+    /// Phí cố định
+    Fixed { amount: i64 },
+}
+
+/// Cấu hình lưu ở `loan_contract.prepayment_penalty` (JSONB)
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct PrepaymentPenalty {
+    #[serde(flatten)]
+    pub rule: PrepaymentRule,
+    /// Miễn phí khi đã vay đủ N tháng
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waive_after_months: Option<u32>,
+}
+
+impl PrepaymentPenalty {
+    /// Tỉ lệ / số tiền phí không được âm
+    pub fn is_valid(&self) -> bool {
+        match self.rule {
+            PrepaymentRule::None => true,
+            PrepaymentRule::Percent { rate } => rate.is_finite() && rate >= 0.0,
+            PrepaymentRule::Fixed { amount } => amount >= 0,
+        }
+    }
+
+    /// ✅ Phí trả trước khi tất toán tại `as_of`.
+    /// `maturity`: ngày đáo hạn; tất toán từ ngày đáo hạn trở đi không tính phí.
+    pub fn amount(
+        &self,
+        outstanding_principal: i64,
+        date_start: DateTime<Utc>,
+        maturity: Option<DateTime<Utc>>,
+        as_of: DateTime<Utc>,
+    ) -> i64 {
+        if outstanding_principal <= 0 || maturity.is_some_and(|m| as_of >= m) {
+            return 0;
+        }
+        if let Some(n) = self.waive_after_months {
+            if date_start.checked_add_months(Months::new(n)).is_some_and(|d| as_of >= d) {
+                return 0;
+            }
+        }
+        match self.rule {
+            PrepaymentRule::None => 0,
+            PrepaymentRule::Percent { rate } => (outstanding_principal as f64 * rate / 100.0).round() as i64,
+            PrepaymentRule::Fixed { amount } => amount.max(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(m: u32, d: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, m, d, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn percent_until_waived() {
+        let p: PrepaymentPenalty =
+            serde_json::from_str(r#"{"type":"percent","rate":2.0,"waive_after_months":6}"#).unwrap();
+        assert_eq!(p.rule, PrepaymentRule::Percent { rate: 2.0 });
+        assert_eq!(p.amount(10_000_000, at(1, 1), Some(at(12, 1)), at(3, 1)), 200_000);
+        assert_eq!(p.amount(10_000_000, at(1, 1), Some(at(12, 1)), at(7, 1)), 0);
+    }
+
+    #[test]
+    fn no_fee_at_or_after_maturity() {
+        let p = PrepaymentPenalty { rule: PrepaymentRule::Fixed { amount: 500_000 }, waive_after_months: None };
+        assert_eq!(p.amount(1, at(1, 1), Some(at(6, 1)), at(5, 31)), 500_000);
+        assert_eq!(p.amount(1, at(1, 1), Some(at(6, 1)), at(6, 1)), 0);
+        assert_eq!(PrepaymentPenalty::default().amount(1, at(1, 1), None, at(2, 1)), 0);
+    }
+
+    #[test]
+    fn negative_fee_is_invalid() {
+        let percent = |rate| PrepaymentPenalty { rule: PrepaymentRule::Percent { rate }, waive_after_months: None };
+        assert!(percent(2.0).is_valid());
+        assert!(!percent(-1.0).is_valid());
+        assert!(!percent(f64::INFINITY).is_valid());
+        assert!(!PrepaymentPenalty { rule: PrepaymentRule::Fixed { amount: -1 }, waive_after_months: None }.is_valid());
+    }
+}
@@ -0,0 +1,189 @@
+//! Sản phẩm vay (bảng `loan_product`): khung lãi suất / kỳ hạn / số tiền, quy ước tính lãi,
+//! loại tài sản cầm cố bắt buộc, biểu phí và tiền tố số hợp đồng.
+//! Hợp đồng tạo theo sản phẩm lấy mặc định của sản phẩm cho các trường bỏ trống và bị từ chối khi vượt khung.
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::types::Json;
+use uuid::Uuid;
+
+use crate::module::loan::convention::{Compounding, DayCount};
+use crate::module::loan::dto::{CreateContractInput, LoanProductInput};
+use crate::module::loan::prepayment::PrepaymentPenalty;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct LoanProduct {
+    pub id: Uuid,
+    pub code: String,
+    pub name: String,
+    pub description: Option<String>,
+    /// Khung lãi suất %/năm (None = không giới hạn)
+    pub min_rate: Option<f64>,
+    pub max_rate: Option<f64>,
+    pub default_rate: Option<f64>,
+    pub min_term_months: Option<i32>,
+    pub max_term_months: Option<i32>,
+    pub default_term_months: Option<i32>,
+    pub min_principal: Option<i64>,
+    pub max_principal: Option<i64>,
+    pub day_count: DayCount,
+    pub compounding: Compounding,
+    /// Mỗi loại cần ít nhất 1 tài sản cầm cố khi tạo hợp đồng
+    pub required_collateral_types: Vec<String>,
+    pub storage_fee_rate: f64,
+    pub storage_fee_flat: i64,
+    pub penalty_rate: f64,
+    pub prepayment_penalty: Json<PrepaymentPenalty>,
+    /// Mã thứ tự phân bổ khoản trả gộp
+    pub repayment_waterfall: Option<String>,
+    /// Tiền tố số hợp đồng (None = LOAN)
+    pub number_prefix: Option<String>,
+    pub active: bool,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl LoanProduct {
+    /// Điền mặc định của sản phẩm vào các trường hợp đồng bỏ trống
+    pub fn fill_defaults(&self, input: &mut CreateContractInput) {
+        input.interest_rate = input.interest_rate.or(self.default_rate);
+        input.term_months = input.term_months.or(self.default_term_months);
+        input.day_count.get_or_insert(self.day_count);
+        input.compounding.get_or_insert(self.compounding);
+        input.storage_fee_rate.get_or_insert(self.storage_fee_rate);
+        input.storage_fee_flat.get_or_insert(self.storage_fee_flat);
+        input.penalty_rate.get_or_insert(self.penalty_rate);
+        input.prepayment_penalty.get_or_insert(self.prepayment_penalty.0);
+        if input.repayment_waterfall.is_none() {
+            input.repayment_waterfall = self.repayment_waterfall.clone();
+        }
+    }
+
+    /// ✅ Điều khoản hợp đồng phải nằm trong khung sản phẩm, trả về i18n key của vi phạm đầu tiên.
+    /// Trường bỏ trống (khi cập nhật = giữ nguyên) không kiểm tra.
+    pub fn check(&self, input: &CreateContractInput) -> Result<(), &'static str> {
+        if input.interest_rate.is_some_and(|r| !within(r, self.min_rate, self.max_rate)) {
+            return Err("error.loan.product_rate_out_of_range");
+        }
+        if input.term_months.is_some_and(|t| !within(t, self.min_term_months, self.max_term_months)) {
+            return Err("error.loan.product_term_out_of_range");
+        }
+        if !within(input.principal, self.min_principal, self.max_principal) {
+            return Err("error.loan.product_principal_out_of_range");
+        }
+        if input.day_count.is_some_and(|d| d != self.day_count)
+            || input.compounding.is_some_and(|c| c != self.compounding)
+        {
+            return Err("error.loan.product_convention_mismatch");
+        }
+        Ok(())
+    }
+
+    /// Loại tài sản bắt buộc chưa có trong `asset_types`
+    pub fn missing_collateral(&self, asset_types: &[String]) -> Vec<&str> {
+        self.required_collateral_types
+            .iter()
+            .filter(|t| !asset_types.contains(t))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+fn within<T: PartialOrd>(value: T, min: Option<T>, max: Option<T>) -> bool {
+    min.is_none_or(|m| value >= m) && max.is_none_or(|m| value <= m)
+}
+
+/// Cấu hình sản phẩm hợp lệ: có mã + tên, khung min ≤ max, mặc định nằm trong khung
+pub fn valid_config(input: &LoanProductInput) -> bool {
+    let ordered = |min: Option<f64>, max: Option<f64>| min.zip(max).is_none_or(|(a, b)| a <= b);
+    let in_range = |v: Option<f64>, min: Option<f64>, max: Option<f64>| v.is_none_or(|v| within(v, min, max));
+    let as_f64 = |v: Option<i64>| v.map(|v| v as f64);
+    let term = |v: Option<i32>| v.map(f64::from);
+
+    !input.code.trim().is_empty()
+        && !input.name.trim().is_empty()
+        && ordered(input.min_rate, input.max_rate)
+        && in_range(input.default_rate, input.min_rate, input.max_rate)
+        && ordered(term(input.min_term_months), term(input.max_term_months))
+        && in_range(term(input.default_term_months), term(input.min_term_months), term(input.max_term_months))
+        && input.default_term_months.is_none_or(|t| t > 0)
+        && ordered(as_f64(input.min_principal), as_f64(input.max_principal))
+        && input.storage_fee_rate.is_none_or(|v| v >= 0.0)
+        && input.storage_fee_flat.is_none_or(|v| v >= 0)
+        && input.penalty_rate.is_none_or(|v| v >= 0.0)
+        && input.prepayment_penalty.is_none_or(|p| p.is_valid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn product() -> LoanProduct {
+        LoanProduct {
+            id: Uuid::nil(),
+            code: "gold".into(),
+            name: "Cầm vàng".into(),
+            description: None,
+            min_rate: Some(12.0),
+            max_rate: Some(24.0),
+            default_rate: Some(18.0),
+            min_term_months: Some(1),
+            max_term_months: Some(12),
+            default_term_months: Some(6),
+            min_principal: Some(1_000_000),
+            max_principal: Some(500_000_000),
+            day_count: DayCount::Act360,
+            compounding: Compounding::Simple,
+            required_collateral_types: vec!["gold".into()],
+            storage_fee_rate: 0.01,
+            storage_fee_flat: 0,
+            penalty_rate: 36.0,
+            prepayment_penalty: Json(PrepaymentPenalty::default()),
+            repayment_waterfall: Some("standard".into()),
+            number_prefix: Some("VANG".into()),
+            active: true,
+            created_by: Uuid::nil(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn input(extra: serde_json::Value) -> CreateContractInput {
+        let mut v = json!({
+            "contact_id": Uuid::nil(),
+            "principal": 10_000_000,
+            "date_start": "2025-01-01T00:00:00Z",
+        });
+        v.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(v).unwrap()
+    }
+
+    #[test]
+    fn defaults_then_policy() {
+        let p = product();
+        let mut i = input(json!({ "term_months": 3 }));
+        p.fill_defaults(&mut i);
+        assert_eq!((i.interest_rate, i.term_months), (Some(18.0), Some(3)));
+        assert_eq!((i.day_count, i.penalty_rate), (Some(DayCount::Act360), Some(36.0)));
+        assert_eq!(i.repayment_waterfall.as_deref(), Some("standard"));
+        assert_eq!(p.check(&i), Ok(()));
+
+        assert_eq!(p.check(&input(json!({ "interest_rate": 30.0 }))), Err("error.loan.product_rate_out_of_range"));
+        assert_eq!(p.check(&input(json!({ "term_months": 24 }))), Err("error.loan.product_term_out_of_range"));
+        assert_eq!(p.check(&input(json!({ "principal": 500 }))), Err("error.loan.product_principal_out_of_range"));
+        assert_eq!(p.check(&input(json!({ "day_count": "act_365" }))), Err("error.loan.product_convention_mismatch"));
+
+        assert_eq!(p.missing_collateral(&["car".into()]), vec!["gold"]);
+        assert!(p.missing_collateral(&["car".into(), "gold".into()]).is_empty());
+    }
+
+    #[test]
+    fn config_ranges_must_be_consistent() {
+        let cfg = |v: serde_json::Value| -> LoanProductInput { serde_json::from_value(v).unwrap() };
+        assert!(valid_config(&cfg(json!({ "code": "a", "name": "A", "min_rate": 10.0, "max_rate": 20.0, "default_rate": 15.0 }))));
+        assert!(!valid_config(&cfg(json!({ "code": "a", "name": "A", "min_rate": 20.0, "max_rate": 10.0 }))));
+        assert!(!valid_config(&cfg(json!({ "code": "a", "name": "A", "max_term_months": 6, "default_term_months": 12 }))));
+        assert!(!valid_config(&cfg(json!({ "code": " ", "name": "A" }))));
+    }
+}
@@ -1,38 +1,120 @@
-use sqlx::{PgPool, query_as};
+use sqlx::{PgExecutor, PgPool, Postgres, QueryBuilder, query_as};
 use uuid::Uuid;
-use crate::module::loan::model::{LoanContract, LoanTransaction};
+use crate::core::auth::AuthUser;
+use crate::core::scope::{Scope, ScopeTarget, OWNERSHIP_COLUMNS};
+use crate::module::loan::model::{LoanContract, LoanStateHistory, LoanTransaction, LoanTransactionRow, RateIndex, ReportBackfill};
 use crate::module::loan::calculator::calculate_interest_fields;
+use crate::module::loan::convention::{Compounding, DayCount};
+pub use crate::core::timezone::tenant_timezone;
+use crate::module::loan::liquidation::{Liquidation, LiquidationAsset};
+use crate::module::loan::ltv::{LtvAlert, LtvThreshold};
+use crate::module::loan::prepayment::PrepaymentPenalty;
+use crate::module::loan::product::LoanProduct;
+use crate::module::loan::rate::RateIndexValue;
+use crate::module::loan::restructure::TermChange;
+use crate::module::loan::state::LoanState;
+use crate::module::loan::storage::Pledge;
+use crate::module::loan::waterfall::{RepaymentWaterfall, Waterfall};
+use crate::module::loan::schedule::{Installment, RepaymentPlan};
+use sqlx::types::Json;
 use sqlx::types::BigDecimal; // báo cáo
 
-pub async fn list_contracts(pool: &PgPool, tenant_id: Uuid) -> sqlx::Result<Vec<LoanContract>> {
+pub async fn list_contracts<'e>(executor: impl PgExecutor<'e>, tenant_id: Uuid) -> sqlx::Result<Vec<LoanContract>> {
     let contracts = sqlx::query_as!(
         LoanContract,
         r#"
         SELECT
             id, tenant_id, contact_id, contract_number,
-            interest_rate, term_months,
+            interest_rate, rate_index, rate_margin, term_months,
             date_start, date_end,
-            storage_fee_rate, storage_fee,
+            storage_fee_rate, storage_fee, penalty_rate,
+            storage_fee_flat,
+            prepayment_penalty AS "prepayment_penalty: Json<PrepaymentPenalty>",
+            repayment_waterfall AS "repayment_waterfall: Json<Waterfall>",
+            d.collateral_value AS "collateral_value!",
+            day_count AS "day_count: DayCount", compounding AS "compounding: Compounding",
+            d.timezone AS "timezone!",
             current_principal, current_interest,
             accumulated_interest, total_paid_interest, total_settlement_amount,
-            state, created_at, updated_at,
-            created_by, assignee_id, shared_with,
+            state AS "state: LoanState", created_at, updated_at,
+            created_by, assignee_id, shared_with, refinanced_from, product_id,
             0::int8 AS "total_paid_principal!",
-            0::int8 AS "payoff_due!"
+            0::int8 AS "payoff_due!",
+            0::int8 AS "current_penalty!",
+            0::int4 AS "days_past_due!",
+            0::int8 AS "current_storage_fee!",
+            0::int8 AS "credit_balance!",
+            d.schedule AS "schedule!: Json<Vec<Installment>>",
+            d.term_changes AS "term_changes!: Json<Vec<TermChange>>",
+            d.rate_index_values AS "rate_index_values!: Json<Vec<RateIndexValue>>",
+            d.collateral_pledges AS "collateral_pledges!: Json<Vec<Pledge>>"
         FROM loan_contract
+        JOIN loan_contract_derived d USING (tenant_id, id)
         WHERE tenant_id = $1
         ORDER BY contract_number DESC
         "#,
         tenant_id
     )
-    .fetch_all(pool)
+    .fetch_all(executor)
     .await?;
 
     Ok(contracts)
 }
 
-pub async fn get_contract_by_id(
+/// Bảng + cột được dùng trong scope ABAC của resource `loan`
+pub const SCOPE: ScopeTarget = ScopeTarget { table: "loan_contract", columns: OWNERSHIP_COLUMNS };
+
+/// Danh sách hợp đồng user được thấy theo scope của quyền `loan.read`
+pub async fn list_visible_contracts(
+    pool: &PgPool,
+    auth: &AuthUser,
+    scope: &Scope,
+) -> sqlx::Result<Vec<LoanContract>> {
+    let mut qb = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT
+            id, tenant_id, contact_id, contract_number,
+            interest_rate, rate_index, rate_margin, term_months,
+            date_start, date_end,
+            storage_fee_rate, storage_fee, penalty_rate,
+            storage_fee_flat,
+            prepayment_penalty,
+            repayment_waterfall,
+            d.collateral_value,
+            day_count, compounding,
+            d.timezone,
+            current_principal, current_interest,
+            accumulated_interest, total_paid_interest, total_settlement_amount,
+            state, created_at, updated_at,
+            created_by, assignee_id, shared_with, refinanced_from, product_id,
+            0::int8 AS total_paid_principal,
+            d.schedule, d.term_changes, d.rate_index_values, d.collateral_pledges
+        FROM loan_contract
+        JOIN loan_contract_derived d USING (tenant_id, id)
+        WHERE tenant_id = "#,
+    );
+    qb.push_bind(auth.tenant_id);
+    scope.push_filter(&mut qb, &SCOPE, "", auth);
+    qb.push(" ORDER BY contract_number DESC");
+
+    qb.build_query_as::<LoanContract>().fetch_all(pool).await
+}
+
+/// Chi tiết hợp đồng nếu nằm trong scope, ngoài scope → RowNotFound (không lộ sự tồn tại)
+pub async fn get_visible_contract(
     pool: &PgPool,
+    auth: &AuthUser,
+    scope: &Scope,
+    contract_id: Uuid,
+) -> sqlx::Result<LoanContract> {
+    if !scope.is_visible(pool, &SCOPE, auth, contract_id).await? {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    get_contract_by_id(pool, auth.tenant_id, contract_id).await
+}
+
+pub async fn get_contract_by_id<'e>(
+    executor: impl PgExecutor<'e>,
     tenant_id: Uuid,
     contract_id: Uuid,
 ) -> sqlx::Result<LoanContract> {
@@ -41,30 +123,45 @@ pub async fn get_contract_by_id(
         r#"
         SELECT
             id, tenant_id, contact_id, contract_number,
-            interest_rate, term_months,
+            interest_rate, rate_index, rate_margin, term_months,
             date_start, date_end,
-            storage_fee_rate, storage_fee,
+            storage_fee_rate, storage_fee, penalty_rate,
+            storage_fee_flat,
+            prepayment_penalty AS "prepayment_penalty: Json<PrepaymentPenalty>",
+            repayment_waterfall AS "repayment_waterfall: Json<Waterfall>",
+            d.collateral_value AS "collateral_value!",
+            day_count AS "day_count: DayCount", compounding AS "compounding: Compounding",
+            d.timezone AS "timezone!",
             current_principal, current_interest,
             accumulated_interest, total_paid_interest, total_settlement_amount,
-            state, created_at, updated_at,
-            created_by, assignee_id, shared_with,
+            state AS "state: LoanState", created_at, updated_at,
+            created_by, assignee_id, shared_with, refinanced_from, product_id,
             0::int8 AS "total_paid_principal!",
-            0::int8 AS "payoff_due!"
+            0::int8 AS "payoff_due!",
+            0::int8 AS "current_penalty!",
+            0::int4 AS "days_past_due!",
+            0::int8 AS "current_storage_fee!",
+            0::int8 AS "credit_balance!",
+            d.schedule AS "schedule!: Json<Vec<Installment>>",
+            d.term_changes AS "term_changes!: Json<Vec<TermChange>>",
+            d.rate_index_values AS "rate_index_values!: Json<Vec<RateIndexValue>>",
+            d.collateral_pledges AS "collateral_pledges!: Json<Vec<Pledge>>"
         FROM loan_contract
+        JOIN loan_contract_derived d USING (tenant_id, id)
         WHERE tenant_id = $1 AND id = $2
         "#,
         tenant_id,
         contract_id
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
 
     Ok(contract)
 }
 
 /// Lấy giao dịch RAW (không tính trong SQL).
-pub async fn get_transactions_by_contract(
-    pool: &PgPool,
+pub async fn get_transactions_by_contract<'e>(
+    executor: impl PgExecutor<'e>,
     tenant_id: Uuid,
     contract_id: Uuid,
 ) -> Result<Vec<LoanTransaction>, sqlx::Error> {
@@ -86,6 +183,8 @@ pub async fn get_transactions_by_contract(
             0::int8                         AS "principal_balance!",
             0::int8                         AS "principal_applied!",
             0::int8                         AS "interest_applied!",
+            0::int8                         AS "penalty_applied!",
+            0::int8                         AS "fee_applied!",
             lt.created_at                   AS "created_at!",
             lt.updated_at                   AS "updated_at!"
         FROM loan_transaction lt
@@ -96,7 +195,7 @@ pub async fn get_transactions_by_contract(
         tenant_id,
         contract_id
     )
-    .fetch_all(pool)
+    .fetch_all(executor)
     .await?;
 
     Ok(rows)
@@ -139,7 +238,7 @@ pub async fn aggregate_by_month(
         SELECT
             EXTRACT(MONTH FROM lt.date)::double precision AS "group_key?",
             SUM(CASE WHEN lt.transaction_type IN ('disbursement','additional') THEN lt.amount ELSE 0 END)::numeric AS total_issued,
-            SUM(CASE WHEN lt.transaction_type IN ('principal','interest','settlement','liquidation') THEN lt.amount ELSE 0 END)::numeric   AS total_repaid
+            SUM(CASE WHEN lt.transaction_type IN ('principal','interest','penalty','settlement','liquidation') THEN lt.amount ELSE 0 END)::numeric   AS total_repaid
         FROM loan_transaction lt
         WHERE lt.tenant_id = $1
           AND EXTRACT(YEAR FROM lt.date)::int = $2
@@ -167,7 +266,7 @@ pub async fn aggregate_by_day(
         SELECT
             EXTRACT(DAY FROM lt.date)::double precision AS "group_key?",
             SUM(CASE WHEN lt.transaction_type IN ('disbursement','additional') THEN lt.amount ELSE 0 END)::numeric AS total_issued,
-            SUM(CASE WHEN lt.transaction_type IN ('principal','interest','settlement','liquidation') THEN lt.amount ELSE 0 END)::numeric   AS total_repaid
+            SUM(CASE WHEN lt.transaction_type IN ('principal','interest','penalty','settlement','liquidation') THEN lt.amount ELSE 0 END)::numeric   AS total_repaid
         FROM loan_transaction lt
         WHERE lt.tenant_id = $1
           AND EXTRACT(YEAR  FROM lt.date)::int = $2
@@ -195,7 +294,7 @@ pub async fn aggregate_by_year(
         SELECT
             EXTRACT(YEAR FROM lt.date)::double precision AS "group_key?",
             SUM(CASE WHEN lt.transaction_type IN ('disbursement','additional') THEN lt.amount ELSE 0 END)::numeric AS total_issued,
-            SUM(CASE WHEN lt.transaction_type IN ('principal','interest','settlement','liquidation') THEN lt.amount ELSE 0 END)::numeric   AS total_repaid
+            SUM(CASE WHEN lt.transaction_type IN ('principal','interest','penalty','settlement','liquidation') THEN lt.amount ELSE 0 END)::numeric   AS total_repaid
         FROM loan_transaction lt
         WHERE lt.tenant_id = $1
         GROUP BY EXTRACT(YEAR FROM lt.date)
@@ -208,3 +307,321 @@ pub async fn aggregate_by_year(
 
     Ok(rows)
 }
+
+/// Lịch trả nợ đã lưu của hợp đồng (theo thứ tự kỳ) + phương thức trả nợ
+pub async fn get_schedule(
+    pool: &PgPool,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+) -> sqlx::Result<(RepaymentPlan, Vec<Installment>)> {
+    let plan = sqlx::query_scalar!(
+        "SELECT repayment_plan FROM loan_contract WHERE tenant_id = $1 AND id = $2",
+        tenant_id,
+        contract_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let rows = sqlx::query_as!(
+        Installment,
+        r#"
+        SELECT seq, due_date, principal, interest, total_due, remaining_balance
+        FROM loan_schedule
+        WHERE tenant_id = $1 AND contract_id = $2
+        ORDER BY seq
+        "#,
+        tenant_id,
+        contract_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok((RepaymentPlan::parse(&plan), rows))
+}
+
+/// Lịch sử chuyển trạng thái của hợp đồng (cũ → mới)
+pub async fn get_state_history(
+    pool: &PgPool,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+) -> sqlx::Result<Vec<LoanStateHistory>> {
+    sqlx::query_as!(
+        LoanStateHistory,
+        r#"
+        SELECT from_state AS "from_state: LoanState", to_state AS "to_state: LoanState",
+               reason, changed_by, changed_at
+        FROM loan_state_history
+        WHERE tenant_id = $1 AND contract_id = $2
+        ORDER BY changed_at, id
+        "#,
+        tenant_id,
+        contract_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Các lần cơ cấu lãi suất / kỳ hạn của hợp đồng (theo ngày hiệu lực)
+pub async fn get_term_changes(
+    pool: &PgPool,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+) -> sqlx::Result<Vec<TermChange>> {
+    sqlx::query_as!(
+        TermChange,
+        r#"
+        SELECT id, effective_date, previous_interest_rate, interest_rate,
+               previous_term_months, term_months, reason, created_by, created_at
+        FROM loan_term_change
+        WHERE tenant_id = $1 AND contract_id = $2
+        ORDER BY effective_date
+        "#,
+        tenant_id,
+        contract_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Chỉ số lãi suất tham chiếu của tenant kèm các giá trị
+pub async fn list_rate_indexes(pool: &PgPool, tenant_id: Uuid) -> sqlx::Result<Vec<RateIndex>> {
+    sqlx::query_as!(
+        RateIndex,
+        r#"
+        SELECT
+            i.code, i.name, i.created_at,
+            COALESCE((
+                SELECT jsonb_agg(jsonb_build_object('effective_date', v.effective_date, 'rate', v.rate) ORDER BY v.effective_date)
+                FROM loan_rate_index_value v
+                WHERE v.tenant_id = i.tenant_id AND v.index_code = i.code
+            ), '[]'::jsonb) AS "values!: Json<Vec<RateIndexValue>>"
+        FROM loan_rate_index i
+        WHERE i.tenant_id = $1
+        ORDER BY i.code
+        "#,
+        tenant_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Đợt thanh lý tài sản của hợp đồng kèm tiền bán từng tài sản (None = chưa thu giữ)
+pub async fn get_liquidation<'e>(
+    executor: impl PgExecutor<'e>,
+    tenant_id: Uuid,
+    contract_id: Uuid,
+) -> sqlx::Result<Option<Liquidation>> {
+    sqlx::query_as!(
+        Liquidation,
+        r#"
+        SELECT
+            l.id, l.contract_id, l.status, l.reason, l.seized_by, l.seized_at,
+            l.liquidation_date, l.proceeds_total, l.interest_allocated, l.principal_allocated,
+            l.fees_allocated, l.surplus, l.shortfall, l.completed_by, l.completed_at,
+            COALESCE((
+                SELECT jsonb_agg(jsonb_build_object(
+                    'asset_id', a.asset_id,
+                    'value_estimate', a.value_estimate::text,
+                    'proceeds', a.proceeds,
+                    'sold_at', a.sold_at,
+                    'note', a.note,
+                    'recorded_by', a.recorded_by,
+                    'recorded_at', a.recorded_at
+                ) ORDER BY a.asset_id)
+                FROM loan_liquidation_asset a
+                WHERE a.tenant_id = l.tenant_id AND a.liquidation_id = l.id
+            ), '[]'::jsonb) AS "assets!: Json<Vec<LiquidationAsset>>",
+            (
+                SELECT p.id FROM loan_contact_payable p
+                WHERE p.tenant_id = l.tenant_id AND p.liquidation_id = l.id
+            ) AS payable_id
+        FROM loan_liquidation l
+        WHERE l.tenant_id = $1 AND l.contract_id = $2
+        "#,
+        tenant_id,
+        contract_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Ngưỡng LTV của tenant, thấp → cao
+pub async fn list_ltv_thresholds<'e>(
+    executor: impl PgExecutor<'e>,
+    tenant_id: Uuid,
+) -> sqlx::Result<Vec<LtvThreshold>> {
+    sqlx::query_as!(
+        LtvThreshold,
+        "SELECT code, name, ltv, created_at FROM loan_ltv_threshold WHERE tenant_id = $1 ORDER BY ltv",
+        tenant_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Cờ LTV của tenant, LTV cao nhất trước
+pub async fn list_ltv_alerts(pool: &PgPool, tenant_id: Uuid) -> sqlx::Result<Vec<LtvAlert>> {
+    sqlx::query_as!(
+        LtvAlert,
+        r#"
+        SELECT a.contract_id, c.contract_number, c.contact_id,
+               a.threshold_code, t.name AS threshold_name, t.ltv AS threshold_ltv,
+               a.ltv, a.principal, a.collateral_value, a.flagged_at, a.checked_at
+        FROM loan_ltv_alert a
+        JOIN loan_contract c ON c.tenant_id = a.tenant_id AND c.id = a.contract_id
+        JOIN loan_ltv_threshold t ON t.tenant_id = a.tenant_id AND t.code = a.threshold_code
+        WHERE a.tenant_id = $1
+        ORDER BY a.ltv DESC
+        "#,
+        tenant_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Sản phẩm vay của tenant (cả sản phẩm đã ngừng)
+pub async fn list_products(pool: &PgPool, tenant_id: Uuid) -> sqlx::Result<Vec<LoanProduct>> {
+    sqlx::query_as!(
+        LoanProduct,
+        r#"
+        SELECT id, code, name, description,
+            min_rate, max_rate, default_rate,
+            min_term_months, max_term_months, default_term_months,
+            min_principal, max_principal,
+            day_count AS "day_count: DayCount", compounding AS "compounding: Compounding",
+            required_collateral_types, storage_fee_rate, storage_fee_flat, penalty_rate,
+            prepayment_penalty AS "prepayment_penalty: Json<PrepaymentPenalty>",
+            repayment_waterfall, number_prefix, active, created_by, created_at, updated_at
+        FROM loan_product
+        WHERE tenant_id = $1
+        ORDER BY active DESC, code
+        "#,
+        tenant_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_product<'e>(
+    executor: impl PgExecutor<'e>,
+    tenant_id: Uuid,
+    product_id: Uuid,
+) -> sqlx::Result<Option<LoanProduct>> {
+    sqlx::query_as!(
+        LoanProduct,
+        r#"
+        SELECT id, code, name, description,
+            min_rate, max_rate, default_rate,
+            min_term_months, max_term_months, default_term_months,
+            min_principal, max_principal,
+            day_count AS "day_count: DayCount", compounding AS "compounding: Compounding",
+            required_collateral_types, storage_fee_rate, storage_fee_flat, penalty_rate,
+            prepayment_penalty AS "prepayment_penalty: Json<PrepaymentPenalty>",
+            repayment_waterfall, number_prefix, active, created_by, created_at, updated_at
+        FROM loan_product
+        WHERE tenant_id = $1 AND id = $2
+        "#,
+        tenant_id,
+        product_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Các thứ tự phân bổ khoản trả gộp của tenant
+pub async fn list_repayment_waterfalls(pool: &PgPool, tenant_id: Uuid) -> sqlx::Result<Vec<RepaymentWaterfall>> {
+    sqlx::query_as!(
+        RepaymentWaterfall,
+        r#"
+        SELECT code, name, steps AS "steps: Json<Waterfall>", created_at
+        FROM loan_repayment_waterfall
+        WHERE tenant_id = $1
+        ORDER BY code
+        "#,
+        tenant_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Toàn bộ giao dịch của tenant (1 query, dùng cho snapshot loan_report hàng loạt)
+pub async fn get_transactions_by_tenant<'e>(executor: impl PgExecutor<'e>, tenant_id: Uuid) -> sqlx::Result<Vec<LoanTransaction>> {
+    let rows: Vec<LoanTransactionRow> = sqlx::query_as!(
+        LoanTransactionRow,
+        r#"
+        SELECT id, contract_id, tenant_id, contact_id,
+            transaction_type, amount, date, note,
+            days_from_prev, interest_for_period,
+            accumulated_interest, principal_balance,
+            created_at, updated_at
+        FROM loan_transaction
+        WHERE tenant_id = $1
+        ORDER BY contract_id, date, id
+        "#,
+        tenant_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| LoanTransaction {
+            principal_applied: 0,
+            interest_applied: 0,
+            penalty_applied: 0,
+            fee_applied: 0,
+            id: r.id,
+            contract_id: r.contract_id,
+            tenant_id: r.tenant_id,
+            contact_id: r.contact_id,
+            transaction_type: r.transaction_type,
+            amount: r.amount,
+            date: r.date,
+            note: r.note,
+            days_from_prev: r.days_from_prev,
+            interest_for_period: r.interest_for_period,
+            accumulated_interest: r.accumulated_interest,
+            principal_balance: r.principal_balance,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+        })
+        .collect())
+}
+
+/// Trạng thái từng hợp đồng tại thời điểm `as_of` theo lịch sử chuyển trạng thái
+/// (hợp đồng chưa có lịch sử trước `as_of` thì không có trong kết quả)
+pub async fn get_states_as_of<'e>(
+    executor: impl PgExecutor<'e>,
+    tenant_id: Uuid,
+    as_of: chrono::DateTime<chrono::Utc>,
+) -> sqlx::Result<Vec<(Uuid, LoanState)>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT DISTINCT ON (contract_id) contract_id, to_state AS "to_state: LoanState"
+        FROM loan_state_history
+        WHERE tenant_id = $1 AND changed_at <= $2
+        ORDER BY contract_id, changed_at DESC
+        "#,
+        tenant_id,
+        as_of
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.contract_id, r.to_state)).collect())
+}
+
+/// Yêu cầu chạy bù snapshot của tenant, mới nhất trước
+pub async fn list_report_backfills(pool: &PgPool, tenant_id: Uuid) -> sqlx::Result<Vec<ReportBackfill>> {
+    sqlx::query_as!(
+        ReportBackfill,
+        r#"
+        SELECT id, date_from, date_to, next_date, status, error, requested_by, created_at, finished_at
+        FROM loan_report_backfill
+        WHERE tenant_id = $1
+        ORDER BY created_at DESC
+        "#,
+        tenant_id
+    )
+    .fetch_all(pool)
+    .await
+}
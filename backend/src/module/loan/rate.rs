@@ -0,0 +1,180 @@
+//! Lãi suất theo thời gian của hợp đồng.
+//! - Lãi cố định: `interest_rate`, thay đổi qua các lần cơ cấu (`loan_term_change`).
+//! - Lãi thả nổi: giá trị chỉ số tham chiếu của tenant (`loan_rate_index_value`) + biên độ;
+//!   trước ngày chỉ số có giá trị đầu tiên thì dùng lãi cố định.
+//!
+//! Lãi được chốt theo từng đoạn giữa các ngày đổi lãi suất.
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::module::loan::convention::{business_date, InterestConvention};
+use crate::module::loan::restructure::TermChange;
+
+/// 1 giá trị của chỉ số tham chiếu (%/năm)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RateIndexValue {
+    pub effective_date: DateTime<Utc>,
+    pub rate: f64,
+}
+
+/// Lãi thả nổi = chỉ số + biên độ
+#[derive(Debug, Clone)]
+struct Floating {
+    /// (ngày hiệu lực, giá trị chỉ số) – tăng dần theo ngày
+    values: Vec<(NaiveDate, f64)>,
+    margin: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateTimeline {
+    /// (ngày hiệu lực, lãi suất áp dụng TRƯỚC ngày đó) – tăng dần theo ngày
+    steps: Vec<(NaiveDate, f64)>,
+    /// Lãi suất sau lần thay đổi cuối cùng
+    current: f64,
+    floating: Option<Floating>,
+}
+
+impl RateTimeline {
+    pub fn new(current_rate: f64, changes: &[TermChange], tz: Tz) -> Self {
+        let mut steps: Vec<(NaiveDate, f64)> = changes
+            .iter()
+            .map(|c| (business_date(c.effective_date, tz), c.previous_interest_rate))
+            .collect();
+        steps.sort_by_key(|(d, _)| *d);
+        RateTimeline { steps, current: current_rate, floating: None }
+    }
+
+    /// Thả nổi theo chỉ số tham chiếu + biên độ
+    pub fn with_index(mut self, values: &[RateIndexValue], margin: f64, tz: Tz) -> Self {
+        let mut values: Vec<(NaiveDate, f64)> = values
+            .iter()
+            .map(|v| (business_date(v.effective_date, tz), v.rate))
+            .collect();
+        values.sort_by_key(|(d, _)| *d);
+        // nhiều giá trị cùng 1 ngày nghiệp vụ → giữ giá trị sau cùng
+        values.dedup_by(|later, earlier| {
+            let same = later.0 == earlier.0;
+            if same {
+                earlier.1 = later.1;
+            }
+            same
+        });
+        self.floating = Some(Floating { values, margin });
+        self
+    }
+
+    /// %/năm áp dụng cho ngày `d`
+    pub fn rate_at(&self, d: NaiveDate) -> f64 {
+        if let Some(f) = &self.floating {
+            if let Some((_, index)) = f.values.iter().rev().find(|(effective, _)| *effective <= d) {
+                return index + f.margin;
+            }
+        }
+        self.steps
+            .iter()
+            .find(|(effective, _)| d < *effective)
+            .map(|(_, rate)| *rate)
+            .unwrap_or(self.current)
+    }
+
+    /// Ngày đổi lãi suất đầu tiên trong (after, before)
+    fn next_change(&self, after: NaiveDate, before: NaiveDate) -> Option<NaiveDate> {
+        let index_dates = self.floating.iter().flat_map(|f| f.values.iter().map(|(d, _)| *d));
+        self.steps
+            .iter()
+            .map(|(d, _)| *d)
+            .chain(index_dates)
+            .filter(|d| *d > after && *d < before)
+            .min()
+    }
+
+    /// ✅ Lãi phát sinh trong [from, to), chốt kỳ tính lãi tại mỗi ngày đổi lãi suất.
+    /// Khi ghép lãi, lãi của đoạn trước được cộng vào cơ sở của đoạn sau.
+    pub fn accrue(
+        &self,
+        convention: &InterestConvention,
+        principal: f64,
+        unpaid_interest: f64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> f64 {
+        let mut total = 0.0;
+        let mut cur = from;
+        while cur < to {
+            let next = self.next_change(cur, to).unwrap_or(to);
+            total += convention.accrue(principal, unpaid_interest + total, self.rate_at(cur), cur, next);
+            cur = next;
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn d(m: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, m, day).unwrap()
+    }
+
+    fn at(m: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, m, day, 0, 0, 0).unwrap()
+    }
+
+    fn change(m: u32, day: u32, from_rate: f64, to_rate: f64) -> TermChange {
+        TermChange {
+            id: Uuid::nil(),
+            effective_date: at(m, day),
+            previous_interest_rate: from_rate,
+            interest_rate: to_rate,
+            previous_term_months: 6,
+            term_months: 12,
+            reason: None,
+            created_by: Uuid::nil(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn rate_switches_on_effective_date() {
+        let timeline = RateTimeline::new(6.0, &[change(4, 1, 12.0, 6.0)], chrono_tz::UTC);
+        assert_eq!(timeline.rate_at(d(3, 31)), 12.0);
+        assert_eq!(timeline.rate_at(d(4, 1)), 6.0);
+
+        // 1tr, ACT/365: 31 ngày × 36.5% + 30 ngày × 18.25% (lãi đơn)
+        let timeline = RateTimeline::new(18.25, &[change(4, 1, 36.5, 18.25)], chrono_tz::UTC);
+        let interest = timeline.accrue(&InterestConvention::default(), 1_000_000.0, 0.0, d(3, 1), d(5, 1));
+        assert!((interest - (31_000.0 + 15_000.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn no_changes_uses_contract_rate() {
+        let timeline = RateTimeline::new(12.0, &[], chrono_tz::UTC);
+        let conv = InterestConvention::default();
+        assert_eq!(
+            timeline.accrue(&conv, 1_000.0, 0.0, d(1, 1), d(7, 1)),
+            conv.accrue(1_000.0, 0.0, 12.0, d(1, 1), d(7, 1))
+        );
+    }
+
+    #[test]
+    fn floating_index_plus_margin() {
+        let values = [
+            RateIndexValue { effective_date: at(3, 1), rate: 30.0 },
+            RateIndexValue { effective_date: at(4, 1), rate: 15.75 },
+        ];
+        let timeline = RateTimeline::new(36.5, &[], chrono_tz::UTC).with_index(&values, 2.5, chrono_tz::UTC);
+        // trước giá trị đầu tiên → lãi cố định
+        assert_eq!(timeline.rate_at(d(2, 28)), 36.5);
+        assert_eq!(timeline.rate_at(d(3, 15)), 32.5);
+        assert_eq!(timeline.rate_at(d(4, 1)), 18.25);
+
+        // 1tr: 28 ngày × 36.5% + 31 ngày × 32.5% + 30 ngày × 18.25%
+        let interest = timeline.accrue(&InterestConvention::default(), 1_000_000.0, 0.0, d(2, 1), d(5, 1));
+        let expected = 28_000.0 + 1_000_000.0 * 0.325 * 31.0 / 365.0 + 15_000.0;
+        assert!((interest - expected).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,20 @@
+//! Cơ cấu lại hợp đồng: thay đổi lãi suất / kỳ hạn có hiệu lực từ 1 ngày.
+//! Lãi trước ngày hiệu lực tính theo lãi suất cũ (`previous_interest_rate`),
+//! sau lần thay đổi cuối cùng tính theo lãi suất hiện tại của hợp đồng (xem `rate`).
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 1 lần cơ cấu (bảng `loan_term_change`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TermChange {
+    pub id: Uuid,
+    pub effective_date: DateTime<Utc>,
+    pub previous_interest_rate: f64,
+    pub interest_rate: f64,
+    pub previous_term_months: i32,
+    pub term_months: i32,
+    pub reason: Option<String>,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+}
@@ -3,6 +3,7 @@ use std::sync::Arc;
 
 use axum::routing::delete; // 👈 để dùng delete()
 use crate::core::{state::AppState, auth::jwt_auth};
+use crate::core::iam::RequirePermission;
 use crate::module::loan::handler;
 
 pub fn routes() -> Router<Arc<AppState>> {
@@ -12,33 +13,64 @@ pub fn routes() -> Router<Arc<AppState>> {
         .nest(
             "/loan",
             Router::new()
-                .route("/create", post(handler::create_contract))
-                .route("/list", get(handler::list_contracts))
-                .route("/:id", get(handler::get_contract_by_id))       // lấy chi tiết
-                .route("/:id/update", post(handler::update_contract))  // cập nhật
-                .route("/:id", delete(handler::delete_contract))       // ✅ Xoá hợp đồng
-                .route("/stats", get(handler::get_loan_stats))         //bao cao
-                       .route("/monthly-interest", get(handler::get_monthly_interest_income)) // lãi tháng
-                       .route("/dashboard-stats", get(handler::get_dashboard_stats)) // 6 ô dashboard
-                       .route("/portfolio-quality", get(handler::get_loan_portfolio_quality)) // chất lượng danh mục
-                       .route("/contract-status", get(handler::get_contract_status)) // trạng thái hợp đồng
-                       .route("/top-contracts", get(handler::get_top_contracts)) // top hợp đồng có lợi nhuận cao nhất
-                       .route("/activity-report", get(handler::get_loan_activity_report)) // báo cáo hoạt động cho vay
-                       .route("/recent-activities", get(handler::get_recent_activities)) // hoạt động gần đây
+                .route("/create", post(handler::create_contract).route_layer(RequirePermission::new("loan", "create")))
+                .route("/list", get(handler::list_contracts).route_layer(RequirePermission::new("loan", "read")))
+                .route("/:id", get(handler::get_contract_by_id).route_layer(RequirePermission::new("loan", "read")))       // lấy chi tiết
+                .route("/:id/update", post(handler::update_contract).route_layer(RequirePermission::new("loan", "update")))  // cập nhật
+                .route("/:id", delete(handler::delete_contract).route_layer(RequirePermission::new("loan", "delete")))       // ✅ Xoá hợp đồng
+                .route("/:id/schedule", get(handler::get_schedule).route_layer(RequirePermission::new("loan", "read")))  // lịch trả nợ
+                .route("/:id/settlement-quote", post(handler::settlement_quote).route_layer(RequirePermission::new("loan", "read")))  // báo giá tất toán
+                .route("/:id/activate", post(handler::activate_contract).route_layer(RequirePermission::new("loan", "update")))  // nháp → hoạt động
+                .route("/:id/write-off", post(handler::write_off_contract).route_layer(RequirePermission::new("loan", "write_off")))  // xoá nợ
+                .route("/:id/state-history", get(handler::get_state_history).route_layer(RequirePermission::new("loan", "read")))  // lịch sử trạng thái
+                .route("/:id/restructure", post(handler::restructure_contract).route_layer(RequirePermission::new("loan", "update")))  // cơ cấu lãi suất / kỳ hạn
+                .route("/:id/term-changes", get(handler::get_term_changes).route_layer(RequirePermission::new("loan", "read")))  // lịch sử cơ cấu
+                .route("/:id/refinance", post(handler::refinance_contract).route_layer(RequirePermission::new("loan", "refinance")))  // tái cấp vốn
+                .route("/rate-index", get(handler::list_rate_indexes).route_layer(RequirePermission::new("loan", "read")))  // chỉ số lãi suất tham chiếu
+                .route("/rate-index", post(handler::create_rate_index).route_layer(RequirePermission::new("loan", "manage_rates")))
+                .route("/rate-index/:code/values", post(handler::set_rate_index_value).route_layer(RequirePermission::new("loan", "manage_rates")))  // giá trị chỉ số theo ngày
+                .route("/ltv-thresholds", get(handler::list_ltv_thresholds).route_layer(RequirePermission::new("loan", "read")))  // ngưỡng LTV của tenant
+                .route("/ltv-thresholds", post(handler::set_ltv_threshold).route_layer(RequirePermission::new("loan", "manage_ltv")))
+                .route("/ltv-thresholds/:code", delete(handler::delete_ltv_threshold).route_layer(RequirePermission::new("loan", "manage_ltv")))
+                .route("/repayment-waterfalls", get(handler::list_repayment_waterfalls).route_layer(RequirePermission::new("loan", "read")))  // thứ tự phân bổ khoản trả gộp
+                .route("/repayment-waterfalls", post(handler::set_repayment_waterfall).route_layer(RequirePermission::new("loan", "manage_products")))
+                .route("/repayment-waterfalls/:code", delete(handler::delete_repayment_waterfall).route_layer(RequirePermission::new("loan", "manage_products")))
+                .route("/products", get(handler::list_products).route_layer(RequirePermission::new("loan", "read")))  // sản phẩm vay
+                .route("/products", post(handler::create_product).route_layer(RequirePermission::new("loan", "manage_products")))
+                .route("/products/:product_id", get(handler::get_product).route_layer(RequirePermission::new("loan", "read")))
+                .route("/products/:product_id", post(handler::update_product).route_layer(RequirePermission::new("loan", "manage_products")))
+                .route("/stats", get(handler::get_loan_stats).route_layer(RequirePermission::new("loan", "read")))         //bao cao
+                       .route("/monthly-interest", get(handler::get_monthly_interest_income).route_layer(RequirePermission::new("loan", "read"))) // lãi tháng
+                       .route("/dashboard-stats", get(handler::get_dashboard_stats).route_layer(RequirePermission::new("loan", "read"))) // 6 ô dashboard
+                       .route("/portfolio-quality", get(handler::get_loan_portfolio_quality).route_layer(RequirePermission::new("loan", "read"))) // chất lượng danh mục
+                       .route("/ltv-alerts", get(handler::get_ltv_alerts).route_layer(RequirePermission::new("loan", "read"))) // hợp đồng vượt ngưỡng LTV
+                       .route("/contract-status", get(handler::get_contract_status).route_layer(RequirePermission::new("loan", "read"))) // trạng thái hợp đồng
+                       .route("/top-contracts", get(handler::get_top_contracts).route_layer(RequirePermission::new("loan", "read"))) // top hợp đồng có lợi nhuận cao nhất
+                       .route("/activity-report", get(handler::get_loan_activity_report).route_layer(RequirePermission::new("loan", "read"))) // báo cáo hoạt động cho vay
+                       .route("/recent-activities", get(handler::get_recent_activities).route_layer(RequirePermission::new("loan", "read"))) // hoạt động gần đây
                 .nest("/report", Router::new()
-                    .route("/", get(handler::get_loan_report)) // ✅ API load báo cáo pivot
-                    .route("/pivot-now", post(handler::pivot_now_all_contracts)) // ✅ Tính tất cả
-                    .route("/:id/pivot-now", post(handler::pivot_now_contract))  // ✅ Tính 1 hợp đồng
+                    .route("/", get(handler::get_loan_report).route_layer(RequirePermission::new("loan", "read"))) // ✅ API load báo cáo pivot
+                    .route("/pivot-now", post(handler::pivot_now_all_contracts).route_layer(RequirePermission::new("loan", "update"))) // ✅ Tính tất cả
+                    .route("/:id/pivot-now", post(handler::pivot_now_contract).route_layer(RequirePermission::new("loan", "update")))  // ✅ Tính 1 hợp đồng
+                    .route("/backfill", post(handler::request_report_backfill).route_layer(RequirePermission::new("loan", "update"))) // ✅ Chạy bù snapshot theo khoảng ngày
+                    .route("/backfill", get(handler::list_report_backfills).route_layer(RequirePermission::new("loan", "read")))
                 )
                 // ✅ Các route tài sản thế chấp theo hợp đồng
-                .route("/:id/collaterals", get(handler::get_collaterals_by_contract))
-                .route("/:id/collaterals/add", post(handler::add_collateral_to_contract))
-                .route("/:id/collaterals/release", post(handler::release_collateral_from_contract))
+                .route("/:id/collaterals", get(handler::get_collaterals_by_contract).route_layer(RequirePermission::new("loan", "read")))
+                .route("/:id/collaterals/add", post(handler::add_collateral_to_contract).route_layer(RequirePermission::new("loan", "update")))
+                .route("/:id/collaterals/release", post(handler::release_collateral_from_contract).route_layer(RequirePermission::new("loan", "update")))
+                // ✅ Thanh lý tài sản: thu giữ → tiền bán từng tài sản → hoàn tất
+                .route("/:id/collaterals/seize", post(handler::seize_collateral).route_layer(RequirePermission::new("loan", "liquidate")))
+                .route("/:id/collaterals/proceeds", post(handler::record_liquidation_proceeds).route_layer(RequirePermission::new("loan", "liquidate")))
+                .route("/:id/collaterals/liquidate", post(handler::complete_liquidation).route_layer(RequirePermission::new("loan", "liquidate")))
+                .route("/:id/collaterals/liquidation", get(handler::get_liquidation).route_layer(RequirePermission::new("loan", "read")))
 
                 // ✅ Tạo/gộp trực tiếp tài sản
-                .route("/collateral", post(handler::create_collateral))
-                .route("/collateral", get(handler::list_collateral))
-                .route("/collateral/:asset_id", post(handler::update_collateral))
+                .route("/collateral", post(handler::create_collateral).route_layer(RequirePermission::new("loan", "update")))
+                .route("/collateral", get(handler::list_collateral).route_layer(RequirePermission::new("loan", "read")))
+                .route("/collateral/:asset_id", post(handler::update_collateral).route_layer(RequirePermission::new("loan", "update")))
+                .route("/collateral/:asset_id/valuations", get(handler::list_valuations).route_layer(RequirePermission::new("loan", "read")))  // lịch sử định giá
+                .route("/collateral/:asset_id/valuations", post(handler::add_valuation).route_layer(RequirePermission::new("loan", "update")))  // định giá lại
                 .layer(middleware::from_fn(jwt_auth)),           // Tất cả require JWT
         )
 }
@@ -0,0 +1,258 @@
+//! Lịch trả nợ dự kiến (amortization) theo điều khoản hợp đồng.
+//! Độc lập với `calculator` (tính trên giao dịch thực tế): lịch chỉ là kế hoạch.
+//! Lãi mỗi kỳ tính trên dư nợ đầu kỳ theo quy ước lãi của hợp đồng (cùng quy ước với `calculator`).
+use chrono::{Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::module::loan::convention::InterestConvention;
+
+/// Phương thức trả nợ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepaymentPlan {
+    /// Gốc chia đều mỗi kỳ, lãi tính trên dư nợ giảm dần
+    #[default]
+    EqualPrincipal,
+    /// Tổng gốc + lãi mỗi kỳ bằng nhau (niên kim)
+    Annuity,
+    /// Chỉ trả lãi hằng kỳ, gốc trả 1 lần ở kỳ cuối
+    InterestOnlyBullet,
+    /// Gốc từng kỳ do người dùng nhập
+    Custom,
+}
+
+impl RepaymentPlan {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RepaymentPlan::EqualPrincipal => "equal_principal",
+            RepaymentPlan::Annuity => "annuity",
+            RepaymentPlan::InterestOnlyBullet => "interest_only_bullet",
+            RepaymentPlan::Custom => "custom",
+        }
+    }
+
+    /// Giá trị lưu trong DB, không nhận ra → mặc định
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "annuity" => RepaymentPlan::Annuity,
+            "interest_only_bullet" => RepaymentPlan::InterestOnlyBullet,
+            "custom" => RepaymentPlan::Custom,
+            _ => RepaymentPlan::EqualPrincipal,
+        }
+    }
+}
+
+/// 1 kỳ gốc nhập tay cho plan `custom`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomInstallment {
+    pub due_date: NaiveDate,
+    pub principal: i64,
+}
+
+/// 1 kỳ trong lịch trả nợ
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Installment {
+    pub seq: i32,
+    pub due_date: NaiveDate,
+    pub principal: i64,
+    pub interest: i64,
+    pub total_due: i64,
+    /// Dư nợ gốc sau kỳ này
+    pub remaining_balance: i64,
+}
+
+/// Lỗi dựng lịch → key i18n `error.loan.*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleError {
+    InvalidTerm,
+    InvalidPrincipal,
+    CustomMismatch,
+}
+
+impl ScheduleError {
+    pub fn i18n_key(&self) -> &'static str {
+        match self {
+            ScheduleError::InvalidTerm => "error.loan.schedule_invalid_term",
+            ScheduleError::InvalidPrincipal => "error.loan.invalid_amount",
+            ScheduleError::CustomMismatch => "error.loan.schedule_custom_mismatch",
+        }
+    }
+}
+
+/// Điều khoản đầu vào của lịch
+#[derive(Debug, Clone)]
+pub struct ScheduleTerms<'a> {
+    pub plan: RepaymentPlan,
+    pub principal: i64,
+    /// %/năm
+    pub interest_rate: f64,
+    pub term_months: i32,
+    pub start: NaiveDate,
+    pub custom: &'a [CustomInstallment],
+    pub convention: InterestConvention,
+}
+
+/// ✅ Sinh lịch trả nợ. Làm tròn tới đơn vị tiền, kỳ cuối gánh phần lệch để dư nợ về 0.
+pub fn generate(terms: &ScheduleTerms) -> Result<Vec<Installment>, ScheduleError> {
+    if terms.principal <= 0 {
+        return Err(ScheduleError::InvalidPrincipal);
+    }
+
+    // (ngày đến hạn, gốc dự kiến) – annuity tính gốc động theo lãi từng kỳ nên để None
+    let plan: Vec<(NaiveDate, Option<i64>)> = match terms.plan {
+        RepaymentPlan::Custom => {
+            if terms.custom.is_empty() || terms.custom.iter().any(|c| c.principal < 0 || c.due_date <= terms.start) {
+                return Err(ScheduleError::CustomMismatch);
+            }
+            if terms.custom.iter().map(|c| c.principal).sum::<i64>() != terms.principal {
+                return Err(ScheduleError::CustomMismatch);
+            }
+            let mut custom = terms.custom.to_vec();
+            custom.sort_by_key(|c| c.due_date);
+            custom.into_iter().map(|c| (c.due_date, Some(c.principal))).collect()
+        }
+        _ => {
+            if terms.term_months <= 0 {
+                return Err(ScheduleError::InvalidTerm);
+            }
+            let n = terms.term_months as i64;
+            (1..=n)
+                .map(|k| {
+                    let due = terms
+                        .start
+                        .checked_add_months(Months::new(k as u32))
+                        .ok_or(ScheduleError::InvalidTerm)?;
+                    let principal = match terms.plan {
+                        RepaymentPlan::EqualPrincipal => Some(terms.principal / n),
+                        RepaymentPlan::InterestOnlyBullet => Some(if k == n { terms.principal } else { 0 }),
+                        _ => None,
+                    };
+                    Ok((due, principal))
+                })
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let annual = terms.interest_rate / 100.0;
+    let annuity_payment = annuity_payment(terms.principal as f64, annual / 12.0, plan.len());
+
+    let mut balance = terms.principal;
+    let mut prev = terms.start;
+    let last = plan.len() - 1;
+    let mut out = Vec::with_capacity(plan.len());
+
+    for (i, (due, planned)) in plan.into_iter().enumerate() {
+        let interest = terms
+            .convention
+            .accrue(balance as f64, 0.0, terms.interest_rate, prev, due)
+            .round() as i64;
+
+        let principal = if i == last {
+            balance
+        } else {
+            match planned {
+                Some(p) => p,
+                None => (annuity_payment.round() as i64 - interest).max(0),
+            }
+            .min(balance)
+        };
+        balance -= principal;
+
+        out.push(Installment {
+            seq: i as i32 + 1,
+            due_date: due,
+            principal,
+            interest,
+            total_due: principal + interest,
+            remaining_balance: balance,
+        });
+        prev = due;
+    }
+
+    Ok(out)
+}
+
+/// Khoản trả đều mỗi kỳ: P·r / (1 − (1+r)^−n)
+fn annuity_payment(principal: f64, rate_per_period: f64, periods: usize) -> f64 {
+    if periods == 0 {
+        return 0.0;
+    }
+    if rate_per_period <= 0.0 {
+        return principal / periods as f64;
+    }
+    principal * rate_per_period / (1.0 - (1.0 + rate_per_period).powi(-(periods as i32)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(plan: RepaymentPlan) -> ScheduleTerms<'static> {
+        ScheduleTerms {
+            plan,
+            principal: 12_000_000,
+            interest_rate: 12.0,
+            term_months: 12,
+            start: NaiveDate::from_ymd_opt(2025, 1, 31).unwrap(),
+            custom: &[],
+            convention: InterestConvention::default(),
+        }
+    }
+
+    fn principal_sum(rows: &[Installment]) -> i64 {
+        rows.iter().map(|r| r.principal).sum()
+    }
+
+    #[test]
+    fn equal_principal_amortizes_to_zero() {
+        let rows = generate(&terms(RepaymentPlan::EqualPrincipal)).unwrap();
+        assert_eq!(rows.len(), 12);
+        assert!(rows.iter().all(|r| r.principal == 1_000_000));
+        assert_eq!(principal_sum(&rows), 12_000_000);
+        assert_eq!(rows.last().unwrap().remaining_balance, 0);
+        // ngày cuối tháng được kẹp về ngày hợp lệ
+        assert_eq!(rows[0].due_date, NaiveDate::from_ymd_opt(2025, 2, 28).unwrap());
+        // lãi giảm dần theo dư nợ
+        assert!(rows[11].interest < rows[0].interest);
+    }
+
+    #[test]
+    fn annuity_has_near_equal_totals() {
+        let rows = generate(&terms(RepaymentPlan::Annuity)).unwrap();
+        assert_eq!(principal_sum(&rows), 12_000_000);
+        assert_eq!(rows.last().unwrap().remaining_balance, 0);
+        let min = rows.iter().map(|r| r.total_due).min().unwrap();
+        let max = rows.iter().map(|r| r.total_due).max().unwrap();
+        // lãi theo số ngày thực tế → chênh lệch nhỏ giữa các kỳ
+        assert!(max - min < 15_000, "min={min} max={max}");
+    }
+
+    #[test]
+    fn interest_only_then_bullet() {
+        let rows = generate(&terms(RepaymentPlan::InterestOnlyBullet)).unwrap();
+        assert!(rows[..11].iter().all(|r| r.principal == 0 && r.remaining_balance == 12_000_000));
+        assert_eq!(rows[11].principal, 12_000_000);
+    }
+
+    #[test]
+    fn custom_must_cover_principal() {
+        let d = |m| NaiveDate::from_ymd_opt(2025, m, 15).unwrap();
+        let custom = [
+            CustomInstallment { due_date: d(6), principal: 7_000_000 },
+            CustomInstallment { due_date: d(3), principal: 5_000_000 },
+        ];
+        let rows = generate(&ScheduleTerms { custom: &custom, ..terms(RepaymentPlan::Custom) }).unwrap();
+        assert_eq!(rows[0].due_date, d(3));
+        assert_eq!(rows[0].remaining_balance, 7_000_000);
+
+        let short = [CustomInstallment { due_date: d(6), principal: 1 }];
+        assert_eq!(
+            generate(&ScheduleTerms { custom: &short, ..terms(RepaymentPlan::Custom) }),
+            Err(ScheduleError::CustomMismatch)
+        );
+        assert_eq!(
+            generate(&ScheduleTerms { term_months: 0, ..terms(RepaymentPlan::Annuity) }),
+            Err(ScheduleError::InvalidTerm)
+        );
+    }
+}
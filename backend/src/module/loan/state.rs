@@ -0,0 +1,112 @@
+//! Trạng thái hợp đồng vay và các chuyển trạng thái hợp lệ:
+//! draft → active ⇄ overdue → settled / liquidated / written_off.
+//! DB chỉ lưu mã trạng thái; nhãn hiển thị lấy qua i18n `loan.state.*`.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LoanState {
+    /// Nháp, chưa giải ngân
+    #[default]
+    Draft,
+    /// Đang hoạt động
+    Active,
+    /// Có kỳ quá hạn theo lịch trả nợ
+    Overdue,
+    /// Đã tất toán
+    Settled,
+    /// Đã thanh lý tài sản
+    Liquidated,
+    /// Đã xoá nợ
+    WrittenOff,
+}
+
+impl LoanState {
+    pub const ALL: [LoanState; 6] = [
+        LoanState::Draft,
+        LoanState::Active,
+        LoanState::Overdue,
+        LoanState::Settled,
+        LoanState::Liquidated,
+        LoanState::WrittenOff,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoanState::Draft => "draft",
+            LoanState::Active => "active",
+            LoanState::Overdue => "overdue",
+            LoanState::Settled => "settled",
+            LoanState::Liquidated => "liquidated",
+            LoanState::WrittenOff => "written_off",
+        }
+    }
+
+    /// Key i18n của nhãn hiển thị
+    pub fn i18n_key(&self) -> &'static str {
+        match self {
+            LoanState::Draft => "loan.state.draft",
+            LoanState::Active => "loan.state.active",
+            LoanState::Overdue => "loan.state.overdue",
+            LoanState::Settled => "loan.state.settled",
+            LoanState::Liquidated => "loan.state.liquidated",
+            LoanState::WrittenOff => "loan.state.writtenOff",
+        }
+    }
+
+    /// ✅ Bảng chuyển trạng thái
+    pub fn can_transition_to(&self, next: LoanState) -> bool {
+        use LoanState::*;
+        matches!(
+            (self, next),
+            (Draft, Active)
+                | (Active, Overdue)
+                | (Overdue, Active)
+                | (Active | Overdue, Settled | Liquidated | WrittenOff)
+        )
+    }
+
+    /// Trạng thái suy ra từ sổ giao dịch: giải ngân → active, tất toán / thanh lý → đóng.
+    /// Thanh lý được ưu tiên nếu có cả 2.
+    pub fn from_ledger<'a>(transaction_types: impl IntoIterator<Item = &'a str>) -> LoanState {
+        let mut state = LoanState::Draft;
+        for t in transaction_types {
+            match t {
+                "liquidation" => return LoanState::Liquidated,
+                "settlement" => state = LoanState::Settled,
+                "disbursement" | "additional" if state == LoanState::Draft => state = LoanState::Active,
+                _ => {}
+            }
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions() {
+        use LoanState::*;
+        assert!(Draft.can_transition_to(Active));
+        assert!(Active.can_transition_to(Overdue) && Overdue.can_transition_to(Active));
+        assert!(Overdue.can_transition_to(WrittenOff));
+        assert!(!Draft.can_transition_to(Settled));
+        assert!(!Settled.can_transition_to(Active));
+        assert!(!WrittenOff.can_transition_to(WrittenOff));
+        // trạng thái đã đóng không chuyển tiếp được nữa
+        for closed in [Settled, Liquidated, WrittenOff] {
+            assert!(LoanState::ALL.iter().all(|n| !closed.can_transition_to(*n)));
+        }
+    }
+
+    #[test]
+    fn ledger_state() {
+        assert_eq!(LoanState::from_ledger([]), LoanState::Draft);
+        assert_eq!(LoanState::from_ledger(["disbursement", "interest"]), LoanState::Active);
+        assert_eq!(LoanState::from_ledger(["disbursement", "settlement"]), LoanState::Settled);
+        assert_eq!(LoanState::from_ledger(["disbursement", "liquidation", "settlement"]), LoanState::Liquidated);
+    }
+}
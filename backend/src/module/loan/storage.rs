@@ -0,0 +1,139 @@
+//! Phí lưu kho (hợp đồng cầm đồ) phát sinh theo ngày:
+//! phí cố định/ngày + `storage_fee_rate`% giá trị các tài sản đang cầm cố trong ngày đó.
+//! Giá trị 1 ngày = lần định giá gần nhất tới ngày đó (`collateral_valuation`), chưa định giá → `pledge_value`.
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::module::loan::convention::business_date;
+
+/// 1 lần định giá tài sản
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Valuation {
+    pub date: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// 1 tài sản bảo đảm cho hợp đồng trong [pledged_at, released_at) (`loan_collateral`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pledge {
+    pub pledged_at: DateTime<Utc>,
+    pub released_at: Option<DateTime<Utc>>,
+    pub pledge_value: Option<f64>,
+    /// Sắp theo ngày định giá
+    #[serde(default)]
+    pub valuations: Vec<Valuation>,
+}
+
+impl Pledge {
+    /// Σ giá trị × số ngày cầm cố trong [from, to)
+    fn value_days(&self, from: NaiveDate, to: NaiveDate, tz: Tz) -> f64 {
+        let start = business_date(self.pledged_at, tz).max(from);
+        let end = self.released_at.map_or(to, |r| business_date(r, tz).min(to));
+        if end <= start {
+            return 0.0;
+        }
+
+        let mut value = self.value_on(start, tz);
+        let mut cur = start;
+        let mut total = 0.0;
+        for v in &self.valuations {
+            let d = business_date(v.date, tz);
+            if d <= start {
+                continue;
+            }
+            if d >= end {
+                break;
+            }
+            total += value * (d - cur).num_days() as f64;
+            cur = d;
+            value = v.value;
+        }
+        total + value * (end - cur).num_days() as f64
+    }
+
+    /// Giá trị áp dụng cho ngày `d`
+    fn value_on(&self, d: NaiveDate, tz: Tz) -> f64 {
+        self.valuations
+            .iter()
+            .rev()
+            .find(|v| business_date(v.date, tz) <= d)
+            .map(|v| v.value)
+            .or(self.pledge_value)
+            .or_else(|| self.valuations.first().map(|v| v.value))
+            .unwrap_or(0.0)
+    }
+}
+
+/// Cách tính phí lưu kho của 1 hợp đồng
+#[derive(Debug, Clone, Copy)]
+pub struct StorageFee<'a> {
+    /// % giá trị tài sản / ngày
+    pub rate: f64,
+    /// Phí cố định / ngày
+    pub flat: i64,
+    pub pledges: &'a [Pledge],
+    pub tz: Tz,
+}
+
+impl StorageFee<'_> {
+    /// Phí lưu kho phát sinh trong [from, to)
+    pub fn accrue(&self, from: NaiveDate, to: NaiveDate) -> f64 {
+        if to <= from {
+            return 0.0;
+        }
+        let flat = self.flat as f64 * (to - from).num_days() as f64;
+        if self.rate == 0.0 {
+            return flat;
+        }
+        let value_days: f64 = self.pledges.iter().map(|p| p.value_days(from, to, self.tz)).sum();
+        flat + value_days * self.rate / 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const TZ: Tz = chrono_tz::Asia::Bangkok;
+
+    fn at(y: i32, m: u32, d: u32) -> DateTime<Utc> {
+        TZ.with_ymd_and_hms(y, m, d, 10, 0, 0).unwrap().with_timezone(&Utc)
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn revaluation_only_changes_later_days() {
+        let pledges = [Pledge {
+            pledged_at: at(2025, 1, 1),
+            released_at: None,
+            pledge_value: Some(1_000_000.0),
+            valuations: vec![
+                Valuation { date: at(2025, 1, 1), value: 1_000_000.0 },
+                Valuation { date: at(2025, 1, 11), value: 2_000_000.0 },
+            ],
+        }];
+        let fee = StorageFee { rate: 0.1, flat: 500, pledges: &pledges, tz: TZ };
+
+        // 10 ngày × 1.000 + 10 ngày × 2.000 + 20 ngày × 500
+        assert_eq!(fee.accrue(day(2025, 1, 1), day(2025, 1, 21)), 40_000.0);
+        // phần đã phát sinh trước lần định giá lại không đổi
+        assert_eq!(fee.accrue(day(2025, 1, 1), day(2025, 1, 11)), 15_000.0);
+    }
+
+    #[test]
+    fn only_days_under_pledge_are_charged() {
+        let pledges = [
+            Pledge { pledged_at: at(2025, 1, 1), released_at: Some(at(2025, 1, 6)), pledge_value: Some(1_000_000.0), valuations: vec![] },
+            Pledge { pledged_at: at(2025, 1, 4), released_at: None, pledge_value: None, valuations: vec![Valuation { date: at(2025, 1, 4), value: 3_000_000.0 }] },
+        ];
+        let fee = StorageFee { rate: 0.1, flat: 0, pledges: &pledges, tz: TZ };
+
+        // tài sản 1: 5 ngày × 1.000, tài sản 2: 7 ngày × 3.000
+        assert_eq!(fee.accrue(day(2025, 1, 1), day(2025, 1, 11)), 26_000.0);
+    }
+}
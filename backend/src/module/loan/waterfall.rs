@@ -0,0 +1,133 @@
+//! Thứ tự phân bổ khoản trả nợ gộp (giao dịch `repayment`) vào các nghĩa vụ của hợp đồng.
+//! Tenant cấu hình theo sản phẩm vay (bảng `loan_repayment_waterfall`); hợp đồng lưu bản sao
+//! lúc tạo nên sửa cấu hình về sau không làm phân bổ lại các khoản đã thu.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+
+/// Nhóm nghĩa vụ nhận tiền
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Bucket {
+    /// Lãi phạt quá hạn
+    Penalty,
+    /// Phí lưu kho
+    Fees,
+    Interest,
+    Principal,
+}
+
+impl Bucket {
+    pub const ALL: [Bucket; 4] = [Bucket::Penalty, Bucket::Fees, Bucket::Interest, Bucket::Principal];
+}
+
+/// Thứ tự phân bổ, lưu ở `loan_contract.repayment_waterfall` (JSONB, vd `["penalty","fees","interest","principal"]`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Waterfall(Vec<Bucket>);
+
+impl Default for Waterfall {
+    /// Phạt → phí → lãi → gốc
+    fn default() -> Self {
+        Waterfall(Bucket::ALL.to_vec())
+    }
+}
+
+impl Waterfall {
+    /// Mỗi nhóm phải xuất hiện đúng 1 lần
+    pub fn new(steps: Vec<Bucket>) -> Option<Self> {
+        let complete = steps.len() == Bucket::ALL.len() && Bucket::ALL.iter().all(|b| steps.contains(b));
+        complete.then_some(Waterfall(steps))
+    }
+
+    /// ✅ Phân bổ `amount` lần lượt theo thứ tự, mỗi nhóm không vượt quá số đang nợ.
+    /// Phần còn thừa sau khi trả hết các nhóm nằm ở `excess`.
+    pub fn apply(&self, amount: i64, due: &Split) -> Applied {
+        let mut left = amount.max(0);
+        let mut split = Split::default();
+        for &bucket in &self.0 {
+            let paid = left.min(due.get(bucket).max(0));
+            *split.get_mut(bucket) = paid;
+            left -= paid;
+        }
+        Applied { split, excess: left }
+    }
+}
+
+/// Số tiền theo từng nhóm nghĩa vụ (đang nợ hoặc đã phân bổ)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Split {
+    pub penalty: i64,
+    pub fees: i64,
+    pub interest: i64,
+    pub principal: i64,
+}
+
+impl Split {
+    pub fn get(&self, bucket: Bucket) -> i64 {
+        match bucket {
+            Bucket::Penalty => self.penalty,
+            Bucket::Fees => self.fees,
+            Bucket::Interest => self.interest,
+            Bucket::Principal => self.principal,
+        }
+    }
+
+    fn get_mut(&mut self, bucket: Bucket) -> &mut i64 {
+        match bucket {
+            Bucket::Penalty => &mut self.penalty,
+            Bucket::Fees => &mut self.fees,
+            Bucket::Interest => &mut self.interest,
+            Bucket::Principal => &mut self.principal,
+        }
+    }
+}
+
+/// Kết quả phân bổ 1 khoản trả gộp
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Applied {
+    pub split: Split,
+    /// Vượt quá tổng nghĩa vụ → không ghi nhận vào khoản nào
+    pub excess: i64,
+}
+
+/// Cấu hình thứ tự phân bổ của tenant (bảng `loan_repayment_waterfall`)
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct RepaymentWaterfall {
+    pub code: String,
+    pub name: String,
+    pub steps: Json<Waterfall>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn due() -> Split {
+        Split { penalty: 50_000, fees: 30_000, interest: 200_000, principal: 5_000_000 }
+    }
+
+    #[test]
+    fn standard_order_pays_penalty_first() {
+        let a = Waterfall::default().apply(260_000, &due());
+        assert_eq!(a.split, Split { penalty: 50_000, fees: 30_000, interest: 180_000, principal: 0 });
+        assert_eq!(a.excess, 0);
+
+        let a = Waterfall::default().apply(6_000_000, &due());
+        assert_eq!(a.split, due());
+        assert_eq!(a.excess, 720_000);
+    }
+
+    #[test]
+    fn principal_first_and_validation() {
+        let principal_first: Waterfall = serde_json::from_str(r#"["principal","interest","fees","penalty"]"#).unwrap();
+        let a = principal_first.apply(5_100_000, &due());
+        assert_eq!(a.split, Split { penalty: 0, fees: 0, interest: 100_000, principal: 5_000_000 });
+
+        assert!(Waterfall::new(vec![Bucket::Interest, Bucket::Principal]).is_none());
+        assert!(Waterfall::new(vec![Bucket::Interest, Bucket::Interest, Bucket::Fees, Bucket::Penalty]).is_none());
+        let steps = vec![Bucket::Principal, Bucket::Interest, Bucket::Fees, Bucket::Penalty];
+        assert_eq!(Waterfall::new(steps), Some(principal_first));
+    }
+}
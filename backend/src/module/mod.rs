@@ -1,3 +1,7 @@
+// Tên variant của các enum domain event (ContactCreated, InvoicePosted, ...) là `type` đã lưu trong
+// outbox/inbox và event_type trên bus → giữ nguyên dù trùng tiền tố với tên enum
+#![allow(clippy::enum_variant_names)]
+
 pub mod user;
 pub mod tenant;
 pub mod iam;
@@ -5,4 +9,5 @@ pub mod loan;
 pub mod contact;
 pub mod invoice;
 pub mod invoice_link;
-pub mod app;
\ No newline at end of file
+pub mod app;
+pub mod sequence;
\ No newline at end of file
@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::command_bus::{Command, CommandContext};
+use crate::core::error::AppError;
+use crate::core::i18n::I18n;
+use crate::core::timezone;
+use super::dto::NumberSequenceInput;
+use super::model::{valid_pattern, NumberSequence};
+use super::query;
+
+/// Số vừa cấp
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assigned {
+    /// Số chứng từ đã ghép theo mẫu
+    pub name: String,
+    /// Kỳ của counter (YYYY / YYYYMM / 0)
+    pub period_key: i32,
+    /// Số thứ tự trong kỳ
+    pub number: i64,
+}
+
+/// 🔢 Cấp số tiếp theo của dãy `code` tại thời điểm `at` (quy về ngày theo múi giờ của dãy / tenant).
+/// Chạy trong transaction của caller: dòng counter bị khoá tới khi transaction kết thúc,
+/// chứng từ rollback thì counter rollback theo → không lủng số, không trùng số.
+/// Dãy con (vd `invoice.series.1C26TAA`) có counter riêng, chưa cấu hình thì dùng cấu hình của mã gốc (`invoice`).
+pub async fn next_number(
+    conn: &mut PgConnection,
+    tenant_id: Uuid,
+    code: &str,
+    at: DateTime<Utc>,
+    prefix: Option<&str>,
+    journal: Option<&str>,
+) -> Result<Assigned, sqlx::Error> {
+    let base = code.split('.').next().unwrap_or(code);
+    let mut seq = query::get_sequence(&mut *conn, tenant_id, code).await?;
+    if seq.is_none() && base != code {
+        seq = query::get_sequence(&mut *conn, tenant_id, base).await?;
+    }
+    let seq = seq.unwrap_or_else(|| NumberSequence::default_for(base));
+    let tz = match seq.timezone.as_deref() {
+        Some(name) => timezone::parse_timezone(name),
+        None => timezone::tenant_timezone(&mut *conn, tenant_id).await?,
+    };
+    let date = timezone::business_date(at, tz);
+    let period_key = seq.reset_period.period_key(date);
+
+    let number = sqlx::query_scalar!(
+        r#"
+        INSERT INTO number_sequence_counter (tenant_id, code, period_key, counter, updated_at)
+        VALUES ($1, $2, $3, 1, now())
+        ON CONFLICT (tenant_id, code, period_key) DO UPDATE
+          SET counter = number_sequence_counter.counter + 1,
+              updated_at = now()
+        RETURNING counter
+        "#,
+        tenant_id,
+        code,
+        period_key
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // code tenant: 5 ký tự đầu UUID
+    let tenant = &tenant_id.to_string()[..5];
+    Ok(Assigned { name: seq.render(date, tenant, number, prefix, journal), period_key, number })
+}
+
+/* ========== Command bus ========== */
+
+/// Cấu hình dãy số (POST /sequences) – ghi đè nếu trùng mã, counter giữ nguyên
+pub struct SetNumberSequence {
+    pub input: NumberSequenceInput,
+}
+
+#[async_trait]
+impl Command for SetNumberSequence {
+    type Output = ();
+    const NAME: &'static str = "sequence.set";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("sequence", "manage"));
+
+    fn validate(&self, i18n: &I18n) -> Result<(), AppError> {
+        let input = &self.input;
+        if input.code.trim().is_empty()
+            || !(1..=12).contains(&input.padding)
+            || !valid_pattern(&input.pattern, input.reset_period)
+        {
+            return Err(AppError::bad_request_i18n(i18n, "error.sequence.invalid"));
+        }
+        if input.timezone.as_deref().is_some_and(|tz| tz.parse::<chrono_tz::Tz>().is_err()) {
+            return Err(AppError::bad_request_i18n(i18n, "error.tenant.invalid_timezone"));
+        }
+        Ok(())
+    }
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        let input = self.input;
+        sqlx::query!(
+            r#"
+            INSERT INTO number_sequence (tenant_id, code, prefix, pattern, padding, reset_period, timezone)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (tenant_id, code) DO UPDATE SET
+                prefix = EXCLUDED.prefix,
+                pattern = EXCLUDED.pattern,
+                padding = EXCLUDED.padding,
+                reset_period = EXCLUDED.reset_period,
+                timezone = EXCLUDED.timezone,
+                updated_at = now()
+            "#,
+            tenant_id,
+            input.code.trim(),
+            input.prefix.trim(),
+            input.pattern,
+            input.padding,
+            input.reset_period as _,
+            input.timezone.as_deref().filter(|tz| !tz.is_empty())
+        )
+        .execute(ctx.conn())
+        .await?;
+        Ok(())
+    }
+}
+
+/// Xoá cấu hình dãy số (DELETE /sequences/:code) – quay về mặc định, counter giữ nguyên
+pub struct DeleteNumberSequence {
+    pub code: String,
+}
+
+#[async_trait]
+impl Command for DeleteNumberSequence {
+    type Output = ();
+    const NAME: &'static str = "sequence.delete";
+    const PERMISSION: Option<(&'static str, &'static str)> = Some(("sequence", "manage"));
+
+    async fn handle(self, ctx: &mut CommandContext) -> Result<(), AppError> {
+        let tenant_id = ctx.tenant_id();
+        let deleted = sqlx::query!(
+            "DELETE FROM number_sequence WHERE tenant_id = $1 AND code = $2",
+            tenant_id,
+            self.code
+        )
+        .execute(ctx.conn())
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(AppError::not_found_i18n(&ctx.i18n, "error.sequence.not_found"));
+        }
+        Ok(())
+    }
+}
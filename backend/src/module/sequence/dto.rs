@@ -0,0 +1,17 @@
+use serde::Deserialize;
+
+use super::model::ResetPeriod;
+
+/// Dùng cho POST /sequences – ghi đè nếu trùng mã
+#[derive(Debug, Deserialize)]
+pub struct NumberSequenceInput {
+    pub code: String,
+    #[serde(default)]
+    pub prefix: String,
+    pub pattern: String,
+    pub padding: i32,
+    pub reset_period: ResetPeriod,
+    /// Bỏ trống = múi giờ của tenant
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
@@ -0,0 +1,47 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::core::{auth::AuthUser, error::AppError, i18n::I18n, state::AppState};
+use crate::command_bus;
+
+use super::{command, dto::NumberSequenceInput, query};
+
+/// ✅ Dãy số chứng từ của tenant (GET /sequences)
+pub async fn list_sequences(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = state.shard.get_pool_for_tenant(&auth.tenant_id).await?;
+    let rows = query::list_sequences(pool, auth.tenant_id).await?;
+    Ok(Json(json!(rows)))
+}
+
+/// ✅ Cấu hình dãy số (POST /sequences)
+pub async fn set_sequence(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Json(input): Json<NumberSequenceInput>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let code = input.code.trim().to_string();
+    command_bus::dispatch(&state, &auth, &i18n, command::SetNumberSequence { input }).await?;
+    Ok(Json(json!({ "code": code })))
+}
+
+/// ✅ Xoá cấu hình dãy số, quay về mặc định (DELETE /sequences/:code)
+pub async fn delete_sequence(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path(code): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    command_bus::dispatch(&state, &auth, &i18n, command::DeleteNumberSequence { code: code.clone() }).await?;
+    Ok(Json(json!({ "code": code, "deleted": true })))
+}
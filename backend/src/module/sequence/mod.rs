@@ -0,0 +1,7 @@
+//! Dãy số chứng từ dùng chung cho các module (số hợp đồng vay, số hoá đơn, ...)
+pub mod router;
+pub mod handler;
+pub mod command;
+pub mod query;
+pub mod model;
+pub mod dto;
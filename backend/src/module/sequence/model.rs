@@ -0,0 +1,125 @@
+//! Dãy số chứng từ (bảng `number_sequence`): mẫu số, độ dài phần số, chu kỳ reset và múi giờ.
+//! Mẫu gồm các placeholder `{prefix}`, `{tenant}`, `{journal}`, `{yyyy}`, `{yy}`, `{mm}`, `{dd}`, `{seq}`.
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Số hợp đồng vay
+pub const LOAN_CONTRACT: &str = "loan_contract";
+/// Số hoá đơn khi ghi sổ
+pub const INVOICE: &str = "invoice";
+
+const PLACEHOLDERS: [&str; 8] = ["{prefix}", "{tenant}", "{journal}", "{yyyy}", "{yy}", "{mm}", "{dd}", "{seq}"];
+
+/// Chu kỳ đánh lại số từ 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ResetPeriod {
+    Yearly,
+    Monthly,
+    Never,
+}
+
+impl ResetPeriod {
+    /// Khoá kỳ của counter: YYYY / YYYYMM / 0
+    pub fn period_key(&self, date: NaiveDate) -> i32 {
+        match self {
+            ResetPeriod::Yearly => date.year(),
+            ResetPeriod::Monthly => date.year() * 100 + date.month() as i32,
+            ResetPeriod::Never => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct NumberSequence {
+    pub code: String,
+    pub prefix: String,
+    pub pattern: String,
+    pub padding: i32,
+    pub reset_period: ResetPeriod,
+    /// None = múi giờ của tenant
+    pub timezone: Option<String>,
+    /// None = cấu hình mặc định, chưa lưu
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl NumberSequence {
+    /// Cấu hình mặc định khi tenant chưa cấu hình dãy `code`
+    pub fn default_for(code: &str) -> Self {
+        let (prefix, pattern, padding, reset_period) = match code {
+            LOAN_CONTRACT => ("LOAN", "{prefix}-{tenant}-{yyyy}{mm}-{seq}", 6, ResetPeriod::Monthly),
+            INVOICE => ("INV", "{prefix}/{journal}/{yyyy}/{seq}", 5, ResetPeriod::Yearly),
+            _ => ("", "{prefix}{yyyy}/{seq}", 6, ResetPeriod::Yearly),
+        };
+        NumberSequence {
+            code: code.to_string(),
+            prefix: prefix.to_string(),
+            pattern: pattern.to_string(),
+            padding,
+            reset_period,
+            timezone: None,
+            updated_at: None,
+        }
+    }
+
+    /// Ghép số chứng từ; `prefix` (vd theo sản phẩm vay) thay cho tiền tố của dãy, `journal` = mã sổ nhật ký
+    pub fn render(&self, date: NaiveDate, tenant: &str, number: i64, prefix: Option<&str>, journal: Option<&str>) -> String {
+        let prefix = prefix.map(str::trim).filter(|p| !p.is_empty()).unwrap_or(&self.prefix);
+        self.pattern
+            .replace("{prefix}", prefix)
+            .replace("{tenant}", tenant)
+            .replace("{journal}", journal.unwrap_or_default())
+            .replace("{yyyy}", &format!("{:04}", date.year()))
+            .replace("{yy}", &format!("{:02}", date.year() % 100))
+            .replace("{mm}", &format!("{:02}", date.month()))
+            .replace("{dd}", &format!("{:02}", date.day()))
+            .replace("{seq}", &format!("{:0width$}", number, width = self.padding.max(1) as usize))
+    }
+}
+
+/// ✅ Mẫu hợp lệ: có `{seq}`, chỉ dùng placeholder đã biết, và có đủ phần ngày của chu kỳ reset
+/// (reset theo tháng mà thiếu `{mm}` thì tháng sau cấp trùng số)
+pub fn valid_pattern(pattern: &str, reset_period: ResetPeriod) -> bool {
+    let rest = PLACEHOLDERS.iter().fold(pattern.to_string(), |s, p| s.replace(p, ""));
+    let has_year = pattern.contains("{yyyy}") || pattern.contains("{yy}");
+    let has_period = match reset_period {
+        ResetPeriod::Yearly => has_year,
+        ResetPeriod::Monthly => has_year && pattern.contains("{mm}"),
+        ResetPeriod::Never => true,
+    };
+    pattern.contains("{seq}") && !rest.contains(['{', '}']) && has_period
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn render_defaults() {
+        let loan = NumberSequence::default_for(LOAN_CONTRACT);
+        assert_eq!(loan.render(date(2025, 3, 9), "11111", 42, None, None), "LOAN-11111-202503-000042");
+        assert_eq!(loan.render(date(2025, 3, 9), "11111", 42, Some("XE"), None), "XE-11111-202503-000042");
+        assert_eq!(loan.reset_period.period_key(date(2025, 3, 9)), 202503);
+
+        let invoice = NumberSequence::default_for(INVOICE);
+        assert_eq!(invoice.render(date(2025, 12, 31), "11111", 7, None, Some("SALE")), "INV/SALE/2025/00007");
+        assert_eq!(invoice.render(date(2025, 12, 31), "11111", 7, Some("1C26TAA"), Some("SALE")), "1C26TAA/SALE/2025/00007");
+        assert_eq!(invoice.reset_period.period_key(date(2025, 12, 31)), 2025);
+        assert_eq!(ResetPeriod::Never.period_key(date(2025, 12, 31)), 0);
+    }
+
+    #[test]
+    fn pattern_must_cover_reset_period() {
+        assert!(valid_pattern("{prefix}/{yy}{mm}{dd}/{seq}", ResetPeriod::Monthly));
+        assert!(valid_pattern("HD{seq}", ResetPeriod::Never));
+        assert!(!valid_pattern("HD{seq}", ResetPeriod::Yearly));
+        assert!(!valid_pattern("{prefix}/{yyyy}/{seq}", ResetPeriod::Monthly));
+        assert!(!valid_pattern("{prefix}/{yyyy}", ResetPeriod::Yearly));
+        assert!(!valid_pattern("{prefix}/{year}/{seq}", ResetPeriod::Never));
+    }
+}
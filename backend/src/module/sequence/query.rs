@@ -0,0 +1,50 @@
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use super::model::{NumberSequence, ResetPeriod, INVOICE, LOAN_CONTRACT};
+
+/// Cấu hình dãy `code` của tenant (None = chưa cấu hình → dùng mặc định)
+pub async fn get_sequence<'e>(
+    executor: impl PgExecutor<'e>,
+    tenant_id: Uuid,
+    code: &str,
+) -> sqlx::Result<Option<NumberSequence>> {
+    sqlx::query_as!(
+        NumberSequence,
+        r#"
+        SELECT code, prefix, pattern, padding, reset_period AS "reset_period: ResetPeriod",
+               timezone, updated_at AS "updated_at?"
+        FROM number_sequence
+        WHERE tenant_id = $1 AND code = $2
+        "#,
+        tenant_id,
+        code
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Các dãy đã cấu hình + mặc định của các dãy có sẵn chưa cấu hình
+pub async fn list_sequences(pool: &PgPool, tenant_id: Uuid) -> sqlx::Result<Vec<NumberSequence>> {
+    let mut rows = sqlx::query_as!(
+        NumberSequence,
+        r#"
+        SELECT code, prefix, pattern, padding, reset_period AS "reset_period: ResetPeriod",
+               timezone, updated_at AS "updated_at?"
+        FROM number_sequence
+        WHERE tenant_id = $1
+        ORDER BY code
+        "#,
+        tenant_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for code in [LOAN_CONTRACT, INVOICE] {
+        if !rows.iter().any(|s| s.code == code) {
+            rows.push(NumberSequence::default_for(code));
+        }
+    }
+    rows.sort_by(|a, b| a.code.cmp(&b.code));
+    Ok(rows)
+}
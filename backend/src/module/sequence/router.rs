@@ -0,0 +1,17 @@
+use axum::{Router, routing::{delete, get, post}, middleware};
+use std::sync::Arc;
+
+use crate::core::{state::AppState, auth::jwt_auth};
+use crate::core::iam::RequirePermission;
+use super::handler;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().nest(
+        "/sequences",
+        Router::new()
+            .route("/", get(handler::list_sequences).route_layer(RequirePermission::new("sequence", "read")))
+            .route("/", post(handler::set_sequence).route_layer(RequirePermission::new("sequence", "manage")))
+            .route("/:code", delete(handler::delete_sequence).route_layer(RequirePermission::new("sequence", "manage")))
+            .layer(middleware::from_fn(jwt_auth)),
+    )
+}
@@ -24,4 +24,18 @@ pub struct AssignModuleCommand {
 pub struct EnableEnterpriseModuleCommand {
     pub module_name: String,
     pub config_json: Option<Value>,
-}
\ No newline at end of file
+}
+/// Payload JSON khi chuyển tenant sang shard khác
+#[derive(Debug, Deserialize)]
+pub struct MigrateTenantShardCommand {
+    pub target_shard_id: String,      // Shard đích (phải có trong catalog)
+    #[serde(default)]
+    pub delete_source: bool,          // Xoá dữ liệu ở shard cũ sau khi chuyển xong
+    pub drain_ms: Option<u64>,        // Thời gian chờ các request ghi đang chạy kết thúc
+}
+
+/// Payload JSON khi đổi múi giờ nghiệp vụ của tenant
+#[derive(Debug, Deserialize)]
+pub struct SetTenantTimezoneCommand {
+    pub timezone: String,             // Tên IANA, vd "Asia/Ho_Chi_Minh"
+}
@@ -1,7 +1,7 @@
 use axum::{
     extract::{Path, State},
     response::{IntoResponse, Response},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     Json,
     debug_handler,
 };
@@ -13,10 +13,11 @@ use serde_json::{json, Value};
 use std::collections::HashMap;
 
 use crate::{
-    core::{state::AppState, error::AppError, json_with_log::JsonWithLog},
-    module::tenant::command::{AssignModuleCommand, EnableEnterpriseModuleCommand},
+    core::{state::AppState, error::AppError, json_with_log::JsonWithLog, auth::AuthUser, iam::is_sys_admin, i18n::I18n},
+    module::tenant::command::{AssignModuleCommand, EnableEnterpriseModuleCommand, MigrateTenantShardCommand, SetTenantTimezoneCommand},
 };
-use super::model::Tenant;
+use super::model::{Tenant, TenantShardMigration};
+use super::shard_migration;
 use super::command::CreateTenantCommand;
 use super::dto::{CreateEnterpriseCommand, CreateCompanyCommand};
 
@@ -29,10 +30,12 @@ pub async fn create_tenant(
     State(state): State<Arc<AppState>>,
     JsonWithLog(payload): JsonWithLog<CreateTenantCommand>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state
+    // shard_id phải có trong catalog; bản ghi tenant luôn nằm ở meta DB
+    let shard_pool = state
         .shard
         .get_pool_for_shard(&payload.shard_id)
-        .map_err(|msg| AppError::bad_request(msg))?;
+        .map_err(AppError::bad_request)?;
+    let pool = state.shard.get_pool_for_system();
 
     let tenant_id = Uuid::new_v4();
     let created_at = Utc::now();
@@ -55,6 +58,15 @@ pub async fn create_tenant(
     .fetch_one(pool)
     .await?;
 
+    // Shard khác meta DB → chép dòng tenant (và dữ liệu tham chiếu) sang trước khi định tuyến tới shard
+    if !std::ptr::eq(shard_pool, pool) {
+        shard_migration::sync_reference_rows(pool, shard_pool, tenant.tenant_id)
+            .await
+            .map_err(AppError::internal)?;
+    }
+
+    state.shard.register_tenant(tenant.tenant_id, &tenant.shard_id);
+
     Ok((StatusCode::CREATED, Json(tenant)))
 }
 
@@ -63,7 +75,7 @@ pub async fn create_enterprise(
     State(state): State<Arc<AppState>>,
     JsonWithLog(payload): JsonWithLog<CreateEnterpriseCommand>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&Uuid::nil());
+    let pool = state.shard.get_pool_for_system();
 
     let res = sqlx::query!(
         r#"
@@ -91,7 +103,7 @@ pub async fn create_company(
     State(state): State<Arc<AppState>>,
     JsonWithLog(payload): JsonWithLog<CreateCompanyCommand>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pool = state.shard.get_pool_for_tenant(&Uuid::nil());
+    let pool = state.shard.get_pool_for_system();
 
     let company_id = Uuid::new_v4();
     let enterprise_id = payload.enterprise_id;
@@ -170,7 +182,7 @@ pub async fn create_company(
 pub async fn list_tenants_with_modules(
     State(state): State<Arc<AppState>>,
 ) -> Response {
-    let pool = state.shard.get_pool_for_tenant(&Uuid::nil());
+    let pool = state.shard.get_pool_for_system();
 
     let rows = sqlx::query!(
         r#"
@@ -254,7 +266,10 @@ pub async fn assign_module(
     Path(tenant_id): Path<Uuid>,
     axum::Json(payload): axum::Json<AssignModuleCommand>,
 ) -> Response {
-    let pool = state.shard.get_pool_for_tenant(&tenant_id);
+    let pool = match state.shard.get_pool_for_tenant(&tenant_id).await {
+        Ok(pool) => pool,
+        Err(e) => return AppError::from(e).into_response(),
+    };
     let cfg: Value = payload.config_json.unwrap_or_else(|| json!({}));
 
     let res = sqlx::query!(
@@ -315,7 +330,10 @@ pub async fn list_modules(
     State(state): State<Arc<AppState>>,
     Path(tenant_id): Path<Uuid>,
 ) -> Response {
-    let pool = state.shard.get_pool_for_tenant(&tenant_id);
+    let pool = match state.shard.get_pool_for_tenant(&tenant_id).await {
+        Ok(pool) => pool,
+        Err(e) => return AppError::from(e).into_response(),
+    };
 
     let res = sqlx::query!(
         r#"
@@ -365,7 +383,10 @@ pub async fn remove_module(
     State(state): State<Arc<AppState>>,
     Path((tenant_id, module_name)): Path<(Uuid, String)>,
 ) -> Response {
-    let pool = state.shard.get_pool_for_tenant(&tenant_id);
+    let pool = match state.shard.get_pool_for_tenant(&tenant_id).await {
+        Ok(pool) => pool,
+        Err(e) => return AppError::from(e).into_response(),
+    };
 
     let res = sqlx::query!(
         r#"
@@ -408,7 +429,7 @@ pub async fn enable_enterprise_module(
     Path(enterprise_id): Path<Uuid>,
     axum::Json(payload): axum::Json<EnableEnterpriseModuleCommand>,
 ) -> Response {
-    let pool = state.shard.get_pool_for_tenant(&Uuid::nil()); 
+    let pool = state.shard.get_pool_for_system(); 
 
     let cfg = payload.config_json.unwrap_or_else(|| json!({}));
     let res = sqlx::query!(
@@ -455,3 +476,186 @@ pub async fn enable_enterprise_module(
         }
     }
 }
+
+/// POST /tenant/:tenant_id/shard-migration — chuyển tenant sang shard khác (chỉ admin hệ thống)
+/// Job chạy nền, trả về 202 kèm thông tin job để theo dõi
+pub async fn migrate_tenant_shard(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path(tenant_id): Path<Uuid>,
+    axum::Json(payload): axum::Json<MigrateTenantShardCommand>,
+) -> Result<impl IntoResponse, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    if !is_sys_admin(&auth) {
+        return Err(AppError::forbidden_i18n(&i18n, "error.auth.forbidden"));
+    }
+
+    let pool = state.shard.get_pool_for_system();
+    let source_shard_id = sqlx::query_scalar!(
+        "SELECT shard_id FROM tenant WHERE tenant_id = $1",
+        tenant_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::not_found_i18n(&i18n, "error.tenant.not_found"))?;
+
+    state
+        .shard
+        .get_pool_for_shard(&payload.target_shard_id)
+        .map_err(AppError::bad_request)?;
+    if source_shard_id == payload.target_shard_id {
+        return Err(AppError::bad_request_i18n(&i18n, "error.tenant.same_shard"));
+    }
+
+    let job = sqlx::query_as!(
+        TenantShardMigration,
+        r#"
+        INSERT INTO tenant_shard_migration (id, tenant_id, source_shard_id, target_shard_id, delete_source, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, tenant_id, source_shard_id, target_shard_id, status, delete_source,
+                  report, error, created_by, started_at, finished_at
+        "#,
+        Uuid::new_v4(),
+        tenant_id,
+        source_shard_id,
+        payload.target_shard_id,
+        payload.delete_source,
+        auth.user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.code().as_deref() == Some("23505") => {
+            AppError::bad_request_i18n(&i18n, "error.tenant.migration_in_progress")
+        }
+        other => AppError::from(other),
+    })?;
+
+    let body = json!({
+        "id": job.id,
+        "tenant_id": job.tenant_id,
+        "source_shard_id": job.source_shard_id,
+        "target_shard_id": job.target_shard_id,
+        "status": job.status,
+    });
+
+    tokio::spawn(shard_migration::run(state.clone(), job, payload.drain_ms));
+
+    Ok((StatusCode::ACCEPTED, Json(body)))
+}
+
+/// GET /tenant/:tenant_id/shard-migrations — lịch sử chuyển shard của tenant
+pub async fn list_shard_migrations(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path(tenant_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    if !is_sys_admin(&auth) {
+        return Err(AppError::forbidden_i18n(&i18n, "error.auth.forbidden"));
+    }
+
+    let jobs = sqlx::query_as!(
+        TenantShardMigration,
+        r#"
+        SELECT id, tenant_id, source_shard_id, target_shard_id, status, delete_source,
+               report, error, created_by, started_at, finished_at
+        FROM tenant_shard_migration
+        WHERE tenant_id = $1
+        ORDER BY started_at DESC
+        "#,
+        tenant_id
+    )
+    .fetch_all(state.shard.get_pool_for_system())
+    .await?;
+
+    Ok(Json(jobs))
+}
+
+/// POST /tenant/:tenant_id/shard-migrations/:job_id/resume — chạy lại bước đổi routing / dọn shard cũ
+/// cho job lỗi sau khi dữ liệu đã commit ở shard đích (status `switching`)
+pub async fn resume_shard_migration(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path((tenant_id, job_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    if !is_sys_admin(&auth) {
+        return Err(AppError::forbidden_i18n(&i18n, "error.auth.forbidden"));
+    }
+
+    let job = sqlx::query_as!(
+        TenantShardMigration,
+        r#"
+        SELECT id, tenant_id, source_shard_id, target_shard_id, status, delete_source,
+               report, error, created_by, started_at, finished_at
+        FROM tenant_shard_migration
+        WHERE id = $1 AND tenant_id = $2
+        "#,
+        job_id,
+        tenant_id
+    )
+    .fetch_optional(state.shard.get_pool_for_system())
+    .await?
+    .ok_or_else(|| AppError::not_found_i18n(&i18n, "error.tenant.migration_not_found"))?;
+
+    if job.status != "switching" {
+        return Err(AppError::bad_request_i18n(&i18n, "error.tenant.migration_not_resumable"));
+    }
+
+    shard_migration::resume(&state, &job)
+        .await
+        .map_err(AppError::internal)?;
+
+    Ok(Json(json!({ "id": job.id, "tenant_id": job.tenant_id, "status": "completed" })))
+}
+
+/// POST /tenant/:tenant_id/timezone — đổi múi giờ nghiệp vụ (ngày tính lãi, cắt ngày báo cáo)
+pub async fn set_tenant_timezone(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    auth: AuthUser,
+    Path(tenant_id): Path<Uuid>,
+    axum::Json(payload): axum::Json<SetTenantTimezoneCommand>,
+) -> Result<impl IntoResponse, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    if !is_sys_admin(&auth) {
+        return Err(AppError::forbidden_i18n(&i18n, "error.auth.forbidden"));
+    }
+    let tz: chrono_tz::Tz = payload
+        .timezone
+        .parse()
+        .map_err(|_| AppError::bad_request_i18n(&i18n, "error.tenant.invalid_timezone"))?;
+
+    // Meta DB là nguồn chuẩn, bản sao trên shard dùng khi tính lãi
+    let updated = sqlx::query!(
+        "UPDATE tenant SET timezone = $2 WHERE tenant_id = $1",
+        tenant_id,
+        tz.name()
+    )
+    .execute(state.shard.get_pool_for_system())
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(AppError::not_found_i18n(&i18n, "error.tenant.not_found"));
+    }
+
+    let system = state.shard.get_pool_for_system();
+    let shard_pool = state.shard.get_pool_for_tenant(&tenant_id).await?;
+    if !std::ptr::eq(shard_pool, system) {
+        let synced = sqlx::query!(
+            "UPDATE tenant SET timezone = $2 WHERE tenant_id = $1",
+            tenant_id,
+            tz.name()
+        )
+        .execute(shard_pool)
+        .await?;
+        if synced.rows_affected() == 0 {
+            return Err(AppError::internal(format!("Shard chưa có dòng tenant {}", tenant_id)));
+        }
+    }
+
+    Ok(Json(json!({ "tenant_id": tenant_id, "timezone": tz.name() })))
+}
@@ -5,4 +5,5 @@ pub mod query;
 pub mod handler;
 pub mod router;
 pub mod metadata;
-pub mod dto;
\ No newline at end of file
+pub mod dto;
+pub mod shard_migration;
\ No newline at end of file
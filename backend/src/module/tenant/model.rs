@@ -22,3 +22,19 @@ pub struct TenantModule {
     pub config_json: serde_json::Value,
     pub enabled_at: Option<DateTime<Utc>>, // giữ Option để match query_as!
 }
+
+/// Struct ánh xạ bảng `tenant_shard_migration` (job chuyển tenant giữa các shard)
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TenantShardMigration {
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub source_shard_id: String,
+    pub target_shard_id: String,
+    pub status: String,
+    pub delete_source: bool,
+    pub report: serde_json::Value,
+    pub error: Option<String>,
+    pub created_by: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
@@ -1,10 +1,11 @@
 use axum::{
     Router,
     routing::{get, post, delete},
+    middleware,
 };
 use std::sync::Arc;
 
-use crate::core::state::AppState;
+use crate::core::{state::AppState, auth::jwt_auth};
 
 // Các handler có tồn tại
 use crate::module::tenant::handler::{
@@ -18,6 +19,10 @@ use crate::module::tenant::handler::{
     remove_module,
     list_tenants_with_modules,
     enable_enterprise_module,
+    migrate_tenant_shard,
+    list_shard_migrations,
+    resume_shard_migration,
+    set_tenant_timezone,
     // list_tenants_by_enterprise,
     // list_tenants_by_company,
     // list_tenants_by_company_subtree,
@@ -47,4 +52,14 @@ pub fn routes() -> Router<Arc<AppState>> {
         // Tạo tổ chức & công ty
         .route("/enterprise", post(create_enterprise))
         .route("/company", post(create_company))
+
+        // 🔒 Admin: chuyển tenant giữa các shard, cấu hình múi giờ
+        .merge(
+            Router::new()
+                .route("/tenant/:tenant_id/shard-migration", post(migrate_tenant_shard))
+                .route("/tenant/:tenant_id/shard-migrations", get(list_shard_migrations))
+                .route("/tenant/:tenant_id/shard-migrations/:job_id/resume", post(resume_shard_migration))
+                .route("/tenant/:tenant_id/timezone", post(set_tenant_timezone))
+                .layer(middleware::from_fn(jwt_auth)),
+        )
 }
@@ -0,0 +1,565 @@
+//! Chuyển toàn bộ dữ liệu của 1 tenant từ shard này sang shard khác (online).
+//!
+//! Quy trình:
+//! 1. Chặn ghi cho tenant (fence ghi ở meta DB, mọi instance kiểm tra mỗi request)
+//!    và chờ các request ghi đang chạy kết thúc
+//! 2. Copy các dòng có `tenant_id` (mọi bảng có cột tenant_id) sang shard đích trong 1 transaction,
+//!    đẩy sequence của cột serial / identity ở shard đích lên quá giá trị đã copy
+//! 3. So khớp số dòng + checksum từng bảng giữa 2 shard, lệch thì rollback
+//! 4. `switching`: đổi `tenant.shard_id` ở meta DB, tuỳ chọn xoá dữ liệu ở shard cũ.
+//!    Các bước này idempotent: lỗi giữa chừng giữ nguyên fence + status để chạy lại (`resume`)
+//! 5. Bỏ chặn ghi
+
+use futures::TryStreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::core::state::AppState;
+use super::model::TenantShardMigration;
+
+/// Bảng meta/hệ thống: không copy theo tenant_id
+const EXCLUDED_TABLES: &[&str] = &["tenant", "tenant_shard_migration"];
+
+/// Dữ liệu tham chiếu (FK cha) cần có sẵn ở shard đích trước khi copy.
+/// Đọc từ meta DB, ghi ON CONFLICT DO NOTHING. `$1` = tenant_id.
+const REFERENCE_ROWS: &[(&str, &str)] = &[
+    ("tenant_enterprise", "enterprise_id = (SELECT enterprise_id FROM tenant WHERE tenant_id = $1)"),
+    ("tenant_company", "enterprise_id = (SELECT enterprise_id FROM tenant WHERE tenant_id = $1)"),
+    ("available_module", "$1::uuid IS NOT NULL"),
+    ("tenant_enterprise_module", "enterprise_id = (SELECT enterprise_id FROM tenant WHERE tenant_id = $1)"),
+    ("permissions", "$1::uuid IS NOT NULL"),
+    ("tenant", "tenant_id = $1"),
+];
+
+const BATCH_SIZE: usize = 500;
+/// Thời gian chờ request ghi đã qua kiểm tra fence chạy xong (phải dài hơn request ghi lâu nhất)
+const DEFAULT_DRAIN_MS: u64 = 2000;
+
+/// Kết quả copy 1 bảng
+#[derive(Debug, Serialize)]
+pub struct TableReport {
+    pub table: String,
+    pub rows: i64,
+    pub checksum: String,
+}
+
+/// Bảng có cột tenant_id + thông tin cần để copy
+struct TenantTable {
+    name: String,
+    columns: Vec<String>,
+    self_referencing: bool,
+}
+
+/// Chạy job đã được tạo (status = pending).
+/// Hết chặn ghi khi job kết thúc, trừ khi lỗi sau lúc dữ liệu đã commit ở shard đích (status `switching`).
+pub async fn run(state: Arc<AppState>, job: TenantShardMigration, drain_ms: Option<u64>) {
+    let drain = Duration::from_millis(drain_ms.unwrap_or(DEFAULT_DRAIN_MS));
+
+    match execute(&state, &job, drain).await {
+        Ok(report) => {
+            tracing::info!(
+                "✅ Tenant {} đã chuyển {} → {}",
+                job.tenant_id, job.source_shard_id, job.target_shard_id
+            );
+            complete(&state, &job, Some(serde_json::to_value(report).unwrap_or_default())).await;
+        }
+        Err(e) => {
+            tracing::error!("❌ Chuyển shard tenant {} thất bại: {}", job.tenant_id, e);
+            fail(&state, &job, e).await;
+        }
+    }
+}
+
+/// Chạy lại bước đổi routing / dọn shard cũ cho job đang `switching` (lần trước lỗi giữa chừng)
+pub async fn resume(state: &AppState, job: &TenantShardMigration) -> Result<(), String> {
+    match finish(state, job).await {
+        Ok(()) => {
+            tracing::info!("✅ Tenant {} đã chuyển xong sang {} (resume)", job.tenant_id, job.target_shard_id);
+            complete(state, job, None).await;
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("❌ Resume chuyển shard tenant {} lỗi: {}", job.tenant_id, e);
+            fail(state, job, e.clone()).await;
+            Err(e)
+        }
+    }
+}
+
+/// Job xong: ghi completed (giữ report đã lưu nếu `report` = None) → bỏ chặn ghi
+async fn complete(state: &AppState, job: &TenantShardMigration, report: Option<Value>) {
+    if let Err(e) = sqlx::query!(
+        r#"
+        UPDATE tenant_shard_migration
+        SET status = 'completed', report = COALESCE($2, report), error = NULL, finished_at = now()
+        WHERE id = $1
+        "#,
+        job.id,
+        report,
+    )
+    .execute(state.shard.get_pool_for_system())
+    .await
+    {
+        tracing::error!("❌ Không cập nhật được job chuyển shard {}: {}", job.id, e);
+    }
+}
+
+/// Job lỗi. Trước khi commit shard đích → failed + bỏ chặn ghi (shard cũ vẫn là bản chuẩn).
+/// Đã `switching` → dữ liệu nằm ở cả 2 shard: giữ fence + status, chỉ ghi lỗi để resume.
+async fn fail(state: &AppState, job: &TenantShardMigration, error: String) {
+    let status = sqlx::query_scalar!(
+        r#"
+        UPDATE tenant_shard_migration
+        SET status = CASE WHEN status = 'switching' THEN status ELSE 'failed' END,
+            error = $2,
+            finished_at = CASE WHEN status = 'switching' THEN NULL ELSE now() END
+        WHERE id = $1
+        RETURNING status
+        "#,
+        job.id,
+        error,
+    )
+    .fetch_one(state.shard.get_pool_for_system())
+    .await;
+
+    match status.as_deref() {
+        Ok("switching") => {
+            tracing::warn!("⏸️ Job chuyển shard {} giữ trạng thái switching, chạy lại bằng resume", job.id);
+        }
+        Ok(_) => {}
+        Err(e) => tracing::error!("❌ Không cập nhật được job chuyển shard {}: {}", job.id, e),
+    }
+}
+
+async fn execute(
+    state: &AppState,
+    job: &TenantShardMigration,
+    drain: Duration,
+) -> Result<Vec<TableReport>, String> {
+    let shard = &state.shard;
+    let system = shard.get_pool_for_system();
+    let tenant_id = job.tenant_id;
+
+    // 1) Fence: status ở meta DB, mọi instance đọc mỗi request ghi
+    set_status(system, job.id, "fenced").await?;
+    tokio::time::sleep(drain).await;
+
+    // Cùng 1 DB vật lý → không cần copy, chỉ đổi routing
+    if shard.same_database(&job.source_shard_id, &job.target_shard_id) {
+        start_switching(system, job.id, &[]).await?;
+        finish(state, job).await?;
+        return Ok(vec![]);
+    }
+
+    let source = shard.get_pool_for_shard(&job.source_shard_id)?;
+    let target = shard.get_pool_for_shard(&job.target_shard_id)?;
+
+    let tables = discover_tenant_tables(source).await.map_err(|e| e.to_string())?;
+    ensure_target_columns(target, &tables).await?;
+
+    // 2) Copy trong 1 transaction ở shard đích
+    set_status(system, job.id, "copying").await?;
+    let mut tx = target.begin().await.map_err(|e| e.to_string())?;
+
+    for (table, filter) in REFERENCE_ROWS {
+        copy_reference_rows(system, &mut tx, table, filter, tenant_id).await?;
+    }
+
+    for table in &tables {
+        let existing = table_checksum(&mut tx, &table.name, tenant_id).await?;
+        if existing.0 > 0 {
+            return Err(format!("Shard đích đã có {} dòng của tenant trong bảng {}", existing.0, table.name));
+        }
+        copy_table(source, &mut tx, table, tenant_id).await?;
+    }
+
+    // 3) Verify: số dòng + checksum phải khớp
+    set_status(system, job.id, "verifying").await?;
+    let mut report = Vec::with_capacity(tables.len());
+    for table in &tables {
+        let mut conn = source.acquire().await.map_err(|e| e.to_string())?;
+        let (src_rows, src_sum) = table_checksum(&mut conn, &table.name, tenant_id).await?;
+        let (dst_rows, dst_sum) = table_checksum(&mut tx, &table.name, tenant_id).await?;
+        if src_rows != dst_rows || src_sum != dst_sum {
+            return Err(format!(
+                "Lệch dữ liệu bảng {}: nguồn {} dòng ({}), đích {} dòng ({})",
+                table.name, src_rows, src_sum, dst_rows, dst_sum
+            ));
+        }
+        report.push(TableReport { table: table.name.clone(), rows: src_rows, checksum: src_sum });
+    }
+
+    // Bản sao tenant ở shard đích cũng phải trỏ đúng shard mới
+    sqlx::query("UPDATE tenant SET shard_id = $2 WHERE tenant_id = $1")
+        .bind(tenant_id)
+        .bind(&job.target_shard_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    // 4) Dữ liệu đã ở shard đích: từ đây lỗi không rollback được nữa → chỉ chạy tiếp (resume)
+    start_switching(system, job.id, &report).await?;
+    finish(state, job).await?;
+
+    Ok(report)
+}
+
+/// Đánh dấu `switching` + lưu report để resume hoàn tất job
+async fn start_switching(pool: &PgPool, job_id: Uuid, report: &[TableReport]) -> Result<(), String> {
+    let report = serde_json::to_value(report).map_err(|e| e.to_string())?;
+    sqlx::query!(
+        "UPDATE tenant_shard_migration SET status = 'switching', report = $2 WHERE id = $1",
+        job_id,
+        report
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Đổi routing rồi dọn shard cũ (bảng con trước, bảng cha sau).
+/// Idempotent: UPDATE shard_id / DELETE theo tenant_id chạy lại không đổi kết quả.
+async fn finish(state: &AppState, job: &TenantShardMigration) -> Result<(), String> {
+    let shard = &state.shard;
+    let tenant_id = job.tenant_id;
+
+    switch_shard(state, tenant_id, &job.target_shard_id).await?;
+
+    if !job.delete_source || shard.same_database(&job.source_shard_id, &job.target_shard_id) {
+        return Ok(());
+    }
+
+    let source = shard.get_pool_for_shard(&job.source_shard_id)?;
+    let tables = discover_tenant_tables(source).await.map_err(|e| e.to_string())?;
+    let mut tx = source.begin().await.map_err(|e| e.to_string())?;
+    for table in tables.iter().rev() {
+        let sql = format!(r#"DELETE FROM public."{}" WHERE tenant_id = $1"#, table.name);
+        sqlx::query(&sql)
+            .bind(tenant_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Xoá dữ liệu cũ bảng {} lỗi: {}", table.name, e))?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+async fn set_status(pool: &PgPool, job_id: Uuid, status: &str) -> Result<(), String> {
+    sqlx::query!(
+        "UPDATE tenant_shard_migration SET status = $2 WHERE id = $1",
+        job_id,
+        status
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Đổi tenant.shard_id ở meta DB + làm mới cache routing
+async fn switch_shard(state: &AppState, tenant_id: Uuid, target_shard_id: &str) -> Result<(), String> {
+    sqlx::query!(
+        "UPDATE tenant SET shard_id = $2 WHERE tenant_id = $1",
+        tenant_id,
+        target_shard_id
+    )
+    .execute(state.shard.get_pool_for_system())
+    .await
+    .map_err(|e| e.to_string())?;
+
+    state.shard.invalidate_tenant(&tenant_id);
+    state.shard.register_tenant(tenant_id, target_shard_id);
+    Ok(())
+}
+
+/// Liệt kê bảng có cột tenant_id, sắp xếp theo FK (bảng cha trước)
+async fn discover_tenant_tables(pool: &PgPool) -> Result<Vec<TenantTable>, sqlx::Error> {
+    let names: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT c.table_name::text
+        FROM information_schema.columns c
+        JOIN information_schema.tables t
+          ON t.table_schema = c.table_schema AND t.table_name = c.table_name
+        WHERE c.table_schema = 'public'
+          AND c.column_name = 'tenant_id'
+          AND t.table_type = 'BASE TABLE'
+        ORDER BY 1
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let names: BTreeSet<String> = names
+        .into_iter()
+        .filter(|n| !EXCLUDED_TABLES.contains(&n.as_str()))
+        .collect();
+
+    let edges: Vec<(String, String)> = sqlx::query_as(
+        r#"
+        SELECT DISTINCT child.relname::text, parent.relname::text
+        FROM pg_constraint con
+        JOIN pg_class child ON child.oid = con.conrelid
+        JOIN pg_class parent ON parent.oid = con.confrelid
+        JOIN pg_namespace n ON n.oid = child.relnamespace
+        WHERE con.contype = 'f' AND n.nspname = 'public'
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tables = Vec::with_capacity(names.len());
+    for name in sort_by_dependencies(&names, &edges) {
+        let columns = insertable_columns(pool, &name).await?;
+        let self_referencing = edges.iter().any(|(c, p)| c == &name && p == &name);
+        tables.push(TenantTable { name, columns, self_referencing });
+    }
+    Ok(tables)
+}
+
+/// Topological sort theo FK (child → parent). Bảng trong vòng lặp FK xếp cuối theo tên.
+fn sort_by_dependencies(names: &BTreeSet<String>, edges: &[(String, String)]) -> Vec<String> {
+    let mut parents: HashMap<&str, HashSet<&str>> = names.iter().map(|n| (n.as_str(), HashSet::new())).collect();
+    for (child, parent) in edges {
+        if child != parent && names.contains(parent) {
+            if let Some(set) = parents.get_mut(child.as_str()) {
+                set.insert(parent.as_str());
+            }
+        }
+    }
+
+    let mut ordered: Vec<String> = Vec::with_capacity(names.len());
+    let mut done: HashSet<&str> = HashSet::new();
+    loop {
+        let ready: Vec<&str> = names
+            .iter()
+            .map(String::as_str)
+            .filter(|n| !done.contains(n) && parents[n].iter().all(|p| done.contains(p)))
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+        for n in ready {
+            done.insert(n);
+            ordered.push(n.to_string());
+        }
+    }
+
+    for n in names {
+        if !done.contains(n.as_str()) {
+            tracing::warn!("⚠️ Bảng {} nằm trong vòng FK, copy sau cùng", n);
+            ordered.push(n.clone());
+        }
+    }
+    ordered
+}
+
+/// Cột có thể INSERT (bỏ generated column)
+async fn insertable_columns(pool: &PgPool, table: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT column_name::text
+        FROM information_schema.columns
+        WHERE table_schema = 'public' AND table_name = $1 AND is_generated = 'NEVER'
+        ORDER BY ordinal_position
+        "#,
+    )
+    .bind(table)
+    .fetch_all(pool)
+    .await
+}
+
+/// Shard đích phải có đủ bảng/cột như shard nguồn (đã chạy cùng migration)
+async fn ensure_target_columns(target: &PgPool, tables: &[TenantTable]) -> Result<(), String> {
+    for table in tables {
+        let target_cols: HashSet<String> = insertable_columns(target, &table.name)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .collect();
+        if let Some(missing) = table.columns.iter().find(|c| !target_cols.contains(*c)) {
+            return Err(format!("Shard đích thiếu cột {}.{} (chưa chạy migration?)", table.name, missing));
+        }
+    }
+    Ok(())
+}
+
+fn quoted_columns(columns: &[String]) -> String {
+    columns
+        .iter()
+        .map(|c| format!("\"{}\"", c))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Ghi 1 lô dòng (dạng jsonb) vào bảng đích, giữ nguyên kiểu dữ liệu qua jsonb_populate_recordset
+async fn insert_rows(
+    conn: &mut PgConnection,
+    table: &str,
+    columns: &str,
+    rows: Vec<Value>,
+    on_conflict: &str,
+) -> Result<(), String> {
+    let sql = format!(
+        r#"INSERT INTO public."{table}" ({columns}) OVERRIDING SYSTEM VALUE
+           SELECT {columns} FROM jsonb_populate_recordset(NULL::public."{table}", $1) {on_conflict}"#
+    );
+    sqlx::query(&sql)
+        .bind(Value::Array(rows))
+        .execute(conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Copy bảng {} lỗi: {}", table, e))
+}
+
+async fn copy_table(
+    source: &PgPool,
+    conn: &mut PgConnection,
+    table: &TenantTable,
+    tenant_id: Uuid,
+) -> Result<(), String> {
+    let columns = quoted_columns(&table.columns);
+    let select = format!(r#"SELECT to_jsonb(t) FROM public."{}" t WHERE tenant_id = $1"#, table.name);
+    // Bảng tự tham chiếu ghi 1 lần để FK được kiểm tra cuối câu lệnh
+    let batch_size = if table.self_referencing { usize::MAX } else { BATCH_SIZE };
+
+    let mut rows = sqlx::query_scalar::<_, Value>(&select).bind(tenant_id).fetch(source);
+    let mut batch = Vec::new();
+    while let Some(row) = rows.try_next().await.map_err(|e| e.to_string())? {
+        batch.push(row);
+        if batch.len() >= batch_size {
+            insert_rows(conn, &table.name, &columns, std::mem::take(&mut batch), "").await?;
+        }
+    }
+    if !batch.is_empty() {
+        insert_rows(conn, &table.name, &columns, batch, "").await?;
+    }
+    sync_sequences(conn, &table.name).await
+}
+
+/// Dòng được copy kèm giá trị cột serial / identity (OVERRIDING SYSTEM VALUE) nên sequence ở shard đích
+/// không tự tăng theo → đẩy sequence lên giá trị lớn nhất của bảng, tránh trùng khoá ở lần ghi sau
+async fn sync_sequences(conn: &mut PgConnection, table: &str) -> Result<(), String> {
+    let sequences: Vec<(String, String)> = sqlx::query_as(
+        r#"
+        SELECT a.attname::text, s.seq
+        FROM pg_attribute a
+        CROSS JOIN LATERAL (SELECT pg_get_serial_sequence(format('public.%I', $1), a.attname) AS seq) s
+        WHERE a.attrelid = format('public.%I', $1)::regclass
+          AND a.attnum > 0 AND NOT a.attisdropped AND s.seq IS NOT NULL
+        "#,
+    )
+    .bind(table)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    for (column, sequence) in sequences {
+        let sql = format!(
+            r#"
+            SELECT setval($1::regclass, m)
+            FROM (SELECT MAX("{column}")::int8 AS m FROM public."{table}") t
+            WHERE m > COALESCE(pg_sequence_last_value($1::regclass), 0)
+            "#
+        );
+        sqlx::query(&sql)
+            .bind(&sequence)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Đồng bộ sequence {} lỗi: {}", sequence, e))?;
+    }
+    Ok(())
+}
+
+/// Đồng bộ dữ liệu tham chiếu (kể cả dòng `tenant`) từ meta DB sang shard đang chứa tenant.
+/// Dòng đã có thì giữ nguyên (ON CONFLICT DO NOTHING).
+pub async fn sync_reference_rows(system: &PgPool, shard: &PgPool, tenant_id: Uuid) -> Result<(), String> {
+    let mut tx = shard.begin().await.map_err(|e| e.to_string())?;
+    for (table, filter) in REFERENCE_ROWS {
+        copy_reference_rows(system, &mut tx, table, filter, tenant_id).await?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+async fn copy_reference_rows(
+    system: &PgPool,
+    conn: &mut PgConnection,
+    table: &str,
+    filter: &str,
+    tenant_id: Uuid,
+) -> Result<(), String> {
+    let exists: Option<String> = sqlx::query_scalar("SELECT to_regclass($1)::text")
+        .bind(format!("public.{}", table))
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    if exists.is_none() {
+        return Ok(());
+    }
+
+    let select = format!(r#"SELECT to_jsonb(t) FROM public."{}" t WHERE {}"#, table, filter);
+    let rows: Vec<Value> = sqlx::query_scalar(&select)
+        .bind(tenant_id)
+        .fetch_all(system)
+        .await
+        .map_err(|e| e.to_string())?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let columns = insertable_columns(system, table).await.map_err(|e| e.to_string())?;
+    insert_rows(conn, table, &quoted_columns(&columns), rows, "ON CONFLICT DO NOTHING").await?;
+    sync_sequences(conn, table).await
+}
+
+/// (số dòng, checksum) của tenant trong 1 bảng. jsonb sắp key cố định nên không phụ thuộc thứ tự cột.
+async fn table_checksum(
+    conn: &mut PgConnection,
+    table: &str,
+    tenant_id: Uuid,
+) -> Result<(i64, String), String> {
+    let sql = format!(
+        r#"
+        SELECT count(*)::int8, COALESCE(md5(string_agg(h, '' ORDER BY h)), '')
+        FROM (SELECT md5(to_jsonb(t)::text) AS h FROM public."{}" t WHERE tenant_id = $1) s
+        "#,
+        table
+    );
+    sqlx::query_as(&sql)
+        .bind(tenant_id)
+        .fetch_one(conn)
+        .await
+        .map_err(|e| format!("Tính checksum bảng {} lỗi: {}", table, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sort_by_dependencies;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn parents_are_copied_before_children() {
+        let names: BTreeSet<String> = ["loan_transaction", "contact", "loan_contract", "users"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let edges = vec![
+            ("loan_transaction".to_string(), "loan_contract".to_string()),
+            ("loan_contract".to_string(), "contact".to_string()),
+            ("contact".to_string(), "tenant".to_string()),
+            ("contact".to_string(), "contact".to_string()),
+        ];
+
+        let order = sort_by_dependencies(&names, &edges);
+        let pos = |t: &str| order.iter().position(|n| n == t).unwrap();
+        assert_eq!(order.len(), 4);
+        assert!(pos("contact") < pos("loan_contract"));
+        assert!(pos("loan_contract") < pos("loan_transaction"));
+    }
+}
@@ -1,8 +1,10 @@
 use crate::module::user::dto::RegisterDto;
 use crate::module::user::model::User;
 use crate::module::user::event::UserCreated;
+use crate::infra::outbox;
 use uuid::Uuid;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
+use crate::core::{auth::Claims, error::AppError, iam, jwt, state::AppState};
 use chrono::{DateTime, Utc};
 use bcrypt::hash;
 
@@ -23,6 +25,8 @@ pub async fn create_user(
         created_at: Some(now), // 👈 wrapped in Some()
     };
 
+    let mut tx = pool.begin().await?;
+
     sqlx::query!(
         r#"
         INSERT INTO users (tenant_id, user_id, email, password_hash, name, created_at)
@@ -35,17 +39,138 @@ pub async fn create_user(
         user.name,
         user.created_at
     )
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
 
-    println!(
-        "📤 Gửi event: UserCreated: {:?}",
-        UserCreated {
-            tenant_id: user.tenant_id,
-            user_id: user.user_id,
-            email: user.email.clone(),
-        }
-    );
+    let event = UserCreated {
+        tenant_id: user.tenant_id,
+        user_id: user.user_id,
+        email: user.email.clone(),
+    };
+    outbox::enqueue(&mut tx, user.tenant_id, &event).await?;
+    tx.commit().await?;
 
     Ok(user)
 }
+
+/// Cặp token trả cho client sau login / refresh
+#[derive(Debug)]
+pub struct TokenPair {
+    pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+/// Kết quả xoay vòng refresh token
+pub enum RefreshOutcome {
+    Issued(TokenPair),
+    /// Không tồn tại / hết hạn / đã thu hồi
+    Invalid,
+    /// Token đã bị thay thế mà vẫn được dùng lại → đã thu hồi mọi phiên của user
+    Reused,
+}
+
+/// ✅ Phát hành access token (kèm role / phiên bản quyền / module) + refresh token mới (lưu dạng hash)
+pub async fn issue_tokens(
+    conn: &mut PgConnection,
+    state: &AppState,
+    tenant_id: Uuid,
+    user_id: Uuid,
+) -> Result<(TokenPair, Uuid), AppError> {
+    let access = iam::access_claims(&state.permissions, &state.shard, tenant_id, user_id).await?;
+    let keys = jwt::keys();
+    let token = keys
+        .sign(&Claims::for_user(user_id, tenant_id).with_access(access))
+        .map_err(|e| AppError::internal(format!("Lỗi tạo JWT: {e}")))?;
+
+    let refresh_token = jwt::generate_refresh_token(tenant_id);
+    let refresh_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO refresh_tokens (tenant_id, id, user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4, now() + make_interval(days => $5))
+        "#,
+        tenant_id,
+        refresh_id,
+        user_id,
+        jwt::hash_refresh_token(&refresh_token),
+        keys.refresh_ttl_days as i32
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok((TokenPair { token, refresh_token, expires_in: keys.access_ttl_secs }, refresh_id))
+}
+
+/// ✅ Đổi refresh token lấy cặp token mới (token cũ bị thu hồi ngay)
+pub async fn rotate_refresh_token(
+    pool: &PgPool,
+    state: &AppState,
+    refresh_token: &str,
+) -> Result<RefreshOutcome, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT tenant_id, id, user_id, revoked_at, replaced_by, expires_at < now() AS "expired!"
+        FROM refresh_tokens
+        WHERE token_hash = $1
+        FOR UPDATE
+        "#,
+        jwt::hash_refresh_token(refresh_token)
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(RefreshOutcome::Invalid);
+    };
+
+    if row.revoked_at.is_some() {
+        if row.replaced_by.is_none() {
+            return Ok(RefreshOutcome::Invalid);
+        }
+        // Token đã xoay vòng bị dùng lại → nghi bị lộ, thu hồi toàn bộ phiên của user
+        revoke_all_for_user(&mut tx, row.tenant_id, row.user_id).await?;
+        tx.commit().await?;
+        tracing::warn!("🚨 Refresh token bị dùng lại, thu hồi mọi phiên của user {}", row.user_id);
+        return Ok(RefreshOutcome::Reused);
+    }
+    if row.expired {
+        return Ok(RefreshOutcome::Invalid);
+    }
+
+    let (pair, new_id) = issue_tokens(&mut tx, state, row.tenant_id, row.user_id).await?;
+    sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = now(), replaced_by = $3 WHERE tenant_id = $1 AND id = $2",
+        row.tenant_id,
+        row.id,
+        new_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(RefreshOutcome::Issued(pair))
+}
+
+/// ✅ Thu hồi refresh token (logout). Trả false nếu token không còn hiệu lực.
+pub async fn revoke_refresh_token(pool: &PgPool, refresh_token: &str) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = now() WHERE token_hash = $1 AND revoked_at IS NULL",
+        jwt::hash_refresh_token(refresh_token)
+    )
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+async fn revoke_all_for_user(conn: &mut PgConnection, tenant_id: Uuid, user_id: Uuid) -> Result<u64, sqlx::Error> {
+    let res = sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = now() WHERE tenant_id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        tenant_id,
+        user_id
+    )
+    .execute(conn)
+    .await?;
+    Ok(res.rows_affected())
+}
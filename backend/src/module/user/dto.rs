@@ -89,3 +89,9 @@ impl LoginDto {
         Ok(())
     }
 }
+
+/// 📥 Refresh token gửi lên cho /user/refresh và /user/logout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshTokenDto {
+    pub refresh_token: String,
+}
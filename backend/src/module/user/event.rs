@@ -1,10 +1,23 @@
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
+use crate::infra::outbox::DomainEvent;
+
 // 📡 Event gửi đi khi user được tạo
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserCreated {
     pub tenant_id: Uuid,
     pub user_id: Uuid,
     pub email: String,
-}
\ No newline at end of file
+}
+impl DomainEvent for UserCreated {
+    const TOPIC: &'static str = "user";
+
+    fn event_type(&self) -> &'static str {
+        "UserCreated"
+    }
+
+    fn aggregate_id(&self) -> Uuid {
+        self.user_id
+    }
+}
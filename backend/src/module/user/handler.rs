@@ -1,29 +1,19 @@
 use axum::{extract::{State, Extension}, Json};
 use axum::response::IntoResponse;
+use axum::http::HeaderMap;
 use bcrypt::verify as bcrypt_verify;
 use chrono::DateTime;
-use jsonwebtoken::{encode, EncodingKey, Header};
-use serde::{Serialize, Deserialize};
 use serde_json::json;
 use sqlx::Row;
 use std::sync::Arc;
 use uuid::Uuid;
 
-use crate::core::{auth::AuthUser, state::AppState, error::AppError};
+use crate::core::{auth::AuthUser, state::AppState, error::AppError, i18n::I18n, jwt};
 use crate::module::user::{
-    dto::{RegisterDto, LoginDto},
-    command::create_user,
+    dto::{RegisterDto, LoginDto, RefreshTokenDto},
+    command::{create_user, issue_tokens, revoke_refresh_token, rotate_refresh_token, RefreshOutcome},
 };
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    tenant_id: String,
-    exp: usize,
-}
-
-const SECRET_KEY: &[u8] = b"super_secret_jwt_key";
-
 /// ✅ Đăng ký người dùng mới (dùng AppError)
 pub async fn register(
     State(state): State<Arc<AppState>>,
@@ -32,7 +22,8 @@ pub async fn register(
     // BE normalize để không phụ thuộc FE
     input.email = input.email.trim().to_lowercase();
 
-    let pool = state.shard.get_pool_for_tenant(&Uuid::nil());
+    // users nằm trên shard của tenant → tra routing (cache miss sẽ đọc bảng tenant)
+    let pool = state.shard.get_pool_for_tenant(&input.tenant_id).await?;
 
     // create_user trả Result<_, Box<dyn Error>> nên KHÔNG dùng AppError::from
     let user = create_user(pool, input)
@@ -53,7 +44,7 @@ pub async fn login(
     let password    = input.password;
 
     // 2) Tra tenant từ meta DB (pool nil)
-    let global_pool = state.shard.get_pool_for_tenant(&Uuid::nil());
+    let global_pool = state.shard.get_pool_for_system();
     let tenant = sqlx::query!(
         r#"SELECT tenant_id, shard_id FROM tenant WHERE slug = $1"#,
         tenant_slug
@@ -63,8 +54,9 @@ pub async fn login(
     .map_err(AppError::from)? // chỉ dùng From cho sqlx::Error
     .ok_or_else(|| AppError::bad_request("Tenant không tồn tại hoặc slug không hợp lệ"))?;
 
-    // 3) Lấy pool theo tenant và tìm user (email đã lowercase)
-    let pool = state.shard.get_pool_for_tenant(&tenant.tenant_id);
+    // 3) Lấy pool theo tenant (nạp routing vào cache) và tìm user (email đã lowercase)
+    state.shard.register_tenant(tenant.tenant_id, &tenant.shard_id);
+    let pool = state.shard.get_pool_for_tenant(&tenant.tenant_id).await?;
 
     // Nếu bảng users có CHECK (email = lower(email)) thì so sánh trực tiếp email = $2
     let user = sqlx::query!(
@@ -84,19 +76,13 @@ pub async fn login(
     // 4) Kiểm tra mật khẩu
     match bcrypt_verify(&password, &user.password_hash) {
         Ok(true) => {
-            let expiration = (chrono::Utc::now().timestamp() + 3600) as usize;
-            let claims = Claims {
-                sub: user.user_id.to_string(),
-                tenant_id: user.tenant_id.to_string(),
-                exp: expiration,
-            };
-
-            let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET_KEY))
-                .map_err(|e| AppError::bad_request(format!("Lỗi tạo JWT: {e}")))?; // KHÔNG dùng internal
+            let (tokens, _) = issue_tokens(&mut *pool.acquire().await?, &state, user.tenant_id, user.user_id).await?;
 
             Ok(Json(json!({
                 "status": "ok",
-                "token": token,
+                "token": tokens.token,
+                "refresh_token": tokens.refresh_token,
+                "expires_in": tokens.expires_in,
                 "user": {
                     "id": user.user_id,
                     "email": user.email,
@@ -109,6 +95,41 @@ pub async fn login(
     }
 }
 
+/// ✅ Đổi refresh token lấy access token mới (refresh token cũ bị thu hồi)
+pub async fn refresh(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input): Json<RefreshTokenDto>,
+) -> Result<impl IntoResponse, AppError> {
+    let i18n = I18n::from_headers(&headers);
+    let invalid = || AppError::unauthorized_i18n(&i18n, "error.auth.invalid_token");
+
+    let tenant_id = jwt::refresh_token_tenant(&input.refresh_token).ok_or_else(invalid)?;
+    let pool = state.shard.get_pool_for_tenant(&tenant_id).await?;
+
+    match rotate_refresh_token(pool, &state, &input.refresh_token).await? {
+        RefreshOutcome::Issued(tokens) => Ok(Json(json!({
+            "status": "ok",
+            "token": tokens.token,
+            "refresh_token": tokens.refresh_token,
+            "expires_in": tokens.expires_in,
+        }))),
+        RefreshOutcome::Invalid | RefreshOutcome::Reused => Err(invalid()),
+    }
+}
+
+/// ✅ Đăng xuất: thu hồi refresh token (idempotent)
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<RefreshTokenDto>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(tenant_id) = jwt::refresh_token_tenant(&input.refresh_token) {
+        let pool = state.shard.get_pool_for_tenant(&tenant_id).await?;
+        revoke_refresh_token(pool, &input.refresh_token).await?;
+    }
+    Ok(Json(json!({ "status": "ok" })))
+}
+
 /// ✅ Trả về thông tin user đang đăng nhập
 pub async fn whoami(
     Extension(auth_user): Extension<AuthUser>,
@@ -125,7 +146,7 @@ pub async fn list_users(
     State(state): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, AppError> {
     let is_admin = auth_user.tenant_id == Uuid::nil();
-    let pool = state.shard.get_pool_for_tenant(&auth_user.tenant_id);
+    let pool = state.shard.get_pool_for_tenant(&auth_user.tenant_id).await?;
 
     let rows = if is_admin {
         sqlx::query(
@@ -1 +1,45 @@
 // tenant_router: extract tenant_id từ header / token
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+
+use crate::core::{auth::auth_user_from_headers, error::{AppError, ErrorResponse}, i18n::I18n, state::AppState};
+
+/// Middleware chặn ghi cho tenant đang chuyển shard.
+/// Mỗi request ghi (POST/PUT/PATCH/DELETE) có Bearer token hợp lệ đọc lại routing + trạng thái fence
+/// từ meta DB (fence do instance khác đặt cũng có hiệu lực ngay, không chờ chu kỳ refresh routing).
+/// Request đọc không chạm meta DB: đi theo routing đã cache (đồng bộ bởi `spawn_refresh`),
+/// vẫn vào shard cũ cho tới khi routing được đổi. Không đọc được meta DB → từ chối ghi.
+pub async fn tenant_write_fence(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_write = !matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+
+    if is_write && request.headers().contains_key("Authorization") {
+        if let Ok(user) = auth_user_from_headers(request.headers()) {
+            if !user.tenant_id.is_nil() {
+                let fenced = match state.shard.refresh_tenant(&user.tenant_id).await {
+                    Ok(fenced) => fenced,
+                    Err(e) => return AppError::from(e).into_response(),
+                };
+                if fenced {
+                    let i18n = I18n::from_headers(request.headers());
+                    let payload = ErrorResponse {
+                        code: "tenant_migrating",
+                        message: i18n.t("error.tenant.migrating"),
+                    };
+                    return (StatusCode::SERVICE_UNAVAILABLE, [(RETRY_AFTER, "5")], Json(payload)).into_response();
+                }
+            }
+        }
+    }
+
+    next.run(request).await
+}